@@ -0,0 +1,110 @@
+use axum::{middleware::Next, response::Response};
+use http::Request;
+use std::{sync::OnceLock, time::Instant};
+use tracing::Level;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Returns the level access log lines are logged at, as configured through `ACCESS_LOG`.
+///
+/// `None` means there's no access log, which is what `ACCESS_LOG=off` asks for. Defaults to
+/// `info`. Panics if `ACCESS_LOG` is set to something other than `off` or a level.
+pub(super) fn level() -> Option<Level> {
+    static LEVEL: OnceLock<Option<Level>> = OnceLock::new();
+    *LEVEL.get_or_init(|| match std::env::var("ACCESS_LOG").as_deref() {
+        Ok("off") => None,
+        Ok(level) => Some(level.parse().unwrap_or_else(|_| {
+            panic!("ACCESS_LOG must be off, trace, debug, info, warn, or error, not {level}")
+        })),
+        Err(_) => Some(Level::INFO),
+    })
+}
+
+/// Gives the path of a request as it's fit to log, which is with any event secret in it replaced
+/// by `:secret`.
+///
+/// The query is left out, since it's rarely what matters when going through the log.
+fn loggable(path: &str) -> String {
+    let mut segments: Vec<_> = path.split('/').collect();
+    for i in 2..segments.len().saturating_sub(1) {
+        if segments[i] == "questions" && segments[i - 2] == "event" {
+            segments[i + 1] = ":secret";
+        }
+    }
+    segments.join("/")
+}
+
+/// Logs one line for every request (through the `access` target), with its method, path, status,
+/// how long it took, and its request id.
+///
+/// The status is that of the response that's actually sent, so rejections by other layers and
+/// errors are logged too. For streamed responses, the time is until the response started.
+pub(super) async fn log<B>(req: Request<B>, next: Next<B>) -> Response {
+    let Some(level) = level() else {
+        return next.run(req).await;
+    };
+    let method = req.method().clone();
+    let path = loggable(req.uri().path());
+    let id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let start = Instant::now();
+    let res = next.run(req).await;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let status = res.status().as_u16();
+
+    // the level has to be known when the event is declared, hence one per level.
+    macro_rules! access {
+        ($level:expr) => {
+            tracing::event!(
+                target: "access",
+                $level,
+                %method,
+                path,
+                status,
+                latency_ms,
+                id,
+                "handled request"
+            )
+        };
+    }
+    if level == Level::TRACE {
+        access!(Level::TRACE);
+    } else if level == Level::DEBUG {
+        access!(Level::DEBUG);
+    } else if level == Level::INFO {
+        access!(Level::INFO);
+    } else if level == Level::WARN {
+        access!(Level::WARN);
+    } else {
+        access!(Level::ERROR);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets() {
+        assert_eq!(
+            loggable("/api/v1/event/01GQ/questions/hunter2/01GR/answer"),
+            "/api/v1/event/01GQ/questions/:secret/01GR/answer"
+        );
+        assert_eq!(
+            loggable("/api/event/01GQ/questions/hunter2"),
+            "/api/event/01GQ/questions/:secret"
+        );
+        // the list of questions guests see has no secret
+        assert_eq!(
+            loggable("/api/event/01GQ/questions"),
+            "/api/event/01GQ/questions"
+        );
+        assert_eq!(loggable("/api/questions/01GQ"), "/api/questions/01GQ");
+    }
+}
@@ -0,0 +1,123 @@
+use super::Backend;
+use crate::error::ApiError;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct Params {
+    /// The most events to list.
+    limit: Option<usize>,
+}
+
+/// Lists every event in the local backend with how many questions it has, newest first.
+///
+/// Only for poking around during development, so it isn't compiled into release builds.
+pub(super) async fn events(
+    State(dynamo): State<Backend>,
+    Query(params): Query<Params>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let Some(mut all) = dynamo.all_events() else {
+        warn!("event listing requested from backend that can't list every event");
+        return Err(ApiError::NotImplemented);
+    };
+    // ulids sort by when they were made.
+    all.sort_unstable_by_key(|&(eid, _)| std::cmp::Reverse(eid));
+    let total = all.len();
+    let events: Vec<_> = all
+        .into_iter()
+        .take(params.limit.unwrap_or(usize::MAX))
+        .map(|(eid, questions)| {
+            serde_json::json!({
+                "id": eid.to_string(),
+                "questions": questions,
+            })
+        })
+        .collect();
+    Ok(Json(
+        serde_json::json!({ "events": events, "total": total }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Path;
+    use ulid::Ulid;
+
+    async fn inner(backend: Backend) {
+        let mut eids = Vec::new();
+        for _ in 0..2 {
+            let e = crate::new::new(State(backend.clone()), String::new())
+                .await
+                .unwrap();
+            eids.push(Ulid::from_string(e["id"].as_str().unwrap()).unwrap());
+            // ulids made in the same millisecond are in no particular order.
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        let list =
+            |limit| super::events(State(backend.clone()), Query(Params { limit: Some(limit) }));
+        if backend.all_events().is_none() {
+            assert_eq!(list(1).await.unwrap_err(), ApiError::NotImplemented);
+            for eid in &eids {
+                backend.delete(eid).await;
+            }
+            return;
+        }
+        let _ = crate::ask::ask(
+            Path(eids[1]),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        // newest first, and no more than asked for
+        let es = list(1).await.unwrap();
+        assert_eq!(
+            es["events"],
+            serde_json::json!([{ "id": eids[1].to_string(), "questions": 1 }])
+        );
+        assert_eq!(es["total"], 2);
+        let es = list(10).await.unwrap();
+        assert_eq!(es["events"][1]["id"], eids[0].to_string());
+        assert_eq!(es["events"][1]["questions"], 0);
+
+        for eid in &eids {
+            backend.delete(eid).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,304 @@
+use crate::to_dynamo_timestamp;
+
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, ReturnValue};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Deserialize, Debug)]
+pub(super) struct Answer {
+    pub(super) answer: String,
+}
+
+/// Sets (or, if `answer` is `None`, clears) the answer of the stored question `q`.
+fn set<K>(q: &mut HashMap<K, Attr>, answer: Option<(String, SystemTime)>)
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    if let Some((answer, time)) = answer {
+        q.insert("answer".into(), Attr::S(answer));
+        q.insert("answered".into(), to_dynamo_timestamp(time));
+        q.insert("modified".into(), to_dynamo_timestamp(time));
+    } else {
+        q.remove("answer");
+        q.remove("answered");
+        q.insert("modified".into(), to_dynamo_timestamp(SystemTime::now()));
+    }
+}
+
+/// How a storage engine keeps the host's answers to questions.
+#[async_trait]
+pub(super) trait AnswerStore {
+    /// Sets (or, if `answer` is `None`, clears) the host's answer to `qid`.
+    ///
+    /// Answering a question also marks it as answered, and clearing the answer un-marks it. Gives
+    /// the question as it is now, and fails with [`StoreError::ConditionFailed`] if the question
+    /// does not exist in the event `eid`.
+    async fn answer(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        answer: Option<(String, SystemTime)>,
+    ) -> Result<Item, StoreError>;
+}
+
+#[async_trait]
+impl AnswerStore for aws_sdk_dynamodb::Client {
+    async fn answer(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        answer: Option<(String, SystemTime)>,
+    ) -> Result<Item, StoreError> {
+        let q = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .condition_expression("eid = :eid")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()));
+
+        let q = if let Some((answer, time)) = answer {
+            q.update_expression("SET answer = :answer, answered = :answered, modified = :answered")
+                .expression_attribute_values(":answer", AttributeValue::S(answer))
+                .expression_attribute_values(":answered", to_dynamo_timestamp(time))
+        } else {
+            q.update_expression("REMOVE answer, answered SET modified = :now")
+                .expression_attribute_values(":now", to_dynamo_timestamp(SystemTime::now()))
+        };
+        let q = q.return_values(ReturnValue::AllNew);
+        let q = super::retry::retry(|| q.clone().send()).await?;
+        Ok(store::from_dynamo(q.attributes.unwrap_or_default()))
+    }
+}
+
+#[async_trait]
+impl AnswerStore for Mutex<Local> {
+    async fn answer(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        answer: Option<(String, SystemTime)>,
+    ) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let q = questions_by_eid
+            .get(eid)
+            .filter(|qs| qs.contains(qid))
+            .and_then(|_| questions.get_mut(qid));
+        let Some(q) = q else {
+            return Err(StoreError::ConditionFailed);
+        };
+        set(q, answer);
+        let ret = store::from_local(q);
+        let update = serde_json::json!({
+            "qid": qid.to_string(),
+            "answer": q.get("answer").and_then(|v| v.as_s().ok()),
+            "answered": q.get("answered").and_then(|v| v.as_n().ok()),
+        });
+        local.publish(eid, "answer", update);
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl AnswerStore for crate::redis::Redis {
+    async fn answer(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        answer: Option<(String, SystemTime)>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if !store::belongs_to(q, eid) {
+                    return Err(StoreError::ConditionFailed);
+                }
+                set(q, answer.clone());
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl AnswerStore for crate::sqlite::Sqlite {
+    async fn answer(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        answer: Option<(String, SystemTime)>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if !store::belongs_to(q, eid) {
+                return Err(StoreError::ConditionFailed);
+            }
+            set(q, answer);
+            Ok(())
+        })?;
+        Ok(q)
+    }
+}
+
+pub(super) async fn answer(
+    Path((eid, secret, qid)): Path<(Ulid, String, Ulid)>,
+    State(dynamo): State<Backend>,
+    body: String,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    // an empty body (or an empty answer) clears the answer
+    let answer = if body.trim().is_empty() {
+        None
+    } else {
+        match serde_json::from_str::<Answer>(&body) {
+            Ok(a) if a.answer.trim().is_empty() => None,
+            Ok(a) => Some((a.answer.trim().to_string(), SystemTime::now())),
+            Err(e) => {
+                warn!(%eid, %qid, error = %e, "got invalid answer body");
+                return Err(ApiError::BadRequest);
+            }
+        }
+    };
+
+    match dynamo.answer(&eid, &qid, answer).await {
+        Ok(q) => {
+            debug!(%eid, %qid, "answered question");
+            let mut v = serde_json::json!({});
+            if let Some(answer) = q.get("answer").and_then(|v| v.as_s().ok()) {
+                v["answer"] = answer.clone().into();
+            }
+            if let Some(answered) = q
+                .get("answered")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<usize>().ok())
+            {
+                v["answered"] = answered.into();
+            }
+            Ok(Json(v))
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%eid, %qid, "attempted to answer question that isn't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to answer question failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        let answer = |secret: &str, body: &str| {
+            super::answer(
+                Path((eid, secret.to_string(), qid)),
+                State(backend.clone()),
+                body.to_string(),
+            )
+        };
+        let listed = || async {
+            let qs =
+                crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                    .await
+                    .1
+                    .unwrap();
+            qs[0].clone()
+        };
+
+        assert_eq!(
+            answer("wrong", r#"{"answer": "hi"}"#).await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            answer(secret, "not json").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let a = answer(secret, r#"{"answer": "hi there"}"#).await.unwrap();
+        assert_eq!(a["answer"], "hi there");
+        assert!(a["answered"].is_u64());
+        let q = listed().await;
+        assert_eq!(q["answer"], "hi there");
+        assert!(q["answered"].is_u64());
+        let qs = crate::questions::questions(Path(qid.to_string()), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(qs[qid.to_string()]["answer"], "hi there");
+
+        // clearing the answer also marks the question as unanswered
+        let a = answer(secret, "").await.unwrap();
+        assert_eq!(a.0, serde_json::json!({}));
+        let q = listed().await;
+        assert_eq!(q.get("answer"), None);
+        assert_eq!(q.get("answered"), None);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
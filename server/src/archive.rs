@@ -0,0 +1,258 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::list::ListStore;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::extract::{Path, State};
+use http::StatusCode;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How a storage engine freezes events.
+#[async_trait]
+pub(super) trait ArchiveStore {
+    /// Freezes the event `eid` so that it takes no more questions or votes.
+    ///
+    /// Votes only name the question, so each question is marked as archived too. That way votes
+    /// don't have to look up the event first.
+    async fn archive(&self, eid: &Ulid) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl ArchiveStore for aws_sdk_dynamodb::Client {
+    async fn archive(&self, eid: &Ulid) -> Result<(), StoreError> {
+        // the event goes first so that no new questions can sneak in after we've listed
+        // the existing ones.
+        self.update_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .update_expression("SET archived = :true")
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .send()
+            .await?;
+
+        let mut start = None;
+        loop {
+            let r = self
+                .list(eid, true, &Default::default(), None, start)
+                .await?;
+            let updates = r
+                .items
+                .iter()
+                .filter_map(|q| q.get("id").cloned())
+                .map(|qid| {
+                    self.update_item()
+                        .table_name("questions")
+                        .key("id", qid.into())
+                        .update_expression("SET archived = :true")
+                        .condition_expression("attribute_exists(id)")
+                        .expression_attribute_values(":true", AttributeValue::Bool(true))
+                        .send()
+                });
+            for r in futures_util::future::join_all(updates).await {
+                match r {
+                    Ok(_) => {}
+                    // deleted since we listed it, so nothing to archive
+                    Err(e) => match StoreError::from(e) {
+                        StoreError::ConditionFailed => {}
+                        e => return Err(e),
+                    },
+                }
+            }
+            start = r.next;
+            if start.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArchiveStore for Mutex<Local> {
+    async fn archive(&self, eid: &Ulid) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            events,
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let Some(e) = events.get_mut(eid) else {
+            return Err(StoreError::ConditionFailed);
+        };
+        e.insert("archived", Attr::Bool(true));
+        for qid in questions_by_eid.get(eid).into_iter().flatten() {
+            if let Some(q) = questions.get_mut(qid) {
+                q.insert("archived", Attr::Bool(true));
+            }
+        }
+        local.publish(eid, "archive", serde_json::json!({}));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ArchiveStore for crate::redis::Redis {
+    async fn archive(&self, eid: &Ulid) -> Result<(), StoreError> {
+        use crate::redis::{event_key, question_key};
+
+        // like in dynamodb, the event goes first so that no new questions can sneak in.
+        self.modify(&event_key(eid), |e| {
+            e.insert("archived".into(), Attr::Bool(true));
+            Ok(())
+        })
+        .await?;
+        for qid in self.qids_of(eid).await? {
+            let archived = self
+                .modify(&question_key(&qid), |q| {
+                    q.insert("archived".into(), Attr::Bool(true));
+                    Ok(())
+                })
+                .await;
+            match archived {
+                // deleted since we listed it, so nothing to archive
+                Ok(_) | Err(StoreError::ConditionFailed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ArchiveStore for crate::sqlite::Sqlite {
+    async fn archive(&self, eid: &Ulid) -> Result<(), StoreError> {
+        self.run(|con| {
+            crate::sqlite::modify(con, crate::sqlite::Table::Events, eid, |e| {
+                e.insert("archived".into(), Attr::Bool(true));
+                Ok(())
+            })?;
+            con.execute(
+                "UPDATE questions SET archived = 1 WHERE eid = ?1",
+                [eid.to_string()],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+/// Makes an event read-only once the host is done with it.
+///
+/// Questions can still be listed and read, but asking and voting fail with a conflict.
+pub(super) async fn archive(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<StatusCode, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    match dynamo.archive(&eid).await {
+        Ok(()) => {
+            info!(%eid, "archived event");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to archive event failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, Json};
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let ask = || {
+            crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+        let vote = |qid| {
+            crate::vote::vote(
+                Path((qid, crate::vote::UpDown::Up)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+            )
+        };
+        let q = ask().await.unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let _ = vote(qid).await.unwrap();
+
+        // only the host gets to archive the event
+        assert_eq!(
+            super::archive(Path((eid, "wrong".into())), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        let r = super::archive(Path((eid, secret.to_string())), State(backend.clone()))
+            .await
+            .unwrap();
+        assert_eq!(r, StatusCode::NO_CONTENT);
+
+        // no more questions or votes
+        assert_eq!(ask().await.unwrap_err(), StatusCode::CONFLICT);
+        assert_eq!(vote(qid).await.unwrap_err(), StatusCode::CONFLICT);
+
+        // but everything is still there to see
+        let qs = crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap()
+            .0;
+        assert_eq!(qs[0]["qid"], qid.to_string());
+        assert_eq!(qs[0]["votes"], 2);
+        let (_, qs) =
+            crate::questions::questions(Path(qid.to_string()), State(backend.clone())).await;
+        assert_eq!(qs.unwrap()[qid.to_string()]["text"], "hello world");
+        let (_, meta) = crate::event::meta(Path(eid), State(backend.clone())).await;
+        assert_eq!(meta.unwrap()["archived"], true);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
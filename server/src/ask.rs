@@ -1,15 +1,19 @@
 use crate::to_dynamo_timestamp;
 
 use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::PutItemError, model::AttributeValue, output::PutItemOutput, types::SdkError,
-};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, Select};
 use axum::extract::{Path, State};
 use axum::response::Json;
-use http::StatusCode;
+use http::HeaderMap;
 use serde::Deserialize;
+use std::sync::Mutex;
 use std::{
+    borrow::Cow,
     collections::HashMap,
+    sync::OnceLock,
     time::{Duration, SystemTime},
 };
 use ulid::Ulid;
@@ -17,94 +21,445 @@ use ulid::Ulid;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-const QUESTIONS_EXPIRE_AFTER_DAYS: u64 = 30;
+pub(super) const QUESTIONS_EXPIRE_AFTER_DAYS: u64 = 30;
+
+const DEFAULT_MAX_QUESTIONS_PER_EVENT: usize = 1000;
+
+/// Returns the most questions any one event can have, as configured through
+/// `MAX_QUESTIONS_PER_EVENT`.
+///
+/// Panics if `MAX_QUESTIONS_PER_EVENT` is set but isn't a whole number.
+pub(super) fn max_questions_per_event() -> usize {
+    static MAX: OnceLock<usize> = OnceLock::new();
+    *MAX.get_or_init(|| match std::env::var("MAX_QUESTIONS_PER_EVENT") {
+        Ok(n) => n
+            .parse()
+            .expect("MAX_QUESTIONS_PER_EVENT must be a whole number of questions"),
+        Err(_) => DEFAULT_MAX_QUESTIONS_PER_EVENT,
+    })
+}
+
+const DEFAULT_MAX_QUESTION_CHARS: usize = 500;
+
+/// Returns the longest a question can be, in characters, as configured through
+/// `MAX_QUESTION_CHARS`.
+///
+/// Panics if `MAX_QUESTION_CHARS` is set but isn't a whole number of at least one.
+pub(super) fn max_question_chars() -> usize {
+    static MAX: OnceLock<usize> = OnceLock::new();
+    *MAX.get_or_init(|| match std::env::var("MAX_QUESTION_CHARS") {
+        Ok(n) => match n.parse() {
+            Ok(0) | Err(_) => panic!("MAX_QUESTION_CHARS must be a whole number of characters"),
+            Ok(n) => n,
+        },
+        Err(_) => DEFAULT_MAX_QUESTION_CHARS,
+    })
+}
+
+/// The longest a question's author can be, in characters.
+pub(super) const MAX_ASKER_LEN: usize = 64;
+
+/// Gives the attributes the question `q` is stored with when it's asked in `eid`.
+fn attributes(
+    eid: &Ulid,
+    qid: &Ulid,
+    q: Question,
+    moderated: bool,
+    inherited: Vec<(&'static str, Attr)>,
+) -> Vec<(&'static str, Attr)> {
+    let mut attrs = vec![
+        ("id", Attr::S(qid.to_string())),
+        ("eid", Attr::S(eid.to_string())),
+        ("votes", Attr::N(1.to_string())),
+        ("version", Attr::N(1.to_string())),
+        ("text", Attr::S(q.body)),
+        ("when", to_dynamo_timestamp(SystemTime::now())),
+        (
+            "expire",
+            to_dynamo_timestamp(
+                SystemTime::now() + Duration::from_secs(QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60),
+            ),
+        ),
+        ("hidden", Attr::Bool(moderated)),
+    ];
+    if moderated {
+        attrs.push(("approved", Attr::Bool(false)));
+    }
+    attrs.extend(inherited);
+    // dynamodb doesn't allow empty sets
+    if !q.tags.is_empty() {
+        attrs.push(("tags", Attr::Ss(q.tags)));
+    }
+    if let Some(asker) = q.asker {
+        attrs.push(("who", Attr::S(asker)));
+    }
+    attrs
+}
 
-impl Backend {
-    pub(super) async fn ask(
+/// How a storage engine takes in new questions.
+#[async_trait]
+pub(super) trait AskStore {
+    /// Adds the question `q` to `eid`.
+    ///
+    /// Questions asked in `moderated` events start out hidden until the host approves them.
+    ///
+    /// The event's `inherited` attributes (see [`crate::event::inherited`]) are kept with the
+    /// question.
+    async fn ask(
         &self,
         eid: &Ulid,
         qid: &Ulid,
         q: Question,
-    ) -> Result<PutItemOutput, SdkError<PutItemError>> {
-        let attrs = [
-            ("id", AttributeValue::S(qid.to_string())),
-            ("eid", AttributeValue::S(eid.to_string())),
-            ("votes", AttributeValue::N(1.to_string())),
-            ("text", AttributeValue::S(q.body.into())),
-            ("when", to_dynamo_timestamp(SystemTime::now())),
-            (
-                "expire",
-                to_dynamo_timestamp(
-                    SystemTime::now()
-                        + Duration::from_secs(QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60),
-                ),
-            ),
-            ("hidden", AttributeValue::Bool(false)),
-        ];
-
-        match self {
-            Self::Dynamo(dynamo) => {
-                let mut r = dynamo.put_item().table_name("questions");
-                for (k, v) in attrs {
-                    r = r.item(k, v);
-                }
-                if let Some(asker) = q.asker {
-                    r = r.item("who", AttributeValue::S(asker));
-                }
-                r.send().await
-            }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local {
-                    questions,
-                    questions_by_eid,
-                    ..
-                } = &mut *local;
-
-                let mut question = HashMap::from_iter(attrs);
-                if let Some(asker) = q.asker {
-                    question.insert("who", AttributeValue::S(asker));
-                }
-                questions.insert(qid.clone(), question);
-                questions_by_eid
-                    .get_mut(eid)
-                    .expect("adding question to event that doesn't exist")
-                    .push(qid.clone());
-                Ok(PutItemOutput::builder().build())
+        moderated: bool,
+        inherited: Vec<(&'static str, Attr)>,
+    ) -> Result<(), StoreError>;
+
+    /// Counts the questions in `eid`, hidden ones included.
+    async fn count(&self, eid: &Ulid) -> Result<usize, StoreError>;
+}
+
+#[async_trait]
+impl AskStore for aws_sdk_dynamodb::Client {
+    async fn ask(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        q: Question,
+        moderated: bool,
+        inherited: Vec<(&'static str, Attr)>,
+    ) -> Result<(), StoreError> {
+        let mut r = self.put_item().table_name("questions");
+        for (k, v) in attributes(eid, qid, q, moderated, inherited) {
+            r = r.item(k, v.into());
+        }
+        super::retry::retry(|| r.clone().send()).await?;
+        Ok(())
+    }
+
+    async fn count(&self, eid: &Ulid) -> Result<usize, StoreError> {
+        let mut n = 0;
+        let mut start = None;
+        loop {
+            let r = self
+                .query()
+                .table_name("questions")
+                .index_name("top")
+                .key_condition_expression("eid = :eid")
+                .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+                .select(Select::Count)
+                .set_exclusive_start_key(start)
+                .send()
+                .await?;
+            n += r.count() as usize;
+            start = r.last_evaluated_key().cloned();
+            if start.is_none() {
+                break Ok(n);
             }
         }
     }
 }
 
+#[async_trait]
+impl AskStore for Mutex<Local> {
+    async fn ask(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        q: Question,
+        moderated: bool,
+        inherited: Vec<(&'static str, Attr)>,
+    ) -> Result<(), StoreError> {
+        let question = HashMap::from_iter(attributes(eid, qid, q, moderated, inherited));
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+        questions.insert(*qid, question);
+        questions_by_eid
+            .get_mut(eid)
+            .expect("adding question to event that doesn't exist")
+            .push(*qid);
+        local.publish(eid, "ask", serde_json::json!({ "qid": qid.to_string() }));
+        Ok(())
+    }
+
+    async fn count(&self, eid: &Ulid) -> Result<usize, StoreError> {
+        Ok(super::lock(self)
+            .questions_by_eid
+            .get(eid)
+            .map_or(0, Vec::len))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl AskStore for crate::redis::Redis {
+    async fn ask(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        q: Question,
+        moderated: bool,
+        inherited: Vec<(&'static str, Attr)>,
+    ) -> Result<(), StoreError> {
+        let question = HashMap::from_iter(attributes(eid, qid, q, moderated, inherited));
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        crate::redis::add_question(&mut pipe, &crate::store::from_local(&question))?;
+        self.run::<()>(&pipe).await
+    }
+
+    async fn count(&self, eid: &Ulid) -> Result<usize, StoreError> {
+        self.query(redis::cmd("ZCARD").arg(crate::redis::questions_key(eid)))
+            .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl AskStore for crate::sqlite::Sqlite {
+    async fn ask(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        q: Question,
+        moderated: bool,
+        inherited: Vec<(&'static str, Attr)>,
+    ) -> Result<(), StoreError> {
+        let question = attributes(eid, qid, q, moderated, inherited);
+        self.run(|con| {
+            crate::sqlite::insert(
+                con,
+                crate::sqlite::Table::Questions,
+                question.iter().map(|(k, v)| (*k, v)),
+            )
+        })
+    }
+
+    async fn count(&self, eid: &Ulid) -> Result<usize, StoreError> {
+        self.run(|con| {
+            Ok(con.query_row(
+                "SELECT COUNT(*) FROM questions WHERE eid = ?1",
+                [eid.to_string()],
+                |row| row.get(0),
+            )?)
+        })
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub(super) struct Question {
     pub(super) body: String,
+    /// Who's asking, if they want to say. Some clients call this the author.
+    #[serde(alias = "author")]
     pub(super) asker: Option<String>,
+    #[serde(default)]
+    pub(super) tags: Vec<String>,
+}
+
+/// The fewest letters a question can have, so that a row of emoji or punctuation isn't one.
+const MIN_LETTERS: usize = 2;
+
+/// Tidies up question text before it's checked and stored.
+///
+/// Markup goes, and whitespace is trimmed and every run of it within a line is collapsed into a
+/// single space. Line breaks are kept, but not blank lines, so that questions padded out with them
+/// don't take over the list.
+pub(super) fn tidy_text(text: &str) -> String {
+    crate::sanitize::strip(text)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Checks that `text`, once [tidied](tidy_text), is acceptable as the body of a question in `eid`.
+///
+/// This applies both to newly asked questions and to questions edited by the host.
+pub(super) fn check_text(eid: &Ulid, text: &str) -> Result<(), ApiError> {
+    if text.is_empty() {
+        warn!(%eid, "ignoring empty question");
+        Err(ApiError::BadRequest)
+    } else if !text.contains([' ', '\n']) {
+        warn!(%eid, body = text, "rejecting single-word question");
+        Err(ApiError::BadRequest)
+    } else if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_LETTERS {
+        warn!(%eid, body = text, "rejecting question without words");
+        Err(ApiError::BadRequest)
+    } else if text.chars().count() > max_question_chars() {
+        warn!(%eid, len = text.chars().count(), "rejecting overly long question");
+        Err(ApiError::QuestionTooLong)
+    } else {
+        Ok(())
+    }
 }
 
+/// Tidies up the name a question in `eid` is signed with.
+///
+/// Blank names mean the question is anonymous. Names go through the same checks as question text,
+/// since they're shown right next to it.
+pub(super) fn clean_asker(eid: &Ulid, asker: Option<String>) -> Result<Option<String>, ApiError> {
+    let Some(asker) = asker else {
+        return Ok(None);
+    };
+    let asker = crate::sanitize::strip(&asker);
+    let asker = asker.trim();
+    if asker.is_empty() {
+        return Ok(None);
+    }
+    if asker.chars().count() > MAX_ASKER_LEN {
+        warn!(%eid, asker, "rejecting question with overly long author");
+        return Err(ApiError::BadRequest);
+    }
+    Ok(Some(
+        crate::profanity::filter().apply(eid, asker)?.into_owned(),
+    ))
+}
+
+/// Asks a question without an idempotency key, which is all most tests need.
+#[cfg(test)]
 pub(super) async fn ask(
     Path(eid): Path<Ulid>,
     State(dynamo): State<Backend>,
-    q: Json<Question>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    if q.body.trim().is_empty() {
-        warn!(%eid, "ignoring empty question");
-        return Err(http::StatusCode::BAD_REQUEST);
-    } else if !q.body.trim().contains(' ') {
-        warn!(%eid, body = q.body, "rejecting single-word question");
-        return Err(http::StatusCode::BAD_REQUEST);
+    Json(q): Json<Question>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    create(&eid, &dynamo, q, Ulid::new()).await
+}
+
+/// Asks a question in an event.
+///
+/// Retries of the same request can be recognized through the `Idempotency-Key` header, and get
+/// the question the first attempt created.
+pub(super) async fn ask_idempotent(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+    headers: HeaderMap,
+    Json(q): Json<Question>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let Some(key) = crate::idempotency::key(&headers)? else {
+        return create(&eid, &dynamo, q, Ulid::new()).await;
+    };
+    let fingerprint = crate::idempotency::fingerprint(&serde_json::json!({
+        "body": q.body,
+        "asker": q.asker,
+        "tags": q.tags,
+    }));
+
+    let qid = Ulid::new();
+    match dynamo.claim(&eid, key, &qid, &fingerprint).await {
+        Ok(None) => {}
+        Ok(Some(claim)) if claim.fingerprint == fingerprint => {
+            debug!(%eid, qid = %claim.qid, "replaying retried question");
+            return Ok(Json(
+                serde_json::json!({ "id": claim.qid.to_string(), "version": 1 }),
+            ));
+        }
+        Ok(Some(_)) => {
+            warn!(%eid, key, "idempotency key reused for a different question");
+            return Err(ApiError::IdempotencyConflict);
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to claim idempotency key failed");
+            return Err(ApiError::Internal);
+        }
+    }
+
+    let r = create(&eid, &dynamo, q, qid).await;
+    if r.is_err() {
+        // so that the client's retry gets to try again, rather than being told about a question
+        // that was never created.
+        if let Err(e) = dynamo.release(&eid, key).await {
+            error!(%eid, error = %e, "dynamodb request to release idempotency key failed");
+        }
+    }
+    r
+}
+
+/// Asks `q` in `eid` as the question `qid`.
+async fn create(
+    eid: &Ulid,
+    dynamo: &Backend,
+    mut q: Question,
+    qid: Ulid,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let eid = *eid;
+    q.body = tidy_text(&q.body);
+    check_text(&eid, &q.body)?;
+    q.tags = crate::tags::clean(&eid, q.tags)?;
+    q.asker = clean_asker(&eid, q.asker)?;
+    if let Cow::Owned(masked) = crate::profanity::filter().apply(&eid, &q.body)? {
+        q.body = masked;
+    }
+
+    let (moderated, max, inherited, webhook) = match dynamo.event(&eid).await {
+        Ok(e) => match e.as_ref() {
+            Some(e) if crate::event::is_archived(e) => {
+                warn!(%eid, "question asked in archived event");
+                return Err(ApiError::EventArchived);
+            }
+            Some(e) if crate::event::is_locked(e) => {
+                warn!(%eid, "question asked in event that's stopped taking them");
+                return Err(ApiError::QuestionsLocked);
+            }
+            Some(e) => (
+                crate::event::is_moderated(e),
+                crate::event::max_questions(e),
+                crate::event::inherited(e),
+                crate::event::webhook_url(e).map(String::from),
+            ),
+            None => {
+                warn!(%eid, "question asked in non-existing event");
+                return Err(ApiError::EventNotFound);
+            }
+        },
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for event failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    // NOTE: questions asked at the same time can all get past this, so the limit is a little
+    // soft. that's fine, it's only there to stop things from getting out of hand.
+    match dynamo.count(&eid).await {
+        Ok(n) if n >= max => {
+            warn!(%eid, max, "rejecting question in event that has too many already");
+            return Err(ApiError::TooManyQuestions);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to count questions failed");
+            return Err(ApiError::Internal);
+        }
     }
 
-    // TODO: check that eid actually exists
-    let qid = ulid::Ulid::new();
-    match dynamo.ask(&eid, &qid, q.0).await {
+    let hook = webhook.map(|url| {
+        let body = serde_json::json!({
+            "eid": eid.to_string(),
+            "qid": qid.to_string(),
+            "text": q.body,
+            "who": q.asker,
+            "tags": q.tags,
+            "hidden": moderated,
+        });
+        (url, body)
+    });
+    match dynamo.ask(&eid, &qid, q, moderated, inherited).await {
         Ok(_) => {
             debug!(%eid, %qid, "created question");
-            Ok(Json(serde_json::json!({ "id": qid.to_string() })))
+            crate::listcache::invalidate(&eid);
+            if let Some((url, body)) = hook {
+                crate::webhook::notify(eid, url, body);
+            }
+            Ok(Json(
+                serde_json::json!({ "id": qid.to_string(), "version": 1 }),
+            ))
         }
         Err(e) => {
             error!(%eid, %qid, error = %e, "dynamodb request to create question failed");
-            Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal)
         }
     }
 }
@@ -112,34 +467,333 @@ pub(super) async fn ask(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
 
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
         let _secret = e["secret"].as_str().unwrap();
         let q = super::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(Question {
                 body: "hello world".into(),
                 asker: Some("person".into()),
+                tags: Vec::new(),
             }),
         )
         .await
         .unwrap();
-        let _qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
         // the list test checks that it's actually returned
+        let qid1 = q["id"].as_str().unwrap().to_string();
+        let q = super::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(Question {
+                body: "hello again".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid2 = q["id"].as_str().unwrap().to_string();
+
+        // both get a creation time, and later questions are never older
+        let (_, qs) =
+            crate::questions::questions(Path(format!("{qid1},{qid2}")), State(backend.clone()))
+                .await;
+        let qs = qs.unwrap().0;
+        let when = |qid: &str| qs[qid]["when"].as_u64().unwrap();
+        assert!(when(&qid1) <= when(&qid2));
+        assert_eq!(qs[&qid1]["who"], "person");
+        assert_eq!(qs[&qid2].get("who"), None);
+
+        // blank names are anonymous, and long ones aren't allowed
+        let q = super::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(
+                serde_json::from_value(serde_json::json!({
+                    "body": "hello once more",
+                    "author": "  ",
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        let qid3 = q["id"].as_str().unwrap().to_string();
+        let (_, qs) = crate::questions::questions(Path(qid3.clone()), State(backend.clone())).await;
+        assert_eq!(qs.unwrap()[&qid3].get("who"), None);
+        assert_eq!(
+            super::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(Question {
+                    body: "hello world".into(),
+                    asker: Some("x".repeat(MAX_ASKER_LEN + 1)),
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // whitespace is tidied up before the question is stored
+        let q = super::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(Question {
+                body: "  hello \n\n  there  ".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = q["id"].as_str().unwrap().to_string();
+        let (_, qs) = crate::questions::questions(Path(qid.clone()), State(backend.clone())).await;
+        assert_eq!(qs.unwrap()[&qid]["text"], "hello\nthere");
+
+        // markup is stripped before the question is stored, but plain angle brackets are kept
+        let q = super::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(Question {
+                body: "<script>alert(1)</script>is 3 < 5? <b>really</b>".into(),
+                asker: Some("<i>person</i>".into()),
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid4 = q["id"].as_str().unwrap().to_string();
+        let (_, qs) = crate::questions::questions(Path(qid4.clone()), State(backend.clone())).await;
+        let qs = qs.unwrap();
+        assert_eq!(qs[&qid4]["text"], "alert(1)is 3 < 5? really");
+        assert_eq!(qs[&qid4]["who"], "person");
+        // and a question that was nothing but markup is no question at all
+        assert_eq!(
+            super::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(Question {
+                    body: "<img src=x onerror=alert(1)>".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        backend.delete(&eid).await;
+
+        // asking in an event that doesn't exist is an error
+        assert_eq!(
+            super::ask(
+                Path(Ulid::new()),
+                State(backend.clone()),
+                Json(Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    async fn idempotent(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let ask = |key: Option<&'static str>, body: &str| {
+            let mut headers = HeaderMap::new();
+            if let Some(key) = key {
+                headers.insert(
+                    crate::idempotency::IDEMPOTENCY_KEY_HEADER,
+                    http::HeaderValue::from_static(key),
+                );
+            }
+            super::ask_idempotent(
+                Path(eid),
+                State(backend.clone()),
+                headers,
+                Json(Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+
+        // retries get the same question back
+        let q1 = ask(Some("once"), "hello world").await.unwrap();
+        let q2 = ask(Some("once"), "hello world").await.unwrap();
+        assert_eq!(q1.0, q2.0);
+        // but not when the key is used for something else
+        assert_eq!(
+            ask(Some("once"), "hello moon").await.unwrap_err(),
+            StatusCode::CONFLICT
+        );
+        // and without a key, every request is a new question
+        let q3 = ask(None, "hello world").await.unwrap();
+        assert_ne!(q1.0, q3.0);
+
+        // failed requests can be retried for real
+        assert_eq!(
+            ask(Some("twice"), "hello").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        let _ = ask(Some("twice"), "hello sun").await.unwrap();
+
+        let qs = crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap()
+            .0;
+        assert_eq!(qs.as_array().unwrap().len(), 3);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local_idempotent() {
+        idempotent(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_idempotent() {
+        idempotent(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_idempotent() {
+        idempotent(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_idempotent() {
+        idempotent(crate::sqlite_backend().await).await;
+    }
+
+    #[test]
+    fn text() {
+        let eid = Ulid::new();
+        let check = |text: &str| check_text(&eid, &tidy_text(text));
+        assert_eq!(check("").unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(check("   ").unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(check(" \n\t ").unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(check("?! 🎉🎉").unwrap_err(), StatusCode::BAD_REQUEST);
+        assert!(check("why 🎉?").is_ok());
+
+        // the limit is on characters, not bytes
+        let max = max_question_chars();
+        let long = format!("é {}", "é".repeat(max - 2));
+        assert!(long.len() > max);
+        assert!(check(&long).is_ok());
+        let e = check(&format!("{long}é")).unwrap_err();
+        assert_eq!(e, ApiError::QuestionTooLong);
+        assert_eq!(e.body()["max"], max);
+
+        assert_eq!(
+            tidy_text("  what  about\n\n the <b>rest</b>?  "),
+            "what about\nthe rest?"
+        );
+    }
+
+    #[tokio::test]
+    async fn local_limit() {
+        let (backend, local) = crate::local_state();
+        let ask = |eid| {
+            super::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+
+        let e = crate::new::new(
+            State(backend.clone()),
+            String::from(r#"{"max_questions": 2}"#),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let _ = ask(eid).await.unwrap();
+        let _ = ask(eid).await.unwrap();
+        assert_eq!(ask(eid).await.unwrap_err(), StatusCode::FORBIDDEN);
+
+        // making room lets questions in again
+        let qid = crate::lock(&local).questions_by_eid[&eid][0];
+        let secret = e["secret"].as_str().unwrap();
+        crate::remove::remove(Path((eid, secret.to_string(), qid)), State(backend.clone()))
+            .await
+            .unwrap();
+        let _ = ask(eid).await.unwrap();
+        backend.delete(&eid).await;
+
+        // the server-wide limit applies to every event
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        for _ in 0..max_questions_per_event() {
+            let _ = ask(eid).await.unwrap();
+        }
+        assert_eq!(ask(eid).await.unwrap_err(), StatusCode::FORBIDDEN);
         backend.delete(&eid).await;
+
+        // and events can't get around it
+        assert_eq!(
+            crate::new::new(
+                State(backend.clone()),
+                serde_json::json!({ "max_questions": max_questions_per_event() + 1 }).to_string(),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
     }
 
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
     }
 }
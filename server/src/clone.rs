@@ -0,0 +1,173 @@
+use super::Backend;
+use crate::error::ApiError;
+use axum::extract::{Path, State};
+use axum::response::Json;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Creates a new event with the same settings as this one, for events that recur (like a weekly
+/// AMA).
+///
+/// Only the settings are copied: the new event starts out with no questions, and it's neither
+/// locked nor archived. It gets its own id and secret.
+pub(super) async fn clone(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let meta = match dynamo.event(&eid).await {
+        Ok(e) => match e.as_ref() {
+            Some(e) => {
+                let s = |k| e.get(k).and_then(|v| v.as_s().ok()).cloned();
+                crate::new::Meta {
+                    title: s("title"),
+                    description: s("description"),
+                    moderated: crate::event::is_moderated(e),
+                    // not event::max_questions, since that's the server-wide limit if the host
+                    // never picked one, and the clone should follow that limit if it changes.
+                    max_questions: e
+                        .get("max_questions")
+                        .and_then(|v| v.as_n().ok())
+                        .and_then(|v| v.parse().ok()),
+                    vote_budget: crate::event::vote_budget(e),
+                    webhook_url: crate::event::webhook_url(e).map(String::from),
+                    downvotes_enabled: Some(crate::event::downvotes_enabled(e)),
+                    hide_counts: crate::event::hides_counts(e),
+                }
+            }
+            None => return Err(ApiError::EventNotFound),
+        },
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for event failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    let new = Ulid::new();
+    let secret = crate::new::generate_secret();
+    let expire = std::time::SystemTime::now() + crate::new::event_ttl();
+    let code = crate::code::assign(&dynamo, &new, expire).await?;
+    let hash = super::hash_secret(&secret);
+    let token = crate::hosttoken::issue(&new, &hash);
+    match dynamo.new(&new, hash, meta, Some(&code), expire).await {
+        Ok(_) => {
+            debug!(%eid, clone = %new, code, "cloned event");
+            let mut e =
+                serde_json::json!({ "id": new.to_string(), "secret": secret, "code": code });
+            if let Some(token) = token {
+                e["token"] = token.into();
+            }
+            Ok(Json(e))
+        }
+        Err(e) => {
+            error!(%eid, clone = %new, error = %e, "dynamodb request to create cloned event failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            serde_json::json!({
+                "title": "Weekly AMA",
+                "moderated": true,
+                "max_questions": 10,
+                "vote_budget": 3,
+                "downvotes_enabled": false,
+                "hide_counts": true,
+            })
+            .to_string(),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let _ = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let _ = crate::lock::lock(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            "on".into(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            clone(Path((eid, "wrong".into())), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let c = clone(Path((eid, secret.to_string())), State(backend.clone()))
+            .await
+            .unwrap();
+        let cid = Ulid::from_string(c["id"].as_str().unwrap()).unwrap();
+        let csecret = c["secret"].as_str().unwrap();
+        assert_ne!(cid, eid);
+        assert_ne!(csecret, secret);
+        crate::check_secret(&backend, &cid, csecret).await.unwrap();
+        assert_eq!(
+            crate::check_secret(&backend, &cid, secret)
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let e = backend.event(&cid).await.unwrap();
+        let e = e.as_ref().unwrap();
+        assert_eq!(e["title"].as_s().unwrap(), "Weekly AMA");
+        assert!(crate::event::is_moderated(e));
+        assert!(!crate::event::is_locked(e));
+        assert_eq!(crate::event::max_questions(e), 10);
+        assert_eq!(crate::event::vote_budget(e), Some(3));
+        assert!(!crate::event::downvotes_enabled(e));
+        assert!(crate::event::hides_counts(e));
+        assert_eq!(backend.count(&cid).await.unwrap(), 0);
+
+        backend.delete(&cid).await;
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
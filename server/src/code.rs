@@ -0,0 +1,413 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use http::{Request, Uri};
+use rand::{thread_rng, Rng};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How many characters an event code has.
+const CODE_LEN: usize = 6;
+
+/// Crockford's base32, which leaves out the letters that are easily mistaken for digits (and U).
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// How many codes we make up for an event before giving up on finding one that isn't taken.
+///
+/// With 32^6 codes to pick from, running out of attempts means something is wrong, not that an
+/// event was unlucky.
+const MAX_ATTEMPTS: usize = 5;
+
+/// Makes up a code, which may or may not already be taken.
+fn generate() -> String {
+    let mut rng = thread_rng();
+    (0..CODE_LEN)
+        .map(|_| char::from(ALPHABET[rng.gen_range(0..ALPHABET.len())]))
+        .collect()
+}
+
+/// Gives the canonical form of the code a person typed in (or read out), if it could be one.
+///
+/// Codes are case-insensitive, and letters that look like digits are taken to be those digits.
+fn normalize(code: &str) -> Option<String> {
+    if code.len() != CODE_LEN {
+        return None;
+    }
+    code.chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => Some('0'),
+            'I' | 'L' => Some('1'),
+            c if c.is_ascii() && ALPHABET.contains(&(c as u8)) => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// How a storage engine keeps track of which event each short code is for.
+#[async_trait]
+pub(super) trait CodeStore {
+    /// Points `code` at `eid`, unless it's already taken.
+    ///
+    /// Returns `false` if it was taken.
+    async fn claim_code(
+        &self,
+        code: &str,
+        eid: &Ulid,
+        expire: SystemTime,
+    ) -> Result<bool, StoreError>;
+
+    /// Gives the event that (the canonical) `code` points at, if any.
+    async fn resolve_code(&self, code: &str) -> Result<Option<Ulid>, StoreError>;
+}
+
+#[async_trait]
+impl CodeStore for aws_sdk_dynamodb::Client {
+    async fn claim_code(
+        &self,
+        code: &str,
+        eid: &Ulid,
+        expire: SystemTime,
+    ) -> Result<bool, StoreError> {
+        let put = self
+            .put_item()
+            .table_name("event_codes")
+            .item("code", AttributeValue::S(code.to_string()))
+            .item("eid", AttributeValue::S(eid.to_string()))
+            .item("expire", crate::to_dynamo_timestamp(expire))
+            // codes of expired events may still be around, but they're free to take.
+            .condition_expression("attribute_not_exists(code) OR expire <= :now")
+            .expression_attribute_values(":now", crate::to_dynamo_timestamp(SystemTime::now()));
+        match super::retry::retry(|| put.clone().send())
+            .await
+            .map_err(StoreError::from)
+        {
+            Ok(_) => Ok(true),
+            Err(StoreError::ConditionFailed) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn resolve_code(&self, code: &str) -> Result<Option<Ulid>, StoreError> {
+        let get = self
+            .get_item()
+            .table_name("event_codes")
+            .key("code", AttributeValue::S(code.to_string()))
+            .projection_expression("eid, expire");
+        let r = super::retry::retry(|| get.clone().send()).await?;
+        let Some(item) = r.item() else {
+            return Ok(None);
+        };
+        // like events, codes linger for a while after they expire.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expire = item
+            .get("expire")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if expire.is_some_and(|expire| expire <= now) {
+            return Ok(None);
+        }
+        Ok(item
+            .get("eid")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+}
+
+#[async_trait]
+impl CodeStore for Mutex<Local> {
+    async fn claim_code(
+        &self,
+        code: &str,
+        eid: &Ulid,
+        _expire: SystemTime,
+    ) -> Result<bool, StoreError> {
+        let mut local = super::lock(self);
+        let Local { codes, .. } = &mut *local;
+        match codes.entry(code.to_string()) {
+            std::collections::hash_map::Entry::Occupied(_) => Ok(false),
+            std::collections::hash_map::Entry::Vacant(v) => {
+                v.insert(*eid);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn resolve_code(&self, code: &str) -> Result<Option<Ulid>, StoreError> {
+        let local = super::lock(self);
+        Ok(local.codes.get(code).copied())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl CodeStore for crate::redis::Redis {
+    async fn claim_code(
+        &self,
+        code: &str,
+        eid: &Ulid,
+        expire: SystemTime,
+    ) -> Result<bool, StoreError> {
+        let expire = expire
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        // codes of expired events expire along with them, and are then free to take.
+        let set: Option<String> = self
+            .query(
+                redis::cmd("SET")
+                    .arg(crate::redis::code_key(code))
+                    .arg(eid.to_string())
+                    .arg("NX")
+                    .arg("EXAT")
+                    .arg(expire),
+            )
+            .await?;
+        Ok(set.is_some())
+    }
+
+    async fn resolve_code(&self, code: &str) -> Result<Option<Ulid>, StoreError> {
+        let eid: Option<String> = self
+            .query(redis::cmd("GET").arg(crate::redis::code_key(code)))
+            .await?;
+        Ok(eid.and_then(|eid| eid.parse().ok()))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl CodeStore for crate::sqlite::Sqlite {
+    async fn claim_code(
+        &self,
+        code: &str,
+        eid: &Ulid,
+        expire: SystemTime,
+    ) -> Result<bool, StoreError> {
+        let expire = expire
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        // codes of expired events are free to take, even before they're swept out.
+        self.run(|con| {
+            let claimed = con.execute(
+                "INSERT INTO codes (code, eid, expire) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (code) DO UPDATE SET eid = excluded.eid, expire = excluded.expire
+                 WHERE codes.expire <= ?4",
+                rusqlite::params![code, eid.to_string(), expire, crate::sqlite::now()],
+            )?;
+            Ok(claimed == 1)
+        })
+    }
+
+    async fn resolve_code(&self, code: &str) -> Result<Option<Ulid>, StoreError> {
+        let eid: Option<String> = self.run(|con| {
+            crate::sqlite::first(
+                con,
+                "SELECT eid FROM codes WHERE code = ?1 AND expire > ?2",
+                rusqlite::params![code, crate::sqlite::now()],
+            )
+        })?;
+        Ok(eid.and_then(|eid| eid.parse().ok()))
+    }
+}
+
+/// Finds a code that isn't taken yet and points it at `eid` until `expire`.
+pub(super) async fn assign(
+    dynamo: &Backend,
+    eid: &Ulid,
+    expire: SystemTime,
+) -> Result<String, ApiError> {
+    for _ in 0..MAX_ATTEMPTS {
+        let code = generate();
+        match dynamo.claim_code(&code, eid, expire).await {
+            Ok(true) => return Ok(code),
+            Ok(false) => debug!(%eid, code, "event code already taken"),
+            Err(e) => {
+                error!(%eid, error = %e, "dynamodb request to claim event code failed");
+                return Err(ApiError::Internal);
+            }
+        }
+    }
+    error!(%eid, "could not find an event code that isn't taken");
+    Err(ApiError::Internal)
+}
+
+/// Tells which event a code is for, so that guests can type in the code instead of the event id.
+pub(super) async fn lookup(
+    Path(code): Path<String>,
+    State(dynamo): State<Backend>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let Some(code) = normalize(&code) else {
+        warn!(code, "got lookup of malformed event code");
+        return Err(ApiError::EventNotFound);
+    };
+    match dynamo.resolve_code(&code).await {
+        Ok(Some(eid)) => Ok(Json(serde_json::json!({ "id": eid.to_string() }))),
+        Ok(None) => {
+            warn!(code, "got lookup of unknown event code");
+            Err(ApiError::EventNotFound)
+        }
+        Err(e) => {
+            error!(code, error = %e, "dynamodb request to look up event code failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+/// Lets an event's code stand in for its id in every `/event/:eid` route, by swapping in the id
+/// before the request is routed.
+///
+/// Ids are never as short as codes, so anything that could be an id is left alone.
+pub(super) async fn resolve<B>(
+    State(dynamo): State<Backend>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(rest) = req.uri().path().strip_prefix("/event/") else {
+        return next.run(req).await;
+    };
+    let (segment, tail) = rest
+        .split_once('/')
+        .map_or((rest, None), |(s, t)| (s, Some(t)));
+    let Some(code) = normalize(segment) else {
+        return next.run(req).await;
+    };
+
+    let eid = match dynamo.resolve_code(&code).await {
+        Ok(Some(eid)) => eid,
+        Ok(None) => {
+            warn!(code, "got request for unknown event code");
+            return ApiError::EventNotFound.into_response();
+        }
+        Err(e) => {
+            error!(code, error = %e, "dynamodb request to resolve event code failed");
+            return ApiError::Internal.into_response();
+        }
+    };
+    let mut path = format!("/event/{eid}");
+    if let Some(tail) = tail {
+        path.push('/');
+        path.push_str(tail);
+    }
+    if let Some(query) = req.uri().query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = Some(path.parse().expect("ids and codes are valid in a path"));
+    *req.uri_mut() = Uri::from_parts(parts).expect("only the path changed");
+    trace!(code, %eid, "resolved event code");
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    #[test]
+    fn normalizing() {
+        assert_eq!(normalize("ab12cd").as_deref(), Some("AB12CD"));
+        assert_eq!(normalize("o1lIxz").as_deref(), Some("0111XZ"));
+        assert_eq!(normalize("ABCDEU"), None);
+        assert_eq!(normalize("ABCDE"), None);
+        assert_eq!(normalize("ABCDEÅ"), None);
+        for _ in 0..100 {
+            let code = generate();
+            assert_eq!(normalize(&code), Some(code));
+        }
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = e["id"].as_str().unwrap().to_string();
+        let code = e["code"].as_str().unwrap().to_string();
+        assert_eq!(code.len(), CODE_LEN);
+
+        let found = lookup(Path(code.to_lowercase()), State(backend.clone()))
+            .await
+            .unwrap();
+        assert_eq!(found["id"], eid);
+        // and codes are only handed out once
+        let eid: Ulid = eid.parse().unwrap();
+        let expire = SystemTime::now() + crate::new::event_ttl();
+        assert!(!backend
+            .claim_code(&code, &Ulid::new(), expire)
+            .await
+            .unwrap());
+        assert_eq!(backend.resolve_code(&code).await.unwrap(), Some(eid));
+
+        // the code works in place of the id everywhere
+        let app = crate::app(
+            backend.clone(),
+            Default::default(),
+            crate::ratelimit::RateLimitLayer::from_env(),
+        );
+        let get = |path: String| {
+            app.clone()
+                .oneshot(Request::get(path).body(axum::body::Body::empty()).unwrap())
+        };
+        let res = get(format!("/api/event/{code}")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get(format!("/api/v1/event/{code}/questions?limit=5"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get(format!("/api/e/{code}")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // while ids still work as before
+        let res = get(format!("/api/event/{eid}")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = get(String::from("/api/event/ZZZZZZ")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            lookup(Path(String::from("nope")), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,161 @@
+use tower_http::compression::{
+    predicate::{And, NotForContentType, Predicate, SizeAbove},
+    CompressionLayer, CompressionLevel,
+};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Responses smaller than this gain too little from compression to be worth the time.
+const DEFAULT_MIN_BYTES: u16 = 1024;
+
+/// Which responses get compressed.
+pub(super) type Compressible =
+    And<And<And<SizeAbove, NotForContentType>, NotForContentType>, NotForContentType>;
+
+/// Builds the compression layer from `COMPRESSION` (the algorithms to offer), `COMPRESSION_LEVEL`,
+/// and `COMPRESSION_MIN_BYTES`.
+///
+/// Panics if any of them is set to something that doesn't make sense.
+pub(super) fn layer() -> CompressionLayer<Compressible> {
+    let var = |name| std::env::var(name).ok();
+    from_config(
+        var("COMPRESSION").as_deref(),
+        var("COMPRESSION_LEVEL").as_deref(),
+        var("COMPRESSION_MIN_BYTES").as_deref(),
+    )
+}
+
+/// Builds a layer that compresses responses of at least `min_bytes` with whichever of
+/// `algorithms` (comma-separated, or `off`) the client takes, at `level`.
+///
+/// Only gzip is offered unless other algorithms are asked for, since every client takes it, and
+/// brotli and zstd take noticeably more time at their best levels.
+fn from_config(
+    algorithms: Option<&str>,
+    level: Option<&str>,
+    min_bytes: Option<&str>,
+) -> CompressionLayer<Compressible> {
+    let mut layer = CompressionLayer::new()
+        .no_gzip()
+        .no_deflate()
+        .no_br()
+        .no_zstd();
+    match algorithms.map(str::trim) {
+        None | Some("") => layer = layer.gzip(true),
+        Some("off") => {}
+        Some(algorithms) => {
+            for algorithm in algorithms.split(',').map(str::trim) {
+                layer = match algorithm {
+                    "gzip" => layer.gzip(true),
+                    "deflate" => layer.deflate(true),
+                    "br" => layer.br(true),
+                    "zstd" => layer.zstd(true),
+                    _ => panic!(
+                        "COMPRESSION must be off or a list of gzip, deflate, br, and zstd, not {algorithm}"
+                    ),
+                };
+            }
+        }
+    }
+
+    let level = match level.map(str::trim) {
+        None | Some("default") => CompressionLevel::Default,
+        Some("fastest") => CompressionLevel::Fastest,
+        Some("best") => CompressionLevel::Best,
+        Some(n) => match n.parse() {
+            Ok(n) => CompressionLevel::Precise(n),
+            Err(_) => panic!("COMPRESSION_LEVEL must be fastest, default, best, or a number"),
+        },
+    };
+
+    let min_bytes = match min_bytes {
+        Some(n) => n.trim().parse().unwrap_or_else(|_| {
+            panic!(
+                "COMPRESSION_MIN_BYTES must be a number of bytes no larger than {}",
+                u16::MAX
+            )
+        }),
+        None => DEFAULT_MIN_BYTES,
+    };
+
+    // event streams have to reach the client as they're sent, not once a compressor is done with
+    // them, and images are compressed already.
+    layer.quality(level).compress_when(
+        SizeAbove::new(min_bytes)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::const_new("text/event-stream")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use http::{header, Request};
+    use tower::ServiceExt;
+
+    async fn encoding(
+        layer: CompressionLayer<Compressible>,
+        path: &str,
+        accept: &str,
+    ) -> Option<String> {
+        let res = Router::new()
+            .route("/big", get(|| async { "hello ".repeat(1000) }))
+            .route("/small", get(|| async { "hello" }))
+            .route(
+                "/stream",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "text/event-stream")],
+                        "data: hello\n\n".repeat(1000),
+                    )
+                }),
+            )
+            .layer(layer)
+            .oneshot(
+                Request::get(path)
+                    .header(header::ACCEPT_ENCODING, accept)
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        res.headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn algorithms() {
+        let default = || from_config(None, None, None);
+        assert_eq!(
+            encoding(default(), "/big", "br, gzip").await.as_deref(),
+            Some("gzip")
+        );
+        assert_eq!(encoding(default(), "/big", "br").await, None);
+        assert_eq!(encoding(default(), "/small", "gzip").await, None);
+        assert_eq!(encoding(default(), "/stream", "gzip").await, None);
+
+        let br = || from_config(Some("zstd, br"), Some("best"), Some("0"));
+        assert_eq!(
+            encoding(br(), "/small", "gzip, br").await.as_deref(),
+            Some("br")
+        );
+        assert_eq!(
+            encoding(br(), "/big", "zstd").await.as_deref(),
+            Some("zstd")
+        );
+        assert_eq!(encoding(br(), "/big", "gzip").await, None);
+
+        let off = from_config(Some("off"), None, None);
+        assert_eq!(encoding(off, "/big", "gzip").await, None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_algorithm() {
+        from_config(Some("gzip,lzma"), None, None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::error::ApiError;
+use axum::{
+    extract::State,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::Request;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_MAX_CONCURRENCY: usize = 256;
+
+/// How long a request waits for a turn when the server is already busy, before it's turned away.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns how many requests are handled at once, as configured through `MAX_CONCURRENCY`.
+///
+/// `None` means there's no limit, which is what `MAX_CONCURRENCY=0` asks for. Panics if
+/// `MAX_CONCURRENCY` is set but isn't a number.
+pub(super) fn max_concurrency() -> Option<usize> {
+    static MAX: OnceLock<usize> = OnceLock::new();
+    let max = *MAX.get_or_init(|| match std::env::var("MAX_CONCURRENCY") {
+        Ok(n) => n
+            .parse()
+            .expect("MAX_CONCURRENCY must be a number of requests"),
+        Err(_) => DEFAULT_MAX_CONCURRENCY,
+    });
+    (max != 0).then_some(max)
+}
+
+/// The requests that may be in flight at once.
+#[derive(Debug, Clone)]
+pub(super) struct Limit {
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl Limit {
+    /// As many as [`max_concurrency`] says.
+    pub(super) fn from_env() -> Self {
+        let max = max_concurrency().unwrap_or(Semaphore::MAX_PERMITS);
+        Self::with_queue_timeout(max, QUEUE_TIMEOUT)
+    }
+
+    fn with_queue_timeout(max: usize, queue_timeout: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max)),
+            queue_timeout,
+        }
+    }
+}
+
+/// Caps how many requests are handled at once, so that a burst queues up (for a little while)
+/// instead of piling onto a small instance until it runs out of memory. Requests that don't get
+/// a turn in time get a `503`.
+///
+/// tower's `ConcurrencyLimitLayer` would do the same, except that it makes requests wait for as
+/// long as it takes.
+///
+/// A request only holds on to its turn until its handler has responded, so event streams and
+/// websockets don't take up turns for as long as they stay open.
+pub(super) async fn limit<B>(
+    State(limit): State<Limit>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let permit = match tokio::time::timeout(limit.queue_timeout, limit.permits.acquire()).await {
+        Ok(Ok(permit)) => permit,
+        Ok(Err(_)) => unreachable!("the semaphore is never closed"),
+        Err(_) => {
+            warn!(
+                path = req.uri().path(),
+                "turning away request since we're too busy"
+            );
+            return ApiError::Overloaded.into_response();
+        }
+    };
+    let res = next.run(req).await;
+    drop(permit);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use http::StatusCode;
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn saturated() {
+        let release = Arc::new(Notify::new());
+        let limit = Limit::with_queue_timeout(1, Duration::from_millis(50));
+        let app = Router::new()
+            .route(
+                "/",
+                get({
+                    let release = release.clone();
+                    move || async move {
+                        release.notified().await;
+                        "hello"
+                    }
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                limit.clone(),
+                super::limit,
+            ));
+        let get = || {
+            app.clone()
+                .oneshot(Request::get("/").body(axum::body::Body::empty()).unwrap())
+        };
+
+        let first = tokio::spawn(get());
+        while limit.permits.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+        // the first request has the only turn, and doesn't give it up in time.
+        let res = get().await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "overloaded");
+
+        // but once it's done, the next one gets to go after waiting its turn.
+        let second = tokio::spawn(get());
+        tokio::task::yield_now().await;
+        release.notify_one();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        release.notify_one();
+        assert_eq!(second.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,103 @@
+use http::{header, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The request headers the client may send along with cross-origin requests.
+const ALLOWED_HEADERS: [&str; 7] = [
+    "authorization",
+    "content-type",
+    "idempotency-key",
+    "if-match",
+    "last-event-id",
+    "x-api-key",
+    "x-client-id",
+];
+
+/// Builds the CORS layer from `ALLOWED_ORIGINS`.
+///
+/// Panics if `ALLOWED_ORIGINS` contains something that isn't a valid origin.
+pub(super) fn layer() -> CorsLayer {
+    from_origins(std::env::var("ALLOWED_ORIGINS").ok().as_deref())
+}
+
+/// Builds a CORS layer that lets `origins` (comma-separated, or `*`) make requests to the API.
+///
+/// If no origins are given, no cross-origin requests are allowed at all, which is what we want
+/// when the client is served from the same origin as the API (as it is behind CloudFront).
+fn from_origins(origins: Option<&str>) -> CorsLayer {
+    let layer = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers(ALLOWED_HEADERS.map(header::HeaderName::from_static));
+
+    match origins.map(str::trim) {
+        None | Some("") => layer,
+        Some("*") => layer.allow_origin(AllowOrigin::any()),
+        Some(origins) => {
+            let origins: Vec<_> = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(|o| {
+                    HeaderValue::from_str(o)
+                        .unwrap_or_else(|_| panic!("ALLOWED_ORIGINS has invalid origin {o}"))
+                })
+                .collect();
+            debug!(?origins, "allowing cross-origin requests");
+            layer.allow_origin(origins)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    async fn preflight(origins: Option<&str>, origin: &str) -> http::Response<axum::body::BoxBody> {
+        Router::new()
+            .route("/api/event/:eid", post(|| async {}))
+            .layer(from_origins(origins))
+            .oneshot(
+                Request::options("/api/event/00000000000000000000000000")
+                    .header(header::ORIGIN, origin)
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                    .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn origins() {
+        let allowed = |res: &http::Response<_>| {
+            res.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+
+        // nothing is allowed by default
+        let res = preflight(None, "https://example.com").await;
+        assert_eq!(allowed(&res), None);
+
+        let res = preflight(Some("*"), "https://example.com").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(allowed(&res).as_deref(), Some("*"));
+
+        let only = Some("https://a.example.com, https://b.example.com");
+        let res = preflight(only, "https://b.example.com").await;
+        assert_eq!(allowed(&res).as_deref(), Some("https://b.example.com"));
+        let methods = res.headers()[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(methods.contains("POST"), "{methods}");
+        let res = preflight(only, "https://c.example.com").await;
+        assert_eq!(allowed(&res), None);
+    }
+}
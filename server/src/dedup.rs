@@ -0,0 +1,159 @@
+use super::Local;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::{net::IpAddr, sync::OnceLock};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How votes are told apart by who cast them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Dedup {
+    /// By the client id the client sends, and failing that, by the IP it votes from.
+    Ip,
+    /// By the client id the client sends, if any.
+    Client,
+    /// Not at all, so every vote counts.
+    Off,
+}
+
+/// Returns how votes are de-duplicated, as configured through `VOTE_DEDUP`.
+///
+/// Defaults to `client`. Panics if `VOTE_DEDUP` is set to something other than `ip`, `client`, or
+/// `off`.
+pub(super) fn mode() -> Dedup {
+    static MODE: OnceLock<Dedup> = OnceLock::new();
+    *MODE.get_or_init(|| match std::env::var("VOTE_DEDUP").as_deref() {
+        Ok("ip") => Dedup::Ip,
+        Ok("client") | Err(_) => Dedup::Client,
+        Ok("off") => Dedup::Off,
+        Ok(v) => panic!("VOTE_DEDUP must be ip, client, or off, not {v}"),
+    })
+}
+
+/// Gives the IP to tell the voter apart by, if votes are de-duplicated by IP.
+///
+/// The IP is taken from `X-Forwarded-For` as [`crate::ratelimit::forwarded_for`] leaves it, which
+/// is the same address the rate limits go by. Whatever the client itself put there is gone by
+/// then, so making up addresses doesn't get anyone more votes.
+pub(super) fn ip(headers: &HeaderMap) -> Option<IpAddr> {
+    if mode() != Dedup::Ip {
+        return None;
+    }
+    crate::ratelimit::forwarded_ip(headers)
+}
+
+/// Gives the voter identity for votes from `ip` in the event with `salt`.
+///
+/// Only the hash is ever stored, and since every event has its own salt, the same IP can't be
+/// followed from one event to the next.
+fn hash(salt: &str, ip: IpAddr) -> String {
+    format!("ip:{:x}", Sha256::digest(format!("{salt}:{ip}").as_bytes()))
+}
+
+/// Makes up the salt for a new event's IP hashes.
+pub(super) fn generate_salt() -> String {
+    crate::new::generate_secret()
+}
+
+/// How a storage engine hands out the salt that votes are de-duplicated with.
+#[async_trait]
+pub(super) trait DedupStore {
+    /// Gets the salt for IP hashes of `eid`.
+    ///
+    /// Returns `None` for events from before there were salts, which aren't de-duplicated by IP.
+    async fn vote_salt(&self, eid: &Ulid) -> Result<Option<String>, StoreError>;
+}
+
+#[async_trait]
+impl DedupStore for aws_sdk_dynamodb::Client {
+    async fn vote_salt(&self, eid: &Ulid) -> Result<Option<String>, StoreError> {
+        let get = self
+            .get_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .projection_expression("vote_salt");
+        let r = super::retry::retry(|| get.clone().send()).await?;
+        Ok(r.item()
+            .and_then(|e| e.get("vote_salt"))
+            .and_then(|v| v.as_s().ok())
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl DedupStore for Mutex<Local> {
+    async fn vote_salt(&self, eid: &Ulid) -> Result<Option<String>, StoreError> {
+        Ok(super::lock(self)
+            .events
+            .get(eid)
+            .and_then(|e| e.get("vote_salt"))
+            .and_then(|v| v.as_s().ok())
+            .cloned())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl DedupStore for crate::redis::Redis {
+    async fn vote_salt(&self, eid: &Ulid) -> Result<Option<String>, StoreError> {
+        self.query(
+            redis::cmd("HGET")
+                .arg(crate::redis::event_key(eid))
+                .arg("vote_salt"),
+        )
+        .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DedupStore for crate::sqlite::Sqlite {
+    async fn vote_salt(&self, eid: &Ulid) -> Result<Option<String>, StoreError> {
+        let salt: Option<Option<String>> = self.run(|con| {
+            crate::sqlite::first(
+                con,
+                "SELECT vote_salt FROM events WHERE id = ?1",
+                [eid.to_string()],
+            )
+        })?;
+        Ok(salt.flatten())
+    }
+}
+
+/// Gives the voter identity of votes from `ip` in `eid`, if the event has a salt.
+///
+/// Failures are only logged, and leave the vote to count like any vote without a client id would.
+pub(super) async fn voter<S>(dynamo: &S, eid: &Ulid, ip: IpAddr) -> Option<String>
+where
+    S: DedupStore + ?Sized,
+{
+    match dynamo.vote_salt(eid).await {
+        Ok(salt) => salt.map(|salt| hash(&salt, ip)),
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for vote salt failed");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashing() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let hashed = hash("salt", ip);
+        assert!(hashed.starts_with("ip:"));
+        assert!(!hashed.contains("10.0.0.1"));
+        assert_eq!(hashed, hash("salt", ip));
+        assert_ne!(hashed, hash("pepper", ip));
+        assert_ne!(hashed, hash("salt", "10.0.0.2".parse().unwrap()));
+    }
+}
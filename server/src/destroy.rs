@@ -0,0 +1,250 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::list::ListStore;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, DeleteRequest, WriteRequest};
+use axum::extract::{Path, State};
+use http::StatusCode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The most requests a single BatchWriteItem may contain.
+const BATCH_SIZE: usize = 25;
+
+/// How many times to re-send deletes that DynamoDB didn't get around to processing.
+const MAX_RETRIES: usize = 3;
+
+/// How a storage engine gets rid of an event and all of its questions.
+#[async_trait]
+pub(super) trait DestroyStore {
+    /// Permanently deletes the event `eid` along with all of its questions.
+    async fn destroy(&self, eid: &Ulid) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl DestroyStore for aws_sdk_dynamodb::Client {
+    async fn destroy(&self, eid: &Ulid) -> Result<(), StoreError> {
+        let mut qids = Vec::new();
+        let mut start = None;
+        loop {
+            let r = self
+                .list(eid, true, &Default::default(), None, start)
+                .await?;
+            qids.extend(
+                r.items
+                    .into_iter()
+                    .filter_map(|mut q| q.remove("id"))
+                    .map(AttributeValue::from),
+            );
+            start = r.next;
+            if start.is_none() {
+                break;
+            }
+        }
+
+        // the questions go first, so that if we fail part-way through, the host can still
+        // try again.
+        for chunk in qids.chunks(BATCH_SIZE) {
+            let mut todo: Vec<_> = chunk
+                .iter()
+                .map(|qid| {
+                    WriteRequest::builder()
+                        .delete_request(
+                            DeleteRequest::builder()
+                                .set_key(Some(HashMap::from_iter([(
+                                    String::from("id"),
+                                    qid.clone(),
+                                )])))
+                                .build(),
+                        )
+                        .build()
+                })
+                .collect();
+            for _ in 0..MAX_RETRIES {
+                if todo.is_empty() {
+                    break;
+                }
+                let r = self
+                    .batch_write_item()
+                    .request_items("questions", todo)
+                    .send()
+                    .await?;
+                todo = r
+                    .unprocessed_items()
+                    .and_then(|r| r.get("questions"))
+                    .cloned()
+                    .unwrap_or_default();
+            }
+            if !todo.is_empty() {
+                return Err(StoreError::Other(
+                    "dynamodb did not process all question deletes".into(),
+                ));
+            }
+        }
+
+        self.delete_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .send()
+            .await?;
+        super::secretcache::invalidate(eid);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DestroyStore for Mutex<Local> {
+    async fn destroy(&self, eid: &Ulid) -> Result<(), StoreError> {
+        super::lock(self).remove_event(eid);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl DestroyStore for crate::redis::Redis {
+    async fn destroy(&self, eid: &Ulid) -> Result<(), StoreError> {
+        use crate::redis::{code_key, event_key, question_key, voters_key};
+
+        let code = self
+            .item(&event_key(eid))
+            .await?
+            .and_then(|e| e.get("code").and_then(|c| c.as_s().ok()).cloned());
+        let qids = self.qids_of(eid).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(event_key(eid))
+            .del(crate::redis::questions_key(eid))
+            .del(crate::redis::spent_key(eid))
+            .del(crate::redis::vote_log_key(eid))
+            .ignore();
+        if let Some(code) = code {
+            pipe.del(code_key(&code)).ignore();
+        }
+        for qid in &qids {
+            pipe.del(question_key(qid)).del(voters_key(qid)).ignore();
+        }
+        // idempotency keys are left to expire on their own within the hour, since they can't be
+        // found without scanning every key.
+        self.run::<()>(&pipe).await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DestroyStore for crate::sqlite::Sqlite {
+    async fn destroy(&self, eid: &Ulid) -> Result<(), StoreError> {
+        self.run(|con| {
+            for sql in [
+                "DELETE FROM voters WHERE qid IN (SELECT id FROM questions WHERE eid = ?1)",
+                "DELETE FROM questions WHERE eid = ?1",
+                "DELETE FROM spent WHERE eid = ?1",
+                "DELETE FROM vote_log WHERE eid = ?1",
+                "DELETE FROM idempotency WHERE eid = ?1",
+                "DELETE FROM codes WHERE eid = ?1",
+                "DELETE FROM events WHERE id = ?1",
+            ] {
+                con.execute(sql, [eid.to_string()])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Tears down an event once the host is done with it.
+pub(super) async fn destroy(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<StatusCode, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    match dynamo.destroy(&eid).await {
+        Ok(()) => {
+            info!(%eid, "deleted event");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to delete event failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Json;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        // only the host gets to delete the event
+        assert_eq!(
+            super::destroy(Path((eid, "wrong".into())), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let r = super::destroy(Path((eid, secret.to_string())), State(backend.clone()))
+            .await
+            .unwrap();
+        assert_eq!(r, StatusCode::NO_CONTENT);
+
+        let (_, r) = crate::event::event(Path(eid), State(backend.clone())).await;
+        assert_eq!(r.unwrap_err(), StatusCode::NOT_FOUND);
+        assert!(backend.questions_by_id(&[qid]).await.unwrap().is_empty());
+        // and the secret is no good anymore either
+        assert_eq!(
+            super::destroy(Path((eid, secret.to_string())), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,496 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, ReturnValue};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use http::HeaderMap;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Deserialize, Debug)]
+pub(super) struct Edit {
+    pub(super) text: String,
+}
+
+/// How a storage engine changes the text of a question.
+#[async_trait]
+pub(super) trait EditStore {
+    /// Replaces the text of `qid`, provided it belongs to the event `eid` (and, if given, is still
+    /// at `version`).
+    ///
+    /// Gives the question as it is now, and fails with [`StoreError::ConditionFailed`] if the
+    /// question does not exist in that event, or has moved on from `version`.
+    async fn edit(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        text: String,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError>;
+}
+
+#[async_trait]
+impl EditStore for aws_sdk_dynamodb::Client {
+    async fn edit(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        text: String,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let upd = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .update_expression("SET #text = :text, modified = :now ADD #version :one")
+            .expression_attribute_names("#text", "text")
+            .expression_attribute_names("#version", "version")
+            .expression_attribute_values(":text", AttributeValue::S(text))
+            .expression_attribute_values(":now", crate::to_dynamo_timestamp(SystemTime::now()))
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+            .return_values(ReturnValue::AllNew);
+        let upd = match version {
+            Some(version) => upd
+                .condition_expression(format!(
+                    "eid = :eid AND {}",
+                    crate::version::condition(version)
+                ))
+                .expression_attribute_values(":version", AttributeValue::N(version.to_string())),
+            None => upd.condition_expression("eid = :eid"),
+        };
+        let upd = super::retry::retry(|| upd.clone().send()).await?;
+        Ok(store::from_dynamo(upd.attributes.unwrap_or_default()))
+    }
+}
+
+#[async_trait]
+impl EditStore for Mutex<Local> {
+    async fn edit(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        text: String,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let q = questions_by_eid
+            .get(eid)
+            .filter(|qs| qs.contains(qid))
+            .and_then(|_| questions.get_mut(qid))
+            .filter(|q| version.is_none_or(|version| crate::version::of(q) == version));
+        let Some(q) = q else {
+            return Err(StoreError::ConditionFailed);
+        };
+        q.insert("text", Attr::S(text));
+        q.insert("modified", crate::to_dynamo_timestamp(SystemTime::now()));
+        crate::version::bump(q);
+        let version = crate::version::of(q);
+        let ret = store::from_local(q);
+        local.publish(
+            eid,
+            "edit",
+            serde_json::json!({ "qid": qid.to_string(), "version": version }),
+        );
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl EditStore for crate::redis::Redis {
+    async fn edit(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        text: String,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if !store::belongs_to(q, eid)
+                    || version.is_some_and(|version| crate::version::of(q) != version)
+                {
+                    return Err(StoreError::ConditionFailed);
+                }
+                q.insert("text".into(), Attr::S(text.clone()));
+                q.insert(
+                    "modified".into(),
+                    crate::to_dynamo_timestamp(SystemTime::now()),
+                );
+                crate::version::bump(q);
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl EditStore for crate::sqlite::Sqlite {
+    async fn edit(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        text: String,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if !store::belongs_to(q, eid)
+                || version.is_some_and(|version| crate::version::of(q) != version)
+            {
+                return Err(StoreError::ConditionFailed);
+            }
+            q.insert("text".into(), Attr::S(text));
+            q.insert(
+                "modified".into(),
+                crate::to_dynamo_timestamp(SystemTime::now()),
+            );
+            crate::version::bump(q);
+            Ok(())
+        })?;
+        Ok(q)
+    }
+}
+
+/// Replaces the text of a question.
+///
+/// If `If-Match` gives a version, the edit only goes through if the question is still at that
+/// version, so that hosts don't overwrite each other's edits without noticing.
+pub(super) async fn edit(
+    Path((eid, secret, qid)): Path<(Ulid, String, Ulid)>,
+    State(dynamo): State<Backend>,
+    headers: HeaderMap,
+    Json(edit): Json<Edit>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+    let version = crate::version::if_match(&headers)?;
+    let text = crate::ask::tidy_text(&edit.text);
+    crate::ask::check_text(&eid, &text)?;
+
+    match dynamo.edit(&eid, &qid, text, version).await {
+        Ok(q) => {
+            debug!(%eid, %qid, "edited question");
+            let text = q.get("text").and_then(|v| v.as_s().ok());
+            let who = q.get("who").and_then(|v| v.as_s().ok());
+            let when = q
+                .get("when")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<usize>().ok());
+            let votes = q
+                .get("votes")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<usize>().ok());
+            let hidden = q.get("hidden").and_then(|v| v.as_bool().ok());
+            let answered = q
+                .get("answered")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<usize>().ok());
+            match (text, when, votes, hidden) {
+                (Some(text), Some(when), Some(votes), Some(hidden)) => {
+                    let mut v = serde_json::json!({
+                        "qid": qid.to_string(),
+                        "text": text,
+                        "when": when,
+                        "votes": votes,
+                        "hidden": hidden,
+                        "version": crate::version::of(&q),
+                    });
+                    if let Some(who) = who {
+                        v["who"] = who.clone().into();
+                    }
+                    if let Some(answered) = answered {
+                        v["answered"] = answered.into();
+                    }
+                    Ok(Json(v))
+                }
+                _ => {
+                    error!(%eid, %qid, ?q, "bad data types for edited question");
+                    Err(ApiError::Internal)
+                }
+            }
+        }
+        Err(StoreError::ConditionFailed) => {
+            let in_event = match version {
+                None => false,
+                Some(_) => match dynamo.in_event(&eid, &qid).await {
+                    Ok(in_event) => in_event,
+                    Err(e) => {
+                        error!(%qid, error = %e, "dynamodb request for edited question failed");
+                        return Err(ApiError::Internal);
+                    }
+                },
+            };
+            if in_event {
+                warn!(%eid, %qid, ?version, "edit of question that has since changed");
+                Err(ApiError::Conflict)
+            } else {
+                warn!(%eid, %qid, "attempted to edit question that isn't in event");
+                Err(ApiError::QuestionNotFound)
+            }
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to edit question failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello wrold".into(),
+                asker: Some("person".into()),
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let _ = crate::vote::vote(
+            Path((qid, crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        let edit = |secret: &str, text: &str| {
+            super::edit(
+                Path((eid, secret.to_string(), qid)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                Json(Edit { text: text.into() }),
+            )
+        };
+
+        assert_eq!(
+            edit("wrong", "hello world").await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            edit(secret, "  ").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let q = edit(secret, "hello world").await.unwrap();
+        assert_eq!(q["qid"], qid.to_string());
+        assert_eq!(q["text"], "hello world");
+        assert_eq!(q["who"], "person");
+        assert_eq!(q["votes"], 2);
+        assert_eq!(q["hidden"], false);
+        assert!(q["when"].is_u64());
+
+        // hosts don't get to slip markup in either
+        let q = edit(secret, "hello <em>world</em>").await.unwrap();
+        assert_eq!(q["text"], "hello world");
+        assert_eq!(
+            edit(secret, "<p> </p>").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            edit(secret, &"hello world ".repeat(100)).await.unwrap_err(),
+            crate::error::ApiError::QuestionTooLong
+        );
+
+        let qs = crate::questions::questions(Path(qid.to_string()), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(qs[qid.to_string()]["text"], "hello world");
+
+        // can't edit a question that isn't in the event
+        assert_eq!(
+            super::edit(
+                Path((eid, secret.to_string(), Ulid::new())),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                Json(Edit {
+                    text: "hello world".into()
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    async fn versions(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello wrold".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(q["version"], 1);
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let if_match = |version: &str| {
+            let mut headers = http::HeaderMap::new();
+            headers.insert(http::header::IF_MATCH, version.parse().unwrap());
+            headers
+        };
+        let edit = |headers, text: &str| {
+            super::edit(
+                Path((eid, secret.clone(), qid)),
+                State(backend.clone()),
+                headers,
+                Json(Edit { text: text.into() }),
+            )
+        };
+
+        let v = crate::vote::vote(
+            Path((qid, crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["version"], 2);
+
+        // a host who's still looking at the question as it was asked is too late
+        assert_eq!(
+            edit(if_match("1"), "hello world").await.unwrap_err(),
+            StatusCode::CONFLICT
+        );
+        let q = edit(if_match("\"2\""), "hello world").await.unwrap();
+        assert_eq!(q["version"], 3);
+
+        let t = crate::toggle::toggle(
+            Path((eid, secret.clone(), qid, crate::toggle::Property::Hidden)),
+            State(backend.clone()),
+            if_match("2"),
+            String::from("on"),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(t, StatusCode::CONFLICT);
+        let t = crate::toggle::toggle(
+            Path((eid, secret.clone(), qid, crate::toggle::Property::Hidden)),
+            State(backend.clone()),
+            if_match("3"),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(t["version"], 4);
+
+        // without If-Match, changes go through regardless
+        let q = edit(http::HeaderMap::new(), "hello, world").await.unwrap();
+        assert_eq!(q["version"], 5);
+        let qs = crate::list::list_all(
+            Path((eid, secret.clone())),
+            State(backend.clone()),
+            axum::extract::Query(Default::default()),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(qs[0]["version"], 5);
+        // and a question that isn't there is still just not found
+        assert_eq!(
+            super::edit(
+                Path((eid, secret.clone(), Ulid::new())),
+                State(backend.clone()),
+                if_match("1"),
+                Json(Edit {
+                    text: "hello world".into()
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            edit(if_match("five"), "hello world").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_versions() {
+        versions(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_versions() {
+        versions(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_versions() {
+        versions(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_versions() {
+        versions(crate::sqlite_backend().await).await;
+    }
+}
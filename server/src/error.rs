@@ -0,0 +1,190 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::StatusCode;
+
+/// Why a request failed, as told to the client.
+///
+/// Each error has a stable `code` that clients can match on, so they don't have to guess at what
+/// a bare status code meant (a 404 could be the event or the question, say). Errors come back as
+/// `{ "error": <code>, "message": <something a human can read> }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ApiError {
+    /// The request was malformed, or asked for something that isn't allowed.
+    BadRequest,
+    /// The event secret didn't match.
+    ForbiddenSecret,
+    /// Events can only be created with an API key, and the request didn't have one we know.
+    ForbiddenApiKey,
+    /// The event doesn't exist (or has expired).
+    EventNotFound,
+    /// The question doesn't exist, or isn't in the event it was asked about through.
+    QuestionNotFound,
+    /// Whatever was asked for doesn't exist.
+    NotFound,
+    /// The event has been archived, so it can't be changed any more.
+    EventArchived,
+    /// An idempotency key was reused for a different request.
+    IdempotencyConflict,
+    /// The question changed since the client last saw it.
+    Conflict,
+    /// The event has stopped taking new questions.
+    QuestionsLocked,
+    /// The event already has as many questions as it takes.
+    TooManyQuestions,
+    /// The question is longer than we take.
+    QuestionTooLong,
+    /// The voter has used all the up-votes the event gives them.
+    VoteBudgetExhausted,
+    /// The event only takes up-votes.
+    DownvotesDisabled,
+    /// The request body was larger than we accept.
+    PayloadTooLarge,
+    /// The client has sent too many requests recently.
+    RateLimited,
+    /// Something went wrong on our end.
+    Internal,
+    /// The backend we're using can't do this.
+    NotImplemented,
+    /// The backend can't be reached.
+    Unavailable,
+    /// The request took too long to handle.
+    Timeout,
+    /// The server is too busy to take the request.
+    Overloaded,
+}
+
+impl ApiError {
+    pub(super) fn status(self) -> StatusCode {
+        match self {
+            Self::BadRequest | Self::QuestionTooLong => StatusCode::BAD_REQUEST,
+            Self::ForbiddenSecret | Self::ForbiddenApiKey => StatusCode::UNAUTHORIZED,
+            Self::EventNotFound | Self::QuestionNotFound | Self::NotFound => StatusCode::NOT_FOUND,
+            Self::EventArchived | Self::IdempotencyConflict | Self::Conflict => {
+                StatusCode::CONFLICT
+            }
+            Self::QuestionsLocked => StatusCode::LOCKED,
+            Self::TooManyQuestions | Self::VoteBudgetExhausted | Self::DownvotesDisabled => {
+                StatusCode::FORBIDDEN
+            }
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            Self::Unavailable | Self::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// The code clients can match on. These must never change once they've shipped.
+    pub(super) fn code(self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::ForbiddenSecret => "forbidden_secret",
+            Self::ForbiddenApiKey => "forbidden_api_key",
+            Self::EventNotFound => "event_not_found",
+            Self::QuestionNotFound => "question_not_found",
+            Self::NotFound => "not_found",
+            Self::EventArchived => "event_archived",
+            Self::IdempotencyConflict => "idempotency_conflict",
+            Self::Conflict => "conflict",
+            Self::QuestionsLocked => "questions_locked",
+            Self::TooManyQuestions => "too_many_questions",
+            Self::QuestionTooLong => "question_too_long",
+            Self::VoteBudgetExhausted => "vote_budget_exhausted",
+            Self::DownvotesDisabled => "downvotes_disabled",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::RateLimited => "rate_limited",
+            Self::Internal => "internal",
+            Self::NotImplemented => "not_implemented",
+            Self::Unavailable => "unavailable",
+            Self::Timeout => "timeout",
+            Self::Overloaded => "overloaded",
+        }
+    }
+
+    pub(super) fn message(self) -> &'static str {
+        match self {
+            Self::BadRequest => "The request is invalid.",
+            Self::ForbiddenSecret => "The event secret is wrong.",
+            Self::ForbiddenApiKey => "Creating events takes a valid API key.",
+            Self::EventNotFound => "The event doesn't exist.",
+            Self::QuestionNotFound => "The question doesn't exist in this event.",
+            Self::NotFound => "Nothing was found.",
+            Self::EventArchived => "The event has been archived.",
+            Self::IdempotencyConflict => {
+                "The idempotency key was already used for a different request."
+            }
+            Self::Conflict => "The question has changed since you last saw it.",
+            Self::QuestionsLocked => "The event isn't taking new questions.",
+            Self::TooManyQuestions => "The event can't take any more questions.",
+            Self::QuestionTooLong => "The question is too long.",
+            Self::VoteBudgetExhausted => "You've used all your votes in this event.",
+            Self::DownvotesDisabled => "This event doesn't take down-votes.",
+            Self::PayloadTooLarge => "The request body is too large.",
+            Self::RateLimited => "Too many requests; try again later.",
+            Self::Internal => "Something went wrong on our end.",
+            Self::NotImplemented => "This isn't available on this server.",
+            Self::Unavailable => "The server can't reach its database.",
+            Self::Timeout => "The request took too long; try again later.",
+            Self::Overloaded => "The server is too busy right now; try again later.",
+        }
+    }
+
+    /// The error body, for places that put it together themselves.
+    pub(super) fn body(self) -> serde_json::Value {
+        let mut body = serde_json::json!({ "error": self.code(), "message": self.message() });
+        if self == Self::QuestionTooLong {
+            // so clients can tell the user how much to cut
+            body["max"] = crate::ask::max_question_chars().into();
+        }
+        body
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+/// For helpers that only know which status they fail with.
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => Self::BadRequest,
+            StatusCode::UNAUTHORIZED => Self::ForbiddenSecret,
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::LOCKED => Self::QuestionsLocked,
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited,
+            StatusCode::NOT_IMPLEMENTED => Self::NotImplemented,
+            StatusCode::SERVICE_UNAVAILABLE => Self::Unavailable,
+            _ => Self::Internal,
+        }
+    }
+}
+
+/// So that tests can keep checking just the status.
+#[cfg(test)]
+impl PartialEq<StatusCode> for ApiError {
+    fn eq(&self, other: &StatusCode) -> bool {
+        self.status() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn body() {
+        let res = ApiError::ForbiddenSecret.into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "forbidden_secret");
+        assert!(body["message"].is_string());
+    }
+}
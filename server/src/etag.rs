@@ -0,0 +1,117 @@
+use axum::{
+    body::{self, BoxBody, Full},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header, HeaderValue, Request, StatusCode};
+use sha2::{Digest, Sha256};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Returns true if the `If-None-Match` header value `given` matches the entity tag `etag`.
+///
+/// Uses the weak comparison, which is the only one allowed for `If-None-Match`.
+fn matches(given: &HeaderValue, etag: &str) -> bool {
+    let Ok(given) = given.to_str() else {
+        return false;
+    };
+    let opaque = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    given
+        .split(',')
+        .any(|t| t.trim() == "*" || opaque(t) == opaque(etag))
+}
+
+/// Tags successful responses with a weak `ETag` computed from their body, and answers requests
+/// whose `If-None-Match` already has that tag with a `304 Not Modified`.
+///
+/// The handler still runs, so this saves bandwidth rather than work. That's still worth it for
+/// endpoints clients poll all the time, since the response rarely changes between polls.
+pub(super) async fn etag<B>(req: Request<B>, next: Next<B>) -> Response {
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    let res = next.run(req).await;
+    if res.status() != StatusCode::OK {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!(error = %e, "failed to read response body to tag it");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    // the body is json we generated, so the same content always serializes the same way.
+    let digest = Sha256::digest(&bytes);
+    let etag = format!(
+        "W/\"{}\"",
+        digest[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    );
+    parts.headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("etags are ascii"),
+    );
+
+    if if_none_match.is_some_and(|given| matches(&given, &etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, BoxBody::default());
+    }
+    Response::from_parts(parts, body::boxed(Full::from(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn conditional() {
+        let app = Router::new()
+            .route("/", get(|| async { "hello" }))
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(axum::middleware::from_fn(etag));
+        let get = |path: &'static str, tag: Option<String>| {
+            let mut req = Request::get(path);
+            if let Some(tag) = tag {
+                req = req.header(header::IF_NONE_MATCH, tag);
+            }
+            app.clone()
+                .oneshot(req.body(axum::body::Body::empty()).unwrap())
+        };
+
+        let res = get("/", None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let tag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert!(tag.starts_with("W/\""), "{tag}");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let res = get("/", Some(tag.clone())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], &*tag);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        // strong and listed forms of the same tag match too
+        let strong = tag.trim_start_matches("W/").to_string();
+        let res = get("/", Some(format!("\"other\", {strong}")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        let res = get("/", Some("\"other\"".into())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // errors are left alone
+        let res = get("/missing", Some("*".into())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+}
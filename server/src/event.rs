@@ -1,83 +1,383 @@
-use std::collections::HashMap;
-
 use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::GetItemError, model::AttributeValue, output::GetItemOutput, types::SdkError,
-};
+use crate::error::ApiError;
+use crate::lastmodified::LastModified;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
 use axum::{
     extract::{Path, State},
     response::AppendHeaders,
     Json,
 };
-use http::{
-    header::{self, HeaderName},
-    StatusCode,
-};
+use http::header::{self, HeaderName};
 use serde_json::Value;
+use std::sync::Mutex;
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-impl Backend {
-    pub(super) async fn event(&self, eid: &Ulid) -> Result<GetItemOutput, SdkError<GetItemError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                dynamo
-                    .get_item()
-                    .table_name("events")
-                    .key("id", AttributeValue::S(eid.to_string()))
-                    .projection_expression("id")
-                    .send()
-                    .await
-            }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local { events, .. } = &mut *local;
-
-                Ok(GetItemOutput::builder()
-                    .set_item(events.get(eid).map(|_| {
-                        HashMap::from_iter([(
-                            String::from("id"),
-                            AttributeValue::S(eid.to_string()),
-                        )])
-                    }))
-                    .build())
-            }
+/// The attributes of an event that [`EventStore::event`] gives.
+const ATTRIBUTES: [&str; 13] = [
+    "id",
+    "code",
+    "title",
+    "description",
+    "when",
+    "moderated",
+    "archived",
+    "questions_locked",
+    "max_questions",
+    "vote_budget",
+    "webhook_url",
+    "downvotes_disabled",
+    "hide_counts",
+];
+
+/// How a storage engine reads and updates an event itself.
+#[async_trait]
+pub(super) trait EventStore {
+    /// Fetches the attributes of `eid` other than the secret, or `None` if there's no such event.
+    ///
+    /// Not all of them are for guests to see; [`serialize_meta`] picks out the ones that are.
+    async fn event(&self, eid: &Ulid) -> Result<Option<Item>, StoreError>;
+}
+
+#[async_trait]
+impl EventStore for aws_sdk_dynamodb::Client {
+    async fn event(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let e = self
+            .get_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .projection_expression(
+                "id,code,#title,#description,#when,moderated,archived,questions_locked,max_questions,vote_budget,webhook_url,downvotes_disabled,hide_counts",
+            )
+            .expression_attribute_names("#title", "title")
+            .expression_attribute_names("#when", "when")
+            .expression_attribute_names("#description", "description")
+            .send()
+            .await?;
+        Ok(e.item.map(store::from_dynamo))
+    }
+}
+
+#[async_trait]
+impl EventStore for Mutex<Local> {
+    async fn event(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let mut local = super::lock(self);
+        let Local { events, .. } = &mut *local;
+
+        Ok(events.get(eid).map(|e| {
+            e.iter()
+                .filter(|&(k, _)| ATTRIBUTES.contains(k))
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect()
+        }))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl EventStore for crate::redis::Redis {
+    async fn event(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let mut e = self.item(&crate::redis::event_key(eid)).await?;
+        if let Some(e) = &mut e {
+            e.retain(|k, _| ATTRIBUTES.contains(&k.as_str()));
+        }
+        Ok(e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl EventStore for crate::sqlite::Sqlite {
+    async fn event(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let mut e = self.item(crate::sqlite::Table::Events, eid)?;
+        if let Some(e) = &mut e {
+            e.retain(|k, _| ATTRIBUTES.contains(&k.as_str()));
+        }
+        Ok(e)
+    }
+}
+
+/// Returns true if questions asked in the event `e` need approval before guests can see them.
+pub(super) fn is_moderated(e: &Item) -> bool {
+    e.get("moderated") == Some(&Attr::Bool(true))
+}
+
+/// Returns true if the event `e` has been archived, and so takes no more questions or votes.
+pub(super) fn is_archived(e: &Item) -> bool {
+    e.get("archived") == Some(&Attr::Bool(true))
+}
+
+/// Returns true if the host has stopped taking new questions in the event `e`.
+pub(super) fn is_locked(e: &Item) -> bool {
+    e.get("questions_locked") == Some(&Attr::Bool(true))
+}
+
+/// Returns the most questions that can be asked in the event `e`.
+pub(super) fn max_questions(e: &Item) -> usize {
+    let max = crate::ask::max_questions_per_event();
+    e.get("max_questions")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .map_or(max, |n| n.min(max))
+}
+
+/// Returns how many up-votes each guest gets in the event `e`, if that's limited.
+pub(super) fn vote_budget(e: &Item) -> Option<u64> {
+    e.get("vote_budget")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Returns true if guests can down-vote questions in the event `e`.
+pub(super) fn downvotes_enabled(e: &Item) -> bool {
+    e.get("downvotes_disabled") != Some(&Attr::Bool(true))
+}
+
+/// Returns true if guests of the event `e` shouldn't see how many votes questions have.
+pub(super) fn hides_counts(e: &Item) -> bool {
+    e.get("hide_counts") == Some(&Attr::Bool(true))
+}
+
+/// Returns the attributes of the event `e` that are kept with each of its questions, so that
+/// requests about a question don't have to look up the event to find them.
+pub(super) fn inherited(e: &Item) -> Vec<(&'static str, Attr)> {
+    let mut attrs = Vec::new();
+    if let Some(budget) = vote_budget(e) {
+        attrs.push(("vote_budget", Attr::N(budget.to_string())));
+    }
+    if !downvotes_enabled(e) {
+        attrs.push(("downvotes_disabled", Attr::Bool(true)));
+    }
+    if hides_counts(e) {
+        attrs.push(("hide_counts", Attr::Bool(true)));
+    }
+    attrs
+}
+
+/// Returns where new questions in the event `e` should be sent, if anywhere.
+pub(super) fn webhook_url(e: &Item) -> Option<&str> {
+    e.get("webhook_url")
+        .and_then(|v| v.as_s().ok())
+        .map(String::as_str)
+}
+
+/// Extracts the host-provided metadata from an event item.
+pub(super) fn serialize_meta(e: &Item) -> Value {
+    let mut v = serde_json::json!({});
+    for k in ["code", "title", "description"] {
+        if let Some(s) = e.get(k).and_then(|v| v.as_s().ok()) {
+            v[k] = s.clone().into();
+        }
+    }
+    if is_moderated(e) {
+        v["moderated"] = true.into();
+    }
+    if is_archived(e) {
+        v["archived"] = true.into();
+    }
+    if is_locked(e) {
+        v["questions_locked"] = true.into();
+    }
+    if let Some(max) = e.get("max_questions").and_then(|v| v.as_n().ok()) {
+        if let Ok(max) = max.parse::<usize>() {
+            v["max_questions"] = max.into();
         }
     }
+    if let Some(budget) = vote_budget(e) {
+        v["vote_budget"] = budget.into();
+    }
+    if !downvotes_enabled(e) {
+        v["downvotes_enabled"] = false.into();
+    }
+    if hides_counts(e) {
+        v["hide_counts"] = true.into();
+    }
+    v
 }
 
+/// Tells whether an event exists.
+///
+/// The answer never changes while the event is around, so it's dated to when the event was made.
 pub(super) async fn event(
     Path(eid): Path<Ulid>,
     State(dynamo): State<Backend>,
 ) -> (
-    AppendHeaders<[(HeaderName, &'static str); 1]>,
-    Result<Json<Value>, StatusCode>,
+    (
+        AppendHeaders<[(HeaderName, &'static str); 1]>,
+        Option<LastModified>,
+    ),
+    Result<Json<Value>, ApiError>,
 ) {
     match dynamo.event(&eid).await {
         Ok(v) => {
-            if let Some(_) = v.item() {
+            if let Some(e) = &v {
                 (
-                    AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                    (
+                        AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                        crate::lastmodified::of(e).map(LastModified),
+                    ),
                     Ok(Json(serde_json::json!({}))),
                 )
             } else {
                 warn!(%eid, "non-existing event");
-                return (
+                (
                     // it's relatively unlikely that an event Ulid that didn't exist will start
                     // existing. but just in case, don't make it _too_ long.
-                    AppendHeaders([(header::CACHE_CONTROL, "max-age=3600")]),
-                    Err(http::StatusCode::NOT_FOUND),
-                );
+                    (
+                        AppendHeaders([(header::CACHE_CONTROL, "max-age=3600")]),
+                        None,
+                    ),
+                    Err(ApiError::EventNotFound),
+                )
             }
         }
         Err(e) => {
             error!(%eid, error = %e, "dynamodb event request failed");
+            (
+                (AppendHeaders([(header::CACHE_CONTROL, "no-cache")]), None),
+                Err(ApiError::Internal),
+            )
+        }
+    }
+}
+
+pub(super) async fn meta(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+) -> (
+    AppendHeaders<[(HeaderName, &'static str); 1]>,
+    Result<Json<Value>, ApiError>,
+) {
+    match dynamo.event(&eid).await {
+        Ok(v) => {
+            if let Some(e) = &v {
+                let mut meta = serialize_meta(e);
+                // not stored with the event, but it's here so clients can count down to it.
+                meta["max_question_chars"] = crate::ask::max_question_chars().into();
+                (
+                    // event metadata never changes. well, except for being archived or locked, but
+                    // guests find out about that as soon as they try to ask or vote.
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                    Ok(Json(meta)),
+                )
+            } else {
+                warn!(%eid, "metadata requested for non-existing event");
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=3600")]),
+                    Err(ApiError::EventNotFound),
+                )
+            }
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb event metadata request failed");
             (
                 AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
-                Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+                Err(ApiError::Internal),
             )
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            String::from(
+                r#"{"title": " Weekly AMA ", "description": "Ask us anything", "downvotes_enabled": false}"#,
+            ),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+
+        let _ = super::event(Path(eid), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        let meta = super::meta(Path(eid), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(meta["title"], "Weekly AMA");
+        assert_eq!(meta["description"], "Ask us anything");
+        assert_eq!(meta["downvotes_enabled"], false);
+        assert_eq!(meta.get("secret"), None);
+        backend.delete(&eid).await;
+
+        // metadata is optional
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let meta = super::meta(Path(eid), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(
+            meta.0,
+            serde_json::json!({
+                "code": e["code"],
+                "max_question_chars": crate::ask::max_question_chars(),
+            })
+        );
+        backend.delete(&eid).await;
+
+        // but has to be reasonable if given
+        assert_eq!(
+            crate::new::new(
+                State(backend.clone()),
+                serde_json::json!({ "title": "x".repeat(101) }).to_string(),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            crate::new::new(State(backend.clone()), String::from("title"))
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // unknown events 404
+        assert_eq!(
+            super::meta(Path(Ulid::new()), State(backend.clone()))
+                .await
+                .1
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,638 @@
+use super::Backend;
+use crate::error::ApiError;
+use crate::store::Attr;
+use axum::{
+    body::StreamBody,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use http::header;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{borrow::Cow, collections::HashMap};
+use tokio_stream::StreamExt;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How many questions to export at a time.
+const PAGE_SIZE: usize = 100;
+
+/// The version of the JSON export format, bumped whenever it changes incompatibly.
+pub(super) const FORMAT_VERSION: u64 = 1;
+
+const HEADER: &str = "qid,text,votes,answered,hidden,when\n";
+
+type Key = HashMap<String, Attr>;
+
+/// Quotes `s` for use as a CSV field if it needs it.
+fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Escapes `s` for use as HTML text or in a quoted attribute.
+fn html_escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// The styles of the HTML export, inlined so that the page works on its own.
+const HTML_STYLE: &str = "\
+body { font-family: system-ui, sans-serif; max-width: 50rem; margin: 2rem auto; padding: 0 1rem; color: #222; }
+h1 { margin-bottom: 0.25rem; }
+ol { padding: 0; list-style: none; }
+li { border-bottom: 1px solid #ddd; padding: 0.75rem 0; display: flex; gap: 1rem; }
+.votes { font-weight: bold; min-width: 3rem; text-align: right; }
+.text { white-space: pre-wrap; }
+.who, .answered { color: #666; font-size: 0.9em; }
+.answer { margin-top: 0.5rem; padding-left: 0.75rem; border-left: 3px solid #8ab; white-space: pre-wrap; }
+";
+
+/// Fetches the page of `eid`'s questions that starts at `start`, with all their attributes.
+///
+/// Also returns where the next page starts, if there is one.
+async fn page(
+    backend: &Backend,
+    eid: &Ulid,
+    start: Option<Key>,
+) -> Result<(Vec<Key>, Option<Key>), ApiError> {
+    let qs = match backend
+        .list(eid, true, &Default::default(), Some(PAGE_SIZE), start)
+        .await
+    {
+        Ok(qs) => qs,
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to list questions for export failed");
+            return Err(ApiError::Internal);
+        }
+    };
+    let items = qs.items;
+
+    // the question list doesn't include the question texts, so those we have to fetch separately.
+    let qids: Vec<_> = items
+        .iter()
+        .filter_map(|q| q.get("id")?.as_s().ok()?.parse::<Ulid>().ok())
+        .collect();
+    let mut texts = match backend.questions_by_id(&qids).await {
+        Ok(texts) => texts,
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for question texts for export failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    let mut out = Vec::with_capacity(items.len());
+    for q in items {
+        let Some(qid) = q.get("id").and_then(|v| v.as_s().ok()) else {
+            error!(%eid, ?q, "found non-string question id");
+            continue;
+        };
+        let Some(t) = texts.remove(qid) else {
+            // most likely deleted while we were exporting
+            warn!(%eid, %qid, "no text found for exported question");
+            continue;
+        };
+        let mut q = q.clone();
+        q.extend(t);
+        out.push(q);
+    }
+
+    Ok((out, qs.next))
+}
+
+/// Renders exported questions as CSV rows.
+fn csv_rows(qs: &[Key]) -> String {
+    let mut out = String::new();
+    for q in qs {
+        let s = |k| q.get(k).and_then(|v| v.as_s().ok()).map(String::as_str);
+        let n = |k| q.get(k).and_then(|v| v.as_n().ok()).map(String::as_str);
+        let hidden = q.get("hidden").and_then(|v| v.as_bool().ok());
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            s("id").unwrap_or_default(),
+            csv_field(s("text").unwrap_or_default()),
+            n("votes").unwrap_or_default(),
+            n("answered").unwrap_or_default(),
+            hidden.copied().unwrap_or(false),
+            n("when").unwrap_or_default(),
+        ));
+    }
+    out
+}
+
+/// Converts a stored attribute to its plain JSON equivalent.
+fn to_json(v: &Attr) -> Value {
+    match v {
+        Attr::S(s) => Value::from(s.clone()),
+        Attr::Bool(b) => Value::from(*b),
+        Attr::N(n) => n
+            .parse::<u64>()
+            .map(Value::from)
+            .or_else(|_| n.parse::<f64>().map(Value::from))
+            .unwrap_or_else(|_| Value::from(n.clone())),
+        Attr::Null(_) => Value::Null,
+        Attr::L(l) => l.iter().map(to_json).collect(),
+        Attr::M(m) => m.iter().map(|(k, v)| (k.clone(), to_json(v))).collect(),
+        Attr::Ss(ss) => ss.iter().cloned().collect(),
+        v => {
+            error!(?v, "exporting attribute of unsupported type");
+            Value::Null
+        }
+    }
+}
+
+/// Exports all of `eid`'s questions as CSV.
+///
+/// The questions are fetched a page at a time as the response is sent, so large events are never
+/// held in memory in full.
+pub(super) async fn export_csv(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<impl IntoResponse, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    // fetch the first page up front so that errors there can still get a proper status code.
+    let (first, next) = page(&dynamo, &eid, None).await?;
+    let rest = futures_util::stream::try_unfold(next, move |start| {
+        let dynamo = dynamo.clone();
+        async move {
+            let Some(start) = start else {
+                return Ok(None);
+            };
+            match page(&dynamo, &eid, Some(start)).await {
+                Ok((qs, next)) => Ok(Some((csv_rows(&qs), next))),
+                // all we can do at this point is cut the response short
+                Err(_) => Err(std::io::Error::other("question export failed")),
+            }
+        }
+    });
+    let first = format!("{HEADER}{}", csv_rows(&first));
+    let body = tokio_stream::once(Ok(first)).chain(rest);
+
+    debug!(%eid, "exporting questions");
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                String::from("text/csv; charset=utf-8"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{eid}.csv\""),
+            ),
+            (header::CACHE_CONTROL, String::from("no-cache")),
+        ],
+        StreamBody::new(body),
+    ))
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct JsonParams {
+    #[serde(default)]
+    pretty: bool,
+}
+
+/// Exports `eid` along with all of its questions as a single JSON document.
+///
+/// The document is versioned through [`FORMAT_VERSION`] so that it can be imported again later.
+pub(super) async fn export_json(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Query(params): Query<JsonParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let mut event = match dynamo.event(&eid).await {
+        Ok(e) => e
+            .as_ref()
+            .map(crate::event::serialize_meta)
+            .unwrap_or_default(),
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb event request for export failed");
+            return Err(ApiError::Internal);
+        }
+    };
+    event["id"] = eid.to_string().into();
+
+    let mut questions = Vec::new();
+    let mut start = None;
+    loop {
+        let (qs, next) = page(&dynamo, &eid, start).await?;
+        questions.extend(qs.iter().map(|q| {
+            q.iter()
+                .map(|(k, v)| (k.clone(), to_json(v)))
+                .collect::<serde_json::Map<_, _>>()
+        }));
+        match next {
+            Some(next) => start = Some(next),
+            None => break,
+        }
+    }
+
+    let doc = serde_json::json!({
+        "version": FORMAT_VERSION,
+        "event": event,
+        "questions": questions,
+    });
+    let body = if params.pretty {
+        serde_json::to_string_pretty(&doc)
+    } else {
+        serde_json::to_string(&doc)
+    }
+    .expect("serializing a json value never fails");
+
+    debug!(%eid, n = questions.len(), "exported event as json");
+    Ok((
+        [
+            (header::CONTENT_TYPE, String::from("application/json")),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{eid}.json\""),
+            ),
+            (header::CACHE_CONTROL, String::from("no-cache")),
+        ],
+        body,
+    ))
+}
+
+/// Renders the (exported) `questions` of the event with `meta` as a self-contained HTML page.
+fn html_page(meta: &Value, questions: &[Key]) -> String {
+    let title = meta["title"].as_str().unwrap_or("Questions");
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        html_escape(title),
+        html_escape(title),
+    );
+    if let Some(description) = meta["description"].as_str() {
+        out.push_str(&format!("<p>{}</p>\n", html_escape(description)));
+    }
+    out.push_str("<ol>\n");
+    for q in questions {
+        let s = |k| q.get(k).and_then(|v| v.as_s().ok()).map(String::as_str);
+        let votes = q
+            .get("votes")
+            .and_then(|v| v.as_n().ok())
+            .map(String::as_str)
+            .unwrap_or("0");
+        out.push_str(&format!(
+            "<li><div class=\"votes\">{}</div><div><div class=\"text\">{}</div>",
+            html_escape(votes),
+            html_escape(s("text").unwrap_or_default()),
+        ));
+        if let Some(who) = s("who") {
+            out.push_str(&format!("<div class=\"who\">{}</div>", html_escape(who)));
+        }
+        if q.contains_key("answered") {
+            out.push_str("<div class=\"answered\">Answered</div>");
+        }
+        if let Some(answer) = s("answer") {
+            out.push_str(&format!(
+                "<div class=\"answer\">{}</div>",
+                html_escape(answer)
+            ));
+        }
+        out.push_str("</div></li>\n");
+    }
+    out.push_str("</ol>\n</body>\n</html>\n");
+    out
+}
+
+/// Exports `eid` as a page that recaps its questions, most-voted first, for hosts to share after
+/// the event.
+///
+/// Hidden questions are left out, since the host didn't want them seen.
+pub(super) async fn export_html(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<impl IntoResponse, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let meta = match dynamo.event(&eid).await {
+        Ok(e) => e
+            .as_ref()
+            .map(crate::event::serialize_meta)
+            .unwrap_or_default(),
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb event request for export failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    let mut questions = Vec::new();
+    let mut start = None;
+    loop {
+        let (qs, next) = page(&dynamo, &eid, start).await?;
+        questions.extend(
+            qs.into_iter()
+                .filter(|q| q.get("hidden") != Some(&Attr::Bool(true))),
+        );
+        match next {
+            Some(next) => start = Some(next),
+            None => break,
+        }
+    }
+    // pages come in vote order, but only within each page.
+    let votes = |q: &Key| {
+        q.get("votes")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    questions.sort_by(|a, b| {
+        votes(b).cmp(&votes(a)).then_with(|| {
+            let id = |q: &Key| q.get("id").and_then(|v| v.as_s().ok()).cloned();
+            id(a).cmp(&id(b))
+        })
+    });
+
+    debug!(%eid, n = questions.len(), "exported event as html");
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                String::from("text/html; charset=utf-8"),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{eid}.html\""),
+            ),
+            (header::CACHE_CONTROL, String::from("no-cache")),
+        ],
+        html_page(&meta, &questions),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Json;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+
+        let export = || async {
+            let res = super::export_csv(Path((eid, secret.to_string())), State(backend.clone()))
+                .await
+                .unwrap()
+                .into_response();
+            assert_eq!(
+                res.headers()[header::CONTENT_TYPE],
+                "text/csv; charset=utf-8"
+            );
+            assert!(res.headers()[header::CONTENT_DISPOSITION]
+                .to_str()
+                .unwrap()
+                .contains(&format!("{eid}.csv")));
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        // an empty event just has the header
+        assert_eq!(export().await, HEADER);
+
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello, \"quoted\"\nworld"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(q["id"].as_str().unwrap().to_string());
+        }
+        let csv = export().await;
+        assert!(csv.starts_with(HEADER));
+        assert!(csv.contains(&format!("{},hello world,1,,false,", qids[0])));
+        assert!(csv.contains(&format!(
+            "{},\"hello, \"\"quoted\"\"\nworld\",1,,false,",
+            qids[1]
+        )));
+
+        // the json export has everything
+        let json = super::export_json(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(json.headers()[header::CONTENT_TYPE], "application/json");
+        let body = hyper::body::to_bytes(json.into_body()).await.unwrap();
+        assert!(!body.contains(&b'\n'));
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["version"], FORMAT_VERSION);
+        assert_eq!(json["event"]["id"], eid.to_string());
+        let qs = json["questions"].as_array().unwrap();
+        assert_eq!(qs.len(), 2);
+        let q = qs.iter().find(|q| q["id"] == qids[1]).unwrap();
+        assert_eq!(q["text"], "hello, \"quoted\"\nworld");
+        assert_eq!(q["votes"], 1);
+        assert_eq!(q["hidden"], false);
+        assert!(q["when"].is_u64());
+
+        let pretty = super::export_json(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            Query(JsonParams { pretty: true }),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        let body = hyper::body::to_bytes(pretty.into_body()).await.unwrap();
+        assert!(body.contains(&b'\n'));
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), json);
+
+        // needs the right secret
+        assert_eq!(
+            super::export_json(
+                Path((eid, String::from("wrong"))),
+                State(backend.clone()),
+                Query(Default::default()),
+            )
+            .await
+            .err()
+            .unwrap(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            super::export_csv(Path((eid, String::from("wrong"))), State(backend.clone()))
+                .await
+                .err()
+                .unwrap(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+
+    async fn html(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            serde_json::json!({ "title": "Q&A <3 all" }).to_string(),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let mut qids = Vec::new();
+        for body in ["is 1 < 2 & 3 > 2?", "what's the plan?", "hide me please"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        let _ = crate::vote::vote(
+            Path((qids[1], crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret.clone(),
+                qids[2],
+                crate::toggle::Property::Hidden,
+            )),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            super::export_html(Path((eid, String::from("wrong"))), State(backend.clone()))
+                .await
+                .err()
+                .unwrap(),
+            StatusCode::UNAUTHORIZED
+        );
+        let res = super::export_html(Path((eid, secret)), State(backend.clone()))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert!(res.headers()[header::CONTENT_DISPOSITION]
+            .to_str()
+            .unwrap()
+            .contains(&format!("{eid}.html")));
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("<title>Q&amp;A &lt;3 all</title>"));
+        // most-voted first, and no markup gets through
+        let plan = page.find("what&#39;s the plan?").unwrap();
+        let math = page.find("is 1 &lt; 2 &amp; 3 &gt; 2?").unwrap();
+        assert!(plan < math);
+        assert!(!page.contains("hide me"));
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local_html() {
+        html(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_html() {
+        html(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_html() {
+        html(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_html() {
+        html(crate::sqlite_backend().await).await;
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(html_escape("plain"), "plain");
+        assert_eq!(
+            html_escape("<b>\"Tom\" & 'Jerry'</b>"),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -0,0 +1,143 @@
+use super::{Backend, Local};
+use crate::store::StoreError;
+use async_trait::async_trait;
+use axum::{extract::State, response::AppendHeaders, Json};
+use http::{
+    header::{self, HeaderName},
+    StatusCode,
+};
+use serde_json::Value;
+use std::sync::Mutex;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How a storage engine tells whether it can be reached.
+#[async_trait]
+pub(super) trait HealthStore {
+    /// Checks that the backend is reachable.
+    async fn health(&self) -> Result<(), StoreError>;
+
+    /// Names the backend, as the health check reports it.
+    fn name(&self) -> &'static str;
+}
+
+#[async_trait]
+impl HealthStore for aws_sdk_dynamodb::Client {
+    async fn health(&self) -> Result<(), StoreError> {
+        // one of the cheapest calls there is, and it exercises both credentials and
+        // connectivity.
+        self.describe_table().table_name("events").send().await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "dynamo"
+    }
+}
+
+#[async_trait]
+impl HealthStore for Mutex<Local> {
+    async fn health(&self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "local"
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl HealthStore for crate::redis::Redis {
+    async fn health(&self) -> Result<(), StoreError> {
+        self.query::<()>(&redis::cmd("PING")).await
+    }
+
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl HealthStore for crate::sqlite::Sqlite {
+    async fn health(&self) -> Result<(), StoreError> {
+        self.run(|con| Ok(con.query_row("SELECT 1", [], |_| Ok(()))?))
+    }
+
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+}
+
+pub(super) async fn health(
+    State(dynamo): State<Backend>,
+) -> (
+    AppendHeaders<[(HeaderName, &'static str); 1]>,
+    Result<Json<Value>, (StatusCode, Json<Value>)>,
+) {
+    let backend = dynamo.name();
+    match dynamo.health().await {
+        Ok(()) => (
+            AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
+            Ok(Json(
+                serde_json::json!({ "status": "ok", "backend": backend }),
+            )),
+        ),
+        Err(e) => {
+            error!(error = %e, "health check failed to reach dynamodb");
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
+                Err((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(serde_json::json!({ "status": "unavailable", "backend": backend })),
+                )),
+            )
+        }
+    }
+}
+
+/// Answers right away without going anywhere near the backend.
+///
+/// This is for scheduled keep-warm invocations, which only need the Lambda to be up, and
+/// shouldn't cost any more than that.
+pub(super) async fn ping() -> (AppendHeaders<[(HeaderName, &'static str); 1]>, &'static str) {
+    (AppendHeaders([(header::CACHE_CONTROL, "no-cache")]), "pong")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn inner(backend: Backend) {
+        let (_, res) = super::health(State(backend.clone())).await;
+        let res = res.unwrap();
+        assert_eq!(res["status"], "ok");
+        assert_eq!(res["backend"], backend.name());
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
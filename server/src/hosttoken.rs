@@ -0,0 +1,261 @@
+use crate::error::ApiError;
+use axum::{
+    http::{header::AUTHORIZATION, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{sync::OnceLock, time::SystemTime};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The shortest `HOST_TOKEN_KEY` we accept, since anyone who guesses it can host every event.
+const MIN_KEY_BYTES: usize = 32;
+
+/// The header of every token we issue. Tokens with any other header are rejected, which keeps
+/// clients from picking a weaker algorithm (or none at all).
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Returns the key host tokens are signed with, as configured through `HOST_TOKEN_KEY`.
+///
+/// `None` means host tokens are off, which they are in release builds unless a key is set. Debug
+/// builds make one up instead, so tokens work locally but don't outlive the server. Panics if
+/// `HOST_TOKEN_KEY` is set but shorter than 32 bytes.
+pub(super) fn key() -> Option<&'static [u8]> {
+    static KEY: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    KEY.get_or_init(|| match std::env::var("HOST_TOKEN_KEY") {
+        Ok(key) => {
+            assert!(
+                key.len() >= MIN_KEY_BYTES,
+                "HOST_TOKEN_KEY must be at least {MIN_KEY_BYTES} bytes long"
+            );
+            Some(key.into_bytes())
+        }
+        Err(_) if cfg!(debug_assertions) => Some(rand::random::<[u8; 32]>().to_vec()),
+        Err(_) => None,
+    })
+    .as_deref()
+}
+
+/// What a host token says about its bearer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Claims {
+    /// The event the token is for.
+    sub: Ulid,
+    /// Whether the bearer hosts the event, which is the only kind of token there is for now.
+    host: bool,
+    /// A [fingerprint] of the event's stored secret when the token was issued, so that rotating
+    /// the secret revokes the token too.
+    sec: String,
+    /// When the token stops working, in seconds since the epoch.
+    exp: u64,
+}
+
+/// Gives a short digest of an event's stored secret that says nothing about the secret itself.
+fn fingerprint(stored: &str) -> String {
+    let mut digest = format!("{:x}", Sha256::digest(stored.as_bytes()));
+    digest.truncate(16);
+    digest
+}
+
+/// Gives the MAC that `signed` (the header and claims of a token) is signed with under `key`.
+fn mac(key: &[u8], signed: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(signed.as_bytes());
+    mac
+}
+
+fn sign(key: &[u8], claims: &Claims) -> String {
+    let claims = serde_json::to_vec(claims).expect("claims are always valid json");
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(HEADER),
+        URL_SAFE_NO_PAD.encode(claims)
+    );
+    let signature = URL_SAFE_NO_PAD.encode(mac(key, &signed).finalize().into_bytes());
+    format!("{signed}.{signature}")
+}
+
+/// Gives the claims of `token` if it's one we signed with `key` and it hasn't expired.
+fn verify(key: &[u8], token: &str) -> Option<Claims> {
+    let (signed, signature) = token.rsplit_once('.')?;
+    let (header, claims) = signed.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    // the comparison takes as long however much of the signature is right.
+    mac(key, signed).verify_slice(&signature).ok()?;
+    if URL_SAFE_NO_PAD.decode(header).ok()? != HEADER.as_bytes() {
+        return None;
+    }
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    (claims.exp > now).then_some(claims)
+}
+
+/// Makes a token that lets its bearer host `eid` for as long as events live, or until the secret
+/// stored as `stored` is rotated.
+///
+/// `None` if host tokens are [off](key).
+pub(super) fn issue(eid: &Ulid, stored: &str) -> Option<String> {
+    let exp = SystemTime::now() + crate::new::event_ttl();
+    let claims = Claims {
+        sub: *eid,
+        host: true,
+        sec: fingerprint(stored),
+        exp: exp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    };
+    Some(sign(key()?, &claims))
+}
+
+tokio::task_local! {
+    /// The claims of the host token the request being handled came with.
+    static BEARER: Claims;
+}
+
+/// Checks the host token the request came with, if any, so that [`authorizes`] can tell handlers
+/// about it.
+///
+/// Requests with a token that's been tampered with or has expired are turned away, whatever
+/// they're for.
+pub(super) async fn bearer<B>(req: Request<B>, next: Next<B>) -> Response {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let (Some(token), Some(key)) = (token, key()) else {
+        return next.run(req).await;
+    };
+    match verify(key, token.trim()) {
+        Some(claims) => BEARER.scope(claims, next.run(req)).await,
+        None => {
+            warn!("rejecting request with invalid host token");
+            ApiError::ForbiddenSecret.into_response()
+        }
+    }
+}
+
+/// Whether the request being handled came with a host token for `eid`, whose secret is stored as
+/// `stored`.
+pub(super) fn authorizes(eid: &Ulid, stored: &str) -> bool {
+    BEARER
+        .try_with(|claims| claims.host && claims.sub == *eid && claims.sec == fingerprint(stored))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::State};
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    #[test]
+    fn signing() {
+        let key = [7; MIN_KEY_BYTES];
+        let claims = Claims {
+            sub: Ulid::new(),
+            host: true,
+            sec: fingerprint("stored"),
+            exp: u64::MAX,
+        };
+        let token = sign(&key, &claims);
+        assert_eq!(verify(&key, &token), Some(claims.clone()));
+        assert_eq!(verify(&[8; MIN_KEY_BYTES], &token), None);
+        // a token can't be made to say something else
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let forged = sign(
+            &key,
+            &Claims {
+                sub: Ulid::new(),
+                ..claims.clone()
+            },
+        );
+        let (forged, _) = forged.rsplit_once('.').unwrap();
+        assert_eq!(verify(&key, &format!("{forged}.{signature}")), None);
+        // nor outlive its expiry
+        let expired = Claims { exp: 1, ..claims };
+        assert_eq!(verify(&key, &sign(&key, &expired)), None);
+        assert_eq!(verify(&key, "not.a.token"), None);
+    }
+
+    async fn inner(backend: crate::Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let token = e["token"].as_str().unwrap().to_owned();
+
+        let app = crate::app(
+            backend.clone(),
+            Default::default(),
+            crate::ratelimit::RateLimitLayer::from_env(),
+        );
+        let check = |eid: Ulid, token: Option<&str>| {
+            // the secret is left out of the path, as a client with a token would.
+            let mut req = Request::get(format!("/api/event/{eid}/questions/token/stats"));
+            if let Some(token) = token {
+                req = req.header(AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let app = app.clone();
+            let req = req.body(Body::empty()).unwrap();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(check(eid, Some(&token)).await, StatusCode::OK);
+        assert_eq!(check(eid, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(check(eid, Some("garbage")).await, StatusCode::UNAUTHORIZED);
+        // a token only works for the event it was issued for
+        let other = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let other = Ulid::from_string(other["id"].as_str().unwrap()).unwrap();
+        assert_eq!(check(other, Some(&token)).await, StatusCode::UNAUTHORIZED);
+
+        // and only until the secret is rotated
+        let secret = e["secret"].as_str().unwrap().to_owned();
+        let rotated =
+            crate::rotate::rotate(axum::extract::Path((eid, secret)), State(backend.clone()))
+                .await
+                .unwrap();
+        assert_eq!(check(eid, Some(&token)).await, StatusCode::UNAUTHORIZED);
+        let token = rotated["token"].as_str().unwrap();
+        assert_eq!(check(eid, Some(token)).await, StatusCode::OK);
+
+        backend.delete(&eid).await;
+        backend.delete(&other).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
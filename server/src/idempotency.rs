@@ -0,0 +1,288 @@
+use super::Local;
+use crate::error::ApiError;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use http::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Header through which clients mark retries of the same request.
+pub(super) const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// How long a retry is recognized as one.
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// What a request with an idempotency key has been recorded as.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Claim {
+    /// The question the request created (or is creating).
+    pub(super) qid: Ulid,
+    /// A hash of the request, to tell retries from different requests that reuse a key.
+    pub(super) fingerprint: String,
+    /// When the key can be used for something else, in seconds since the epoch.
+    pub(super) expire: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Extracts the idempotency key supplied by the client, if any.
+pub(super) fn key(headers: &HeaderMap) -> Result<Option<&str>, ApiError> {
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= 128 => Ok(Some(key)),
+        _ => {
+            warn!(?key, "got invalid idempotency key");
+            Err(ApiError::BadRequest)
+        }
+    }
+}
+
+/// Hashes the parts of a request that a retry has to repeat exactly.
+pub(super) fn fingerprint(parts: &serde_json::Value) -> String {
+    format!("{:x}", Sha256::digest(parts.to_string().as_bytes()))
+}
+
+/// How a storage engine remembers idempotency keys.
+#[async_trait]
+pub(super) trait IdempotencyStore {
+    /// Frees up idempotency `key` in `eid` again, for when the request it was claimed for failed.
+    async fn release(&self, eid: &Ulid, key: &str) -> Result<(), StoreError>;
+
+    /// Records that the request with idempotency `key` in `eid` will create `qid`.
+    ///
+    /// Returns `None` if the key was free (or had expired), and otherwise what the key was already
+    /// claimed for, in which case nothing is recorded.
+    async fn claim(
+        &self,
+        eid: &Ulid,
+        key: &str,
+        qid: &Ulid,
+        fingerprint: &str,
+    ) -> Result<Option<Claim>, StoreError>;
+}
+
+#[async_trait]
+impl IdempotencyStore for aws_sdk_dynamodb::Client {
+    async fn release(&self, eid: &Ulid, key: &str) -> Result<(), StoreError> {
+        self.delete_item()
+            .table_name("idempotency")
+            .key("eid", AttributeValue::S(eid.to_string()))
+            .key("key", AttributeValue::S(key.to_string()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn claim(
+        &self,
+        eid: &Ulid,
+        key: &str,
+        qid: &Ulid,
+        fingerprint: &str,
+    ) -> Result<Option<Claim>, StoreError> {
+        let now = now();
+        let claim = Claim {
+            qid: *qid,
+            fingerprint: fingerprint.to_string(),
+            expire: now + WINDOW.as_secs(),
+        };
+        let r = self
+            .put_item()
+            .table_name("idempotency")
+            .item("eid", AttributeValue::S(eid.to_string()))
+            .item("key", AttributeValue::S(key.to_string()))
+            .item("qid", AttributeValue::S(qid.to_string()))
+            .item("fingerprint", AttributeValue::S(claim.fingerprint))
+            .item("expire", AttributeValue::N(claim.expire.to_string()))
+            // dynamodb doesn't delete expired items right away
+            .condition_expression("attribute_not_exists(#key) OR #expire <= :now")
+            .expression_attribute_names("#key", "key")
+            .expression_attribute_names("#expire", "expire")
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        match r.map_err(StoreError::from) {
+            Ok(_) => return Ok(None),
+            Err(StoreError::ConditionFailed) => {}
+            Err(e) => return Err(e),
+        }
+
+        let r = self
+            .get_item()
+            .table_name("idempotency")
+            .key("eid", AttributeValue::S(eid.to_string()))
+            .key("key", AttributeValue::S(key.to_string()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        let existing = r.item().and_then(|c| {
+            Some(Claim {
+                qid: Ulid::from_string(c.get("qid")?.as_s().ok()?).ok()?,
+                fingerprint: c.get("fingerprint")?.as_s().ok()?.clone(),
+                expire: c.get("expire")?.as_n().ok()?.parse().ok()?,
+            })
+        });
+        match existing {
+            Some(existing) => Ok(Some(existing)),
+            // released again in the meantime
+            None => Err(StoreError::Other(
+                "idempotency key disappeared while claiming it".into(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for Mutex<Local> {
+    async fn release(&self, eid: &Ulid, key: &str) -> Result<(), StoreError> {
+        super::lock(self)
+            .idempotency
+            .remove(&(*eid, key.to_string()));
+        Ok(())
+    }
+
+    async fn claim(
+        &self,
+        eid: &Ulid,
+        key: &str,
+        qid: &Ulid,
+        fingerprint: &str,
+    ) -> Result<Option<Claim>, StoreError> {
+        let now = now();
+        let claim = Claim {
+            qid: *qid,
+            fingerprint: fingerprint.to_string(),
+            expire: now + WINDOW.as_secs(),
+        };
+        let mut local = super::lock(self);
+        let Local { idempotency, .. } = &mut *local;
+
+        let k = (*eid, key.to_string());
+        match idempotency.get(&k) {
+            Some(existing) if existing.expire > now => Ok(Some(existing.clone())),
+            _ => {
+                idempotency.insert(k, claim);
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl IdempotencyStore for crate::redis::Redis {
+    async fn release(&self, eid: &Ulid, key: &str) -> Result<(), StoreError> {
+        self.query(redis::cmd("DEL").arg(crate::redis::idempotency_key(eid, key)))
+            .await
+    }
+
+    async fn claim(
+        &self,
+        eid: &Ulid,
+        key: &str,
+        qid: &Ulid,
+        fingerprint: &str,
+    ) -> Result<Option<Claim>, StoreError> {
+        let key = crate::redis::idempotency_key(eid, key);
+        let expire = now() + WINDOW.as_secs();
+        // kept as <qid>:<expire>:<fingerprint>, and gone once it expires.
+        let set: Option<String> = self
+            .query(
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(format!("{qid}:{expire}:{fingerprint}"))
+                    .arg("NX")
+                    .arg("EXAT")
+                    .arg(expire),
+            )
+            .await?;
+        if set.is_some() {
+            return Ok(None);
+        }
+
+        let existing: Option<String> = self.query(redis::cmd("GET").arg(&key)).await?;
+        let existing = existing.and_then(|c| {
+            let mut parts = c.splitn(3, ':');
+            Some(Claim {
+                qid: Ulid::from_string(parts.next()?).ok()?,
+                expire: parts.next()?.parse().ok()?,
+                fingerprint: parts.next()?.to_string(),
+            })
+        });
+        match existing {
+            Some(existing) => Ok(Some(existing)),
+            // released again in the meantime
+            None => Err(StoreError::Other(
+                "idempotency key disappeared while claiming it".into(),
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl IdempotencyStore for crate::sqlite::Sqlite {
+    async fn release(&self, eid: &Ulid, key: &str) -> Result<(), StoreError> {
+        self.run(|con| {
+            con.execute(
+                "DELETE FROM idempotency WHERE eid = ?1 AND key = ?2",
+                rusqlite::params![eid.to_string(), key],
+            )?;
+            Ok(())
+        })
+    }
+
+    async fn claim(
+        &self,
+        eid: &Ulid,
+        key: &str,
+        qid: &Ulid,
+        fingerprint: &str,
+    ) -> Result<Option<Claim>, StoreError> {
+        let now = now();
+        self.run(|con| {
+            // expired claims are as good as gone, even before they're swept out.
+            let claimed = con.execute(
+                "INSERT INTO idempotency (eid, key, qid, fingerprint, expire)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (eid, key) DO UPDATE
+                 SET qid = excluded.qid, fingerprint = excluded.fingerprint, expire = excluded.expire
+                 WHERE idempotency.expire <= ?6",
+                rusqlite::params![
+                    eid.to_string(),
+                    key,
+                    qid.to_string(),
+                    fingerprint,
+                    now + WINDOW.as_secs(),
+                    now
+                ],
+            )?;
+            if claimed == 1 {
+                return Ok(None);
+            }
+            let (qid, fingerprint, expire): (String, String, u64) = con.query_row(
+                "SELECT qid, fingerprint, expire FROM idempotency WHERE eid = ?1 AND key = ?2",
+                rusqlite::params![eid.to_string(), key],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+            Ok(Some(Claim {
+                qid: Ulid::from_string(&qid).map_err(|e| StoreError::Other(Box::new(e)))?,
+                fingerprint,
+                expire,
+            }))
+        })
+    }
+}
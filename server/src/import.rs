@@ -0,0 +1,422 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{PutRequest, WriteRequest};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The largest import we accept, which is a lot more than any other request needs.
+pub(super) const MAX_IMPORT_BYTES: usize = 1024 * 1024;
+
+/// How many questions go in one `BatchWriteItem`, which is as many as dynamodb allows.
+const BATCH_SIZE: usize = 25;
+
+/// A question to import, as it appears in a JSON export (or written by hand).
+#[derive(Deserialize, Debug)]
+pub(super) struct Imported {
+    text: String,
+    /// Who asked it, if they said. Some exports call this the author.
+    #[serde(default, alias = "author")]
+    who: Option<String>,
+    /// Questions that come without votes get the asker's own vote, same as a new question.
+    #[serde(default = "one")]
+    votes: u64,
+    #[serde(default)]
+    hidden: bool,
+    /// When the question was answered, in seconds since the epoch.
+    #[serde(default)]
+    answered: Option<u64>,
+    /// When the question was asked, in seconds since the epoch. Defaults to now.
+    #[serde(default)]
+    when: Option<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn one() -> u64 {
+    1
+}
+
+/// What can be imported: a whole JSON export, or just its list of questions.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(super) enum Import {
+    Export {
+        version: u64,
+        questions: Vec<Imported>,
+    },
+    Questions(Vec<Imported>),
+}
+
+type Item = HashMap<&'static str, Attr>;
+
+/// How a storage engine stores questions brought in from elsewhere.
+#[async_trait]
+pub(super) trait ImportStore {
+    /// Adds all the already-prepared `questions` (keyed by qid) to `eid`.
+    ///
+    /// This isn't all-or-nothing: if it fails part-way through, some of the questions may have
+    /// been added.
+    async fn import(&self, eid: &Ulid, questions: Vec<(Ulid, Item)>) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl ImportStore for aws_sdk_dynamodb::Client {
+    async fn import(&self, _eid: &Ulid, questions: Vec<(Ulid, Item)>) -> Result<(), StoreError> {
+        for batch in questions.chunks(BATCH_SIZE) {
+            let mut writes: Vec<_> = batch
+                .iter()
+                .map(|(_, q)| {
+                    WriteRequest::builder()
+                        .put_request(
+                            PutRequest::builder()
+                                .set_item(Some(
+                                    q.iter()
+                                        .map(|(k, v)| (k.to_string(), v.clone().into()))
+                                        .collect(),
+                                ))
+                                .build(),
+                        )
+                        .build()
+                })
+                .collect();
+            let mut attempt = 0;
+            loop {
+                let r = crate::retry::retry(|| {
+                    self.batch_write_item()
+                        .request_items("questions", writes.clone())
+                        .send()
+                })
+                .await?;
+                // dynamodb may only get around to some of the batch when it's busy
+                writes = r
+                    .unprocessed_items()
+                    .and_then(|u| u.get("questions"))
+                    .cloned()
+                    .unwrap_or_default();
+                if writes.is_empty() {
+                    break;
+                }
+                if attempt >= crate::retry::max_retries() {
+                    return Err(StoreError::Other(
+                        format!("{} imported questions were never written", writes.len()).into(),
+                    ));
+                }
+                tokio::time::sleep(crate::retry::backoff(attempt)).await;
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ImportStore for Mutex<Local> {
+    async fn import(&self, eid: &Ulid, questions: Vec<(Ulid, Item)>) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions: qs,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let Some(in_event) = questions_by_eid.get_mut(eid) else {
+            return Err(StoreError::Other(
+                "importing into non-existing event".into(),
+            ));
+        };
+        let qids: Vec<_> = questions.iter().map(|(qid, _)| *qid).collect();
+        in_event.extend(&qids);
+        qs.extend(questions);
+        for qid in qids {
+            local.publish(eid, "ask", serde_json::json!({ "qid": qid.to_string() }));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ImportStore for crate::redis::Redis {
+    async fn import(&self, eid: &Ulid, questions: Vec<(Ulid, Item)>) -> Result<(), StoreError> {
+        let exists: bool = self
+            .query(redis::cmd("EXISTS").arg(crate::redis::event_key(eid)))
+            .await?;
+        if !exists {
+            return Err(StoreError::Other(
+                "importing into non-existing event".into(),
+            ));
+        }
+        let mut pipe = redis::pipe();
+        for (_, q) in &questions {
+            crate::redis::add_question(&mut pipe, &crate::store::from_local(q))?;
+        }
+        self.run::<()>(&pipe).await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ImportStore for crate::sqlite::Sqlite {
+    async fn import(&self, eid: &Ulid, questions: Vec<(Ulid, Item)>) -> Result<(), StoreError> {
+        self.run(|con| {
+            if !crate::sqlite::has_event(con, eid)? {
+                return Err(StoreError::Other(
+                    "importing into non-existing event".into(),
+                ));
+            }
+            for (_, q) in &questions {
+                crate::sqlite::insert(con, crate::sqlite::Table::Questions, q)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Turns `q` into a question of `eid` that's ready to be stored, if it passes the same checks as a
+/// newly asked question.
+///
+/// The event's `inherited` attributes are kept with the question, same as for asked ones.
+fn prepare(eid: &Ulid, q: Imported, inherited: &[(&'static str, Attr)]) -> Result<Item, ApiError> {
+    let text = crate::ask::tidy_text(&q.text);
+    crate::ask::check_text(eid, &text)?;
+    let text = match crate::profanity::filter().apply(eid, &text)? {
+        Cow::Owned(masked) => masked,
+        Cow::Borrowed(_) => text,
+    };
+    let tags = crate::tags::clean(eid, q.tags)?;
+    let who = crate::ask::clean_asker(eid, q.who)?;
+
+    let when = q
+        .when
+        .map(|s| SystemTime::UNIX_EPOCH + Duration::from_secs(s))
+        .unwrap_or_else(SystemTime::now);
+    let mut item = HashMap::from_iter([
+        ("eid", Attr::S(eid.to_string())),
+        ("votes", Attr::N(q.votes.to_string())),
+        ("version", Attr::N(1.to_string())),
+        ("text", Attr::S(text)),
+        ("when", crate::to_dynamo_timestamp(when)),
+        // like a newly asked question, an imported one lives for as long as the event does.
+        (
+            "expire",
+            crate::to_dynamo_timestamp(
+                SystemTime::now()
+                    + Duration::from_secs(crate::ask::QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60),
+            ),
+        ),
+        ("hidden", Attr::Bool(q.hidden)),
+    ]);
+    if let Some(answered) = q.answered {
+        item.insert("answered", Attr::N(answered.to_string()));
+    }
+    if let Some(who) = who {
+        item.insert("who", Attr::S(who));
+    }
+    if !tags.is_empty() {
+        item.insert("tags", Attr::Ss(tags));
+    }
+    item.extend(inherited.iter().cloned());
+    Ok(item)
+}
+
+/// Adds questions from a JSON export (or just a list of questions) to an event, for migrating
+/// from another tool or restoring a backup.
+///
+/// Every question gets a new id. If any of them isn't acceptable, none are imported.
+pub(super) async fn import(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Json(import): Json<Import>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let questions = match import {
+        Import::Export { version, .. } if version != crate::export::FORMAT_VERSION => {
+            warn!(%eid, version, "got import of unknown export version");
+            return Err(ApiError::BadRequest);
+        }
+        Import::Export { questions, .. } | Import::Questions(questions) => questions,
+    };
+
+    let (max, inherited) = match dynamo.event(&eid).await {
+        Ok(e) => match e.as_ref() {
+            Some(e) if crate::event::is_archived(e) => {
+                warn!(%eid, "import into archived event");
+                return Err(ApiError::EventArchived);
+            }
+            Some(e) => (crate::event::max_questions(e), crate::event::inherited(e)),
+            None => return Err(ApiError::EventNotFound),
+        },
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for event failed");
+            return Err(ApiError::Internal);
+        }
+    };
+    match dynamo.count(&eid).await {
+        Ok(n) if n + questions.len() > max => {
+            warn!(%eid, n, max, imported = questions.len(), "import would overfill event");
+            return Err(ApiError::TooManyQuestions);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to count questions failed");
+            return Err(ApiError::Internal);
+        }
+    }
+
+    let questions = questions
+        .into_iter()
+        .map(|q| {
+            let qid = Ulid::new();
+            let mut item = prepare(&eid, q, &inherited)?;
+            item.insert("id", Attr::S(qid.to_string()));
+            Ok((qid, item))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    let qids: Vec<_> = questions.iter().map(|(qid, _)| qid.to_string()).collect();
+
+    match dynamo.import(&eid, questions).await {
+        Ok(()) => {
+            debug!(%eid, n = qids.len(), "imported questions");
+            Ok(Json(
+                serde_json::json!({ "imported": qids.len(), "qids": qids }),
+            ))
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to import questions failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let import = |secret: &str, body: serde_json::Value| {
+            super::import(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Json(serde_json::from_value(body).unwrap()),
+            )
+        };
+        let listed = || async {
+            crate::list::list_all(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Query(Default::default()),
+            )
+            .await
+            .1
+            .unwrap()
+            .0
+        };
+
+        let questions = serde_json::json!([
+            { "text": "  what about <b>the</b> rest? ", "votes": 5, "answered": 1700000000 },
+            { "text": "hello world", "hidden": true, "when": 1600000000 },
+        ]);
+        assert_eq!(
+            import("wrong", questions.clone()).await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        // a bad question spoils the whole import
+        assert_eq!(
+            import(
+                secret,
+                serde_json::json!([{ "text": "hello world" }, { "text": "  " }])
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(listed().await.as_array().unwrap().len(), 0);
+        // as does an export format we don't know
+        assert_eq!(
+            import(
+                secret,
+                serde_json::json!({ "version": 0, "questions": questions })
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let r = import(secret, questions).await.unwrap();
+        assert_eq!(r["imported"], 2);
+        let qs = listed().await;
+        let qs = qs.as_array().unwrap();
+        assert_eq!(qs.len(), 2);
+        assert_eq!(qs[0]["votes"], 5);
+        assert_eq!(qs[0]["answered"], 1700000000);
+        assert_eq!(qs[1]["votes"], 1);
+        assert_eq!(qs[1]["hidden"], true);
+        assert_eq!(qs[1]["when"], 1600000000);
+        let qid = qs[0]["qid"].as_str().unwrap().to_string();
+        let (_, texts) =
+            crate::questions::questions(Path(qid.clone()), State(backend.clone())).await;
+        assert_eq!(texts.unwrap()[&qid]["text"], "what about the rest?");
+
+        // and an export of the event goes straight back in
+        let export = crate::export::export_json(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .unwrap();
+        let body = axum::response::IntoResponse::into_response(export).into_body();
+        let export: serde_json::Value =
+            serde_json::from_slice(&hyper::body::to_bytes(body).await.unwrap()).unwrap();
+        let r = import(secret, export).await.unwrap();
+        assert_eq!(r["imported"], 2);
+        assert_eq!(listed().await.as_array().unwrap().len(), 4);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,148 @@
+use crate::store::Attr;
+use axum::{
+    body::BoxBody,
+    middleware::Next,
+    response::{IntoResponseParts, Response, ResponseParts},
+};
+use http::{header, HeaderValue, Request, StatusCode};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    time::{Duration, SystemTime},
+};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Gives when the stored `item` last changed in a way that shows in the responses that carry a
+/// `Last-Modified`, in seconds since the epoch.
+///
+/// That's when it was made, or if later, when it was last edited or answered.
+pub(super) fn of(item: &HashMap<String, Attr>) -> Option<u64> {
+    ["when", "answered", "modified"]
+        .into_iter()
+        .filter_map(|k| item.get(k)?.as_n().ok()?.parse().ok())
+        .max()
+}
+
+/// Sets `Last-Modified` to the given time, in seconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct LastModified(pub(super) u64);
+
+impl IntoResponseParts for LastModified {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let when = SystemTime::UNIX_EPOCH + Duration::from_secs(self.0);
+        res.headers_mut().insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(when)).expect("dates are ascii"),
+        );
+        Ok(res)
+    }
+}
+
+/// Answers requests whose `If-Modified-Since` is no earlier than the `Last-Modified` of the
+/// response with a `304 Not Modified`.
+///
+/// Like [`crate::etag::etag`], this saves bandwidth rather than work. `If-Modified-Since` is
+/// ignored when there's an `If-None-Match`, since that says more.
+pub(super) async fn conditional<B>(req: Request<B>, next: Next<B>) -> Response {
+    let since = req
+        .headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .filter(|_| !req.headers().contains_key(header::IF_NONE_MATCH))
+        .and_then(|v| httpdate::parse_http_date(v.to_str().ok()?).ok());
+    let res = next.run(req).await;
+    let Some(since) = since else {
+        return res;
+    };
+    if res.status() != StatusCode::OK {
+        return res;
+    }
+    let modified = res
+        .headers()
+        .get(header::LAST_MODIFIED)
+        .and_then(|v| httpdate::parse_http_date(v.to_str().ok()?).ok());
+    if modified.is_none_or(|modified| modified > since) {
+        return res;
+    }
+
+    let (mut parts, _) = res.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, BoxBody::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn conditional() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { (LastModified(1_600_000_000), "hello") }),
+            )
+            .route("/undated", get(|| async { "hello" }))
+            .layer(axum::middleware::from_fn(super::conditional));
+        let get = |path: &'static str, headers: &[(&'static str, &'static str)]| {
+            let mut req = Request::get(path);
+            for &(k, v) in headers {
+                req = req.header(k, v);
+            }
+            app.clone()
+                .oneshot(req.body(axum::body::Body::empty()).unwrap())
+        };
+
+        let res = get("/", &[]).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::LAST_MODIFIED],
+            "Sun, 13 Sep 2020 12:26:40 GMT"
+        );
+
+        let since = |date| [("if-modified-since", date)];
+        let res = get("/", &since("Sun, 13 Sep 2020 12:26:40 GMT"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert!(res.headers().contains_key(header::LAST_MODIFIED));
+        let res = get("/", &since("Sun, 13 Sep 2020 12:26:39 GMT"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get("/", &since("not a date")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = get("/undated", &since("Sun, 13 Sep 2020 12:26:40 GMT"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // entity tags win
+        let res = get(
+            "/",
+            &[
+                ("if-modified-since", "Sun, 13 Sep 2020 12:26:40 GMT"),
+                ("if-none-match", "\"nope\""),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn latest() {
+        let n = |v: u64| Attr::N(v.to_string());
+        let mut q = HashMap::from_iter([(String::from("when"), n(100))]);
+        assert_eq!(of(&q), Some(100));
+        q.insert(String::from("modified"), n(300));
+        q.insert(String::from("answered"), n(200));
+        assert_eq!(of(&q), Some(300));
+        assert_eq!(of(&HashMap::new()), None);
+    }
+}
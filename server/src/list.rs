@@ -1,145 +1,543 @@
 use std::collections::HashMap;
 
 use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::{QueryError, QueryErrorKind, ResourceNotFoundException},
-    model::AttributeValue,
-    output::QueryOutput,
-    types::SdkError,
-};
-use aws_smithy_types::Error;
+use crate::error::ApiError;
+use crate::lastmodified::LastModified;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
 use axum::response::Json;
 use axum::{
-    extract::{Path, State},
-    response::AppendHeaders,
+    extract::{Path, Query, State},
+    response::{AppendHeaders, IntoResponse, Response},
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use http::{
     header::{self, HeaderName},
-    StatusCode,
+    HeaderMap, HeaderValue,
 };
+use serde::Deserialize;
+use std::sync::Mutex;
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-impl Backend {
-    pub(super) async fn list(
+/// The most questions a client can ask for in a single page.
+const MAX_PAGE_SIZE: usize = 500;
+
+/// The page size used if a client asks for a page without saying how large.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// The furthest into the list a client can ask to start a page with `offset`.
+///
+/// Every offset page reads the whole list, so they're only meant for events of modest size.
+const MAX_OFFSET: usize = 1000;
+
+/// The headers every list response comes with, plus `X-Total-Count` for offset pages.
+type ListHeaders = (
+    AppendHeaders<[(HeaderName, &'static str); 1]>,
+    Option<AppendHeaders<[(&'static str, String); 1]>>,
+    Option<LastModified>,
+);
+
+/// A list of questions as it's sent to clients, and when it last changed if that's known.
+pub(super) type Listed = (serde_json::Value, Option<LastModified>);
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Sort {
+    /// Most-voted first.
+    #[default]
+    Votes,
+    /// Most recently asked first.
+    Newest,
+    /// Least recently asked first.
+    Oldest,
+    /// In the host's running order, followed by the questions that aren't in it, most-voted first.
+    Order,
+}
+
+impl Sort {
+    /// Orders `questions` according to `self`, breaking ties by qid so the order is stable.
+    fn apply(self, questions: &mut [Item]) {
+        let num = |q: &Item, k: &str| {
+            q.get(k)
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let qid = |q: &Item| q.get("id").and_then(|v| v.as_s().ok()).cloned();
+        match self {
+            Sort::Votes => questions.sort_by(|a, b| {
+                num(b, "votes")
+                    .cmp(&num(a, "votes"))
+                    .then_with(|| qid(a).cmp(&qid(b)))
+            }),
+            Sort::Newest => questions.sort_by(|a, b| {
+                num(b, "when")
+                    .cmp(&num(a, "when"))
+                    .then_with(|| qid(b).cmp(&qid(a)))
+            }),
+            Sort::Oldest => questions.sort_by(|a, b| {
+                num(a, "when")
+                    .cmp(&num(b, "when"))
+                    .then_with(|| qid(a).cmp(&qid(b)))
+            }),
+            Sort::Order => {
+                let order = |q: &Item| {
+                    q.get("order")
+                        .and_then(|v| v.as_n().ok())
+                        .and_then(|v| v.parse::<u64>().ok())
+                };
+                questions.sort_by(|a, b| match (order(a), order(b)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => num(b, "votes")
+                        .cmp(&num(a, "votes"))
+                        .then_with(|| qid(a).cmp(&qid(b))),
+                })
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub(super) struct Params {
+    limit: Option<usize>,
+    cursor: Option<String>,
+    /// How many questions to skip, as an alternative to `cursor`.
+    offset: Option<usize>,
+    /// Only the questions that changed at or after this time (in seconds since the epoch), and
+    /// the ones that were deleted.
+    since: Option<u64>,
+    #[serde(default)]
+    sort: Sort,
+    tag: Option<String>,
+    /// Only honored for hosts.
+    answered: Option<bool>,
+    /// Only honored for hosts.
+    hidden: Option<bool>,
+    /// Only honored for hosts. Has the full list come back as `{questions, event}` rather than an
+    /// array, so that it carries the event's metadata too.
+    #[serde(default)]
+    with_event: bool,
+}
+
+/// Narrows down which questions [`ListStore::list`] returns.
+///
+/// Fields that are `None` don't filter anything.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Filter<'a> {
+    /// Only questions with this (normalized) tag.
+    pub(super) tag: Option<&'a str>,
+    /// Only questions that have (or haven't) been answered.
+    pub(super) answered: Option<bool>,
+    /// Only questions that are (or aren't) hidden.
+    pub(super) hidden: Option<bool>,
+}
+
+impl Filter<'_> {
+    fn matches<K>(&self, q: &HashMap<K, Attr>) -> bool
+    where
+        K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+    {
+        self.tag.is_none_or(|tag| {
+            q.get("tags")
+                .and_then(|v| v.as_ss().ok())
+                .is_some_and(|tags| tags.iter().any(|t| t == tag))
+        }) && self
+            .answered
+            .is_none_or(|answered| q.contains_key("answered") == answered)
+            && self
+                .hidden
+                .is_none_or(|hidden| q["hidden"] == Attr::Bool(hidden))
+    }
+}
+
+/// Encodes a `LastEvaluatedKey` as an opaque cursor that can be handed to clients.
+fn encode_cursor(key: &Item) -> String {
+    let key: serde_json::Map<_, _> = key
+        .iter()
+        .filter_map(|(k, v)| {
+            let v = match v {
+                Attr::S(s) => serde_json::json!({ "S": s }),
+                Attr::N(n) => serde_json::json!({ "N": n }),
+                _ => return None,
+            };
+            Some((k.clone(), v))
+        })
+        .collect();
+    URL_SAFE_NO_PAD.encode(serde_json::Value::from(key).to_string())
+}
+
+/// Decodes a cursor produced by [`encode_cursor`] back into an `ExclusiveStartKey`.
+fn decode_cursor(cursor: &str) -> Option<Item> {
+    let key = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    let key: HashMap<String, HashMap<String, String>> = serde_json::from_slice(&key).ok()?;
+    key.into_iter()
+        .map(|(k, mut v)| {
+            let v = if let Some(s) = v.remove("S") {
+                Attr::S(s)
+            } else {
+                Attr::N(v.remove("N")?)
+            };
+            Some((k, v))
+        })
+        .collect()
+}
+
+/// A page of questions from [`ListStore::list`].
+#[derive(Debug, Default)]
+pub(super) struct Page {
+    pub(super) items: Vec<Item>,
+    /// Where the next page starts, if there may be more questions after this one.
+    pub(super) next: Option<Item>,
+}
+
+/// Picks out the page of the `visible` questions (already in order) that `limit` and `start` ask
+/// for, for storage engines that list by reading all of an event's questions.
+fn paginate<K>(visible: &[&HashMap<K, Attr>], limit: Option<usize>, start: Option<&Item>) -> Page
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    // pick up right after the last question of the previous page. if that question
+    // has since been deleted, there's no telling where to continue, so we just stop.
+    let from = match start.and_then(|k| k.get("id")) {
+        Some(id) => visible
+            .iter()
+            .position(|q| q.get("id") == Some(id))
+            .map_or(visible.len(), |i| i + 1),
+        None => 0,
+    };
+    let to = limit.map_or(visible.len(), |l| visible.len().min(from + l));
+    let page = &visible[from..to];
+    let next = if to < visible.len() {
+        page.last().map(|q| {
+            ["id", "eid", "votes"]
+                .into_iter()
+                .filter_map(|k| Some((k.to_string(), q.get(k)?.clone())))
+                .collect()
+        })
+    } else {
+        None
+    };
+    Page {
+        items: page
+            .iter()
+            .map(|q| {
+                q.iter()
+                    .map(|(k, v)| (k.borrow().to_string(), v.clone()))
+                    .collect()
+            })
+            .collect(),
+        next,
+    }
+}
+
+/// How a storage engine lists the questions of an event.
+#[async_trait]
+pub(super) trait ListStore {
+    /// Lists the questions of `eid`, most-voted first.
+    ///
+    /// If `limit` is given, at most that many questions are returned, and the page's `next` can be
+    /// passed back as `start` to get the next page. Fails with [`StoreError::NotFound`] if there's
+    /// no such event.
+    ///
+    /// Guests never see hidden questions, whatever `filter` says.
+    async fn list(
         &self,
         eid: &Ulid,
         has_secret: bool,
-    ) -> Result<QueryOutput, SdkError<QueryError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                let query = dynamo.query();
-                let query = query
-                    .table_name("questions")
-                    .index_name("top")
-                    .scan_index_forward(false)
-                    .key_condition_expression("eid = :eid")
-                    .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()));
-
-                let query = if has_secret {
-                    query
-                } else {
-                    query
-                        .filter_expression("#hidden = :false")
-                        .expression_attribute_names("#hidden", "hidden".to_string())
-                        .expression_attribute_values(":false", AttributeValue::Bool(false))
-                };
+        filter: &Filter<'_>,
+        limit: Option<usize>,
+        start: Option<Item>,
+    ) -> Result<Page, StoreError>;
 
-                query.send().await
-            }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local {
-                    questions,
-                    questions_by_eid,
-                    events,
-                    ..
-                } = &mut *local;
-
-                if !events.contains_key(&eid) {
-                    return Err(super::mint_service_error(QueryError::new(
-                        QueryErrorKind::ResourceNotFoundException(
-                            ResourceNotFoundException::builder().build(),
-                        ),
-                        Error::builder().build(),
-                    )));
-                }
+    /// Returns true if guests' lists are worth caching for a little while.
+    ///
+    /// They are when each list costs a query, but not when listing is as cheap as looking in the
+    /// cache would be.
+    fn caches_lists(&self) -> bool;
+}
 
-                let qs = questions_by_eid
-                    .get_mut(eid)
-                    .expect("list for non-existing event");
-                qs.sort_unstable_by_key(|qid| {
-                    std::cmp::Reverse(
-                        questions[qid]["votes"]
-                            .as_n()
-                            .expect("votes is always set")
-                            .parse::<usize>()
-                            .expect("votes are always numbers"),
-                    )
-                });
+#[async_trait]
+impl ListStore for aws_sdk_dynamodb::Client {
+    async fn list(
+        &self,
+        eid: &Ulid,
+        has_secret: bool,
+        filter: &Filter<'_>,
+        limit: Option<usize>,
+        start: Option<Item>,
+    ) -> Result<Page, StoreError> {
+        let query = self.query();
+        let query = query
+            .table_name("questions")
+            .index_name("top")
+            .scan_index_forward(false)
+            .key_condition_expression("eid = :eid")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .set_limit(limit.map(|l| l as i32))
+            .set_exclusive_start_key(start.map(store::to_dynamo));
 
-                Ok(QueryOutput::builder()
-                    .set_count(Some(qs.len() as i32))
-                    .set_items(Some(
-                        qs.iter()
-                            .filter_map(|qid| {
-                                let q = &questions[qid];
-                                if has_secret {
-                                    Some(
-                                        q.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
-                                    )
-                                } else if q["hidden"] == AttributeValue::Bool(false) {
-                                    Some(
-                                        q.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
-                                    )
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect(),
-                    ))
-                    .build())
+        // NOTE: the limit applies _before_ the filter, so guests may get short pages.
+        let mut filters = Vec::new();
+        let query = if has_secret {
+            query
+        } else {
+            filters.push("#hidden = :false");
+            query
+                .expression_attribute_names("#hidden", "hidden".to_string())
+                .expression_attribute_values(":false", AttributeValue::Bool(false))
+        };
+        let query = if let Some(tag) = filter.tag {
+            filters.push("contains(#tags, :tag)");
+            query
+                .expression_attribute_names("#tags", "tags".to_string())
+                .expression_attribute_values(":tag", AttributeValue::S(tag.to_string()))
+        } else {
+            query
+        };
+        let query = match filter.answered {
+            Some(true) => {
+                filters.push("attribute_exists(#answered)");
+                query.expression_attribute_names("#answered", "answered".to_string())
             }
+            Some(false) => {
+                filters.push("attribute_not_exists(#answered)");
+                query.expression_attribute_names("#answered", "answered".to_string())
+            }
+            None => query,
+        };
+        let query = if let Some(hidden) = filter.hidden {
+            filters.push("#hidden = :hidden");
+            query
+                .expression_attribute_names("#hidden", "hidden".to_string())
+                .expression_attribute_values(":hidden", AttributeValue::Bool(hidden))
+        } else {
+            query
+        };
+        let query = if filters.is_empty() {
+            query
+        } else {
+            query.filter_expression(filters.join(" AND "))
+        };
+
+        let qs = super::retry::retry(|| query.clone().send()).await?;
+        Ok(Page {
+            items: qs
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .map(store::from_dynamo)
+                .collect(),
+            next: qs.last_evaluated_key.map(store::from_dynamo),
+        })
+    }
+
+    fn caches_lists(&self) -> bool {
+        true
+    }
+}
+
+#[async_trait]
+impl ListStore for Mutex<Local> {
+    async fn list(
+        &self,
+        eid: &Ulid,
+        has_secret: bool,
+        filter: &Filter<'_>,
+        limit: Option<usize>,
+        start: Option<Item>,
+    ) -> Result<Page, StoreError> {
+        let mut local = super::lock(self);
+        // expired events are treated as though they never existed
+        local.reap_if_expired(eid);
+        let Local {
+            questions,
+            questions_by_eid,
+            events,
+            ..
+        } = &mut *local;
+
+        if !events.contains_key(eid) {
+            return Err(StoreError::NotFound);
+        }
+
+        let qs = questions_by_eid
+            .get_mut(eid)
+            .expect("list for non-existing event");
+        qs.sort_unstable_by_key(|qid| {
+            std::cmp::Reverse(
+                questions[qid]["votes"]
+                    .as_n()
+                    .expect("votes is always set")
+                    .parse::<usize>()
+                    .expect("votes are always numbers"),
+            )
+        });
+
+        let visible: Vec<_> = qs
+            .iter()
+            .map(|qid| &questions[qid])
+            .filter(|q| has_secret || q["hidden"] == Attr::Bool(false))
+            .filter(|q| filter.matches(q))
+            .collect();
+        Ok(paginate(&visible, limit, start.as_ref()))
+    }
+
+    fn caches_lists(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ListStore for crate::redis::Redis {
+    async fn list(
+        &self,
+        eid: &Ulid,
+        has_secret: bool,
+        filter: &Filter<'_>,
+        limit: Option<usize>,
+        start: Option<Item>,
+    ) -> Result<Page, StoreError> {
+        let exists: bool = self
+            .query(redis::cmd("EXISTS").arg(crate::redis::event_key(eid)))
+            .await?;
+        if !exists {
+            return Err(StoreError::NotFound);
         }
+
+        let qs = self.questions_of(eid).await?;
+        let visible: Vec<_> = qs
+            .iter()
+            .filter(|q| has_secret || q.get("hidden") == Some(&Attr::Bool(false)))
+            .filter(|q| filter.matches(q))
+            .collect();
+        Ok(paginate(&visible, limit, start.as_ref()))
+    }
+
+    fn caches_lists(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ListStore for crate::sqlite::Sqlite {
+    async fn list(
+        &self,
+        eid: &Ulid,
+        has_secret: bool,
+        filter: &Filter<'_>,
+        limit: Option<usize>,
+        start: Option<Item>,
+    ) -> Result<Page, StoreError> {
+        let qs = self.run(|con| {
+            if !crate::sqlite::has_event(con, eid)? {
+                return Err(StoreError::NotFound);
+            }
+            crate::sqlite::questions(con, eid)
+        })?;
+        let visible: Vec<_> = qs
+            .iter()
+            .filter(|q| has_secret || q.get("hidden") == Some(&Attr::Bool(false)))
+            .filter(|q| filter.matches(q))
+            .collect();
+        Ok(paginate(&visible, limit, start.as_ref()))
+    }
+
+    fn caches_lists(&self) -> bool {
+        false
     }
 }
 
 pub(super) async fn list(
     Path(eid): Path<Ulid>,
     State(dynamo): State<Backend>,
-) -> (
-    AppendHeaders<[(HeaderName, &'static str); 1]>,
-    Result<Json<serde_json::Value>, StatusCode>,
-) {
-    list_inner(Path((eid, None)), State(dynamo)).await
+    params: Query<Params>,
+) -> (ListHeaders, Result<Json<serde_json::Value>, ApiError>) {
+    list_inner(Path((eid, None)), State(dynamo), params).await
 }
 
 pub(super) async fn list_all(
     Path((eid, secret)): Path<(Ulid, String)>,
     State(dynamo): State<Backend>,
-) -> (
-    AppendHeaders<[(HeaderName, &'static str); 1]>,
-    Result<Json<serde_json::Value>, StatusCode>,
-) {
-    list_inner(Path((eid, Some(secret))), State(dynamo)).await
+    params: Query<Params>,
+) -> (ListHeaders, Result<Json<serde_json::Value>, ApiError>) {
+    list_inner(Path((eid, Some(secret))), State(dynamo), params).await
+}
+
+/// Returns true if the client's `Accept` header prefers CSV over JSON.
+///
+/// Anything we can't make sense of (including no `Accept` header at all, or `*/*`) gets JSON,
+/// which is what clients got before they could ask for CSV.
+fn wants_csv(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let mut best: Option<(f32, bool)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let csv = match parts.next().map(str::to_ascii_lowercase).as_deref() {
+            Some("text/csv") => true,
+            Some("application/json" | "application/*" | "*/*") => false,
+            _ => continue,
+        };
+        let q = parts
+            .find_map(|p| p.strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        // on a tie, the range that came first wins.
+        if q > 0.0 && best.is_none_or(|(best, _)| q > best) {
+            best = Some((q, csv));
+        }
+    }
+    best.is_some_and(|(_, csv)| csv)
+}
+
+/// Lists all of `eid`'s questions for its host, as JSON or, if the `Accept` header asks for it,
+/// as CSV (the same CSV that `export.csv` gives).
+pub(super) async fn list_host(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    params: Query<Params>,
+    headers: HeaderMap,
+) -> Response {
+    let mut res = if wants_csv(&headers) {
+        crate::export::export_csv(Path((eid, secret)), State(dynamo))
+            .await
+            .into_response()
+    } else {
+        list_all(Path((eid, secret)), State(dynamo), params)
+            .await
+            .into_response()
+    };
+    // so caches don't hand out JSON to clients that asked for CSV, or the other way around.
+    res.headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    res
 }
 
 async fn list_inner(
     Path((eid, secret)): Path<(Ulid, Option<String>)>,
     State(dynamo): State<Backend>,
-) -> (
-    AppendHeaders<[(HeaderName, &'static str); 1]>,
-    Result<Json<serde_json::Value>, StatusCode>,
-) {
+    Query(params): Query<Params>,
+) -> (ListHeaders, Result<Json<serde_json::Value>, ApiError>) {
     let has_secret = if let Some(secret) = secret {
         debug!("list questions with admin access");
         if let Err(e) = super::check_secret(&dynamo, &eid, &secret).await {
             // a bad secret will not turn good and
             // events are unlikely to re-appear with the same Uuid
             return (
-                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                    None,
+                    None,
+                ),
                 Err(e),
             );
         }
@@ -148,19 +546,95 @@ async fn list_inner(
         trace!("list questions with guest access");
         // ensure that the event exists:
         // this is _just_ so give 404s for old events so clients stop polling
-        if let Err(e) = super::get_secret(&dynamo, &eid).await {
+        if let Err(e) = dynamo.get_secret(&eid).await {
             // events are unlikely to re-appear with the same Uuid
             return (
-                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                    None,
+                    None,
+                ),
                 Err(e),
             );
         }
         false
     };
 
+    // clients only get paged responses if they ask for them, since old clients expect to get the
+    // full list as an array.
+    let paged = params.limit.is_some() || params.cursor.is_some() || params.offset.is_some();
+    let limit = paged.then(|| {
+        params
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    });
+    if params.offset.is_some() && params.cursor.is_some() {
+        warn!(%eid, "got list request with both an offset and a cursor");
+        return (
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                None,
+                None,
+            ),
+            Err(ApiError::BadRequest),
+        );
+    }
+    if params.offset.is_some_and(|offset| offset > MAX_OFFSET) {
+        warn!(%eid, offset = params.offset, "got list request with too large an offset");
+        return (
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                None,
+                None,
+            ),
+            Err(ApiError::BadRequest),
+        );
+    }
+    if paged && params.since.is_some() {
+        warn!(%eid, "got paged list request for changes");
+        return (
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                None,
+                None,
+            ),
+            Err(ApiError::BadRequest),
+        );
+    }
+    if paged && params.offset.is_none() && params.sort != Sort::Votes {
+        // pages come out of dynamodb in vote order, so there's no way to page in any other order.
+        // offset pages are cut from the full list, so those can be in any order.
+        warn!(%eid, sort = ?params.sort, "got paged list request with non-default sort");
+        return (
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                None,
+                None,
+            ),
+            Err(ApiError::BadRequest),
+        );
+    }
+    let start = match params.cursor.as_deref().map(decode_cursor) {
+        Some(None) => {
+            warn!(%eid, cursor = params.cursor, "got invalid cursor");
+            return (
+                // a bad cursor will never become good
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+                    None,
+                    None,
+                ),
+                Err(ApiError::BadRequest),
+            );
+        }
+        Some(Some(start)) => Some(start),
+        None => None,
+    };
+
     // Closure moved out of the filter_map due to rustfmt failing to format the
     // code properly.
-    let serialize_question = |doc: &HashMap<String, AttributeValue>| {
+    let serialize_question = |doc: &Item| {
         let qid = doc["id"].as_s().ok();
         let votes = doc["votes"]
             .as_n()
@@ -173,14 +647,54 @@ async fn list_inner(
             .and_then(|v| v.parse::<usize>().ok());
         match (qid, votes, hidden, answered) {
             (Some(qid), Some(votes), Some(hidden), answered) => {
+                let pinned = doc.get("pinned").and_then(|v| v.as_bool().ok());
                 let mut v = serde_json::json!({
                     "qid": qid,
                     "votes": votes,
                     "hidden": hidden,
+                    "pinned": pinned.copied().unwrap_or(false),
+                    "version": crate::version::of(doc),
                 });
                 if let Some(answered) = answered {
                     v["answered"] = answered.into();
                 }
+                // so clients can show how long ago a question was asked.
+                if let Some(when) = doc
+                    .get("when")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                {
+                    v["when"] = when.into();
+                }
+                if let Some(answer) = doc.get("answer").and_then(|v| v.as_s().ok()) {
+                    v["answer"] = answer.clone().into();
+                }
+                if let Some(order) = doc
+                    .get("order")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                {
+                    v["order"] = order.into();
+                }
+                if let Some(approved) = doc.get("approved").and_then(|v| v.as_bool().ok()) {
+                    v["approved"] = (*approved).into();
+                }
+                if !has_secret && crate::vote::hides_counts(doc) {
+                    // the list is still in vote order, but guests can't tell by how much.
+                    v.as_object_mut().unwrap().remove("votes");
+                }
+                v["reactions"] = crate::vote::reactions_of(doc);
+                if has_secret {
+                    // so hosts can tell questions hidden by reports from ones they hid themselves.
+                    v["reports"] = crate::report::reports_of(doc).into();
+                    if let Some(note) = doc.get("note").and_then(|v| v.as_s().ok()) {
+                        v["note"] = note.clone().into();
+                    }
+                }
+                let tags = crate::tags::of(doc);
+                if !tags.is_empty() {
+                    v["tags"] = tags.into();
+                }
                 Some(v)
             }
             (Some(qid), _, _, _) => {
@@ -194,61 +708,224 @@ async fn list_inner(
         }
     };
 
-    match dynamo.list(&eid, has_secret).await {
-        Ok(qs) => {
-            trace!(%eid, n = %qs.count(), "listed questions");
-            let questions: Vec<_> = qs
-                .items()
-                .map(|qs| qs.iter().filter_map(serialize_question).collect())
-                .unwrap_or_default();
-
-            let max_age = if has_secret {
-                // hosts should be allowed to see more up-to-date views
-                "max-age=3"
-            } else {
-                // guests don't need super up-to-date, so cache for longer
-                "max-age=10"
+    let tag = params.tag.as_deref().map(crate::tags::normalize);
+    let filter = Filter {
+        tag: tag.as_deref(),
+        answered: params.answered.filter(|_| has_secret),
+        hidden: params.hidden.filter(|_| has_secret),
+    };
+    let max_age = if has_secret {
+        // hosts should be allowed to see more up-to-date views
+        "max-age=3"
+    } else {
+        // guests don't need super up-to-date, so cache for longer
+        "max-age=10"
+    };
+    let failed = |e: StoreError| {
+        if let StoreError::NotFound = e {
+            warn!(%eid, error = %e, "request for non-existing event");
+            // it's relatively unlikely that an event Uuid that didn't exist will start
+            // existing. but just in case, don't make it _too_ long.
+            return ("max-age=3600", ApiError::EventNotFound);
+        }
+        error!(%eid, error = %e, "dynamodb request for question list failed");
+        ("no-cache", ApiError::Internal)
+    };
+    // a plain list is all of the event's questions, so it changed when the latest of them did, or
+    // when one was last deleted. lists cut down any other way can change by questions leaving
+    // them without anything to date that by.
+    let dated = !paged && params.since.is_none() && filter == Filter::default();
+    // errors come with how long they may be cached for.
+    let fetch = async {
+        // changes are reported from the start of the read, so that nothing that happens during it
+        // is missed next time.
+        let synced = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let (mut items, last) = if params.offset.is_some() || params.since.is_some() {
+            // the total or the changes have to be known, so the whole list is read. guests
+            // syncing also need to hear of questions that were hidden, so they can drop them.
+            let mut items = Vec::new();
+            let mut start = None;
+            loop {
+                let qs = dynamo
+                    .list(
+                        &eid,
+                        has_secret || params.since.is_some(),
+                        &filter,
+                        None,
+                        start,
+                    )
+                    .await
+                    .map_err(failed)?;
+                items.extend(qs.items);
+                start = qs.next;
+                if start.is_none() {
+                    break;
+                }
+            }
+            (items, None)
+        } else {
+            // questions that were just hidden have left the guest list, so they date it too.
+            let qs = dynamo
+                .list(&eid, has_secret || dated, &filter, limit, start)
+                .await
+                .map_err(failed)?;
+            (qs.items, qs.next)
+        };
+        trace!(%eid, n = %items.len(), "listed questions");
+        let mut modified = None;
+        if dated {
+            let newest = items.iter().map(crate::sync::updated).max();
+            let buried = match dynamo.removed(&eid).await {
+                Ok(e) => e.as_ref().and_then(crate::sync::last_removed),
+                Err(e) => {
+                    error!(%eid, error = %e, "dynamodb request for deleted questions failed");
+                    return Err(("no-cache", ApiError::Internal));
+                }
             };
-            (
-                AppendHeaders([(header::CACHE_CONTROL, max_age)]),
-                Ok(Json(serde_json::Value::from(questions))),
-            )
+            modified = newest.max(buried).map(LastModified);
+            if !has_secret {
+                items.retain(|q| q.get("hidden") != Some(&Attr::Bool(true)));
+            }
         }
-        Err(e) => {
-            if let SdkError::ServiceError { ref err, .. } = e {
-                if err.is_resource_not_found_exception() {
-                    warn!(%eid, error = %e, "request for non-existing event");
-                    return (
-                        // it's relatively unlikely that an event Uuid that didn't exist will start
-                        // existing. but just in case, don't make it _too_ long.
-                        AppendHeaders([(header::CACHE_CONTROL, "max-age=3600")]),
-                        Err(http::StatusCode::NOT_FOUND),
-                    );
+        let mut removed = Vec::new();
+        if let Some(since) = params.since {
+            match dynamo.removed(&eid).await {
+                Ok(e) => {
+                    if let Some(e) = e {
+                        removed = crate::sync::removed_since(&e, since);
+                    }
+                }
+                Err(e) => {
+                    error!(%eid, error = %e, "dynamodb request for deleted questions failed");
+                    return Err(("no-cache", ApiError::Internal));
                 }
             }
-            error!(%eid, error = %e, "dynamodb request for question list failed");
+            items.retain(|q| crate::sync::updated(q) >= since);
+            if !has_secret {
+                // to guests, a question that was just hidden is as good as deleted.
+                items.retain(|q| {
+                    let hidden = q.get("hidden") == Some(&Attr::Bool(true));
+                    if hidden {
+                        if let Some(qid) = q.get("id").and_then(|v| v.as_s().ok()) {
+                            removed.push(qid.clone());
+                        }
+                    }
+                    !hidden
+                });
+            }
+        }
+        params.sort.apply(&mut items);
+        // pinned questions go first, but otherwise keep their order.
+        items.sort_by_key(|q| q.get("pinned") != Some(&Attr::Bool(true)));
+        let questions: Vec<_> = items.iter().filter_map(serialize_question).collect();
+
+        if params.since.is_some() {
+            return Ok((
+                serde_json::json!({
+                    "questions": questions,
+                    "removed": removed,
+                    "next_since": synced,
+                }),
+                None,
+            ));
+        }
+        let with_event = has_secret && params.with_event;
+        if !paged && !with_event {
+            return Ok((serde_json::Value::from(questions), modified));
+        }
+        let mut body = if !paged {
+            serde_json::json!({ "questions": questions })
+        } else if let Some(offset) = params.offset {
+            let total = questions.len();
+            let page: Vec<_> = questions
+                .into_iter()
+                .skip(offset)
+                .take(limit.expect("offset pages are paged"))
+                .collect();
+            let next = offset + page.len();
+            serde_json::json!({
+                "questions": page,
+                "total": total,
+                "next_offset": (next < total).then_some(next),
+            })
+        } else {
+            serde_json::json!({
+                "questions": questions,
+                "next_cursor": last.as_ref().map(encode_cursor),
+            })
+        };
+        if has_secret {
+            // hosts get the event metadata too, so they can show what they're managing. there's
+            // no room for it in the legacy array response, so that takes `with_event`.
+            match dynamo.event(&eid).await {
+                Ok(e) => {
+                    if let Some(e) = e {
+                        body["event"] = crate::event::serialize_meta(&e);
+                    }
+                }
+                Err(e) => {
+                    error!(%eid, error = %e, "dynamodb event metadata request failed");
+                    return Err(("no-cache", ApiError::Internal));
+                }
+            }
+        }
+        // the event metadata that hosts get with the list isn't dated.
+        Ok((body, modified.filter(|_| !with_event)))
+    };
+    // a burst of guests polling the same list only costs one query, for backends where that's
+    // worth a list that's a little stale.
+    let body = if !has_secret && dynamo.caches_lists() {
+        crate::listcache::get_or_fetch(&eid, &params, || fetch).await
+    } else {
+        fetch.await
+    };
+    match body {
+        Ok((body, modified)) => {
+            let total = body
+                .get("total")
+                .and_then(|t| t.as_u64())
+                .map(|t| AppendHeaders([("x-total-count", t.to_string())]));
             (
-                AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
-                Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, max_age)]),
+                    total,
+                    modified,
+                ),
+                Ok(Json(body)),
             )
         }
+        Err((cache_control, e)) => (
+            (
+                AppendHeaders([(header::CACHE_CONTROL, cache_control)]),
+                None,
+                None,
+            ),
+            Err(e),
+        ),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use http::StatusCode;
 
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
         let secret = e["secret"].as_str().unwrap();
         let q = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello world".into(),
                 asker: None,
+                tags: Vec::new(),
             }),
         )
         .await
@@ -266,13 +943,15 @@ mod tests {
             assert_eq!(q["votes"], 1);
             assert_eq!(q.get("answered"), None);
             assert_eq!(q["hidden"], false);
+            assert!(q["when"].is_u64());
             assert_eq!(qids.len(), 1, "extra questions in response: {qids:?}");
         };
 
         check(
             super::list_all(
-                Path((eid.clone(), secret.to_string())),
+                Path((eid, secret.to_string())),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -280,7 +959,7 @@ mod tests {
             .0,
         );
         check(
-            super::list(Path(eid.clone()), State(backend.clone()))
+            super::list(Path(eid), State(backend.clone()), Query(Default::default()))
                 .await
                 .1
                 .unwrap()
@@ -290,8 +969,9 @@ mod tests {
         // lookup with wrong secret gives 401
         assert_eq!(
             super::list_all(
-                Path((eid.clone(), "wrong".to_string())),
+                Path((eid, "wrong".to_string())),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -307,6 +987,7 @@ mod tests {
                     secret.to_string()
                 )),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -314,12 +995,171 @@ mod tests {
             StatusCode::NOT_FOUND
         );
 
+        // paging through the questions gives each one once, in order
+        for body in ["hello moon", "hello sun"] {
+            let _ = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+        }
+        let page = |cursor: Option<String>| {
+            super::list_all(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Query(Params {
+                    limit: Some(2),
+                    cursor,
+                    ..Default::default()
+                }),
+            )
+        };
+        let first = page(None).await.1.unwrap();
+        assert_eq!(first["questions"].as_array().unwrap().len(), 2);
+        assert_eq!(first["event"], serde_json::json!({ "code": e["code"] }));
+        let cursor = first["next_cursor"].as_str().unwrap().to_string();
+        let second = page(Some(cursor)).await.1.unwrap();
+        assert_eq!(second["questions"].as_array().unwrap().len(), 1);
+        assert_eq!(second["next_cursor"], serde_json::Value::Null);
+        let mut seen: Vec<_> = first["questions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .chain(second["questions"].as_array().unwrap())
+            .map(|q| q["qid"].as_str().unwrap().to_string())
+            .collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 3);
+
+        // hosts can have the whole list come with the metadata too
+        let all = super::list_all(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            Query(Params {
+                with_event: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(all["questions"].as_array().unwrap().len(), 3);
+        assert_eq!(all["event"], serde_json::json!({ "code": e["code"] }));
+        assert_eq!(all.get("next_cursor"), None);
+
+        // offset pages say how many questions there are in all, and can be in any order
+        let at = |offset, sort| {
+            super::list(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params {
+                    limit: Some(2),
+                    offset: Some(offset),
+                    sort,
+                    ..Default::default()
+                }),
+            )
+        };
+        let (headers, first) = at(0, Sort::Oldest).await;
+        let first = first.unwrap();
+        assert_eq!(headers.1.unwrap().0[0].1, "3");
+        assert_eq!(first["total"], 3);
+        assert_eq!(first["next_offset"], 2);
+        let second = at(2, Sort::Oldest).await.1.unwrap();
+        assert_eq!(second["questions"].as_array().unwrap().len(), 1);
+        assert_eq!(second["next_offset"], serde_json::Value::Null);
+        assert_eq!(
+            at(3, Sort::Oldest).await.1.unwrap()["questions"],
+            serde_json::json!([])
+        );
+        // but they don't mix with cursors, and can't go too far
+        assert_eq!(
+            super::list(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params {
+                    offset: Some(1),
+                    cursor: Some(first["questions"][0]["qid"].as_str().unwrap().into()),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .1
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            at(MAX_OFFSET + 1, Sort::Votes).await.1.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // newest-first is the exact reverse of oldest-first
+        let sorted = |sort| {
+            super::list(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params {
+                    sort,
+                    ..Default::default()
+                }),
+            )
+        };
+        let qids = |qs: serde_json::Value| -> Vec<String> {
+            qs.as_array()
+                .unwrap()
+                .iter()
+                .map(|q| q["qid"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let oldest = qids(sorted(Sort::Oldest).await.1.unwrap().0);
+        let mut newest = qids(sorted(Sort::Newest).await.1.unwrap().0);
+        assert_eq!(oldest.len(), 3);
+        newest.reverse();
+        assert_eq!(oldest, newest);
+        // and the default is still by votes, with ties broken by qid
+        let by_votes = qids(sorted(Sort::Votes).await.1.unwrap().0);
+        let mut by_qid = by_votes.clone();
+        by_qid.sort();
+        assert_eq!(by_votes, by_qid);
+
+        // sorting by time can't be combined with paging
+        assert_eq!(
+            super::list(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params {
+                    limit: Some(1),
+                    sort: Sort::Newest,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .1
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // a garbled cursor gives 400
+        assert_eq!(
+            page(Some("garbage".into())).await.1.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
         backend.delete(&eid).await;
 
         // lookup for empty but existing event gives 200
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
-        super::list(Path(eid), State(backend.clone()))
+        let _ = super::list(Path(eid), State(backend.clone()), Query(Default::default()))
             .await
             .1
             .unwrap();
@@ -330,6 +1170,7 @@ mod tests {
             super::list(
                 Path(Ulid::from_string("00000000000000000000000001").unwrap()),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -338,14 +1179,581 @@ mod tests {
         );
     }
 
+    async fn filters(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+
+        // one question for every combination of answered and hidden
+        let mut qids = HashMap::new();
+        for answered in [false, true] {
+            for hidden in [false, true] {
+                let q = crate::ask::ask(
+                    Path(eid),
+                    State(backend.clone()),
+                    Json(crate::ask::Question {
+                        body: "hello world".into(),
+                        asker: None,
+                        tags: Vec::new(),
+                    }),
+                )
+                .await
+                .unwrap();
+                let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+                for (on, property) in [
+                    (answered, crate::toggle::Property::Answered),
+                    (hidden, crate::toggle::Property::Hidden),
+                ] {
+                    if on {
+                        let _ = crate::toggle::toggle(
+                            Path((eid, secret.clone(), qid, property)),
+                            State(backend.clone()),
+                            http::HeaderMap::new(),
+                            String::from("on"),
+                        )
+                        .await
+                        .unwrap();
+                    }
+                }
+                qids.insert((answered, hidden), qid.to_string());
+            }
+        }
+
+        let list = |answered: Option<bool>, hidden: Option<bool>| {
+            let backend = backend.clone();
+            let secret = secret.clone();
+            async move {
+                let params = serde_json::from_value(serde_json::json!({
+                    "answered": answered,
+                    "hidden": hidden,
+                }))
+                .unwrap();
+                let qs = super::list_all(Path((eid, secret)), State(backend), Query(params))
+                    .await
+                    .1
+                    .unwrap()
+                    .0;
+                let mut qs: Vec<_> = qs
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|q| q["qid"].as_str().unwrap().to_string())
+                    .collect();
+                qs.sort();
+                qs
+            }
+        };
+        let expect = |keep: &dyn Fn(bool, bool) -> bool| {
+            let mut qs: Vec<_> = qids
+                .iter()
+                .filter(|((answered, hidden), _)| keep(*answered, *hidden))
+                .map(|(_, qid)| qid.clone())
+                .collect();
+            qs.sort();
+            qs
+        };
+
+        assert_eq!(list(None, None).await, expect(&|_, _| true));
+        for set in [false, true] {
+            assert_eq!(list(Some(set), None).await, expect(&|a, _| a == set));
+            assert_eq!(list(None, Some(set)).await, expect(&|_, h| h == set));
+        }
+        // unanswered and not hidden
+        assert_eq!(
+            list(Some(false), Some(false)).await,
+            expect(&|a, h| !a && !h)
+        );
+        assert_eq!(list(Some(true), Some(true)).await, expect(&|a, h| a && h));
+
+        // guests can't use the filters to peek at hidden questions
+        let params = serde_json::from_value(serde_json::json!({ "hidden": true })).unwrap();
+        let qs = super::list(Path(eid), State(backend.clone()), Query(params))
+            .await
+            .1
+            .unwrap()
+            .0;
+        assert_eq!(qs.as_array().unwrap().len(), 2);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn hidden_counts(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            serde_json::json!({ "hide_counts": true }).to_string(),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello moon"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(q["id"].as_str().unwrap().to_string());
+        }
+        let v = crate::vote::vote(
+            Path((qids[1].parse().unwrap(), crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v.get("votes"), None);
+
+        // guests still get the questions in vote order, they just can't see the counts
+        let qs = list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap();
+        let qs = qs.as_array().unwrap();
+        assert_eq!(qs[0]["qid"], qids[1]);
+        assert_eq!(qs[1]["qid"], qids[0]);
+        for q in qs {
+            assert_eq!(q.get("votes"), None);
+        }
+        let qs = list(
+            Path(eid),
+            State(backend.clone()),
+            Query(Params {
+                limit: Some(10),
+                ..Default::default()
+            }),
+        )
+        .await
+        .1
+        .unwrap();
+        for q in qs["questions"].as_array().unwrap() {
+            assert_eq!(q.get("votes"), None);
+        }
+
+        // hosts see them as usual
+        let qs = list_all(
+            Path((eid, secret)),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(qs[0]["votes"], 2);
+        assert_eq!(qs[1]["votes"], 1);
+
+        backend.delete(&eid).await;
+    }
+
+    #[test]
+    fn negotiation() {
+        let accept = |v: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(v));
+            wants_csv(&headers)
+        };
+        assert!(!wants_csv(&HeaderMap::new()));
+        assert!(!accept("*/*"));
+        assert!(!accept("application/json"));
+        assert!(!accept("image/png"));
+        assert!(accept("text/csv"));
+        assert!(accept("Text/CSV; charset=utf-8"));
+        assert!(accept("image/png, text/csv"));
+        assert!(!accept("application/json, text/csv"));
+        assert!(accept("application/json;q=0.5, text/csv"));
+        assert!(!accept("text/csv;q=0, */*"));
+    }
+
+    async fn accept(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = q["id"].as_str().unwrap();
+        let get = |accept: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+            list_host(
+                Path((eid, secret.clone())),
+                State(backend.clone()),
+                Query(Default::default()),
+                headers,
+            )
+        };
+
+        for accept in ["application/json", "*/*"] {
+            let res = get(accept).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+            assert_eq!(res.headers()[header::VARY], "accept");
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let qs: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(qs[0]["qid"], qid);
+        }
+
+        let res = get("text/csv").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(res.headers()[header::VARY], "accept");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        assert!(csv.starts_with("qid,"));
+        assert!(csv.contains(&format!("{qid},hello world,1,")));
+
+        backend.delete(&eid).await;
+    }
+
+    async fn sync(backend: Backend) {
+        let start = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello moon", "hello sun"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret.clone(),
+                qids[1],
+                crate::toggle::Property::Hidden,
+            )),
+            State(backend.clone()),
+            HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        crate::remove::remove(Path((eid, secret.clone(), qids[2])), State(backend.clone()))
+            .await
+            .unwrap();
+
+        let since = |since| Params {
+            since: Some(since),
+            ..Default::default()
+        };
+        let ids = |qs: &serde_json::Value| -> Vec<String> {
+            let mut qids: Vec<_> = qs
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|q| q.as_str().unwrap_or_else(|| q["qid"].as_str().unwrap()))
+                .map(String::from)
+                .collect();
+            qids.sort();
+            qids
+        };
+        let mut expected: Vec<_> = [qids[1], qids[2]].iter().map(Ulid::to_string).collect();
+        expected.sort();
+
+        // guests hear of questions that were hidden as though they were deleted
+        let d = list(Path(eid), State(backend.clone()), Query(since(start)))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(ids(&d["questions"]), vec![qids[0].to_string()]);
+        assert_eq!(ids(&d["removed"]), expected);
+        assert!(d["next_since"].as_u64().unwrap() >= start);
+        // hosts still get them
+        let d = list_all(
+            Path((eid, secret.clone())),
+            State(backend.clone()),
+            Query(since(start)),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(d["questions"].as_array().unwrap().len(), 2);
+        assert_eq!(ids(&d["removed"]), vec![qids[2].to_string()]);
+
+        // nothing has changed since the future
+        let d = list(
+            Path(eid),
+            State(backend.clone()),
+            Query(since(start + 3600)),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(d["questions"], serde_json::json!([]));
+        assert_eq!(d["removed"], serde_json::json!([]));
+
+        // changes don't come in pages
+        assert_eq!(
+            list(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params {
+                    limit: Some(1),
+                    ..since(start)
+                }),
+            )
+            .await
+            .1
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    async fn modified(backend: Backend) {
+        use tower::ServiceExt;
+
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        let app = crate::app(
+            backend.clone(),
+            Default::default(),
+            crate::ratelimit::RateLimitLayer::from_env(),
+        );
+        let get = |path: String, since: Option<&str>| {
+            let mut req = http::Request::get(path);
+            if let Some(since) = since {
+                req = req.header(header::IF_MODIFIED_SINCE, since);
+            }
+            app.clone()
+                .oneshot(req.body(axum::body::Body::empty()).unwrap())
+        };
+        let path = format!("/api/event/{eid}/questions");
+
+        let res = get(path.clone(), None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let modified = res.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let res = get(path.clone(), Some(&modified)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let earlier =
+            httpdate::parse_http_date(&modified).unwrap() - std::time::Duration::from_secs(3600);
+        let earlier = httpdate::fmt_http_date(earlier);
+        let res = get(path.clone(), Some(&earlier)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // hosts' lists are dated too
+        let res = get(format!("{path}/{secret}"), Some(&modified))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        // a hidden question still dates the guest list it left
+        let _ = crate::toggle::toggle(
+            Path((eid, secret.clone(), qid, crate::toggle::Property::Hidden)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        let res = get(path.clone(), None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key(header::LAST_MODIFIED));
+
+        // but pages can't be dated
+        let res = get(format!("{path}?limit=5"), None).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::LAST_MODIFIED));
+
+        backend.delete(&eid).await;
+    }
+
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_modified() {
+        modified(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_modified() {
+        modified(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_modified() {
+        modified(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_modified() {
+        modified(crate::sqlite_backend().await).await;
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_filters() {
+        filters(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_filters() {
+        filters(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_filters() {
+        filters(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_filters() {
+        filters(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_hidden_counts() {
+        hidden_counts(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_hidden_counts() {
+        hidden_counts(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_hidden_counts() {
+        hidden_counts(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_hidden_counts() {
+        hidden_counts(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_accept() {
+        accept(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_accept() {
+        accept(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_accept() {
+        accept(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_accept() {
+        accept(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_sync() {
+        sync(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_sync() {
+        sync(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_sync() {
+        sync(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_sync() {
+        sync(crate::sqlite_backend().await).await;
     }
 }
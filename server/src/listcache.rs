@@ -0,0 +1,143 @@
+use crate::list::{Listed, Params};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_TTL_MS: u64 = 500;
+
+/// Returns for how long a guest list of questions is reused, as configured through
+/// `LIST_CACHE_TTL_MS`.
+///
+/// `LIST_CACHE_TTL_MS=0` turns the cache off. Panics if `LIST_CACHE_TTL_MS` is set but isn't a
+/// number.
+pub(super) fn ttl() -> Duration {
+    static TTL: OnceLock<Duration> = OnceLock::new();
+    *TTL.get_or_init(|| match std::env::var("LIST_CACHE_TTL_MS") {
+        Ok(n) => Duration::from_millis(
+            n.parse()
+                .expect("LIST_CACHE_TTL_MS must be a number of milliseconds"),
+        ),
+        Err(_) => Duration::from_millis(DEFAULT_TTL_MS),
+    })
+}
+
+struct Entry {
+    made: Instant,
+    /// Filled in by whichever request got to fetch the list, and awaited by the others.
+    body: Arc<OnceCell<Listed>>,
+}
+
+/// The guest lists of questions fetched in the last little while, by event and query.
+struct Cache {
+    entries: HashMap<(Ulid, Params), Entry>,
+    ttl: Duration,
+}
+
+impl Cache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Gives the cell holding (or about to hold) the list for `key`.
+    fn slot(&mut self, key: (Ulid, Params), now: Instant) -> Arc<OnceCell<Listed>> {
+        let ttl = self.ttl;
+        // lists go stale long before anyone asks for them again, so don't keep them around.
+        self.entries.retain(|_, e| now.duration_since(e.made) < ttl);
+        let entry = self.entries.entry(key).or_insert_with(|| Entry {
+            made: now,
+            body: Arc::new(OnceCell::new()),
+        });
+        Arc::clone(&entry.body)
+    }
+
+    fn invalidate(&mut self, eid: &Ulid) {
+        self.entries.retain(|(e, _), _| e != eid);
+    }
+}
+
+fn cache() -> std::sync::MutexGuard<'static, Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| Mutex::new(Cache::new(ttl())))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Gives the guest list of questions of `eid` for `params`, reusing one fetched in the last
+/// `LIST_CACHE_TTL_MS` if there is one.
+///
+/// Requests that come in while the list is being fetched wait for that fetch rather than making
+/// their own. Failed fetches aren't cached, so the next request tries again.
+pub(super) async fn get_or_fetch<F, Fut, E>(
+    eid: &Ulid,
+    params: &Params,
+    fetch: F,
+) -> Result<Listed, E>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Listed, E>>,
+{
+    if ttl().is_zero() {
+        return fetch().await;
+    }
+    let slot = cache().slot((*eid, params.clone()), Instant::now());
+    slot.get_or_try_init(fetch).await.cloned()
+}
+
+/// Forgets the cached lists of `eid`, for when its questions have changed.
+pub(super) fn invalidate(eid: &Ulid) {
+    cache().invalidate(eid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn coalescing() {
+        let now = Instant::now();
+        let eid = Ulid::new();
+        let key = || (eid, Params::default());
+        let mut cache = Cache::new(Duration::from_millis(500));
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            Ok::<_, ()>((serde_json::json!([]), None))
+        };
+
+        // a burst of requests only fetches once
+        let (a, b) = (cache.slot(key(), now), cache.slot(key(), now));
+        let (a, b) = tokio::join!(a.get_or_try_init(fetch), b.get_or_try_init(fetch));
+        assert_eq!(a.unwrap(), b.unwrap());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(cache.slot(key(), now).initialized());
+
+        // but lists don't last
+        let later = now + Duration::from_millis(500);
+        assert!(!cache.slot(key(), later).initialized());
+
+        // and changes to the event make them go away
+        cache
+            .slot(key(), later)
+            .get_or_try_init(fetch)
+            .await
+            .unwrap();
+        cache.invalidate(&Ulid::new());
+        assert!(cache.slot(key(), later).initialized());
+        cache.invalidate(&eid);
+        assert!(!cache.slot(key(), later).initialized());
+    }
+}
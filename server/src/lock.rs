@@ -0,0 +1,214 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How a storage engine stops (or resumes) taking questions for an event.
+#[async_trait]
+pub(super) trait LockStore {
+    /// Stops (or, if `locked` is false, resumes) taking new questions in `eid`.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if the event does not exist.
+    async fn lock_questions(&self, eid: &Ulid, locked: bool) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl LockStore for aws_sdk_dynamodb::Client {
+    async fn lock_questions(&self, eid: &Ulid, locked: bool) -> Result<(), StoreError> {
+        let upd = self
+            .update_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .condition_expression("attribute_exists(id)");
+        let upd = if locked {
+            upd.update_expression("SET questions_locked = :true")
+                .expression_attribute_values(":true", AttributeValue::Bool(true))
+        } else {
+            upd.update_expression("REMOVE questions_locked")
+        };
+        super::retry::retry(|| upd.clone().send()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LockStore for Mutex<Local> {
+    async fn lock_questions(&self, eid: &Ulid, locked: bool) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local { events, .. } = &mut *local;
+
+        let Some(e) = events.get_mut(eid) else {
+            return Err(StoreError::ConditionFailed);
+        };
+        if locked {
+            e.insert("questions_locked", Attr::Bool(true));
+        } else {
+            e.remove("questions_locked");
+        }
+        local.publish(
+            eid,
+            "lock",
+            serde_json::json!({ "questions_locked": locked }),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl LockStore for crate::redis::Redis {
+    async fn lock_questions(&self, eid: &Ulid, locked: bool) -> Result<(), StoreError> {
+        self.modify(&crate::redis::event_key(eid), |e| {
+            if locked {
+                e.insert("questions_locked".into(), Attr::Bool(true));
+            } else {
+                e.remove("questions_locked");
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl LockStore for crate::sqlite::Sqlite {
+    async fn lock_questions(&self, eid: &Ulid, locked: bool) -> Result<(), StoreError> {
+        self.modify(crate::sqlite::Table::Events, eid, |e| {
+            if locked {
+                e.insert("questions_locked".into(), Attr::Bool(true));
+            } else {
+                e.remove("questions_locked");
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Stops or resumes taking new questions, for when the host is wrapping up.
+///
+/// Unlike archiving, voting keeps working while questions are locked.
+pub(super) async fn lock(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    body: String,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let locked = match &*body {
+        "on" => true,
+        "off" => false,
+        _ => {
+            error!(%eid, body, "invalid lock value");
+            return Err(ApiError::BadRequest);
+        }
+    };
+
+    match dynamo.lock_questions(&eid, locked).await {
+        Ok(_) => {
+            debug!(%eid, locked, "locked questions");
+            Ok(Json(serde_json::json!({ "questions_locked": locked })))
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%eid, "attempted to lock non-existing event");
+            Err(ApiError::EventNotFound)
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to lock questions failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let ask = || {
+            crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+        let lock = |body: &str| {
+            super::lock(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                body.to_string(),
+            )
+        };
+        let q = ask().await.unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        assert_eq!(lock("maybe").await.unwrap_err(), StatusCode::BAD_REQUEST);
+        let r = lock("on").await.unwrap();
+        assert_eq!(r["questions_locked"], true);
+        let (_, meta) = crate::event::meta(Path(eid), State(backend.clone())).await;
+        assert_eq!(meta.unwrap()["questions_locked"], true);
+
+        // no more questions, but voting still works
+        assert_eq!(ask().await.unwrap_err(), StatusCode::LOCKED);
+        let v = crate::vote::vote(
+            Path((qid, crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["votes"], 2);
+
+        let r = lock("off").await.unwrap();
+        assert_eq!(r["questions_locked"], false);
+        let _ = ask().await.unwrap();
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
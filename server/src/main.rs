@@ -1,21 +1,34 @@
-use aws_sdk_dynamodb::{model::AttributeValue, types::SdkError};
-use aws_smithy_http::body::SdkBody;
-use axum::response::IntoResponse;
-use axum::routing::{get, post};
-use axum::Router;
+// the openapi spec is one big `json!`, which nests deeper than the default allows.
+#![recursion_limit = "256"]
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use error::ApiError;
 use http::StatusCode;
 use lambda_http::Error;
+use sha2::{Digest, Sha256};
 use std::time::SystemTime;
 use std::{
     collections::HashMap,
     future::Future,
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
 };
+use store::Attr;
+use subtle::ConstantTimeEq;
 use tower::Layer;
-use tower_http::{limit::RequestBodyLimitLayer, trace::TraceLayer};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    cors::CorsLayer,
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    trace::TraceLayer,
+};
 use tower_service::Service;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer as _};
 use ulid::Ulid;
 
 #[allow(unused_imports)]
@@ -24,220 +37,1000 @@ use tracing::{debug, error, info, trace, warn};
 #[cfg(debug_assertions)]
 const SEED: &str = include_str!("test.json");
 
-#[derive(Clone, Debug)]
-#[allow(dead_code)]
-enum Backend {
-    Dynamo(aws_sdk_dynamodb::Client),
-    Local(Arc<Mutex<Local>>),
+/// A question from the live Q&A that [`SEED`] was exported from.
+#[cfg(debug_assertions)]
+#[derive(serde::Deserialize)]
+struct LiveAskQuestion {
+    likes: usize,
+    text: String,
+    hidden: bool,
+    answered: bool,
+    #[serde(rename = "createTimeUnix")]
+    created: usize,
 }
 
-#[cfg(test)]
-impl Backend {
-    async fn local() -> Self {
-        Backend::Local(Arc::new(Mutex::new(Local::default())))
+/// Asks all the `seed` questions in `eid` at once, rather than waiting on each in turn.
+///
+/// Returns their ids in the same order as `seed`, which is also the order `eid` lists them in,
+/// just as though they'd been asked one after another.
+#[cfg(debug_assertions)]
+async fn ask_seed(state: &Mutex<Local>, eid: &Ulid, seed: &[LiveAskQuestion]) -> Vec<Ulid> {
+    use ask::AskStore;
+
+    let qids: Vec<_> = seed.iter().map(|_| Ulid::new()).collect();
+    let asks = seed.iter().zip(&qids).map(|(q, qid)| {
+        state.ask(
+            eid,
+            qid,
+            ask::Question {
+                body: q.text.clone(),
+                asker: None,
+                tags: Vec::new(),
+            },
+            false,
+            Vec::new(),
+        )
+    });
+    for asked in futures_util::future::join_all(asks).await {
+        asked.unwrap();
+    }
+    // each ask takes the lock in turn, and nothing promises it's in the order they were made.
+    let mut local = lock(state);
+    let listed = local.questions_by_eid.get_mut(eid).unwrap();
+    listed.retain(|qid| !qids.contains(qid));
+    listed.extend(&qids);
+    drop(local);
+    qids
+}
+
+/// Where events and questions are stored.
+///
+/// Each module declares the storage operations it needs as a trait of its own, and [`Store`] is
+/// all of them together. The storage engines are DynamoDB's [`Client`](aws_sdk_dynamodb::Client),
+/// [`Local`], and (with the `redis` and `sqlite` features) [`Redis`](crate::redis::Redis) and
+/// [`Sqlite`](crate::sqlite::Sqlite), so a new storage engine is a new type that implements each
+/// of those traits; the compiler points out every one of them. The operations speak the backend-neutral types of [`store`], so a storage engine only
+/// has to turn what it stores into [`store::Item`]s and its failures into [`store::StoreError`]s.
+/// [`engine`] picks which one to use.
+type Backend = Arc<dyn Store>;
+
+#[async_trait]
+trait Store:
+    answer::AnswerStore
+    + archive::ArchiveStore
+    + ask::AskStore
+    + code::CodeStore
+    + dedup::DedupStore
+    + destroy::DestroyStore
+    + edit::EditStore
+    + event::EventStore
+    + health::HealthStore
+    + idempotency::IdempotencyStore
+    + import::ImportStore
+    + list::ListStore
+    + lock::LockStore
+    + merge::MergeStore
+    + new::NewStore
+    + note::NoteStore
+    + questions::QuestionsStore
+    + remove::RemoveStore
+    + reorder::ReorderStore
+    + report::ReportStore
+    + rotate::RotateStore
+    + stats::StatsStore
+    + sync::SyncStore
+    + tags::TagsStore
+    + timeseries::TimeseriesStore
+    + toggle::ToggleStore
+    + vote::VoteStore
+    + metrics::MetricsStore
+    + presence::PresenceStore
+    + stream::StreamStore
+    + std::fmt::Debug
+    + Send
+    + Sync
+{
+    /// Gives the stored secret of `eid`, which is hashed for all but the oldest events.
+    async fn get_secret(&self, eid: &Ulid) -> Result<String, ApiError>;
+
+    /// Gives every event along with how many questions it has, in no particular order, or `None`
+    /// if there's no cheap way to go through them all.
+    #[cfg(debug_assertions)]
+    fn all_events(&self) -> Option<Vec<(Ulid, usize)>>;
+}
+
+#[async_trait]
+impl Store for aws_sdk_dynamodb::Client {
+    async fn get_secret(&self, eid: &Ulid) -> Result<String, ApiError> {
+        let (secret, expire) = match secretcache::get(eid) {
+            Some(cached) => cached,
+            None => {
+                let get = self
+                    .get_item()
+                    .table_name("events")
+                    .key("id", AttributeValue::S(eid.to_string()))
+                    .projection_expression("secret,expire");
+                let e = match retry::retry(|| get.clone().send()).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!(
+                            %eid, error = %e,
+                            "dynamodb event request for secret verificaton failed"
+                        );
+                        return Err(ApiError::Internal);
+                    }
+                };
+                let Some(e) = e.item() else {
+                    warn!(%eid, "attempted to access non-existing event");
+                    return Err(ApiError::EventNotFound);
+                };
+                let expire = e
+                    .get("expire")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok());
+                let Some(secret) = e.get("secret").and_then(|s| s.as_s().ok()) else {
+                    error!(%eid, "event has no secret");
+                    return Err(ApiError::Internal);
+                };
+                secretcache::insert(*eid, secret.clone(), expire);
+                (secret.clone(), expire)
+            }
+        };
+        // dynamodb doesn't delete expired items immediately,
+        // so make sure we don't hand out events it hasn't gotten around to yet.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if expire.is_some_and(|expire| expire <= now) {
+            warn!(%eid, "attempted to access expired event");
+            return Err(ApiError::EventNotFound);
+        }
+        Ok(secret)
+    }
+
+    #[cfg(debug_assertions)]
+    fn all_events(&self) -> Option<Vec<(Ulid, usize)>> {
+        // there's no cheap way to go through every event in dynamodb, nor should there be.
+        None
+    }
+}
+
+#[async_trait]
+impl Store for Mutex<Local> {
+    async fn get_secret(&self, eid: &Ulid) -> Result<String, ApiError> {
+        let mut local = lock(self);
+        if local.reap_if_expired(eid) {
+            warn!(%eid, "attempted to access expired event");
+            return Err(ApiError::EventNotFound);
+        }
+        let Local { events, .. } = &mut *local;
+        match events.get(eid) {
+            Some(e) => Ok(e["secret"]
+                .as_s()
+                .expect("secret is always a string")
+                .clone()),
+            None => Err(ApiError::EventNotFound),
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn all_events(&self) -> Option<Vec<(Ulid, usize)>> {
+        let local = lock(self);
+        Some(
+            local
+                .events
+                .keys()
+                .map(|eid| (*eid, local.questions_by_eid.get(eid).map_or(0, Vec::len)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Store for crate::redis::Redis {
+    async fn get_secret(&self, eid: &Ulid) -> Result<String, ApiError> {
+        // expired events are gone from redis as soon as they expire, so there's no need to check.
+        let e = match self.item(&crate::redis::event_key(eid)).await {
+            Ok(e) => e,
+            Err(e) => {
+                error!(%eid, error = %e, "redis event request for secret verificaton failed");
+                return Err(ApiError::Internal);
+            }
+        };
+        let Some(e) = e else {
+            warn!(%eid, "attempted to access non-existing event");
+            return Err(ApiError::EventNotFound);
+        };
+        match e.get("secret").and_then(|s| s.as_s().ok()) {
+            Some(secret) => Ok(secret.clone()),
+            None => {
+                error!(%eid, "event has no secret");
+                Err(ApiError::Internal)
+            }
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn all_events(&self) -> Option<Vec<(Ulid, usize)>> {
+        // that would take scanning every key.
+        None
     }
+}
 
-    async fn dynamo() -> Self {
-        let config = aws_config::load_from_env().await;
-        Backend::Dynamo(aws_sdk_dynamodb::Client::new(&config))
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl Store for crate::sqlite::Sqlite {
+    async fn get_secret(&self, eid: &Ulid) -> Result<String, ApiError> {
+        let e = match self.item(crate::sqlite::Table::Events, eid) {
+            Ok(e) => e,
+            Err(e) => {
+                error!(%eid, error = %e, "sqlite event request for secret verificaton failed");
+                return Err(ApiError::Internal);
+            }
+        };
+        let Some(e) = e else {
+            warn!(%eid, "attempted to access non-existing event");
+            return Err(ApiError::EventNotFound);
+        };
+        // expired events are only swept out when the database is opened.
+        let expire = e
+            .get("expire")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if expire.is_some_and(|expire| expire <= crate::sqlite::now()) {
+            warn!(%eid, "attempted to access expired event");
+            return Err(ApiError::EventNotFound);
+        }
+        match e.get("secret").and_then(|s| s.as_s().ok()) {
+            Some(secret) => Ok(secret.clone()),
+            None => {
+                error!(%eid, "event has no secret");
+                Err(ApiError::Internal)
+            }
+        }
     }
+
+    #[cfg(debug_assertions)]
+    fn all_events(&self) -> Option<Vec<(Ulid, usize)>> {
+        let events = self.run(|con| {
+            let mut stmt = con.prepare(
+                "SELECT id, (SELECT COUNT(*) FROM questions WHERE eid = events.id) FROM events",
+            )?;
+            let events = stmt
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(events)
+        });
+        match events {
+            Ok(events) => Some(
+                events
+                    .into_iter()
+                    .filter_map(|(eid, n)| Some((eid.parse().ok()?, n)))
+                    .collect(),
+            ),
+            Err(e) => {
+                error!(error = %e, "sqlite request for all events failed");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+async fn local_backend() -> Backend {
+    Arc::new(Mutex::new(Local::default()))
+}
+
+/// Gives a local backend along with its state, for tests that look at what was stored.
+#[cfg(test)]
+fn local_state() -> (Backend, Arc<Mutex<Local>>) {
+    let local = Arc::new(Mutex::new(Local::default()));
+    (local.clone(), local)
+}
+
+#[cfg(test)]
+async fn dynamo_backend() -> Backend {
+    let config = telemetry::aws_config().await;
+    Arc::new(aws_sdk_dynamodb::Client::new(&config))
+}
+
+/// Gives a backend on the Redis server at `REDIS_URL` (or on this machine, if that isn't set).
+#[cfg(all(test, feature = "redis"))]
+async fn redis_backend() -> Backend {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| String::from("redis://127.0.0.1/"));
+    Arc::new(crate::redis::Redis::connect(&url).await.unwrap())
+}
+
+/// Gives a backend on a fresh in-memory SQLite database.
+#[cfg(all(test, feature = "sqlite"))]
+async fn sqlite_backend() -> Backend {
+    Arc::new(crate::sqlite::Sqlite::open(":memory:").unwrap())
 }
 
-fn to_dynamo_timestamp(time: SystemTime) -> AttributeValue {
-    AttributeValue::N(
+/// The storage engines events and questions can be kept in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Engine {
+    /// [`Local`], which only debug builds have, since its state is gone when the process is.
+    #[cfg(debug_assertions)]
+    Local,
+    Dynamo,
+    #[cfg(feature = "redis")]
+    Redis,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+/// Returns the storage engine to keep events and questions in, as configured through `BACKEND`.
+///
+/// That's the local backend for debug builds and DynamoDB for release builds unless `BACKEND` says
+/// otherwise. Panics if it names an engine this build doesn't have, or one that isn't configured.
+fn engine() -> Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    *ENGINE.get_or_init(|| match std::env::var("BACKEND").as_deref() {
+        #[cfg(debug_assertions)]
+        Err(_) | Ok("local") => Engine::Local,
+        #[cfg(not(debug_assertions))]
+        Err(_) => Engine::Dynamo,
+        Ok("dynamodb") => Engine::Dynamo,
+        #[cfg(feature = "redis")]
+        Ok("redis") => {
+            crate::redis::url();
+            Engine::Redis
+        }
+        #[cfg(feature = "sqlite")]
+        Ok("sqlite") => {
+            crate::sqlite::path();
+            Engine::Sqlite
+        }
+        Ok(backend) => panic!(
+            "BACKEND must be local (in debug builds), dynamodb, redis (with the redis feature), \
+             or sqlite (with the sqlite feature), not {backend:?}"
+        ),
+    })
+}
+
+/// Gives `time` as the number of seconds that items store times as.
+///
+/// Works for both [`Attr`] and DynamoDB's [`AttributeValue`], so each backend gets its own.
+fn to_dynamo_timestamp<A: From<Attr>>(time: SystemTime) -> A {
+    Attr::N(
         time.duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs()
             .to_string(),
     )
+    .into()
 }
 
 #[derive(Clone, Debug, Default)]
 struct Local {
-    events: HashMap<Ulid, String>,
-    questions: HashMap<Ulid, HashMap<&'static str, AttributeValue>>,
+    events: HashMap<Ulid, HashMap<&'static str, Attr>>,
+    questions: HashMap<Ulid, HashMap<&'static str, Attr>>,
     questions_by_eid: HashMap<Ulid, Vec<Ulid>>,
+    client_votes: HashMap<(Ulid, String), vote::UpDown>,
+    /// How many up-votes each client has used of the vote budget of each event that has one.
+    spent_votes: HashMap<(Ulid, String), u64>,
+    /// When each vote came in (and by how much it moved the votes), if `VOTE_TIMESERIES` is on.
+    vote_log: Vec<(Ulid, u64, isize)>,
+    feeds: HashMap<Ulid, stream::Feed>,
+    /// How many clients are watching each event's live updates.
+    presence: HashMap<Ulid, presence::Presence>,
+    idempotency: HashMap<(Ulid, String), idempotency::Claim>,
+    /// The event each short code is for.
+    codes: HashMap<String, Ulid>,
+}
+
+impl Local {
+    /// Removes `eid` and all of its questions if the event has expired.
+    ///
+    /// Returns `true` if the event was removed. Events without an expiry time never expire.
+    fn reap_if_expired(&mut self, eid: &Ulid) -> bool {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expire = self
+            .events
+            .get(eid)
+            .and_then(|e| e.get("expire"))
+            .and_then(|v| v.as_n().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if expire.is_none_or(|at| at > now) {
+            return false;
+        }
+
+        self.remove_event(eid);
+        true
+    }
+
+    /// Removes `eid`, all of its questions, and all votes on them.
+    fn remove_event(&mut self, eid: &Ulid) {
+        self.events.remove(eid);
+        self.feeds.remove(eid);
+        self.presence.remove(eid);
+        let qids = self.questions_by_eid.remove(eid).unwrap_or_default();
+        for qid in &qids {
+            self.questions.remove(qid);
+        }
+        self.client_votes.retain(|(qid, _), _| !qids.contains(qid));
+        self.vote_log.retain(|(qid, _, _)| !qids.contains(qid));
+        self.spent_votes.retain(|(e, _), _| e != eid);
+        self.idempotency.retain(|(e, _), _| e != eid);
+        self.codes.retain(|_, e| e != eid);
+    }
 }
 
+mod accesslog;
+// never compiled into release builds, so that it can't ship to lambda.
+#[cfg(debug_assertions)]
+mod admin;
+mod answer;
+mod archive;
 mod ask;
+mod clone;
+mod code;
+mod compression;
+mod concurrency;
+mod cors;
+mod dedup;
+mod destroy;
+mod edit;
+mod error;
+mod etag;
 mod event;
+mod export;
+mod health;
+mod hosttoken;
+mod idempotency;
+mod import;
+mod lastmodified;
 mod list;
+mod listcache;
+mod lock;
+mod merge;
+mod metrics;
 mod new;
+mod note;
+mod openapi;
+#[cfg(debug_assertions)]
+mod persist;
+mod pick;
+mod presence;
+mod profanity;
 mod questions;
+mod ratelimit;
+#[cfg(debug_assertions)]
+mod reaper;
+#[cfg(feature = "redis")]
+mod redis;
+mod remove;
+mod reorder;
+mod report;
+mod retry;
+mod rotate;
+mod sanitize;
+mod search;
+mod secretcache;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod store;
+mod stream;
+mod sync;
+mod tags;
+mod telemetry;
+mod timeout;
+mod timeseries;
 mod toggle;
+mod version;
 mod vote;
+mod webhook;
+mod ws;
 
-async fn get_secret(dynamo: &Backend, eid: &Ulid) -> Result<String, StatusCode> {
-    match dynamo {
-        Backend::Dynamo(dynamo) => {
-            match dynamo
-                .get_item()
-                .table_name("events")
-                .key("id", AttributeValue::S(eid.to_string()))
-                .projection_expression("secret")
-                .send()
-                .await
-            {
-                Ok(v) => {
-                    if let Some(s) = v
-                        .item()
-                        .and_then(|e| e.get("secret"))
-                        .and_then(|s| s.as_s().ok())
-                    {
-                        Ok(s.clone())
-                    } else {
-                        warn!(%eid, "attempted to access non-existing event");
-                        Err(StatusCode::NOT_FOUND)
-                    }
-                }
-                Err(e) => {
-                    error!(%eid, error = %e, "dynamodb event request for secret verificaton failed");
-                    Err(http::StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
-        }
-        Backend::Local(local) => {
-            let mut local = local.lock().unwrap();
-            let Local { events, .. } = &mut *local;
-            match events.get(eid) {
-                Some(s) => Ok(s.clone()),
-                None => Err(StatusCode::NOT_FOUND),
-            }
-        }
+/// Locks the state of the local backend.
+///
+/// If a request panics while holding the lock, the lock is poisoned, but that shouldn't take every
+/// later request down with it. The state may be a little inconsistent after that, but it's only
+/// used for local development anyway.
+fn lock(local: &Mutex<Local>) -> MutexGuard<'_, Local> {
+    local.lock().unwrap_or_else(|e| {
+        warn!("recovering local backend state from a panicked request");
+        e.into_inner()
+    })
+}
+
+/// Marks stored secrets that are hashes rather than the secret itself.
+///
+/// Events created before secrets were hashed have their secret stored as-is.
+const SECRET_HASH_PREFIX: &str = "sha256:";
+
+/// Hashes an event secret for storage.
+///
+/// Secrets are long and random, so there's no need for a slow (or salted) hash here; this is only
+/// so that the secrets can't be read straight out of the database.
+fn hash_secret(secret: &str) -> String {
+    format!(
+        "{SECRET_HASH_PREFIX}{:x}",
+        Sha256::digest(secret.as_bytes())
+    )
+}
+
+/// Checks `secret` against the `stored` secret of an event without leaking how much of it matched.
+fn secret_matches(stored: &str, secret: &str) -> bool {
+    let bytes_eq = |a: &str, b: &str| bool::from(a.as_bytes().ct_eq(b.as_bytes()));
+    if stored.starts_with(SECRET_HASH_PREFIX) {
+        bytes_eq(stored, &hash_secret(secret))
+    } else {
+        bytes_eq(stored, secret)
     }
 }
 
-async fn check_secret(dynamo: &Backend, eid: &Ulid, secret: &str) -> Result<(), StatusCode> {
-    let s = get_secret(dynamo, eid).await?;
-    if s == secret {
+async fn check_secret(dynamo: &Backend, eid: &Ulid, secret: &str) -> Result<(), ApiError> {
+    let s = dynamo.get_secret(eid).await?;
+    // hosts with a token don't have to put the secret in the url, where it ends up in logs and
+    // browser histories.
+    if hosttoken::authorizes(eid, &s) || secret_matches(&s, secret) {
         Ok(())
     } else {
         warn!(%eid, secret, "attempted to access event with incorrect secret");
-        Err(StatusCode::UNAUTHORIZED)
+        Err(ApiError::ForbiddenSecret)
     }
 }
 
-fn mint_service_error<E>(e: E) -> SdkError<E> {
-    SdkError::ServiceError {
+/// Makes an error as though DynamoDB had returned `e`, for tests of how errors are handled.
+#[cfg(test)]
+fn mint_service_error<E>(e: E) -> aws_sdk_dynamodb::types::SdkError<E> {
+    aws_sdk_dynamodb::types::SdkError::ServiceError {
         err: e,
         raw: aws_smithy_http::operation::Response::new(
-            http::Response::builder().body(SdkBody::empty()).unwrap(),
+            http::Response::builder()
+                .body(aws_smithy_http::body::SdkBody::empty())
+                .unwrap(),
         ),
     }
 }
 
+const DEFAULT_MAX_BODY_BYTES: usize = 1024;
+
+/// Returns the largest request body we accept, as configured through `MAX_BODY_BYTES`.
+///
+/// Panics if `MAX_BODY_BYTES` is set but isn't a number of bytes.
+fn max_body_bytes() -> usize {
+    static MAX: OnceLock<usize> = OnceLock::new();
+    *MAX.get_or_init(|| match std::env::var("MAX_BODY_BYTES") {
+        Ok(n) => n.parse().expect("MAX_BODY_BYTES must be a number of bytes"),
+        Err(_) => DEFAULT_MAX_BODY_BYTES,
+    })
+}
+
+/// Gives requests rejected by the body size limit a body that explains why.
+async fn payload_too_large(res: Response) -> Response {
+    if res.status() != StatusCode::PAYLOAD_TOO_LARGE {
+        return res;
+    }
+    let mut body = ApiError::PayloadTooLarge.body();
+    body["limit"] = max_body_bytes().into();
+    (StatusCode::PAYLOAD_TOO_LARGE, Json(body)).into_response()
+}
+
+/// Gives rejected requests, and requests for routes that aren't there, the same kind of body as an
+/// [`ApiError`].
+///
+/// Requests that axum rejects before they get to a handler (say, because an id in the path isn't a
+/// valid id) come with a plain-text explanation, which is used as the `message` instead.
+async fn error_body(res: Response) -> Response {
+    let error = match res.status() {
+        StatusCode::BAD_REQUEST => ApiError::BadRequest,
+        StatusCode::NOT_FOUND => ApiError::NotFound,
+        _ => return res,
+    };
+    let (mut parts, body) = res.into_parts();
+    let is_text = parts
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"));
+    let mut error = error.body();
+    if is_text {
+        match hyper::body::to_bytes(body).await {
+            Ok(text) => error["message"] = String::from_utf8_lossy(&text).into(),
+            Err(e) => warn!(error = %e, "failed to read rejection message"),
+        }
+    } else if parts.headers.contains_key(http::header::CONTENT_TYPE) {
+        // already has a body of its own
+        return Response::from_parts(parts, body);
+    }
+    parts.headers.remove(http::header::CONTENT_LENGTH);
+    parts.headers.remove(http::header::CONTENT_TYPE);
+    (parts, Json(error)).into_response()
+}
+
+/// Gives requests that don't come with an `X-Request-Id` one of their own.
+#[derive(Clone, Copy, Debug)]
+struct MakeRequestUlid;
+
+impl MakeRequestId for MakeRequestUlid {
+    fn make_request_id<B>(&mut self, _: &http::Request<B>) -> Option<RequestId> {
+        let id = Ulid::new().to_string();
+        Some(RequestId::new(
+            http::HeaderValue::from_str(&id).expect("ulids are valid header values"),
+        ))
+    }
+}
+
+/// The span that everything logged while handling `req` ends up in.
+///
+/// The path is left out, since it may well contain an event secret.
+fn request_span<B>(req: &http::Request<B>) -> tracing::Span {
+    let id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!("request", id, method = %req.method())
+}
+
+/// Every route of the API, relative to where it's mounted.
+fn routes(limit: ratelimit::RateLimitLayer) -> Router<Backend> {
+    let routes = Router::new()
+        .route(
+            "/event",
+            post(new::new).layer(axum::middleware::from_fn(new::require_api_key)),
+        )
+        .route(
+            "/event/:eid",
+            post(ask::ask_idempotent).layer(limit.clone()),
+        )
+        .route(
+            "/event/:eid",
+            get(event::event).layer(axum::middleware::from_fn(lastmodified::conditional)),
+        )
+        .route("/event/:eid/meta", get(event::meta))
+        .route("/e/:code", get(code::lookup))
+        // lists are polled constantly, but rarely change from one poll to the next.
+        .route(
+            "/event/:eid/questions",
+            get(list::list)
+                .layer(axum::middleware::from_fn(etag::etag))
+                .layer(axum::middleware::from_fn(lastmodified::conditional)),
+        )
+        .route("/event/:eid/search", get(search::search))
+        .route("/event/:eid/stream", get(stream::stream))
+        // votes over the socket count against the same limit as other votes.
+        .route(
+            "/event/:eid/ws",
+            get(ws::ws).layer(axum::Extension(limit.clone())),
+        )
+        .route("/event/:eid/presence", get(presence::presence))
+        .route(
+            "/event/:eid/questions/:secret",
+            get(list::list_host)
+                .layer(axum::middleware::from_fn(etag::etag))
+                .layer(axum::middleware::from_fn(lastmodified::conditional))
+                .delete(destroy::destroy),
+        )
+        .route("/event/:eid/questions/:secret/stats", get(stats::stats))
+        .route(
+            "/event/:eid/questions/:secret/votes/timeseries",
+            get(timeseries::timeseries),
+        )
+        .route(
+            "/event/:eid/questions/:secret/next-random",
+            get(pick::next_random),
+        )
+        .route(
+            "/event/:eid/questions/:secret/toggle-bulk",
+            post(toggle::toggle_bulk),
+        )
+        .route(
+            "/event/:eid/questions/:secret/:qid/toggle/:property",
+            post(toggle::toggle),
+        )
+        .route("/event/:eid/questions/:secret/:qid", delete(remove::remove))
+        .route("/event/:eid/questions/:secret/:qid/tags", post(tags::tags))
+        .route("/event/:eid/questions/:secret/:qid/edit", post(edit::edit))
+        .route(
+            "/event/:eid/questions/:secret/:qid/answer",
+            post(answer::answer),
+        )
+        .route("/event/:eid/questions/:secret/:qid/note", post(note::note))
+        .route("/event/:eid/questions/:secret/lock", post(lock::lock))
+        .route(
+            "/event/:eid/questions/:secret/archive",
+            post(archive::archive),
+        )
+        .route(
+            "/event/:eid/questions/:secret/reorder",
+            post(reorder::reorder),
+        )
+        .route("/event/:eid/questions/:secret/merge", post(merge::merge))
+        .route("/event/:eid/questions/:secret/rotate", post(rotate::rotate))
+        // cloning makes a new event too, so it can't be a way around the api keys.
+        .route(
+            "/event/:eid/questions/:secret/clone",
+            post(clone::clone).layer(axum::middleware::from_fn(new::require_api_key)),
+        )
+        .route(
+            "/event/:eid/questions/:secret/export.csv",
+            get(export::export_csv),
+        )
+        .route(
+            "/event/:eid/questions/:secret/export.json",
+            get(export::export_json),
+        )
+        .route(
+            "/event/:eid/questions/:secret/export.html",
+            get(export::export_html),
+        )
+        .route(
+            "/vote/:qid/:reaction",
+            post(vote::react).layer(limit.clone()),
+        )
+        .route("/votes", post(vote::votes).layer(limit.clone()))
+        .route("/question/:qid/report", post(report::report).layer(limit))
+        .route("/questions", post(questions::questions_post))
+        .route(
+            "/questions/:qids",
+            get(questions::questions).layer(axum::middleware::from_fn(lastmodified::conditional)),
+        );
+    // handy for finding your way around local data, but it must never ship.
+    #[cfg(debug_assertions)]
+    let routes = routes.route("/admin/events", get(admin::events));
+    routes
+        .layer(RequestBodyLimitLayer::new(max_body_bytes()))
+        // imports are the one kind of request that's meant to be big, so they get their own limit.
+        .route(
+            "/event/:eid/questions/:secret/import",
+            post(import::import).layer(RequestBodyLimitLayer::new(import::MAX_IMPORT_BYTES)),
+        )
+        .layer(axum::middleware::from_fn(hosttoken::bearer))
+        .layer(axum::middleware::from_fn_with_state(
+            timeout::request_timeout(),
+            timeout::timeout,
+        ))
+        .layer(axum::middleware::map_response(payload_too_large))
+        .layer(axum::middleware::map_response(error_body))
+        // turn panics into 500s rather than dropped connections
+        .layer(CatchPanicLayer::new())
+        .layer(metrics::MetricsLayer)
+        // probes never send a body, so keep them clear of the layers meant for real requests.
+        // that also keeps them out of the metrics.
+        .route("/health", get(health::health))
+        .route("/metrics", get(metrics::metrics))
+        .route("/openapi.json", get(openapi::openapi))
+}
+
+fn app(backend: Backend, cors: CorsLayer, limit: ratelimit::RateLimitLayer) -> Router {
+    // event codes have to be swapped for ids before the request is routed, which layers on the
+    // router itself happen too late for.
+    let api = axum::middleware::from_fn_with_state(backend.clone(), code::resolve)
+        .layer(routes(limit).with_state(backend));
+    Router::new()
+        // the api is versioned so that responses can change incompatibly under /api/v2 without
+        // breaking clients of /api/v1. the unversioned paths are the same as /api/v1, for clients
+        // from before there were versions, and will go away after the next release.
+        .nest_service("/api/v1", api.clone())
+        .nest_service("/api", api)
+        // everything further in goes by the client's address, so it has to be one we can trust.
+        .layer(axum::middleware::from_fn(ratelimit::forwarded_for))
+        // lists of questions are big and repetitive, so they shrink a lot.
+        .layer(compression::layer())
+        // keep-warm pings should cost as little as possible, so they skip the layers of the api
+        // (body limits and all) and compression.
+        .route("/api/ping", get(health::ping))
+        .route("/api/v1/ping", get(health::ping))
+        // so that preflight requests are answered for every route.
+        .layer(cors)
+        // everything counts towards how busy we are, but rejections still show up in the traces.
+        .layer(axum::middleware::from_fn_with_state(
+            concurrency::Limit::from_env(),
+            concurrency::limit,
+        ))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span::<axum::body::Body>))
+        // outside the request span, so that each line stands on its own.
+        .layer(axum::middleware::from_fn(accesslog::log))
+        // the id goes back to the client too, so they can tell us which request went wrong.
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUlid))
+}
+
+/// Sets up the local backend, from the persisted state if there is one, with a seed event whose
+/// questions keep getting votes, and reaps (and persists) it in the background.
+///
+/// Gives the backend along with its state, which is saved once more at shutdown.
+#[cfg(debug_assertions)]
+async fn seeded_local() -> (Backend, Arc<Mutex<Local>>) {
+    use rand::prelude::SliceRandom;
+    use std::time::Duration;
+
+    let mut state = persist::path().and_then(Local::load).unwrap_or_default();
+    let seed_e = "00000000000000000000000000";
+    let seed_e = Ulid::from_string(seed_e).unwrap();
+    // a restored state already has the seed event, votes and all.
+    let seeded = state.events.contains_key(&seed_e);
+    let seed: Vec<LiveAskQuestion> = if seeded {
+        Vec::new()
+    } else {
+        state.events.insert(
+            seed_e,
+            HashMap::from_iter([
+                ("id", Attr::S(seed_e.to_string())),
+                ("secret", Attr::S(hash_secret("secret"))),
+                ("vote_salt", Attr::S(dedup::generate_salt())),
+            ]),
+        );
+        state.questions_by_eid.insert(seed_e, Vec::new());
+        serde_json::from_str(SEED).unwrap()
+    };
+    let local = Arc::new(Mutex::new(state));
+    let state: Backend = local.clone();
+    let qs = ask_seed(&local, &seed_e, &seed).await;
+    let qids = {
+        let mut state = lock(&local);
+        for (qid, seeded) in qs.iter().zip(seed) {
+            let q = state.questions.get_mut(qid).unwrap();
+            q.insert("votes", Attr::N(seeded.likes.to_string()));
+            if seeded.answered {
+                q.insert("answered", to_dynamo_timestamp(SystemTime::now()));
+            }
+            q.insert("hidden", Attr::Bool(seeded.hidden));
+            q.insert("when", Attr::N(seeded.created.to_string()));
+        }
+        state.questions_by_eid[&seed_e].clone()
+    };
+    let cheat = state.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            // the host may have removed all the questions since.
+            let qid = qids.choose(&mut rand::thread_rng()).copied();
+            if let Some(qid) = qid {
+                let _ = cheat.vote(&qid, vote::UpDown::Up.delta()).await;
+            }
+        }
+    });
+    if let Some(path) = persist::path() {
+        info!(path = %path.display(), "persisting local state");
+        persist::save_periodically(local.clone(), path);
+    }
+    reaper::reap_periodically(local.clone());
+    (state, local)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .without_time(/* cloudwatch does that */).init();
+    // log aggregators want structured logs with their own timestamps, which LOG_FORMAT=json gives
+    // them. otherwise we stick to plain lines, since cloudwatch adds the time itself.
+    let fmt = match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => tracing_subscriber::fmt::layer().json().boxed(),
+        Ok("pretty") | Err(_) => tracing_subscriber::fmt::layer()
+            .without_time(/* cloudwatch does that */)
+            .boxed(),
+        Ok(format) => panic!("LOG_FORMAT must be json or pretty, not {format}"),
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(fmt)
+        .with(telemetry::layer())
+        .init();
 
+    // fail fast on a bad configuration rather than on the first request
+    new::event_ttl();
+    new::api_keys();
+    ask::max_questions_per_event();
+    ask::max_question_chars();
+    report::reports_to_hide();
+    retry::max_retries();
+    profanity::filter();
+    max_body_bytes();
+    timeout::request_timeout();
+    concurrency::max_concurrency();
+    timeseries::enabled();
+    dedup::mode();
+    accesslog::level();
+    secretcache::size();
+    secretcache::ttl();
+    hosttoken::key();
+    ratelimit::proxy_hops();
+    listcache::ttl();
     #[cfg(debug_assertions)]
-    let backend = {
-        use rand::prelude::SliceRandom;
-        use serde::Deserialize;
-        use std::time::Duration;
+    reaper::interval();
+    engine();
+    let cors = cors::layer();
+    let limit = ratelimit::RateLimitLayer::from_env();
 
+    #[cfg(debug_assertions)]
+    let mut local = None;
+    let backend: Backend = match engine() {
         #[cfg(debug_assertions)]
-        #[derive(Deserialize)]
-        struct LiveAskQuestion {
-            likes: usize,
-            text: String,
-            hidden: bool,
-            answered: bool,
-            #[serde(rename = "createTimeUnix")]
-            created: usize,
+        Engine::Local => {
+            let (backend, state) = seeded_local().await;
+            local = Some(state);
+            backend
         }
-
-        let mut state = Local::default();
-        let seed: Vec<LiveAskQuestion> = serde_json::from_str(SEED).unwrap();
-        let seed_e ="00000000000000000000000000";
-        let seed_e = Ulid::from_string(seed_e).unwrap();
-        state.events.insert(seed_e.clone(), String::from("secret"));
-        state.questions_by_eid.insert(seed_e.clone(), Vec::new());
-        let mut state = Backend::Local(Arc::new(Mutex::new(state)));
-        let mut qs = Vec::new();
-        for q in seed {
-            let qid = ulid::Ulid::new();
-            state
-                .ask(
-                    &seed_e,
-                    &qid,
-                    ask::Question {
-                        body: q.text,
-                        asker: None,
-                    },
-                )
+        Engine::Dynamo => {
+            let config = telemetry::aws_config().await;
+            Arc::new(aws_sdk_dynamodb::Client::new(&config))
+        }
+        #[cfg(feature = "redis")]
+        Engine::Redis => {
+            let url = crate::redis::url();
+            let redis = crate::redis::Redis::connect(url)
                 .await
-                .unwrap();
-            qs.push((qid, q.created, q.likes, q.hidden, q.answered));
+                .unwrap_or_else(|e| panic!("could not connect to redis at {url}: {e}"));
+            Arc::new(redis)
         }
-        let mut qids = Vec::new();
-        {
-            let Backend::Local(ref mut state): Backend = state else {
-                unreachable!();
-            };
-            let state = Arc::get_mut(state).unwrap();
-            let state = Mutex::get_mut(state).unwrap();
-            for (qid, created, votes, hidden, answered) in qs {
-                let q = state.questions.get_mut(&qid).unwrap();
-                q.insert("votes", AttributeValue::N(votes.to_string()));
-                if answered {
-                    q.insert("answered", to_dynamo_timestamp(SystemTime::now()));
-                }
-                q.insert("hidden", AttributeValue::Bool(hidden));
-                q.insert("when", AttributeValue::N(created.to_string()));
-                qids.push(qid);
-            }
+        #[cfg(feature = "sqlite")]
+        Engine::Sqlite => {
+            let path = crate::sqlite::path();
+            let sqlite = crate::sqlite::Sqlite::open(path)
+                .unwrap_or_else(|e| panic!("could not open sqlite database at {path}: {e}"));
+            Arc::new(sqlite)
         }
-        let cheat = state.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                let qid = qids.choose(&mut rand::thread_rng()).unwrap();
-                let _ = cheat.vote(qid, vote::UpDown::Up).await;
-            }
-        });
-        state
-    };
-    #[cfg(not(debug_assertions))]
-    let backend = {
-        let config = aws_config::load_from_env().await;
-        Backend::Dynamo(aws_sdk_dynamodb::Client::new(&config))
     };
 
-    let app = Router::new()
-        .route("/api/event", post(new::new))
-        .route("/api/event/:eid", post(ask::ask))
-        .route("/api/event/:eid", get(event::event))
-        .route("/api/event/:eid/questions", get(list::list))
-        .route("/api/event/:eid/questions/:secret", get(list::list_all))
-        .route(
-            "/api/event/:eid/questions/:secret/:qid/toggle/:property",
-            post(toggle::toggle),
-        )
-        .route("/api/vote/:qid/:updown", post(vote::vote))
-        .route("/api/questions/:qids", get(questions::questions))
-        .layer(RequestBodyLimitLayer::new(1024))
-        .with_state(backend);
+    let app = app(backend, cors, limit);
 
     if cfg!(debug_assertions) {
-        let addr = std::net::SocketAddr::from(([127, 0, 0, 1], 3000));
-        Ok(axum::Server::bind(&addr)
-            .serve(app.into_make_service())
-            .await?)
+        let addr = match std::env::var("BIND_ADDR") {
+            Ok(addr) => addr.parse().unwrap_or_else(|e| {
+                panic!("BIND_ADDR must be an address and port like 0.0.0.0:8080, not {addr:?}: {e}")
+            }),
+            Err(_) => std::net::SocketAddr::from(([127, 0, 0, 1], 3000)),
+        };
+        info!(%addr, "listening");
+        let served = axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+            .with_graceful_shutdown(shutdown_signal())
+            .await;
+        // so that nothing since the last periodic save is lost, even if the server failed.
+        #[cfg(debug_assertions)]
+        if let (Some(local), Some(path)) = (&local, persist::path()) {
+            persist::save(local, path);
+        }
+        telemetry::shutdown();
+        Ok(served?)
     } else {
         // If we compile in release mode, use the Lambda Runtime
         // To run with AWS Lambda runtime, wrap in our `LambdaLayer`
-        let app = tower::ServiceBuilder::new()
-            .layer(TraceLayer::new_for_http())
-            .layer(LambdaLayer::default())
-            .service(app);
+        let app = tower::ServiceBuilder::new().layer(LambdaLayer).service(app);
 
-        Ok(lambda_http::run(app).await?)
+        let ran = lambda_http::run(app).await;
+        telemetry::shutdown();
+        Ok(ran?)
     }
 }
 
+/// Resolves once we're asked to shut down, either through Ctrl-C or SIGTERM.
+///
+/// In-flight requests are allowed to complete once this resolves, but no new ones are accepted.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("shutting down once in-flight requests complete");
+    stream::shutdown();
+}
+
 #[derive(Default, Clone, Copy)]
 pub struct LambdaLayer;
 
@@ -298,3 +1091,400 @@ where
         Box::pin(fut)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    #[cfg(debug_assertions)]
+    #[tokio::test]
+    async fn seed() {
+        let (backend, local) = crate::local_state();
+        let seed: Vec<LiveAskQuestion> = serde_json::from_str(SEED).unwrap();
+        let mut eids = Vec::new();
+        for _ in 0..2 {
+            let e = new::new(axum::extract::State(backend.clone()), String::new())
+                .await
+                .unwrap();
+            eids.push(Ulid::from_string(e["id"].as_str().unwrap()).unwrap());
+        }
+
+        let qids = ask_seed(&local, &eids[0], &seed).await;
+        // the way seeding used to be done
+        for q in &seed {
+            backend
+                .ask(
+                    &eids[1],
+                    &Ulid::new(),
+                    ask::Question {
+                        body: q.text.clone(),
+                        asker: None,
+                        tags: Vec::new(),
+                    },
+                    false,
+                    Vec::new(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let local = lock(&local);
+        assert_eq!(local.questions_by_eid[&eids[0]], qids);
+        let listed = |eid| -> Vec<_> {
+            local.questions_by_eid[eid]
+                .iter()
+                .map(|qid| {
+                    let q = &local.questions[qid];
+                    (q["text"].clone(), q["votes"].clone(), q["hidden"].clone())
+                })
+                .collect()
+        };
+        assert_eq!(listed(&eids[0]).len(), seed.len());
+        assert_eq!(listed(&eids[0]), listed(&eids[1]));
+    }
+
+    #[tokio::test]
+    async fn body_limit() {
+        let app = app(
+            crate::local_backend().await,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let post = |n| {
+            app.clone().oneshot(
+                http::Request::post("/api/event")
+                    .body(axum::body::Body::from("x".repeat(n)))
+                    .unwrap(),
+            )
+        };
+
+        // right at the limit is let through (and then rejected for not being json)
+        let res = post(max_body_bytes()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = post(max_body_bytes() + 1).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["limit"], max_body_bytes());
+
+        // except for imports, which get more room
+        let import = |n| {
+            let body = serde_json::json!([{ "text": "x".repeat(n) }]).to_string();
+            app.clone().oneshot(
+                http::Request::post(format!(
+                    "/api/event/{}/questions/secret/import",
+                    Ulid::new()
+                ))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body))
+                .unwrap(),
+            )
+        };
+        let res = import(max_body_bytes() * 2).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = import(import::MAX_IMPORT_BYTES).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn invalid_ids() {
+        let app = app(
+            crate::local_backend().await,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        for (method, uri) in [
+            (http::Method::GET, "/api/event/not-an-id/questions".into()),
+            (http::Method::GET, "/api/questions/not-an-id".into()),
+            (
+                http::Method::GET,
+                format!("/api/questions/{},nope", Ulid::new()),
+            ),
+            (http::Method::POST, "/api/vote/not-an-id/up".into()),
+            (
+                http::Method::POST,
+                format!(
+                    "/api/event/{}/questions/secret/not-an-id/toggle/hidden",
+                    Ulid::new()
+                ),
+            ),
+        ] {
+            let res = app
+                .clone()
+                .oneshot(
+                    http::Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "bad_request");
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_property() {
+        let (backend, local) = crate::local_state();
+        let e = new::new(axum::extract::State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = ask::ask(
+            axum::extract::Path(eid),
+            axum::extract::State(backend.clone()),
+            Json(ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        let app = app(
+            backend.clone(),
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let res = app
+            .oneshot(
+                http::Request::post(format!(
+                    "/api/event/{eid}/questions/{secret}/{qid}/toggle/banana"
+                ))
+                .body(axum::body::Body::from("on"))
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "bad_request");
+
+        // and the question was left alone
+        let local = lock(&local);
+        assert!(!local.questions[&qid].contains_key("banana"));
+        assert_eq!(version::of(&local.questions[&qid]), 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_question() {
+        let app = app(
+            crate::local_backend().await,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let res = app
+            .oneshot(
+                http::Request::get(format!("/api/questions/{}", Ulid::new()))
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[http::header::CACHE_CONTROL], "max-age=600");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "question_not_found");
+    }
+
+    #[tokio::test]
+    async fn error_codes() {
+        let backend = crate::local_backend().await;
+        let e = crate::new::new(axum::extract::State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = e["id"].as_str().unwrap().to_string();
+        let app = app(
+            backend,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        http::Request::get(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = res.status();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (status, body)
+            }
+        };
+
+        // clients can tell a wrong secret from an event that isn't there
+        let (status, body) = get(format!("/api/event/{eid}/questions/wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "forbidden_secret");
+        assert!(body["message"].is_string());
+        let (status, body) = get(format!("/api/event/{}/questions/wrong", Ulid::new())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "event_not_found");
+        // and from a route that isn't there at all
+        let (status, body) = get("/api/nope".into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn versions() {
+        let app = app(
+            crate::local_backend().await,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let send = |req: http::Request<axum::body::Body>| {
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<serde_json::Value>(&body).ok(),
+                )
+            }
+        };
+        let get = |uri: String| send(http::Request::get(uri).body(Default::default()).unwrap());
+
+        // the unversioned paths are just another name for v1
+        for prefix in ["/api/v1", "/api"] {
+            let (status, e) = send(
+                http::Request::post(format!("{prefix}/event"))
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let e = e.unwrap();
+            let (eid, secret) = (e["id"].as_str().unwrap(), e["secret"].as_str().unwrap());
+            for other in ["/api/v1", "/api"] {
+                let (status, _) = get(format!("{other}/event/{eid}/questions/{secret}")).await;
+                assert_eq!(status, StatusCode::OK);
+            }
+            let (status, _) = get(format!("{prefix}/health")).await;
+            assert_eq!(status, StatusCode::OK);
+            let (status, _) = get(format!("{prefix}/ping")).await;
+            assert_eq!(status, StatusCode::OK);
+            let (status, body) = get(format!("{prefix}/nope")).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            assert_eq!(body.unwrap()["error"], "not_found");
+        }
+        let (status, _) = get("/api/v2/health".into()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn request_id() {
+        let app = app(
+            crate::local_backend().await,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        );
+        let get = |id: Option<&'static str>| {
+            let mut req = http::Request::get("/api/health");
+            if let Some(id) = id {
+                req = req.header("x-request-id", id);
+            }
+            app.clone()
+                .oneshot(req.body(axum::body::Body::empty()).unwrap())
+        };
+
+        let res = get(None).await.unwrap();
+        let id = res.headers()["x-request-id"].to_str().unwrap();
+        assert!(Ulid::from_string(id).is_ok(), "{id}");
+        let other = get(None).await.unwrap();
+        assert_ne!(other.headers()["x-request-id"], id);
+
+        // ids from upstream are kept
+        let res = get(Some("from-the-gateway")).await.unwrap();
+        assert_eq!(res.headers()["x-request-id"], "from-the-gateway");
+
+        // including on errors
+        let res = app
+            .clone()
+            .oneshot(
+                http::Request::get("/api/event/not-an-id")
+                    .header("x-request-id", "bad")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers()["x-request-id"], "bad");
+    }
+
+    #[test]
+    fn secrets() {
+        let hashed = hash_secret("secret");
+        assert!(secret_matches(&hashed, "secret"));
+        assert!(!secret_matches(&hashed, "secreT"));
+        assert!(!secret_matches(&hashed, ""));
+        assert!(!secret_matches(&hashed, &hashed));
+        assert!(secret_matches("plain", "plain"));
+        assert!(!secret_matches("plain", "plai"));
+        assert!(!secret_matches("plain", "plainer"));
+    }
+
+    #[tokio::test]
+    async fn poisoned() {
+        let (backend, local) = crate::local_state();
+        let l = Arc::clone(&local);
+        let _ = std::thread::spawn(move || {
+            let _guard = l.lock().unwrap();
+            panic!("poisoning the local backend on purpose");
+        })
+        .join();
+        assert!(local.is_poisoned());
+
+        // later requests still go through
+        let _ = new::new(axum::extract::State(backend.clone()), String::new())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn unknown_event() {
+        let backend = crate::local_backend().await;
+        let eid = Ulid::new();
+        assert_eq!(
+            check_secret(&backend, &eid, "secret").await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        // host endpoints should refuse rather than fall over
+        let res = app(
+            backend,
+            Default::default(),
+            ratelimit::RateLimitLayer::from_env(),
+        )
+        .oneshot(
+            http::Request::post(format!(
+                "/api/event/{eid}/questions/secret/{}/toggle/hidden",
+                Ulid::new()
+            ))
+            .body(axum::body::Body::from("on"))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
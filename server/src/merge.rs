@@ -0,0 +1,450 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{
+    AttributeValue, Delete, Get, TransactGetItem, TransactWriteItem, Update,
+};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The most questions that can be merged into another at once.
+///
+/// A transaction takes at most 100 items, and the target question and the event (which records
+/// that the others are gone) are two of them.
+pub(super) const MAX_MERGE: usize = 98;
+
+/// How many times to retry a merge whose questions got votes while it was happening.
+const MAX_RETRIES: usize = 3;
+
+/// How a storage engine folds duplicate questions into one.
+#[async_trait]
+pub(super) trait MergeStore {
+    /// Folds the questions `from` into `into`, adding their votes to it and deleting them.
+    ///
+    /// Either all of the questions are merged, or none are. Returns `None` if any of the questions
+    /// are not in `eid`, and otherwise the new vote count of `into`.
+    async fn merge(
+        &self,
+        eid: &Ulid,
+        into: &Ulid,
+        from: &[Ulid],
+    ) -> Result<Option<u64>, StoreError>;
+}
+
+#[async_trait]
+impl MergeStore for aws_sdk_dynamodb::Client {
+    async fn merge(
+        &self,
+        eid: &Ulid,
+        into: &Ulid,
+        from: &[Ulid],
+    ) -> Result<Option<u64>, StoreError> {
+        let key = |qid: &Ulid| AttributeValue::S(qid.to_string());
+        let eid_v = AttributeValue::S(eid.to_string());
+        let all: Vec<_> = std::iter::once(into).chain(from).collect();
+        for _ in 0..MAX_RETRIES {
+            // votes can only be moved by knowing how many there are, so read them first
+            // and make the writes conditional on them not having changed since.
+            let r = self
+                .transact_get_items()
+                .set_transact_items(Some(
+                    all.iter()
+                        .map(|qid| {
+                            TransactGetItem::builder()
+                                .get(
+                                    Get::builder()
+                                        .table_name("questions")
+                                        .key("id", key(qid))
+                                        .projection_expression("eid,votes")
+                                        .build(),
+                                )
+                                .build()
+                        })
+                        .collect(),
+                ))
+                .send()
+                .await?;
+            let mut votes = Vec::with_capacity(all.len());
+            for q in r.responses().unwrap_or_default() {
+                let Some(q) = q.item() else {
+                    return Ok(None);
+                };
+                if q.get("eid") != Some(&eid_v) {
+                    return Ok(None);
+                }
+                let Some(v) = q.get("votes").and_then(|v| v.as_n().ok()) else {
+                    return Err(StoreError::Other(
+                        "found question with non-numeric vote count".into(),
+                    ));
+                };
+                votes.push(v.clone());
+            }
+            if votes.len() != all.len() {
+                return Ok(None);
+            }
+            let total = votes
+                .iter()
+                .map(|v| v.parse::<u64>().unwrap_or(0))
+                .sum::<u64>();
+
+            let mut writes = vec![TransactWriteItem::builder()
+                .update(
+                    Update::builder()
+                        .table_name("questions")
+                        .key("id", key(into))
+                        .update_expression("SET votes = :total, updated_at = :updated")
+                        .condition_expression("eid = :eid AND votes = :votes")
+                        .expression_attribute_values(":eid", eid_v.clone())
+                        .expression_attribute_values(":votes", AttributeValue::N(votes[0].clone()))
+                        .expression_attribute_values(":total", AttributeValue::N(total.to_string()))
+                        .expression_attribute_values(":updated", crate::sync::now())
+                        .build(),
+                )
+                .build()];
+            // so clients syncing the list learn that the merged questions are gone.
+            writes.push(
+                TransactWriteItem::builder()
+                    .update(
+                        Update::builder()
+                            .table_name("events")
+                            .key("id", eid_v.clone())
+                            .update_expression("ADD removed :gone")
+                            .expression_attribute_values(":gone", crate::sync::tombstones(from))
+                            .build(),
+                    )
+                    .build(),
+            );
+            for (qid, votes) in from.iter().zip(&votes[1..]) {
+                writes.push(
+                    TransactWriteItem::builder()
+                        .delete(
+                            Delete::builder()
+                                .table_name("questions")
+                                .key("id", key(qid))
+                                .condition_expression("eid = :eid AND votes = :votes")
+                                .expression_attribute_values(":eid", eid_v.clone())
+                                .expression_attribute_values(
+                                    ":votes",
+                                    AttributeValue::N(votes.clone()),
+                                )
+                                .build(),
+                        )
+                        .build(),
+                );
+            }
+            match self
+                .transact_write_items()
+                .set_transact_items(Some(writes))
+                .send()
+                .await
+                .map_err(StoreError::from)
+            {
+                Ok(_) => return Ok(Some(total)),
+                Err(StoreError::TransactionCanceled) => {
+                    debug!(%eid, %into, "merged questions changed during merge");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(StoreError::Other(
+            "questions kept changing while they were being merged".into(),
+        ))
+    }
+}
+
+#[async_trait]
+impl MergeStore for Mutex<Local> {
+    async fn merge(
+        &self,
+        eid: &Ulid,
+        into: &Ulid,
+        from: &[Ulid],
+    ) -> Result<Option<u64>, StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            client_votes,
+            ..
+        } = &mut *local;
+
+        let Some(qs) = questions_by_eid.get_mut(eid) else {
+            return Ok(None);
+        };
+        if !std::iter::once(into)
+            .chain(from)
+            .all(|qid| qs.contains(qid))
+        {
+            return Ok(None);
+        }
+        let votes = |q: &std::collections::HashMap<&'static str, Attr>| {
+            q["votes"]
+                .as_n()
+                .expect("votes values are numbers")
+                .parse::<u64>()
+                .expect("votes values are numbers")
+        };
+        let mut total = votes(&questions[into]);
+        for qid in from {
+            let q = questions.remove(qid).expect("listed questions exist");
+            total += votes(&q);
+            client_votes.retain(|(q, _), _| q != qid);
+        }
+        qs.retain(|qid| !from.contains(qid));
+        let q = questions.get_mut(into).expect("listed questions exist");
+        q.insert("votes", Attr::N(total.to_string()));
+        crate::sync::touch(q);
+        local.bury(eid, from);
+
+        local.publish(
+            eid,
+            "merge",
+            serde_json::json!({
+                "into": into.to_string(),
+                "from": from.iter().map(Ulid::to_string).collect::<Vec<_>>(),
+            }),
+        );
+        Ok(Some(total))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl MergeStore for crate::redis::Redis {
+    async fn merge(
+        &self,
+        eid: &Ulid,
+        into: &Ulid,
+        from: &[Ulid],
+    ) -> Result<Option<u64>, StoreError> {
+        use crate::redis::{event_key, question_key, questions_key, voters_key};
+
+        // the event is read along with the questions, since it records that the others are gone.
+        let keys: Vec<_> = std::iter::once(event_key(eid))
+            .chain(std::iter::once(into).chain(from).map(question_key))
+            .collect();
+        self.transact(&keys, |items, pipe| {
+            let Some(mut items) = items.into_iter().collect::<Option<Vec<_>>>() else {
+                return Ok(None);
+            };
+            let e = items.remove(0);
+            if !items.iter().all(|q| crate::store::belongs_to(q, eid)) {
+                return Ok(None);
+            }
+            let votes = |q: &crate::store::Item| {
+                q.get("votes")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            let total = items.iter().map(votes).sum::<u64>();
+            let mut q = items[0].clone();
+            q.insert("votes".into(), Attr::N(total.to_string()));
+            crate::sync::touch(&mut q);
+            crate::redis::put_changes(pipe, &keys[1], &items[0], &q)?;
+            for qid in from {
+                pipe.del(question_key(qid))
+                    .del(voters_key(qid))
+                    .zrem(questions_key(eid), qid.to_string())
+                    .ignore();
+            }
+            let mut buried = e.clone();
+            crate::sync::add_tombstones(&mut buried, from);
+            crate::redis::put_changes(pipe, &keys[0], &e, &buried)?;
+            Ok(Some(total))
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl MergeStore for crate::sqlite::Sqlite {
+    async fn merge(
+        &self,
+        eid: &Ulid,
+        into: &Ulid,
+        from: &[Ulid],
+    ) -> Result<Option<u64>, StoreError> {
+        use crate::sqlite::{get, update, Table};
+
+        self.run(|con| {
+            let Some(e) = get(con, Table::Events, eid)? else {
+                return Ok(None);
+            };
+            let mut items = Vec::with_capacity(from.len() + 1);
+            for qid in std::iter::once(into).chain(from) {
+                match get(con, Table::Questions, qid)? {
+                    Some(q) if crate::store::belongs_to(&q, eid) => items.push(q),
+                    _ => return Ok(None),
+                }
+            }
+            let votes = |q: &crate::store::Item| {
+                q.get("votes")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            let total = items.iter().map(votes).sum::<u64>();
+            let mut q = items[0].clone();
+            q.insert("votes".into(), Attr::N(total.to_string()));
+            crate::sync::touch(&mut q);
+            update(con, Table::Questions, into, &items[0], &q)?;
+            for qid in from {
+                con.execute("DELETE FROM questions WHERE id = ?1", [qid.to_string()])?;
+                con.execute("DELETE FROM voters WHERE qid = ?1", [qid.to_string()])?;
+            }
+            let mut buried = e.clone();
+            crate::sync::add_tombstones(&mut buried, from);
+            update(con, Table::Events, eid, &e, &buried)?;
+            Ok(Some(total))
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct Merge {
+    into: Ulid,
+    from: Vec<Ulid>,
+}
+
+/// Merges duplicate questions into one, keeping all of their votes.
+pub(super) async fn merge(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Json(Merge { into, mut from }): Json<Merge>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    from.sort_unstable();
+    from.dedup();
+    if from.is_empty() || from.contains(&into) {
+        warn!(%eid, %into, "got merge without any other questions");
+        return Err(ApiError::BadRequest);
+    }
+    if from.len() > MAX_MERGE {
+        warn!(%eid, %into, n = from.len(), "rejecting overly large merge");
+        return Err(ApiError::BadRequest);
+    }
+
+    match dynamo.merge(&eid, &into, &from).await {
+        Ok(Some(votes)) => {
+            debug!(%eid, %into, n = from.len(), "merged questions");
+            Ok(Json(
+                serde_json::json!({ "id": into.to_string(), "votes": votes }),
+            ))
+        }
+        Ok(None) => {
+            warn!(%eid, %into, "attempted to merge questions that aren't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, %into, error = %e, "dynamodb request to merge questions failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let mut qids = Vec::new();
+        for _ in 0..3 {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        let _ = crate::vote::vote(
+            Path((qids[1], crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let merge = |into: Ulid, from: Vec<Ulid>| {
+            super::merge(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Json(Merge { into, from }),
+            )
+        };
+
+        // questions from elsewhere can't be merged in
+        assert_eq!(
+            merge(qids[0], vec![qids[1], Ulid::new()])
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            merge(qids[0], vec![qids[0]]).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let r = merge(qids[0], vec![qids[1], qids[2], qids[1]])
+            .await
+            .unwrap();
+        assert_eq!(r["votes"], 4);
+
+        let qs = crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap()
+            .0;
+        let qs = qs.as_array().unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0]["qid"], qids[0].to_string());
+        assert_eq!(qs[0]["votes"], 4);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
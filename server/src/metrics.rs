@@ -0,0 +1,292 @@
+use super::{Backend, Local};
+use axum::{extract::MatchedPath, extract::State, response::IntoResponse};
+use http::{header, Method, Request, Response, StatusCode};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tower::Layer;
+use tower_service::Service;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The upper bounds (in seconds) of the handler latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// The number of observations that fell into each of `BUCKETS` (not cumulative).
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, took: Duration) {
+        let took = took.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|&le| took <= le) {
+            self.buckets[i] += 1;
+        }
+        self.sum += took;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    /// Requests by route, method, and status code.
+    requests: BTreeMap<(String, String, StatusCode), u64>,
+    /// Handler latency by route.
+    latency: BTreeMap<String, Histogram>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    requests: BTreeMap::new(),
+    latency: BTreeMap::new(),
+});
+
+fn record(route: String, method: Method, status: StatusCode, took: Duration) {
+    let mut metrics = METRICS.lock().unwrap();
+    *metrics
+        .requests
+        .entry((route.clone(), method.to_string(), status))
+        .or_default() += 1;
+    metrics.latency.entry(route).or_default().observe(took);
+}
+
+/// Escapes `v` for use as a label value in the Prometheus text format.
+fn label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render(out: &mut String) {
+    let metrics = METRICS.lock().unwrap();
+
+    out.push_str("# HELP http_requests_total Number of requests handled.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for ((route, method, status), n) in &metrics.requests {
+        let _ = writeln!(
+            out,
+            "http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {n}",
+            label(route),
+            method,
+            status.as_u16()
+        );
+    }
+
+    out.push_str("# HELP http_request_duration_seconds Time taken to handle requests.\n");
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    for (route, h) in &metrics.latency {
+        let route = label(route);
+        let mut cumulative = 0;
+        for (le, n) in BUCKETS.iter().zip(h.buckets) {
+            cumulative += n;
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+            h.count
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+            h.sum
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+            h.count
+        );
+    }
+}
+
+/// Records the number and latency of requests per route.
+///
+/// Routes are identified by their pattern (like `/api/event/:eid`) rather than the actual path,
+/// so this must be applied through [`axum::Router::layer`] for [`MatchedPath`] to be available.
+#[derive(Default, Clone, Copy)]
+pub struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or("unmatched", MatchedPath::as_str)
+            .to_string();
+        let method = req.method().clone();
+        let start = Instant::now();
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            record(route, method, res.status(), start.elapsed());
+            Ok(res)
+        })
+    }
+}
+
+/// How a storage engine counts everything it stores.
+pub(super) trait MetricsStore {
+    /// Gives how many events and questions there are in all, or `None` if that isn't cheap to
+    /// find out.
+    fn totals(&self) -> Option<(usize, usize)>;
+}
+
+impl MetricsStore for aws_sdk_dynamodb::Client {
+    fn totals(&self) -> Option<(usize, usize)> {
+        // there's no cheap way to count everything in dynamodb.
+        None
+    }
+}
+
+impl MetricsStore for Mutex<Local> {
+    fn totals(&self) -> Option<(usize, usize)> {
+        let local = super::lock(self);
+        Some((local.events.len(), local.questions.len()))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl MetricsStore for crate::redis::Redis {
+    fn totals(&self) -> Option<(usize, usize)> {
+        // counting would mean scanning every key.
+        None
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl MetricsStore for crate::sqlite::Sqlite {
+    fn totals(&self) -> Option<(usize, usize)> {
+        let count = |con: &rusqlite::Connection, table| {
+            con.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+        };
+        self.run(|con| Ok((count(con, "events")?, count(con, "questions")?)))
+            .map_err(|e| error!(error = %e, "sqlite request to count events failed"))
+            .ok()
+    }
+}
+
+pub(super) async fn metrics(State(dynamo): State<Backend>) -> impl IntoResponse {
+    let mut out = String::new();
+    render(&mut out);
+
+    if let Some((events, questions)) = dynamo.totals() {
+        out.push_str("# HELP wewerewondering_events Number of events.\n");
+        out.push_str("# TYPE wewerewondering_events gauge\n");
+        let _ = writeln!(out, "wewerewondering_events {events}");
+        out.push_str("# HELP wewerewondering_questions Number of questions across all events.\n");
+        out.push_str("# TYPE wewerewondering_questions gauge\n");
+        let _ = writeln!(out, "wewerewondering_questions {questions}");
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; version=0.0.4"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn local() {
+        let backend = crate::local_backend().await;
+        let app = Router::new()
+            .route("/test/metrics/:id", get(|| async { "hi" }))
+            .layer(MetricsLayer)
+            .route("/test/metrics", get(super::metrics))
+            .with_state(backend.clone());
+
+        for id in ["a", "b"] {
+            let res = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/test/metrics/{id}"))
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let scrape = || async {
+            let res = app
+                .clone()
+                .oneshot(
+                    Request::get("/test/metrics")
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+        let text = scrape().await;
+        assert!(text.contains(
+            "http_requests_total{route=\"/test/metrics/:id\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(
+            text.contains("http_request_duration_seconds_count{route=\"/test/metrics/:id\"} 2\n")
+        );
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{route=\"/test/metrics/:id\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text.contains("wewerewondering_events "));
+
+        // scraping isn't itself counted
+        assert!(!scrape().await.contains("route=\"/test/metrics\""));
+    }
+}
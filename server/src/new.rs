@@ -1,130 +1,439 @@
 use crate::to_dynamo_timestamp;
 
 use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::PutItemError, model::AttributeValue, output::PutItemOutput, types::SdkError,
-};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
 use axum::extract::State;
-use axum::response::Json;
-use http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use http::Request;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::time::{Duration, SystemTime};
+use subtle::ConstantTimeEq;
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-const EVENTS_EXPIRE_AFTER_DAYS: u64 = 60;
+const DEFAULT_EVENT_TTL_DAYS: u64 = 30;
 
-impl Backend {
-    pub(super) async fn new(
-        &self,
-        eid: &Ulid,
-        secret: impl Into<String>,
-    ) -> Result<PutItemOutput, SdkError<PutItemError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                dynamo
-                    .put_item()
-                    .table_name("events")
-                    .item("id", AttributeValue::S(eid.to_string()))
-                    .item("secret", AttributeValue::S(secret.into()))
-                    .item("when", to_dynamo_timestamp(SystemTime::now()))
-                    .item(
-                        "expire",
-                        to_dynamo_timestamp(
-                            SystemTime::now()
-                                + Duration::from_secs(EVENTS_EXPIRE_AFTER_DAYS * 24 * 60 * 60),
-                        ),
-                    )
-                    .send()
-                    .await
+/// Returns how long newly created events live for, as configured through `EVENT_TTL_DAYS`.
+///
+/// Panics if `EVENT_TTL_DAYS` is set but isn't a whole number of days.
+pub(super) fn event_ttl() -> Duration {
+    static TTL: OnceLock<Duration> = OnceLock::new();
+    *TTL.get_or_init(|| {
+        let days = match std::env::var("EVENT_TTL_DAYS") {
+            Ok(days) => days
+                .parse()
+                .expect("EVENT_TTL_DAYS must be a whole number of days"),
+            Err(_) => DEFAULT_EVENT_TTL_DAYS,
+        };
+        Duration::from_secs(days * 24 * 60 * 60)
+    })
+}
+
+/// Returns the API keys that events can be created with, as configured through the
+/// comma-separated `CREATE_API_KEYS`.
+///
+/// `None` means anyone can create events, which is the default. Panics if `CREATE_API_KEYS` is set
+/// but doesn't have any keys in it, since that can only be a mistake.
+pub(super) fn api_keys() -> Option<&'static [String]> {
+    static KEYS: OnceLock<Option<Vec<String>>> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let keys = parse_api_keys(&std::env::var("CREATE_API_KEYS").ok()?);
+        assert!(
+            !keys.is_empty(),
+            "CREATE_API_KEYS must be a comma-separated list of keys"
+        );
+        Some(keys)
+    })
+    .as_deref()
+}
+
+fn parse_api_keys(keys: &str) -> Vec<String> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether `key` is one of `keys`, without leaking how much of it matched.
+fn admits(keys: &[String], key: Option<&str>) -> bool {
+    let Some(key) = key else {
+        return false;
+    };
+    keys.iter()
+        .any(|k| bool::from(k.as_bytes().ct_eq(key.as_bytes())))
+}
+
+/// Turns away requests to create events (or clone them) that don't have a known `X-Api-Key`, if
+/// [`CREATE_API_KEYS`](api_keys) says only some may.
+pub(super) async fn require_api_key<B>(req: Request<B>, next: Next<B>) -> Response {
+    let Some(keys) = api_keys() else {
+        return next.run(req).await;
+    };
+    let key = req.headers().get("x-api-key").and_then(|v| v.to_str().ok());
+    if admits(keys, key) {
+        next.run(req).await
+    } else {
+        warn!(
+            has_key = key.is_some(),
+            "rejecting event creation without a valid api key"
+        );
+        ApiError::ForbiddenApiKey.into_response()
+    }
+}
+
+/// The longest event title we accept, in characters.
+const MAX_TITLE_LEN: usize = 100;
+
+/// The longest event description we accept, in characters.
+const MAX_DESCRIPTION_LEN: usize = 500;
+
+/// Optional information about an event supplied by the host when creating it.
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct Meta {
+    pub(super) title: Option<String>,
+    pub(super) description: Option<String>,
+    /// Whether new questions have to be approved by the host before guests can see them.
+    #[serde(default)]
+    pub(super) moderated: bool,
+    /// A lower cap on the number of questions than the server-wide one.
+    pub(super) max_questions: Option<usize>,
+    /// How many up-votes each guest gets to hand out, if they're limited.
+    pub(super) vote_budget: Option<u64>,
+    /// Where to POST every new question.
+    pub(super) webhook_url: Option<String>,
+    /// Whether guests can down-vote questions, which they can unless the host says otherwise.
+    pub(super) downvotes_enabled: Option<bool>,
+    /// Whether guests only see the questions, and not how many votes they have.
+    #[serde(default)]
+    pub(super) hide_counts: bool,
+}
+
+impl Meta {
+    /// Tidies up the host-provided fields and makes sure they're of a reasonable size.
+    ///
+    /// The title and description are shown to every guest, so they're [tidied] the same way
+    /// question text is. Titles are kept to a single line.
+    ///
+    /// [tidied]: crate::ask::tidy_text
+    fn clean(self) -> Result<Self, ApiError> {
+        let clean = |field: &str, v: Option<String>, max: usize| {
+            let Some(v) = v else {
+                return Ok(None);
+            };
+            let v = crate::ask::tidy_text(&v);
+            let v = if field == "title" {
+                v.replace('\n', " ")
+            } else {
+                v
+            };
+            if v.chars().count() > max {
+                warn!(field, len = v.len(), "rejecting overly long event {field}");
+                Err(ApiError::BadRequest)
+            } else if v.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(v))
             }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local {
-                    events,
-                    questions_by_eid,
-                    ..
-                } = &mut *local;
-
-                questions_by_eid.insert(eid.clone(), Vec::new());
-                if events.insert(eid.clone(), secret.into()).is_some() {
-                    Ok(PutItemOutput::builder().build())
-                } else {
-                    Ok(PutItemOutput::builder().build())
-                }
+        };
+        // events can't raise the limit, since anyone can create one.
+        if let Some(max) = self.max_questions {
+            if max > crate::ask::max_questions_per_event() {
+                warn!(max, "rejecting event with overly high question limit");
+                return Err(ApiError::BadRequest);
             }
         }
+        let webhook_url = self.webhook_url.map(|url| url.trim().to_string());
+        if let Some(url) = &webhook_url {
+            crate::webhook::check(url)?;
+        }
+        if self.vote_budget == Some(0) {
+            warn!("rejecting event without any votes to hand out");
+            return Err(ApiError::BadRequest);
+        }
+        Ok(Self {
+            title: clean("title", self.title, MAX_TITLE_LEN)?,
+            description: clean("description", self.description, MAX_DESCRIPTION_LEN)?,
+            moderated: self.moderated,
+            max_questions: self.max_questions,
+            vote_budget: self.vote_budget,
+            webhook_url,
+            downvotes_enabled: self.downvotes_enabled,
+            hide_counts: self.hide_counts,
+        })
+    }
+}
+
+/// Gives the attributes a new event `eid` is stored with.
+fn attributes(
+    eid: &Ulid,
+    secret_hash: String,
+    meta: Meta,
+    code: Option<&str>,
+    expire: SystemTime,
+) -> Vec<(&'static str, Attr)> {
+    let mut attrs = vec![
+        ("id", Attr::S(eid.to_string())),
+        ("secret", Attr::S(secret_hash)),
+        ("when", to_dynamo_timestamp(SystemTime::now())),
+        ("expire", to_dynamo_timestamp(expire)),
+        ("vote_salt", Attr::S(crate::dedup::generate_salt())),
+    ];
+    if let Some(code) = code {
+        attrs.push(("code", Attr::S(code.to_string())));
+    }
+    if let Some(title) = meta.title {
+        attrs.push(("title", Attr::S(title)));
+    }
+    if let Some(description) = meta.description {
+        attrs.push(("description", Attr::S(description)));
+    }
+    if meta.moderated {
+        attrs.push(("moderated", Attr::Bool(true)));
+    }
+    if let Some(max) = meta.max_questions {
+        attrs.push(("max_questions", Attr::N(max.to_string())));
+    }
+    if let Some(budget) = meta.vote_budget {
+        attrs.push(("vote_budget", Attr::N(budget.to_string())));
+    }
+    if let Some(url) = meta.webhook_url {
+        attrs.push(("webhook_url", Attr::S(url)));
+    }
+    // stored the other way around, so that events from before this was an option allow them.
+    if meta.downvotes_enabled == Some(false) {
+        attrs.push(("downvotes_disabled", Attr::Bool(true)));
+    }
+    if meta.hide_counts {
+        attrs.push(("hide_counts", Attr::Bool(true)));
+    }
+    attrs
+}
+
+/// How a storage engine stores newly created events.
+#[async_trait]
+pub(super) trait NewStore {
+    /// Stores the new event `eid`, whose secret hashes to `secret_hash`.
+    #[allow(clippy::new_ret_no_self, clippy::wrong_self_convention)]
+    async fn new(
+        &self,
+        eid: &Ulid,
+        secret_hash: String,
+        meta: Meta,
+        code: Option<&str>,
+        expire: SystemTime,
+    ) -> Result<(), StoreError>;
+
+    #[cfg(test)]
+    async fn delete(&self, eid: &Ulid);
+}
+
+#[async_trait]
+impl NewStore for aws_sdk_dynamodb::Client {
+    async fn new(
+        &self,
+        eid: &Ulid,
+        secret_hash: String,
+        meta: Meta,
+        code: Option<&str>,
+        expire: SystemTime,
+    ) -> Result<(), StoreError> {
+        let mut r = self.put_item().table_name("events");
+        for (k, v) in attributes(eid, secret_hash, meta, code, expire) {
+            r = r.item(k, v.into());
+        }
+        super::retry::retry(|| r.clone().send()).await?;
+        Ok(())
     }
 
     #[cfg(test)]
-    pub(super) async fn delete(&self, eid: &Ulid) {
-        let qs = self.list(eid, false).await.unwrap();
+    async fn delete(&self, eid: &Ulid) {
+        use crate::list::ListStore;
+        use aws_sdk_dynamodb::model::AttributeValue;
+
+        let qs = self
+            .list(eid, true, &Default::default(), None, None)
+            .await
+            .unwrap();
         let qids: Vec<_> = qs
-            .items()
-            .into_iter()
-            .flat_map(|qs| qs.iter().filter_map(|doc| doc["id"].as_s().ok()))
+            .items
+            .iter()
+            .filter_map(|doc| doc["id"].as_s().ok())
             .cloned()
             .collect();
+        for qid in qids {
+            self.delete_item()
+                .table_name("questions")
+                .key("id", AttributeValue::S(qid))
+                .send()
+                .await
+                .unwrap();
+        }
+        self.delete_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .send()
+            .await
+            .unwrap();
+    }
+}
 
-        match self {
-            Self::Dynamo(dynamo) => {
-                for qid in qids {
-                    dynamo
-                        .delete_item()
-                        .table_name("questions")
-                        .key("id", AttributeValue::S(qid))
-                        .send()
-                        .await
-                        .unwrap();
-                }
-                dynamo
-                    .delete_item()
-                    .table_name("events")
-                    .key("id", AttributeValue::S(eid.to_string()))
-                    .send()
-                    .await
-                    .unwrap();
-            }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local {
-                    events,
-                    questions,
-                    questions_by_eid,
-                    ..
-                } = &mut *local;
-
-                for qid in questions_by_eid.remove(&eid).unwrap() {
-                    questions.remove(&qid).unwrap();
-                }
-                events.remove(&eid).unwrap();
-            }
+#[async_trait]
+impl NewStore for Mutex<Local> {
+    async fn new(
+        &self,
+        eid: &Ulid,
+        secret_hash: String,
+        meta: Meta,
+        code: Option<&str>,
+        expire: SystemTime,
+    ) -> Result<(), StoreError> {
+        let attrs = attributes(eid, secret_hash, meta, code, expire);
+        let mut local = super::lock(self);
+        let Local {
+            events,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        questions_by_eid.insert(*eid, Vec::new());
+        events.insert(*eid, HashMap::from_iter(attrs));
+        Ok(())
+    }
+
+    #[cfg(test)]
+    async fn delete(&self, eid: &Ulid) {
+        let mut local = super::lock(self);
+        let Local {
+            events,
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        for qid in questions_by_eid.remove(eid).unwrap() {
+            questions.remove(&qid).unwrap();
         }
+        events.remove(eid).unwrap();
     }
 }
 
-pub(super) async fn new(
-    State(dynamo): State<Backend>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let eid = ulid::Ulid::new();
-    let secret: String = thread_rng()
+#[cfg(feature = "redis")]
+#[async_trait]
+impl NewStore for crate::redis::Redis {
+    async fn new(
+        &self,
+        eid: &Ulid,
+        secret_hash: String,
+        meta: Meta,
+        code: Option<&str>,
+        expire: SystemTime,
+    ) -> Result<(), StoreError> {
+        let attrs = attributes(eid, secret_hash, meta, code, expire);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        crate::redis::put(
+            &mut pipe,
+            &crate::redis::event_key(eid),
+            attrs.iter().map(|(k, v)| (*k, v)),
+        )?;
+        self.run::<()>(&pipe).await
+    }
+
+    #[cfg(test)]
+    async fn delete(&self, eid: &Ulid) {
+        crate::destroy::DestroyStore::destroy(self, eid)
+            .await
+            .unwrap();
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl NewStore for crate::sqlite::Sqlite {
+    async fn new(
+        &self,
+        eid: &Ulid,
+        secret_hash: String,
+        meta: Meta,
+        code: Option<&str>,
+        expire: SystemTime,
+    ) -> Result<(), StoreError> {
+        let attrs = attributes(eid, secret_hash, meta, code, expire);
+        self.run(|con| {
+            crate::sqlite::insert(
+                con,
+                crate::sqlite::Table::Events,
+                attrs.iter().map(|(k, v)| (*k, v)),
+            )
+        })
+    }
+
+    #[cfg(test)]
+    async fn delete(&self, eid: &Ulid) {
+        crate::destroy::DestroyStore::destroy(self, eid)
+            .await
+            .unwrap();
+    }
+}
+
+/// Makes up a new secret for an event.
+pub(super) fn generate_secret() -> String {
+    thread_rng()
         .sample_iter(&Alphanumeric)
         .take(30)
         .map(char::from)
-        .collect();
-    match dynamo.new(&eid, &secret).await {
+        .collect()
+}
+
+pub(super) async fn new(
+    State(dynamo): State<Backend>,
+    body: String,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    // the body is optional, so we can't just use the Json extractor
+    let meta = if body.trim().is_empty() {
+        Meta::default()
+    } else {
+        match serde_json::from_str::<Meta>(&body) {
+            Ok(meta) => meta.clean()?,
+            Err(e) => {
+                warn!(error = %e, "got invalid event metadata");
+                return Err(ApiError::BadRequest);
+            }
+        }
+    };
+
+    let eid = ulid::Ulid::new();
+    let secret = generate_secret();
+    let expire = SystemTime::now() + event_ttl();
+    // the code is claimed first so that the event never exists without the code it says it has.
+    let code = crate::code::assign(&dynamo, &eid, expire).await?;
+    // only the host gets to see the secret, and only this once
+    let hash = super::hash_secret(&secret);
+    let token = crate::hosttoken::issue(&eid, &hash);
+    match dynamo.new(&eid, hash, meta, Some(&code), expire).await {
         Ok(_) => {
-            debug!(%eid, "created event");
-            Ok(Json(
-                serde_json::json!({ "id": eid.to_string(), "secret": secret }),
-            ))
+            debug!(%eid, code, "created event");
+            let mut e =
+                serde_json::json!({ "id": eid.to_string(), "secret": secret, "code": code });
+            if let Some(token) = token {
+                e["token"] = token.into();
+            }
+            Ok(Json(e))
         }
         Err(e) => {
             error!(%eid, error = %e, "dynamodb request to create event failed");
-            Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal)
         }
     }
 }
@@ -132,22 +441,149 @@ pub(super) async fn new(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use http::StatusCode;
+
+    #[test]
+    fn api_key() {
+        let keys = parse_api_keys(" alpha, ,beta,");
+        assert_eq!(keys, ["alpha", "beta"]);
+        assert!(admits(&keys, Some("beta")));
+        assert!(!admits(&keys, Some("alph")));
+        assert!(!admits(&keys, Some("")));
+        assert!(!admits(&keys, None));
+        assert!(parse_api_keys(",").is_empty());
+    }
+
+    #[test]
+    fn markup() {
+        let meta = Meta {
+            title: Some("  <b>Town</b> hall\n  meeting ".into()),
+            description: Some("<script>alert(1)</script>bring  snacks\n\n<i>please</i>".into()),
+            ..Default::default()
+        }
+        .clean()
+        .unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Town hall meeting"));
+        assert_eq!(
+            meta.description.as_deref(),
+            Some("alert(1)bring snacks\nplease")
+        );
+
+        // a title that's all markup is no title at all
+        let meta = Meta {
+            title: Some("<img src=x onerror=alert(1)>".into()),
+            ..Default::default()
+        }
+        .clean()
+        .unwrap();
+        assert_eq!(meta.title, None);
+    }
 
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
-        let _secret = e["secret"].as_str().unwrap();
+        let secret = e["secret"].as_str().unwrap();
+
+        // the secret itself is never stored
+        let stored = backend.get_secret(&eid).await.unwrap();
+        assert_ne!(stored, secret);
+        crate::check_secret(&backend, &eid, secret).await.unwrap();
+        assert_eq!(
+            crate::check_secret(&backend, &eid, &stored)
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
         backend.delete(&eid).await;
+
+        // but events from before secrets were hashed still work
+        let eid = Ulid::new();
+        backend
+            .new(
+                &eid,
+                "plain".into(),
+                Meta::default(),
+                None,
+                SystemTime::now() + event_ttl(),
+            )
+            .await
+            .unwrap();
+        crate::check_secret(&backend, &eid, "plain").await.unwrap();
+        backend.delete(&eid).await;
+
+        // webhooks have to be somewhere we can reach
+        assert_eq!(
+            crate::new::new(
+                State(backend.clone()),
+                serde_json::json!({ "webhook_url": "not a url" }).to_string(),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
     }
 
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_expired() {
+        let (backend, local) = crate::local_state();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let _ = crate::ask::ask(
+            axum::extract::Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        crate::lock(&local)
+            .events
+            .get_mut(&eid)
+            .unwrap()
+            .insert("expire", Attr::N(0.to_string()));
+
+        assert_eq!(
+            crate::check_secret(&backend, &eid, secret)
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        let local = crate::lock(&local);
+        assert!(!local.events.contains_key(&eid));
+        assert!(!local.questions_by_eid.contains_key(&eid));
+        assert!(local.questions.is_empty());
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
     }
 }
@@ -0,0 +1,273 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, ReturnValue};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Deserialize, Debug)]
+pub(super) struct Note {
+    pub(super) note: String,
+}
+
+/// How a storage engine keeps the host's private notes on questions.
+#[async_trait]
+pub(super) trait NoteStore {
+    /// Sets (or, if `note` is `None`, clears) the host's private note on `qid`.
+    ///
+    /// Gives the question as it is now, and fails with [`StoreError::ConditionFailed`] if the
+    /// question does not exist in the event `eid`.
+    async fn note(&self, eid: &Ulid, qid: &Ulid, note: Option<String>) -> Result<Item, StoreError>;
+}
+
+#[async_trait]
+impl NoteStore for aws_sdk_dynamodb::Client {
+    async fn note(&self, eid: &Ulid, qid: &Ulid, note: Option<String>) -> Result<Item, StoreError> {
+        let q = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .condition_expression("eid = :eid")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .expression_attribute_values(":updated", crate::sync::now());
+
+        let q = if let Some(note) = note {
+            q.update_expression("SET note = :note, updated_at = :updated")
+                .expression_attribute_values(":note", AttributeValue::S(note))
+        } else {
+            q.update_expression("REMOVE note SET updated_at = :updated")
+        };
+        let q = q.return_values(ReturnValue::AllNew);
+        let q = super::retry::retry(|| q.clone().send()).await?;
+        Ok(store::from_dynamo(q.attributes.unwrap_or_default()))
+    }
+}
+
+#[async_trait]
+impl NoteStore for Mutex<Local> {
+    async fn note(&self, eid: &Ulid, qid: &Ulid, note: Option<String>) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let q = questions_by_eid
+            .get(eid)
+            .filter(|qs| qs.contains(qid))
+            .and_then(|_| questions.get_mut(qid));
+        let Some(q) = q else {
+            return Err(StoreError::ConditionFailed);
+        };
+        if let Some(note) = note {
+            q.insert("note", Attr::S(note));
+        } else {
+            q.remove("note");
+        }
+        crate::sync::touch(q);
+        // NOTE: not published, since guests are listening on the same feed.
+        Ok(store::from_local(q))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl NoteStore for crate::redis::Redis {
+    async fn note(&self, eid: &Ulid, qid: &Ulid, note: Option<String>) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if !store::belongs_to(q, eid) {
+                    return Err(StoreError::ConditionFailed);
+                }
+                if let Some(note) = &note {
+                    q.insert("note".into(), Attr::S(note.clone()));
+                } else {
+                    q.remove("note");
+                }
+                crate::sync::touch(q);
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl NoteStore for crate::sqlite::Sqlite {
+    async fn note(&self, eid: &Ulid, qid: &Ulid, note: Option<String>) -> Result<Item, StoreError> {
+        let (q, ()) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if !store::belongs_to(q, eid) {
+                return Err(StoreError::ConditionFailed);
+            }
+            if let Some(note) = note {
+                q.insert("note".into(), Attr::S(note));
+            } else {
+                q.remove("note");
+            }
+            crate::sync::touch(q);
+            Ok(())
+        })?;
+        Ok(q)
+    }
+}
+
+/// Lets the host keep a note on a question that only they can see.
+pub(super) async fn note(
+    Path((eid, secret, qid)): Path<(Ulid, String, Ulid)>,
+    State(dynamo): State<Backend>,
+    body: String,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    // an empty body (or an empty note) clears the note
+    let note = if body.trim().is_empty() {
+        None
+    } else {
+        match serde_json::from_str::<Note>(&body) {
+            Ok(n) if n.note.trim().is_empty() => None,
+            Ok(n) => Some(n.note.trim().to_string()),
+            Err(e) => {
+                warn!(%eid, %qid, error = %e, "got invalid note body");
+                return Err(ApiError::BadRequest);
+            }
+        }
+    };
+
+    match dynamo.note(&eid, &qid, note).await {
+        Ok(q) => {
+            debug!(%eid, %qid, "noted question");
+            let mut v = serde_json::json!({});
+            if let Some(note) = q.get("note").and_then(|v| v.as_s().ok()) {
+                v["note"] = note.clone().into();
+            }
+            Ok(Json(v))
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%eid, %qid, "attempted to note question that isn't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to note question failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        let note = |secret: &str, body: &str| {
+            super::note(
+                Path((eid, secret.to_string(), qid)),
+                State(backend.clone()),
+                body.to_string(),
+            )
+        };
+        let listed = |secret: Option<&str>| {
+            let backend = backend.clone();
+            let secret = secret.map(String::from);
+            async move {
+                let qs = match secret {
+                    Some(secret) => {
+                        crate::list::list_all(
+                            Path((eid, secret)),
+                            State(backend),
+                            Query(Default::default()),
+                        )
+                        .await
+                    }
+                    None => {
+                        crate::list::list(Path(eid), State(backend), Query(Default::default()))
+                            .await
+                    }
+                };
+                qs.1.unwrap()[0].clone()
+            }
+        };
+
+        assert_eq!(
+            note("wrong", r#"{"note": "hi"}"#).await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            note(secret, "not json").await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let n = note(secret, r#"{"note": " ask during Q2 "}"#)
+            .await
+            .unwrap();
+        assert_eq!(n["note"], "ask during Q2");
+        assert_eq!(listed(Some(secret)).await["note"], "ask during Q2");
+
+        // guests never get to see it
+        assert_eq!(listed(None).await.get("note"), None);
+        let qs = crate::questions::questions(Path(qid.to_string()), State(backend.clone()))
+            .await
+            .1
+            .unwrap();
+        assert_eq!(qs[qid.to_string()].get("note"), None);
+
+        // an empty body clears it again
+        let n = note(secret, "").await.unwrap();
+        assert_eq!(n.0, serde_json::json!({}));
+        assert_eq!(listed(Some(secret)).await.get("note"), None);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,1030 @@
+use axum::{response::AppendHeaders, Json};
+use http::header::{self, HeaderName};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+fn path_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn query_param(name: &str, description: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": schema,
+    })
+}
+
+fn if_match() -> Value {
+    json!({
+        "name": "If-Match",
+        "in": "header",
+        "required": false,
+        "description": "Only make the change if the question is still at this version.",
+        "schema": { "type": "string" },
+    })
+}
+
+fn api_key() -> Value {
+    json!({
+        "name": "X-Api-Key",
+        "in": "header",
+        "required": false,
+        "description": "Needed if the server only lets some clients create events.",
+        "schema": { "type": "string" },
+    })
+}
+
+fn eid() -> Value {
+    path_param("eid", "The event id, or its short code.")
+}
+
+fn secret() -> Value {
+    path_param(
+        "secret",
+        "The event's secret, which only the host knows. Hosts with a token send it as \
+         `Authorization: Bearer <token>` instead, and put `token` here.",
+    )
+}
+
+fn qid() -> Value {
+    path_param("qid", "The question id.")
+}
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({ "required": true, "content": { "application/json": { "schema": schema } } })
+}
+
+fn ok(description: &str, schema: Value) -> Value {
+    json!({ "description": description, "content": { "application/json": { "schema": schema } } })
+}
+
+fn status(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+fn error(description: &str) -> Value {
+    ok(description, schema("Error"))
+}
+
+/// What every host-only endpoint may fail with, on top of its own responses.
+fn host_responses(mut responses: Value) -> Value {
+    responses["401"] = error("The secret is wrong.");
+    responses["404"] = error("The event (or question) doesn't exist.");
+    responses
+}
+
+fn spec() -> Value {
+    let property =
+        json!({ "type": "string", "enum": ["hidden", "answered", "pinned", "approved"] });
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "wewerewondering",
+            "description": "Ask questions at events, and vote on what others have asked.\n\n\
+                            Every path is also served under `/api/v1` (so `/api/event` is \
+                            `/api/v1/event`), which is where new clients should go. The paths \
+                            without a version are an alias for `/api/v1` that will go away.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/event": {
+                "post": {
+                    "summary": "Create an event",
+                    "parameters": [api_key()],
+                    "requestBody": {
+                        "required": false,
+                        "content": { "application/json": { "schema": schema("Meta") } },
+                    },
+                    "responses": {
+                        "200": ok("The new event, and the secret the host uses to manage it.", json!({
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "secret": { "type": "string" },
+                                "token": {
+                                    "type": "string",
+                                    "description": "A host token that can stand in for the secret, \
+                                                    if the server issues them.",
+                                },
+                                "code": {
+                                    "type": "string",
+                                    "description": "A short code that works in place of the id.",
+                                },
+                            },
+                        })),
+                        "400": error("The metadata is invalid."),
+                        "401": error("The server needs an API key, and this one is missing or wrong."),
+                    },
+                },
+            },
+            "/api/event/{eid}": {
+                "get": {
+                    "summary": "Check that an event exists",
+                    "parameters": [eid()],
+                    "responses": {
+                        "200": ok("The event exists.", json!({ "type": "object" })),
+                        "304": status("The event hasn't changed since the date in If-Modified-Since."),
+                        "404": error("The event doesn't exist."),
+                    },
+                },
+                "post": {
+                    "summary": "Ask a question",
+                    "parameters": [
+                        eid(),
+                        {
+                            "name": "Idempotency-Key",
+                            "in": "header",
+                            "description": "Retries with the same key get the same question back.",
+                            "schema": { "type": "string", "maxLength": 128 },
+                        },
+                    ],
+                    "requestBody": json_body(json!({
+                        "type": "object",
+                        "required": ["body"],
+                        "properties": {
+                            "body": { "type": "string" },
+                            "asker": { "type": "string", "maxLength": 64 },
+                            "tags": { "type": "array", "items": { "type": "string" } },
+                        },
+                    })),
+                    "responses": {
+                        "200": ok("The question was asked.", json!({
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "version": { "type": "integer" },
+                            },
+                        })),
+                        "400": error("The question (or its tags) aren't acceptable; questions that are too long say how long they can be in `max`."),
+                        "403": error("The event has as many questions as it can take."),
+                        "404": error("The event doesn't exist."),
+                        "409": error("The event has been archived, or the idempotency key was used for a different question."),
+                        "423": error("The host has stopped taking new questions."),
+                        "429": error("The client is asking too often."),
+                    },
+                },
+            },
+            "/api/e/{code}": {
+                "get": {
+                    "summary": "Look up which event a short code is for",
+                    "parameters": [path_param("code", "The event's short code, in any case.")],
+                    "responses": {
+                        "200": ok("The event's id.", json!({
+                            "type": "object",
+                            "properties": { "id": { "type": "string" } },
+                        })),
+                        "404": error("No event has that code."),
+                    },
+                },
+            },
+            "/api/event/{eid}/meta": {
+                "get": {
+                    "summary": "Get an event's title and description",
+                    "parameters": [eid()],
+                    "responses": {
+                        "200": ok("The event's metadata.", schema("Meta")),
+                        "404": error("The event doesn't exist."),
+                    },
+                },
+            },
+            "/api/event/{eid}/questions": {
+                "get": {
+                    "summary": "List the visible questions of an event",
+                    "description": "Without `limit`, `cursor`, or `offset`, all questions are returned as an array. \
+                                    Offset pages also come with the total number of questions in `X-Total-Count`.",
+                    "parameters": [
+                        eid(),
+                        query_param("limit", "Page size.", json!({ "type": "integer" })),
+                        query_param("cursor", "Where the previous page left off.", json!({ "type": "string" })),
+                        query_param("offset", "How many questions to skip (at most 1000), instead of a cursor.", json!({ "type": "integer" })),
+                        query_param("since", "Only what changed at or after this time (in seconds since the epoch). Can't be paged.", json!({ "type": "integer" })),
+                        query_param("sort", "Question order.", json!({ "type": "string", "enum": ["votes", "newest", "oldest", "order"] })),
+                        query_param("tag", "Only questions with this tag.", json!({ "type": "string" })),
+                    ],
+                    "responses": {
+                        "200": ok("The questions.", schema("QuestionList")),
+                        "304": status("The questions haven't changed since the ETag in If-None-Match."),
+                        "400": error("The page parameters are invalid."),
+                        "404": error("The event doesn't exist."),
+                    },
+                },
+            },
+            "/api/event/{eid}/search": {
+                "get": {
+                    "summary": "Find visible questions that look like the query, best match first",
+                    "parameters": [
+                        eid(),
+                        {
+                            "name": "q",
+                            "in": "query",
+                            "required": true,
+                            "description": "What to look for.",
+                            "schema": { "type": "string" },
+                        },
+                        query_param("limit", "How many matches to return (at most 50).", json!({ "type": "integer" })),
+                    ],
+                    "responses": {
+                        "200": ok("The matches.", json!({
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "qid": { "type": "string" },
+                                    "text": { "type": "string" },
+                                    "score": { "type": "number", "description": "From 0 to 1, where 1 means the text contains the query." },
+                                },
+                            },
+                        })),
+                        "400": error("The query is empty."),
+                        "404": error("The event doesn't exist."),
+                    },
+                },
+            },
+            "/api/event/{eid}/stream": {
+                "get": {
+                    "summary": "Subscribe to live updates as server-sent events",
+                    "parameters": [eid()],
+                    "responses": {
+                        "200": { "description": "A stream of updates.", "content": { "text/event-stream": {} } },
+                        "404": error("The event doesn't exist."),
+                        "501": error("The backend doesn't support live updates."),
+                    },
+                },
+            },
+            "/api/event/{eid}/ws": {
+                "get": {
+                    "summary": "Subscribe to live updates, and vote, over a websocket",
+                    "parameters": [eid()],
+                    "responses": {
+                        "101": status("Switching to the websocket protocol."),
+                        "404": error("The event doesn't exist."),
+                        "501": error("The backend doesn't support live updates."),
+                    },
+                },
+            },
+            "/api/event/{eid}/presence": {
+                "get": {
+                    "summary": "Count the clients watching an event's live updates",
+                    "parameters": [eid()],
+                    "responses": {
+                        "200": ok("How many clients are connected.", json!({
+                            "type": "object",
+                            "properties": { "viewers": { "type": "integer" } },
+                        })),
+                        "404": error("The event doesn't exist."),
+                        "501": error("The backend doesn't support live updates."),
+                    },
+                },
+            },
+            "/api/event/{eid}/questions/{secret}": {
+                "get": {
+                    "summary": "List all questions of an event, hidden ones included",
+                    "parameters": [
+                        eid(),
+                        secret(),
+                        query_param("limit", "Page size.", json!({ "type": "integer" })),
+                        query_param("cursor", "Where the previous page left off.", json!({ "type": "string" })),
+                        query_param("offset", "How many questions to skip (at most 1000), instead of a cursor.", json!({ "type": "integer" })),
+                        query_param("since", "Only what changed at or after this time (in seconds since the epoch). Can't be paged.", json!({ "type": "integer" })),
+                        query_param("sort", "Question order.", json!({ "type": "string", "enum": ["votes", "newest", "oldest", "order"] })),
+                        query_param("tag", "Only questions with this tag.", json!({ "type": "string" })),
+                        query_param("answered", "Only (un)answered questions.", json!({ "type": "boolean" })),
+                        query_param("hidden", "Only (un)hidden questions.", json!({ "type": "boolean" })),
+                        query_param("with_event", "Return `{questions, event}` with the event's metadata, even when not paged.", json!({ "type": "boolean" })),
+                    ],
+                    "responses": host_responses(json!({
+                        "200": {
+                            "description": "The questions, as CSV if that's what the Accept header asks for.",
+                            "content": {
+                                "application/json": { "schema": schema("QuestionList") },
+                                "text/csv": {},
+                            },
+                        },
+                        "304": status("The questions haven't changed since the ETag in If-None-Match."),
+                        "400": error("The page parameters are invalid."),
+                    })),
+                },
+                "delete": {
+                    "summary": "Delete an event and all its questions",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({ "204": status("The event is gone.") })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/votes/timeseries": {
+                "get": {
+                    "summary": "Count the votes that came in over time",
+                    "description": "Only available when the server runs with `VOTE_TIMESERIES=on`.",
+                    "parameters": [
+                        eid(),
+                        secret(),
+                        query_param("bucket", "How many seconds each bucket covers (60 by default).", json!({ "type": "integer" })),
+                    ],
+                    "responses": host_responses(json!({
+                        "200": ok("The buckets that got votes, oldest first.", json!({
+                            "type": "object",
+                            "properties": {
+                                "bucket": { "type": "integer" },
+                                "series": {
+                                    "type": "array",
+                                    "items": {
+                                        "type": "object",
+                                        "properties": {
+                                            "start": { "type": "integer" },
+                                            "up": { "type": "integer" },
+                                            "down": { "type": "integer" },
+                                        },
+                                    },
+                                },
+                            },
+                        })),
+                        "400": error("The bucket size is zero."),
+                        "501": error("Votes aren't being logged."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/stats": {
+                "get": {
+                    "summary": "Summarize an event's questions",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({
+                        "200": ok("The summary.", json!({
+                            "type": "object",
+                            "properties": {
+                                "questions": { "type": "integer" },
+                                "answered": { "type": "integer" },
+                                "hidden": { "type": "integer" },
+                                "votes": { "type": "integer" },
+                                "top": { "type": "string", "nullable": true },
+                            },
+                        })),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/next-random": {
+                "get": {
+                    "summary": "Pick a random question that hasn't been answered or hidden",
+                    "parameters": [
+                        eid(),
+                        secret(),
+                        query_param("weighted", "Make each question's chance proportional to its votes.", json!({ "type": "boolean" })),
+                    ],
+                    "responses": host_responses(json!({
+                        "200": ok("The picked question.", json!({
+                            "type": "object",
+                            "properties": {
+                                "qid": { "type": "string" },
+                                "votes": { "type": "integer" },
+                            },
+                        })),
+                        "204": status("There are no questions left to pick from."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/toggle-bulk": {
+                "post": {
+                    "summary": "Toggle a property of many questions",
+                    "parameters": [eid(), secret()],
+                    "requestBody": json_body(json!({
+                        "type": "object",
+                        "required": ["property", "qids", "value"],
+                        "properties": {
+                            "property": property,
+                            "qids": { "type": "array", "items": { "type": "string" } },
+                            "value": { "type": "boolean" },
+                        },
+                    })),
+                    "responses": host_responses(json!({
+                        "200": ok("The outcome for each question, keyed by qid.", json!({
+                            "type": "object",
+                            "additionalProperties": { "type": "object" },
+                        })),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/lock": {
+                "post": {
+                    "summary": "Stop or resume taking new questions",
+                    "description": "While locked, asking fails with 423 Locked, but voting still works.",
+                    "parameters": [eid(), secret()],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "text/plain": { "schema": { "type": "string", "enum": ["on", "off"] } } },
+                    },
+                    "responses": host_responses(json!({
+                        "200": ok("Whether questions are now locked.", json!({
+                            "type": "object",
+                            "properties": { "questions_locked": { "type": "boolean" } },
+                        })),
+                        "400": error("The body is neither `on` nor `off`."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/archive": {
+                "post": {
+                    "summary": "Make an event read-only",
+                    "description": "Questions can still be read, but asking and voting fail with \
+                                    409 Conflict.",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({
+                        "204": status("The event was archived."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/reorder": {
+                "post": {
+                    "summary": "Set the host's running order of questions",
+                    "description": "Questions that aren't named lose their place, and are listed \
+                                    after the named ones (by votes) with `sort=order`.",
+                    "parameters": [eid(), secret()],
+                    "requestBody": json_body(json!({
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "maxItems": 100,
+                    })),
+                    "responses": host_responses(json!({
+                        "204": { "description": "The questions are in the new order." },
+                        "400": error("A question is named twice, or there are too many."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/merge": {
+                "post": {
+                    "summary": "Merge duplicate questions into one",
+                    "description": "The votes of the `from` questions are added to `into`, and \
+                                    the `from` questions are deleted.",
+                    "parameters": [eid(), secret()],
+                    "requestBody": json_body(json!({
+                        "type": "object",
+                        "required": ["into", "from"],
+                        "properties": {
+                            "into": { "type": "string" },
+                            "from": { "type": "array", "items": { "type": "string" }, "maxItems": 99 },
+                        },
+                    })),
+                    "responses": host_responses(json!({
+                        "200": ok("The merged question's new vote count.", json!({
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "votes": { "type": "integer" },
+                            },
+                        })),
+                        "400": error("There were no other questions to merge, or too many."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}/toggle/{property}": {
+                "post": {
+                    "summary": "Toggle a property of a question",
+                    "parameters": [
+                        eid(),
+                        secret(),
+                        qid(),
+                        if_match(),
+                        {
+                            "name": "property",
+                            "in": "path",
+                            "required": true,
+                            "schema": property,
+                        },
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "text/plain": { "schema": { "type": "string", "enum": ["on", "off"] } },
+                            "application/json": { "schema": {
+                                "type": "object",
+                                "required": ["value"],
+                                "properties": {
+                                    "value": { "type": "boolean" },
+                                    "expected": {
+                                        "type": "boolean",
+                                        "description": "Only toggle if the property is currently set to this.",
+                                    },
+                                },
+                            } },
+                        },
+                    },
+                    "responses": host_responses(json!({
+                        "200": ok("The question's new state.", json!({ "type": "object" })),
+                        "400": error("The body is neither `on`, `off`, nor a conditional toggle."),
+                        "409": error("The property isn't currently set to `expected`, or the question has moved on from the version in If-Match."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}": {
+                "delete": {
+                    "summary": "Delete a question",
+                    "parameters": [eid(), secret(), qid()],
+                    "responses": host_responses(json!({ "204": status("The question is gone.") })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}/tags": {
+                "post": {
+                    "summary": "Replace the tags of a question",
+                    "parameters": [eid(), secret(), qid()],
+                    "requestBody": json_body(json!({ "type": "array", "items": { "type": "string" } })),
+                    "responses": host_responses(json!({
+                        "200": ok("The question's new tags.", json!({
+                            "type": "object",
+                            "properties": { "tags": { "type": "array", "items": { "type": "string" } } },
+                        })),
+                        "400": error("The tags aren't acceptable."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}/edit": {
+                "post": {
+                    "summary": "Change the text of a question",
+                    "parameters": [eid(), secret(), qid(), if_match()],
+                    "requestBody": json_body(json!({
+                        "type": "object",
+                        "required": ["text"],
+                        "properties": { "text": { "type": "string" } },
+                    })),
+                    "responses": host_responses(json!({
+                        "200": ok("The edited question.", json!({ "type": "object" })),
+                        "400": error("The new text (or If-Match) isn't acceptable."),
+                        "409": error("The question has moved on from the version in If-Match."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}/answer": {
+                "post": {
+                    "summary": "Answer a question, or clear its answer",
+                    "parameters": [eid(), secret(), qid()],
+                    "requestBody": {
+                        "required": false,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "properties": { "answer": { "type": "string" } },
+                        } } },
+                    },
+                    "responses": host_responses(json!({
+                        "200": ok("The question's answer.", json!({
+                            "type": "object",
+                            "properties": {
+                                "answer": { "type": "string" },
+                                "answered": { "type": "integer" },
+                            },
+                        })),
+                        "400": error("The body is invalid."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/{qid}/note": {
+                "post": {
+                    "summary": "Set or clear the host's private note on a question",
+                    "description": "An empty body (or an empty note) clears the note. \
+                                    Notes are only ever shown to the host.",
+                    "parameters": [eid(), secret(), qid()],
+                    "requestBody": {
+                        "required": false,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["note"],
+                            "properties": { "note": { "type": "string" } },
+                        } } },
+                    },
+                    "responses": host_responses(json!({
+                        "200": ok("The question's note.", json!({
+                            "type": "object",
+                            "properties": { "note": { "type": "string" } },
+                        })),
+                        "400": error("The body is invalid."),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/rotate": {
+                "post": {
+                    "summary": "Replace the event's secret",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({
+                        "200": ok("The new secret. The old one, and tokens issued for it, no longer work.", json!({
+                            "type": "object",
+                            "properties": {
+                                "secret": { "type": "string" },
+                                "token": {
+                                    "type": "string",
+                                    "description": "A host token that can stand in for the new secret, \
+                                                    if the server issues them.",
+                                },
+                            },
+                        })),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/clone": {
+                "post": {
+                    "summary": "Create a new event with the same settings",
+                    "description": "The title, description, moderation, and limits are copied, \
+                                    but not the questions. The new event has its own secret.",
+                    "parameters": [eid(), secret(), api_key()],
+                    "responses": host_responses(json!({
+                        "200": ok("The new event, and its secret.", json!({
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "secret": { "type": "string" },
+                                "token": {
+                                    "type": "string",
+                                    "description": "A host token that can stand in for the secret, \
+                                                    if the server issues them.",
+                                },
+                            },
+                        })),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/export.csv": {
+                "get": {
+                    "summary": "Export all questions as CSV",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({
+                        "200": { "description": "The questions.", "content": { "text/csv": {} } },
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/export.json": {
+                "get": {
+                    "summary": "Export the event and all its questions as JSON",
+                    "parameters": [
+                        eid(),
+                        secret(),
+                        query_param("pretty", "Pretty-print the output.", json!({ "type": "boolean" })),
+                    ],
+                    "responses": host_responses(json!({
+                        "200": ok("The event.", json!({ "type": "object" })),
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/export.html": {
+                "get": {
+                    "summary": "Export the visible questions, most-voted first, as a page to share",
+                    "parameters": [eid(), secret()],
+                    "responses": host_responses(json!({
+                        "200": { "description": "The page.", "content": { "text/html": {} } },
+                    })),
+                },
+            },
+            "/api/event/{eid}/questions/{secret}/import": {
+                "post": {
+                    "summary": "Add questions from a JSON export, or a list of questions",
+                    "description": "Every question is checked like a newly asked one, and if any \
+                                    of them fails, none are imported. Imports may be up to 1 MiB.",
+                    "parameters": [eid(), secret()],
+                    "requestBody": json_body(json!({
+                        "oneOf": [
+                            { "type": "object", "description": "A JSON export of an event." },
+                            { "type": "array", "items": {
+                                "type": "object",
+                                "required": ["text"],
+                                "properties": {
+                                    "text": { "type": "string" },
+                                    "who": { "type": "string" },
+                                    "votes": { "type": "integer", "default": 1 },
+                                    "hidden": { "type": "boolean" },
+                                    "answered": { "type": "integer" },
+                                    "when": { "type": "integer" },
+                                    "tags": { "type": "array", "items": { "type": "string" } },
+                                },
+                            } },
+                        ],
+                    })),
+                    "responses": host_responses(json!({
+                        "200": ok("The new ids of the imported questions.", json!({
+                            "type": "object",
+                            "properties": {
+                                "imported": { "type": "integer" },
+                                "qids": { "type": "array", "items": { "type": "string" } },
+                            },
+                        })),
+                        "400": error("A question is invalid, or the export is of an unknown version."),
+                        "403": error("The event can't take that many more questions."),
+                        "409": error("The event has been archived."),
+                        "413": error("The import is larger than 1 MiB."),
+                    })),
+                },
+            },
+            "/api/vote/{qid}/{reaction}": {
+                "post": {
+                    "summary": "Vote for a question, take a vote back, or react to it",
+                    "description": "Clients that send `X-Client-Id` can only vote once per question. \
+                                    Reactions are not limited per client.",
+                    "parameters": [
+                        qid(),
+                        {
+                            "name": "reaction",
+                            "in": "path",
+                            "required": true,
+                            "schema": {
+                                "type": "string",
+                                "enum": ["up", "down", "none", "laugh", "heart", "clap"],
+                            },
+                        },
+                    ],
+                    "responses": {
+                        "200": ok("The question's new vote count, and reactions if reacting.", json!({
+                            "type": "object",
+                            "properties": {
+                                "qid": { "type": "string" },
+                                "votes": { "type": "integer", "description": "Left out in events that hide vote counts." },
+                                "delta": { "type": "integer", "description": "How much the vote moved the count by." },
+                                "your_vote": { "type": "string", "enum": ["up", "down", "none"] },
+                                "version": { "type": "integer" },
+                                "remaining_votes": { "type": "integer", "nullable": true },
+                                "reactions": schema("Reactions"),
+                            },
+                        })),
+                        "400": error("The reaction is unknown, or the client id is invalid or missing for a retraction (or for any vote in an event with a vote budget)."),
+                        "403": error("The client has used up its vote budget, or the event doesn't take down-votes."),
+                        "409": error("The question's event has been archived."),
+                        "429": error("The client is voting too often."),
+                    },
+                },
+            },
+            "/api/votes": {
+                "post": {
+                    "summary": "Apply several votes at once",
+                    "description": "For clients that queued up votes while offline. Each vote is applied \
+                                    (and may fail) on its own, like through `/api/vote/{qid}/{reaction}`.",
+                    "parameters": [{
+                        "name": "X-Client-Id",
+                        "in": "header",
+                        "required": true,
+                        "schema": { "type": "string" },
+                    }],
+                    "requestBody": json_body(json!({
+                        "type": "array",
+                        "maxItems": 100,
+                        "items": {
+                            "type": "object",
+                            "required": ["qid", "updown"],
+                            "properties": {
+                                "qid": { "type": "string" },
+                                "updown": { "type": "string", "enum": ["up", "down", "none"] },
+                            },
+                        },
+                    })),
+                    "responses": {
+                        "200": ok("One entry per vote, in order: the question's new count, or why the vote failed.", json!({
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "qid": { "type": "string" },
+                                    "votes": { "type": "integer", "description": "Left out in events that hide vote counts." },
+                                    "delta": { "type": "integer" },
+                                    "your_vote": { "type": "string" },
+                                    "remaining_votes": { "type": "integer", "nullable": true },
+                                    "error": { "type": "string" },
+                                    "message": { "type": "string" },
+                                },
+                            },
+                        })),
+                        "400": error("The batch is empty or too large, or the client id is invalid or missing."),
+                        "429": error("The client is voting too often."),
+                    },
+                },
+            },
+            "/api/question/{qid}/report": {
+                "post": {
+                    "summary": "Report a question as abusive",
+                    "description": "Questions that get enough reports are hidden until the host unhides them.",
+                    "parameters": [qid()],
+                    "responses": {
+                        "204": { "description": "The report was recorded." },
+                        "404": error("The question doesn't exist."),
+                        "429": error("The client is reporting too often."),
+                    },
+                },
+            },
+            "/api/questions": {
+                "post": {
+                    "summary": "Get the text of many questions",
+                    "requestBody": json_body(json!({ "type": "array", "items": { "type": "string" } })),
+                    "responses": {
+                        "200": ok("The questions that exist, keyed by qid.", json!({
+                            "type": "object",
+                            "additionalProperties": schema("QuestionText"),
+                        })),
+                    },
+                },
+            },
+            "/api/questions/{qids}": {
+                "get": {
+                    "summary": "Get the text of some questions",
+                    "parameters": [path_param("qids", "Comma-separated question ids.")],
+                    "responses": {
+                        "200": ok("The questions that exist, keyed by qid.", json!({
+                            "type": "object",
+                            "additionalProperties": schema("QuestionText"),
+                        })),
+                        "304": status("None of the questions have changed since the date in If-Modified-Since."),
+                        "400": error("A question id is invalid."),
+                        "404": error("None of the questions exist."),
+                    },
+                },
+            },
+            "/api/health": {
+                "get": {
+                    "summary": "Check that the server can reach its backend",
+                    "responses": {
+                        "200": ok("All is well.", json!({ "type": "object" })),
+                        "503": ok("The backend is unreachable.", json!({ "type": "object" })),
+                    },
+                },
+            },
+            "/api/ping": {
+                "get": {
+                    "summary": "Answer right away, for keeping the server warm",
+                    "responses": {
+                        "200": { "description": "Always.", "content": { "text/plain": {} } },
+                    },
+                },
+            },
+            "/api/metrics": {
+                "get": {
+                    "summary": "Prometheus metrics",
+                    "responses": {
+                        "200": { "description": "The metrics.", "content": { "text/plain": {} } },
+                    },
+                },
+            },
+            "/api/openapi.json": {
+                "get": {
+                    "summary": "This document",
+                    "responses": { "200": ok("The OpenAPI document.", json!({ "type": "object" })) },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "description": "Why the request failed. Clients should match on `error`, not `message`.",
+                    "required": ["error", "message"],
+                    "properties": {
+                        "error": { "type": "string", "example": "forbidden_secret" },
+                        "message": { "type": "string" },
+                    },
+                },
+                "Meta": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "description": { "type": "string" },
+                        "moderated": { "type": "boolean" },
+                        "archived": { "type": "boolean" },
+                        "questions_locked": { "type": "boolean" },
+                        "max_questions": { "type": "integer" },
+                        "vote_budget": { "type": "integer", "minimum": 1, "description": "How many up-votes each guest gets." },
+                        "downvotes_enabled": {
+                            "type": "boolean",
+                            "default": true,
+                            "description": "Whether guests can down-vote. Only listed when they can't.",
+                        },
+                        "hide_counts": {
+                            "type": "boolean",
+                            "description": "Whether guests only see questions in vote order, and not how many votes they have.",
+                        },
+                        "max_question_chars": {
+                            "type": "integer",
+                            "readOnly": true,
+                            "description": "The longest a question can be, in characters.",
+                        },
+                        "webhook_url": {
+                            "type": "string",
+                            "writeOnly": true,
+                            "description": "Where to POST every new question, with the event id in `X-Event-Id`.",
+                        },
+                    },
+                },
+                "Question": {
+                    "type": "object",
+                    "required": ["qid", "hidden", "pinned", "version"],
+                    "properties": {
+                        "qid": { "type": "string" },
+                        "votes": { "type": "integer", "description": "Left out for guests in events that hide vote counts." },
+                        "hidden": { "type": "boolean" },
+                        "pinned": { "type": "boolean" },
+                        "version": { "type": "integer", "description": "Goes up by one every time the question changes." },
+                        "answered": { "type": "integer" },
+                        "when": { "type": "integer" },
+                        "answer": { "type": "string" },
+                        "approved": { "type": "boolean" },
+                        "order": { "type": "integer", "description": "The question's place in the host's running order." },
+                        "tags": { "type": "array", "items": { "type": "string" } },
+                        "reactions": schema("Reactions"),
+                        "reports": { "type": "integer", "description": "Only shown to hosts." },
+                        "note": { "type": "string", "description": "Only shown to hosts." },
+                    },
+                },
+                "Reactions": {
+                    "type": "object",
+                    "properties": {
+                        "laugh": { "type": "integer" },
+                        "heart": { "type": "integer" },
+                        "clap": { "type": "integer" },
+                    },
+                },
+                "QuestionList": {
+                    "oneOf": [
+                        { "type": "array", "items": schema("Question") },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "questions": { "type": "array", "items": schema("Question") },
+                                "next_cursor": { "type": "string", "nullable": true },
+                                "total": { "type": "integer" },
+                                "next_offset": { "type": "integer", "nullable": true },
+                                "removed": { "type": "array", "items": { "type": "string" } },
+                                "next_since": { "type": "integer" },
+                                "event": schema("Meta"),
+                            },
+                        },
+                    ],
+                },
+                "QuestionText": {
+                    "type": "object",
+                    "required": ["text", "when"],
+                    "properties": {
+                        "text": { "type": "string" },
+                        "when": { "type": "integer" },
+                        "who": { "type": "string" },
+                        "answer": { "type": "string" },
+                    },
+                },
+            },
+        },
+    })
+}
+
+/// Describes the API as an OpenAPI 3 document.
+///
+/// This is written by hand, so remember to update it along with the routes in `app`.
+pub(super) async fn openapi() -> (AppendHeaders<[(HeaderName, &'static str); 1]>, Json<Value>) {
+    static SPEC: OnceLock<Value> = OnceLock::new();
+    (
+        // it only changes when the server does
+        AppendHeaders([(header::CACHE_CONTROL, "max-age=3600")]),
+        Json(SPEC.get_or_init(spec).clone()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn consistent() {
+        let (_, Json(spec)) = super::openapi().await;
+        let paths = spec["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/api/event/{eid}/questions/{secret}/{qid}/toggle/{property}"));
+
+        for (path, ops) in paths {
+            for (method, op) in ops.as_object().unwrap() {
+                // every templated path segment is described, and nothing else
+                let mut documented: Vec<_> = op["parameters"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|p| p["in"] == "path")
+                    .map(|p| p["name"].as_str().unwrap())
+                    .collect();
+                let mut templated: Vec<_> = path
+                    .split('/')
+                    .filter_map(|s| s.strip_prefix('{')?.strip_suffix('}'))
+                    .collect();
+                documented.sort_unstable();
+                templated.sort_unstable();
+                assert_eq!(documented, templated, "{method} {path}");
+
+                assert!(op["responses"].is_object(), "{method} {path}");
+            }
+        }
+
+        // references all resolve
+        let text = spec.to_string();
+        for r in text.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = &r[..r.find('"').unwrap()];
+            assert!(spec["components"]["schemas"].get(name).is_some(), "{name}");
+        }
+    }
+}
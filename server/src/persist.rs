@@ -0,0 +1,377 @@
+use super::{vote::UpDown, Local};
+use crate::store::Attr;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How often the local backend is written out while the server is running.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bumped whenever the snapshot format changes in a way old snapshots can't be read.
+const VERSION: u64 = 1;
+
+/// Where to keep the local backend between runs, if anywhere.
+///
+/// Unless `LOCAL_STATE_PATH` is set, the local backend lives only in memory.
+pub(super) fn path() -> Option<&'static Path> {
+    static PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PATH.get_or_init(|| std::env::var_os("LOCAL_STATE_PATH").map(PathBuf::from))
+        .as_deref()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Vote {
+    qid: Ulid,
+    client: String,
+    vote: UpDown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Spent {
+    eid: Ulid,
+    client: String,
+    votes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Logged {
+    qid: Ulid,
+    when: u64,
+    delta: isize,
+}
+
+/// Everything in [`Local`] except the live feeds, which only make sense while clients are
+/// connected, and idempotency keys, which are only good for a little while anyway.
+#[derive(Serialize, Deserialize, Debug)]
+struct Snapshot {
+    version: u64,
+    events: HashMap<Ulid, HashMap<String, Attr>>,
+    questions: HashMap<Ulid, HashMap<String, Attr>>,
+    questions_by_eid: HashMap<Ulid, Vec<Ulid>>,
+    client_votes: Vec<Vote>,
+    #[serde(default)]
+    spent_votes: Vec<Spent>,
+    #[serde(default)]
+    vote_log: Vec<Logged>,
+}
+
+/// Gives a `'static` attribute name for `key`, as [`Local`] wants.
+///
+/// The set of attribute names is small and fixed, so each one is leaked at most once.
+fn intern(key: String) -> &'static str {
+    static KEYS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut keys = KEYS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = keys.get(key.as_str()) {
+        return key;
+    }
+    let key: &'static str = Box::leak(key.into_boxed_str());
+    keys.insert(key);
+    key
+}
+
+fn decode_item(item: HashMap<String, Attr>) -> HashMap<&'static str, Attr> {
+    item.into_iter().map(|(k, v)| (intern(k), v)).collect()
+}
+
+impl Local {
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: VERSION,
+            events: self
+                .events
+                .iter()
+                .map(|(id, e)| (*id, crate::store::from_local(e)))
+                .collect(),
+            questions: self
+                .questions
+                .iter()
+                .map(|(id, q)| (*id, crate::store::from_local(q)))
+                .collect(),
+            questions_by_eid: self.questions_by_eid.clone(),
+            client_votes: self
+                .client_votes
+                .iter()
+                .map(|((qid, client), vote)| Vote {
+                    qid: *qid,
+                    client: client.clone(),
+                    vote: *vote,
+                })
+                .collect(),
+            spent_votes: self
+                .spent_votes
+                .iter()
+                .map(|((eid, client), votes)| Spent {
+                    eid: *eid,
+                    client: client.clone(),
+                    votes: *votes,
+                })
+                .collect(),
+            vote_log: self
+                .vote_log
+                .iter()
+                .map(|&(qid, when, delta)| Logged { qid, when, delta })
+                .collect(),
+        }
+    }
+
+    fn restore(snapshot: Snapshot) -> Self {
+        let events: HashMap<_, _> = snapshot
+            .events
+            .into_iter()
+            .map(|(id, e)| (id, decode_item(e)))
+            .collect();
+        // events keep their code, so there's no need to save the lookup the other way too.
+        let codes = events
+            .iter()
+            .filter_map(|(id, e)| Some((e.get("code")?.as_s().ok()?.clone(), *id)))
+            .collect();
+        Local {
+            events,
+            questions: snapshot
+                .questions
+                .into_iter()
+                .map(|(id, q)| (id, decode_item(q)))
+                .collect(),
+            questions_by_eid: snapshot.questions_by_eid,
+            client_votes: snapshot
+                .client_votes
+                .into_iter()
+                .map(|v| ((v.qid, v.client), v.vote))
+                .collect(),
+            spent_votes: snapshot
+                .spent_votes
+                .into_iter()
+                .map(|s| ((s.eid, s.client), s.votes))
+                .collect(),
+            vote_log: snapshot
+                .vote_log
+                .into_iter()
+                .map(|l| (l.qid, l.when, l.delta))
+                .collect(),
+            feeds: Default::default(),
+            presence: Default::default(),
+            idempotency: Default::default(),
+            codes,
+        }
+    }
+
+    /// Reads back the state last saved to `path`.
+    ///
+    /// Returns `None` if nothing has been saved there yet. A file that's there but can't be read
+    /// is a panic, since carrying on would overwrite it with an empty state.
+    pub(super) fn load(path: &Path) -> Option<Self> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => panic!("could not read local state from {}: {e}", path.display()),
+        };
+        let snapshot: Snapshot = serde_json::from_slice(&json)
+            .unwrap_or_else(|e| panic!("local state in {} is not valid: {e}", path.display()));
+        assert_eq!(
+            snapshot.version,
+            VERSION,
+            "local state in {} is from an incompatible version",
+            path.display()
+        );
+        Some(Self::restore(snapshot))
+    }
+}
+
+/// Writes `contents` to `path` such that `path` never holds only part of it, even if we crash (or
+/// are killed) half-way through.
+///
+/// The contents go into a temporary file next to `path` first, which then replaces `path` in one
+/// go. That only works because the two are on the same filesystem.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut f = std::fs::File::create(&tmp)?;
+    f.write_all(contents)?;
+    // or the rename could make it to disk before the contents do.
+    f.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Saves the local backend's state `local` to `path`, logging rather than failing if that doesn't
+/// work.
+pub(super) fn save(local: &Mutex<Local>, path: &Path) {
+    // the periodic save and the one at shutdown could otherwise share a temporary file.
+    static SAVING: Mutex<()> = Mutex::new(());
+    let _saving = SAVING.lock().unwrap_or_else(|e| e.into_inner());
+    // serialize under the lock, but keep the disk out of it.
+    let snapshot = super::lock(local).snapshot();
+    let r = serde_json::to_vec(&snapshot)
+        .map_err(std::io::Error::from)
+        .and_then(|json| write_atomically(path, &json));
+    match r {
+        Ok(()) => debug!(path = %path.display(), "saved local state"),
+        Err(e) => error!(path = %path.display(), error = %e, "failed to save local state"),
+    }
+}
+
+/// Saves the local backend's state `local` to `path` every so often, for as long as the server
+/// runs.
+pub(super) fn save_periodically(local: Arc<Mutex<Local>>, path: &'static Path) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+        // the first tick is immediate, and there's nothing new to save yet.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            save(&local, path);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+    use axum::{
+        extract::{Path, State},
+        Json,
+    };
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let (backend, local) = crate::local_state();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: Some("person".into()),
+                tags: vec!["rust".into()],
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let mut headers = http::HeaderMap::new();
+        headers.insert("x-client-id", http::HeaderValue::from_static("me"));
+        let _ = crate::vote::vote(Path((qid, UpDown::Up)), State(backend.clone()), headers)
+            .await
+            .unwrap();
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret.to_string(),
+                qid,
+                crate::toggle::Property::Answered,
+            )),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        backend.log_vote(&eid, &qid, 1).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("wewerewondering-{}.json", Ulid::new()));
+        assert!(Local::load(&path).is_none());
+        save(&local, &path);
+        let restored = Local::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let local = crate::lock(&local);
+        assert_eq!(restored.events, local.events);
+        assert_eq!(restored.questions, local.questions);
+        assert_eq!(restored.questions_by_eid, local.questions_by_eid);
+        assert_eq!(restored.client_votes, local.client_votes);
+        assert_eq!(restored.spent_votes, local.spent_votes);
+        assert_eq!(restored.vote_log, local.vote_log);
+    }
+
+    #[tokio::test]
+    async fn ordering() {
+        let (backend, local) = crate::local_state();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello moon", "hello sun"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        // so that the questions are no longer in the order they were asked in
+        for votes in 0..2 {
+            for _ in 0..=votes {
+                let _ = crate::vote::vote(
+                    Path((qids[2 - votes], UpDown::Up)),
+                    State(backend.clone()),
+                    Default::default(),
+                )
+                .await
+                .unwrap();
+            }
+        }
+        let list = |backend: Backend| async move {
+            crate::list::list(
+                Path(eid),
+                State(backend),
+                axum::extract::Query(Default::default()),
+            )
+            .await
+            .1
+            .unwrap()
+            .0
+        };
+        let before = list(backend.clone()).await;
+
+        let path = std::env::temp_dir().join(format!("wewerewondering-{}.json", Ulid::new()));
+        save(&local, &path);
+        let restored = Local::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            restored.questions_by_eid[&eid],
+            crate::lock(&local).questions_by_eid[&eid]
+        );
+        let restored: Backend = Arc::new(Mutex::new(restored));
+        assert_eq!(list(restored).await, before);
+    }
+
+    #[test]
+    fn atomic() {
+        let path = std::env::temp_dir().join(format!("wewerewondering-{}.json", Ulid::new()));
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        // as left behind by a save that was cut short
+        std::fs::write(&tmp, "{ \"version\":").unwrap();
+        std::fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, b"new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!std::path::Path::new(&tmp).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+use super::Backend;
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::StatusCode;
+use rand::{distributions::WeightedIndex, prelude::Distribution, seq::SliceRandom, Rng};
+use serde::Deserialize;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct Params {
+    /// Make questions with more votes more likely to be picked.
+    #[serde(default)]
+    weighted: bool,
+}
+
+/// Picks one of `candidates` (qids and their votes) at random.
+///
+/// If `weighted`, each question's chance is proportional to its votes, so questions without any
+/// votes are only picked if no question has any.
+fn draw<'a, R: Rng>(
+    candidates: &'a [(String, u64)],
+    weighted: bool,
+    rng: &mut R,
+) -> Option<&'a (String, u64)> {
+    if weighted {
+        if let Ok(by_votes) = WeightedIndex::new(candidates.iter().map(|(_, votes)| *votes)) {
+            return Some(&candidates[by_votes.sample(rng)]);
+        }
+        // there's nothing to weigh by, so they're all equally likely.
+    }
+    candidates.choose(rng)
+}
+
+/// Picks a question the host hasn't answered or hidden yet, for hosts who want the next question
+/// chosen fairly.
+///
+/// Returns `204` if there are no such questions.
+pub(super) async fn next_random(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Query(params): Query<Params>,
+) -> Result<Response, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let filter = crate::list::Filter {
+        answered: Some(false),
+        hidden: Some(false),
+        ..Default::default()
+    };
+    let mut candidates = Vec::new();
+    let mut start = None;
+    loop {
+        let r = match dynamo.list(&eid, true, &filter, None, start).await {
+            Ok(r) => r,
+            Err(e) => {
+                error!(%eid, error = %e, "dynamodb request to list questions to pick from failed");
+                return Err(ApiError::Internal);
+            }
+        };
+        candidates.extend(r.items.iter().filter_map(|q| {
+            let qid = q.get("id").and_then(|v| v.as_s().ok())?;
+            let votes = q
+                .get("votes")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0);
+            Some((qid.clone(), votes))
+        }));
+        start = r.next;
+        if start.is_none() {
+            break;
+        }
+    }
+
+    match draw(&candidates, params.weighted, &mut rand::thread_rng()) {
+        Some((qid, votes)) => {
+            debug!(%eid, qid, weighted = params.weighted, "picked random question");
+            Ok(Json(serde_json::json!({ "qid": qid, "votes": votes })).into_response())
+        }
+        None => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn weighting() {
+        let mut rng = rand::thread_rng();
+        let candidates = vec![
+            ("a".to_string(), 0),
+            ("b".to_string(), 1),
+            ("c".to_string(), 9),
+        ];
+        let mut picked = HashMap::new();
+        for _ in 0..1000 {
+            let (qid, _) = draw(&candidates, true, &mut rng).unwrap();
+            *picked.entry(qid.as_str()).or_insert(0) += 1;
+        }
+        // a question without votes is never picked over ones with votes
+        assert_eq!(picked.get("a"), None);
+        assert!(picked["c"] > picked["b"] * 3, "{picked:?}");
+
+        // without weighting, everything's in the running
+        let mut picked = HashMap::new();
+        for _ in 0..1000 {
+            let (qid, _) = draw(&candidates, false, &mut rng).unwrap();
+            *picked.entry(qid.as_str()).or_insert(0) += 1;
+        }
+        assert_eq!(picked.len(), 3);
+
+        // and if nothing has votes, weighting makes no difference
+        let unvoted = vec![("a".to_string(), 0), ("b".to_string(), 0)];
+        assert!(draw(&unvoted, true, &mut rng).is_some());
+        assert_eq!(draw(&[], true, &mut rng), None);
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let pick = |weighted| {
+            next_random(
+                Path((eid, secret.clone())),
+                State(backend.clone()),
+                Query(Params { weighted }),
+            )
+        };
+
+        assert_eq!(
+            next_random(
+                Path((eid, "wrong".into())),
+                State(backend.clone()),
+                Query(Default::default())
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        // there's nothing to pick from yet
+        assert_eq!(pick(false).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello moon", "hello sun"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        // answered and hidden questions are out of the running
+        for (qid, property) in [
+            (qids[0], crate::toggle::Property::Answered),
+            (qids[1], crate::toggle::Property::Hidden),
+        ] {
+            let _ = crate::toggle::toggle(
+                Path((eid, secret.clone(), qid, property)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                String::from("on"),
+            )
+            .await
+            .unwrap();
+        }
+        for weighted in [false, true] {
+            let res = pick(weighted).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["qid"], qids[2].to_string());
+        }
+
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret.clone(),
+                qids[2],
+                crate::toggle::Property::Answered,
+            )),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pick(true).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
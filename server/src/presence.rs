@@ -0,0 +1,298 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How long a viewer who disconnects still counts, so that reconnecting doesn't make the count
+/// dip and come right back.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Who's watching an event's live updates.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Presence {
+    viewers: usize,
+    /// Viewers that have disconnected, but are still within their grace period.
+    leaving: usize,
+}
+
+impl Presence {
+    /// Counts a viewer that connects. Returns `true` if the count changed.
+    ///
+    /// A viewer that leaves and connects again within the grace period is taken to be the same
+    /// one, so it isn't counted twice.
+    fn join(&mut self) -> bool {
+        if self.leaving > 0 {
+            self.leaving -= 1;
+            false
+        } else {
+            self.viewers += 1;
+            true
+        }
+    }
+
+    /// Notes that a viewer disconnected, which only counts once [`Presence::left`] is called.
+    fn leave(&mut self) {
+        self.leaving += 1;
+    }
+
+    /// Stops counting a viewer that disconnected a grace period ago, unless someone has connected
+    /// in its place since. Returns `true` if the count changed.
+    fn left(&mut self) -> bool {
+        if self.leaving == 0 {
+            return false;
+        }
+        self.leaving -= 1;
+        self.viewers -= 1;
+        true
+    }
+}
+
+impl Local {
+    /// Tells everyone subscribed to `eid` how many viewers it has now.
+    fn publish_presence(&mut self, eid: &Ulid) {
+        let viewers = self.presence.get(eid).map_or(0, |p| p.viewers);
+        self.publish(eid, "presence", serde_json::json!({ "viewers": viewers }));
+    }
+}
+
+/// How a storage engine counts who's watching its live updates.
+///
+/// Only storage engines that give out live updates (see [`crate::stream::StreamStore`]) have
+/// anyone to count, so the others can ignore viewers coming and going.
+pub(super) trait PresenceStore {
+    /// Counts a viewer that connects to the live updates of `eid`.
+    fn viewer_joined(&self, eid: &Ulid);
+
+    /// Notes that a viewer of `eid` disconnected, which only counts once
+    /// [`PresenceStore::viewer_left`] is called.
+    ///
+    /// Returns `false` if there's no count to take the viewer out of.
+    fn viewer_leaving(&self, eid: &Ulid) -> bool;
+
+    /// Stops counting a viewer of `eid` that disconnected a grace period ago.
+    fn viewer_left(&self, eid: &Ulid);
+
+    /// Gives how many clients are watching the live updates of `eid`, or `None` if the storage
+    /// engine has no live updates to watch.
+    fn viewers(&self, eid: &Ulid) -> Option<usize>;
+}
+
+impl PresenceStore for aws_sdk_dynamodb::Client {
+    fn viewer_joined(&self, _eid: &Ulid) {}
+
+    fn viewer_leaving(&self, _eid: &Ulid) -> bool {
+        false
+    }
+
+    fn viewer_left(&self, _eid: &Ulid) {}
+
+    fn viewers(&self, _eid: &Ulid) -> Option<usize> {
+        None
+    }
+}
+
+impl PresenceStore for Mutex<Local> {
+    fn viewer_joined(&self, eid: &Ulid) {
+        let mut local = super::lock(self);
+        if local.presence.entry(*eid).or_default().join() {
+            local.publish_presence(eid);
+        }
+    }
+
+    fn viewer_leaving(&self, eid: &Ulid) -> bool {
+        match super::lock(self).presence.get_mut(eid) {
+            Some(p) => {
+                p.leave();
+                true
+            }
+            // the event is gone, and its count with it
+            None => false,
+        }
+    }
+
+    fn viewer_left(&self, eid: &Ulid) {
+        let mut local = super::lock(self);
+        if local.presence.get_mut(eid).is_some_and(Presence::left) {
+            local.publish_presence(eid);
+        }
+    }
+
+    fn viewers(&self, eid: &Ulid) -> Option<usize> {
+        Some(super::lock(self).presence.get(eid).map_or(0, |p| p.viewers))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl PresenceStore for crate::redis::Redis {
+    fn viewer_joined(&self, _eid: &Ulid) {}
+
+    fn viewer_leaving(&self, _eid: &Ulid) -> bool {
+        false
+    }
+
+    fn viewer_left(&self, _eid: &Ulid) {}
+
+    fn viewers(&self, _eid: &Ulid) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl PresenceStore for crate::sqlite::Sqlite {
+    fn viewer_joined(&self, _eid: &Ulid) {}
+
+    fn viewer_leaving(&self, _eid: &Ulid) -> bool {
+        false
+    }
+
+    fn viewer_left(&self, _eid: &Ulid) {}
+
+    fn viewers(&self, _eid: &Ulid) -> Option<usize> {
+        None
+    }
+}
+
+/// A client watching an event's live updates, who stops being counted a grace period after this
+/// is dropped.
+pub(super) struct Viewer {
+    eid: Ulid,
+    backend: Backend,
+}
+
+impl Viewer {
+    pub(super) fn join(backend: &Backend, eid: Ulid) -> Self {
+        backend.viewer_joined(&eid);
+        Self {
+            eid,
+            backend: Arc::clone(backend),
+        }
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        let eid = self.eid;
+        if !self.backend.viewer_leaving(&eid) {
+            return;
+        }
+        let backend = Arc::clone(&self.backend);
+        let left = move || backend.viewer_left(&eid);
+        match tokio::runtime::Handle::try_current() {
+            Ok(rt) => {
+                rt.spawn(async move {
+                    tokio::time::sleep(GRACE).await;
+                    left();
+                });
+            }
+            // nothing will be around to wait out the grace period, so don't.
+            Err(_) => left(),
+        }
+    }
+}
+
+/// Gives how many clients are watching the live updates of `eid`.
+pub(super) async fn presence(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    dynamo.get_secret(&eid).await?;
+
+    let Some(viewers) = dynamo.viewers(&eid) else {
+        // there are no live updates to watch, so there's nobody to count.
+        warn!(%eid, "presence requested from backend without live updates");
+        return Err(ApiError::NotImplemented);
+    };
+    Ok(Json(serde_json::json!({ "viewers": viewers })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grace() {
+        let mut p = Presence::default();
+        assert!(p.join());
+        assert!(p.join());
+        assert_eq!(p.viewers, 2);
+
+        // a quick reconnect doesn't change the count
+        p.leave();
+        assert!(!p.join());
+        assert!(!p.left());
+        assert_eq!(p.viewers, 2);
+
+        // but leaving for good does, once the grace period is up
+        p.leave();
+        assert_eq!(p.viewers, 2);
+        assert!(p.left());
+        assert_eq!(p.viewers, 1);
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let viewers = || super::presence(Path(eid), State(backend.clone()));
+
+        let Some((_, mut rx)) = backend.subscribe(&eid, None) else {
+            assert_eq!(viewers().await.unwrap_err(), ApiError::NotImplemented);
+            backend.delete(&eid).await;
+            return;
+        };
+        assert_eq!(viewers().await.unwrap()["viewers"], 0);
+        let a = Viewer::join(&backend, eid);
+        let _b = Viewer::join(&backend, eid);
+        assert_eq!(viewers().await.unwrap()["viewers"], 2);
+        let update = rx.recv().await.unwrap();
+        assert_eq!(update.kind, "presence");
+        assert_eq!(update.data["viewers"], 1);
+        // viewers that leave are still counted for a bit
+        drop(a);
+        assert_eq!(viewers().await.unwrap()["viewers"], 2);
+
+        assert_eq!(
+            super::presence(Path(Ulid::new()), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            ApiError::EventNotFound
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,198 @@
+use crate::error::ApiError;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::OnceLock;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The words we block if `PROFANITY_LIST` isn't set.
+const DEFAULT_LIST: &[&str] = &[
+    "asshole",
+    "bastard",
+    "bitch",
+    "cunt",
+    "fuck",
+    "fucker",
+    "fucking",
+    "motherfucker",
+    "shit",
+    "wanker",
+];
+
+/// What to do with questions that contain blocked words.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Mode {
+    /// Refuse the question altogether.
+    Reject,
+    /// Replace the blocked words with asterisks.
+    Mask,
+}
+
+#[derive(Debug)]
+pub(super) struct Filter {
+    mode: Mode,
+    words: HashSet<String>,
+}
+
+/// Returns the blocklist filter configured through `PROFANITY_MODE` and `PROFANITY_LIST`.
+///
+/// Panics if `PROFANITY_MODE` is set to something other than `reject` or `mask`, or if the file
+/// named by `PROFANITY_LIST` cannot be read.
+pub(super) fn filter() -> &'static Filter {
+    static FILTER: OnceLock<Filter> = OnceLock::new();
+    FILTER.get_or_init(|| {
+        let mode = match std::env::var("PROFANITY_MODE").as_deref() {
+            Ok("reject") => Mode::Reject,
+            Ok("mask") | Err(_) => Mode::Mask,
+            Ok(mode) => panic!("PROFANITY_MODE must be reject or mask, not {mode}"),
+        };
+        match std::env::var("PROFANITY_LIST") {
+            Ok(path) => {
+                let list = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("could not read PROFANITY_LIST {path}: {e}"));
+                // one word per line, and allow comments so operators can annotate their lists.
+                let words = list
+                    .lines()
+                    .map(str::trim)
+                    .filter(|w| !w.is_empty() && !w.starts_with('#'));
+                Filter::new(mode, words)
+            }
+            Err(_) => Filter::new(mode, DEFAULT_LIST.iter().copied()),
+        }
+    })
+}
+
+/// Undoes the most common ways of dodging a blocklist (`Sh!T`, `a$$`, `f.u.c.k`).
+fn normalize(word: &str) -> String {
+    word.chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            '!' | '1' | '|' => Some('i'),
+            '@' | '4' => Some('a'),
+            '3' => Some('e'),
+            '0' => Some('o'),
+            '$' | '5' => Some('s'),
+            '7' | '+' => Some('t'),
+            c if c.is_alphabetic() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Filter {
+    pub(super) fn new<'a>(mode: Mode, words: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            mode,
+            words: words
+                .into_iter()
+                .map(normalize)
+                .filter(|w| !w.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the part of `token` that should be masked, if any.
+    fn blocked(&self, token: &str) -> Option<Range<usize>> {
+        // the word may be surrounded by punctuation (like "(shit,"), which we shouldn't read as
+        // part of the word and which we should leave alone when masking. we first try to keep
+        // the punctuation that's commonly substituted for letters, and only then drop all of it
+        // (for cases like "shit!").
+        let trim = |f: fn(char) -> bool| {
+            let rest = token.trim_start_matches(f);
+            (token.len() - rest.len(), rest.trim_end_matches(f))
+        };
+        [
+            trim(|c| c.is_ascii_punctuation() && !"!|@$+".contains(c)),
+            trim(|c| c.is_ascii_punctuation()),
+        ]
+        .into_iter()
+        .find(|(_, core)| !core.is_empty() && self.words.contains(&normalize(core)))
+        .map(|(start, core)| start..start + core.len())
+    }
+
+    /// Checks the question `text` of `eid` against the blocklist.
+    ///
+    /// Depending on the configured mode, a question with blocked words is either rejected or
+    /// returned with those words masked.
+    pub(super) fn apply<'t>(&self, eid: &Ulid, text: &'t str) -> Result<Cow<'t, str>, ApiError> {
+        let mut blocked = Vec::new();
+        let mut start = None;
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(i),
+                (Some(s), true) => {
+                    if let Some(r) = self.blocked(&text[s..i]) {
+                        blocked.push(s + r.start..s + r.end);
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if blocked.is_empty() {
+            return Ok(Cow::Borrowed(text));
+        }
+        match self.mode {
+            Mode::Reject => {
+                warn!(%eid, body = text, "rejecting question with blocked words");
+                Err(ApiError::BadRequest)
+            }
+            Mode::Mask => {
+                debug!(%eid, n = blocked.len(), "masking blocked words in question");
+                let mut masked = String::with_capacity(text.len());
+                let mut at = 0;
+                for r in blocked {
+                    masked.push_str(&text[at..r.start]);
+                    masked.extend(std::iter::repeat_n('*', text[r.clone()].chars().count()));
+                    at = r.end;
+                }
+                masked.push_str(&text[at..]);
+                Ok(Cow::Owned(masked))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    #[test]
+    fn mask() {
+        let eid = Ulid::new();
+        let f = Filter::new(Mode::Mask, ["shit", "Darn"]);
+        assert_eq!(f.apply(&eid, "hello world").unwrap(), "hello world");
+        assert_eq!(f.apply(&eid, "what the shit").unwrap(), "what the ****");
+        assert_eq!(f.apply(&eid, "what the SHIT?!").unwrap(), "what the ****?!");
+        assert_eq!(f.apply(&eid, "what the sh!t").unwrap(), "what the ****");
+        assert_eq!(
+            f.apply(&eid, "what the $h1t, darn").unwrap(),
+            "what the ****, ****"
+        );
+        assert_eq!(
+            f.apply(&eid, "what  the\ns.h.i.t").unwrap(),
+            "what  the\n*******"
+        );
+        // only whole words are blocked
+        assert_eq!(
+            f.apply(&eid, "shitake mushrooms").unwrap(),
+            "shitake mushrooms"
+        );
+    }
+
+    #[test]
+    fn reject() {
+        let eid = Ulid::new();
+        let f = Filter::new(Mode::Reject, ["shit"]);
+        assert_eq!(f.apply(&eid, "hello world").unwrap(), "hello world");
+        assert_eq!(
+            f.apply(&eid, "what the Sh!t").unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
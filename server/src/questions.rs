@@ -1,114 +1,282 @@
 use std::collections::HashMap;
 
-use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::BatchGetItemError,
-    model::{AttributeValue, KeysAndAttributes},
-    output::BatchGetItemOutput,
-    types::SdkError,
-};
+use super::{Backend, Local, Store};
+use crate::error::ApiError;
+use crate::lastmodified::LastModified;
+use crate::store::{self, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, KeysAndAttributes};
 use axum::{
     extract::{Path, State},
     response::AppendHeaders,
     Json,
 };
-use http::{
-    header::{self, HeaderName},
-    StatusCode,
-};
+use http::header::{self, HeaderName};
 use serde_json::Value;
+use std::sync::Mutex;
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-impl Backend {
-    pub(super) async fn questions(
+/// The most keys a single BatchGetItem may ask for.
+const BATCH_SIZE: usize = 100;
+
+/// The attributes of each question that [`QuestionsStore::questions`] gives.
+const ATTRIBUTES: [&str; 7] = [
+    "id", "text", "when", "who", "answer", "answered", "modified",
+];
+
+/// How many times to re-request questions that DynamoDB didn't get around to returning.
+const MAX_RETRIES: u32 = 3;
+
+/// The questions [`QuestionsStore::questions`] got around to.
+#[derive(Debug, Default)]
+pub(super) struct Batch {
+    pub(super) found: Vec<Item>,
+    /// The questions that weren't looked up this time, and are worth asking for again.
+    pub(super) unprocessed: Vec<Ulid>,
+}
+
+/// How a storage engine looks up questions by their ids.
+#[async_trait]
+pub(super) trait QuestionsStore {
+    /// Looks up (at most [`BATCH_SIZE`] of) `qids`.
+    ///
+    /// Questions that don't exist are left out of the batch, rather than left unprocessed.
+    async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError>;
+}
+
+#[async_trait]
+impl QuestionsStore for aws_sdk_dynamodb::Client {
+    async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError> {
+        let keys = qids
+            .iter()
+            .map(|qid| {
+                HashMap::from_iter([(String::from("id"), AttributeValue::S(qid.to_string()))])
+            })
+            .collect();
+        let get = self.batch_get_item().request_items(
+            "questions",
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .projection_expression("id,#text,#when,who,answer,answered,modified")
+                .expression_attribute_names("#text", "text")
+                .expression_attribute_names("#when", "when")
+                .build(),
+        );
+        let mut r = super::retry::retry(|| get.clone().send()).await?;
+        Ok(Batch {
+            found: r
+                .responses
+                .as_mut()
+                .and_then(|r| r.remove("questions"))
+                .unwrap_or_default()
+                .into_iter()
+                .map(store::from_dynamo)
+                .collect(),
+            unprocessed: r
+                .unprocessed_keys()
+                .and_then(|r| r.get("questions"))
+                .and_then(|r| r.keys())
+                .into_iter()
+                .flatten()
+                .filter_map(|k| k.get("id")?.as_s().ok()?.parse().ok())
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl QuestionsStore for Mutex<Local> {
+    async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError> {
+        let mut local = super::lock(self);
+        let Local { questions, .. } = &mut *local;
+
+        Ok(Batch {
+            found: qids
+                .iter()
+                .filter_map(|qid| {
+                    Some(
+                        questions
+                            .get(qid)?
+                            .iter()
+                            .filter(|&(k, _)| ATTRIBUTES.contains(k))
+                            .map(|(k, v)| (k.to_string(), v.clone()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+            unprocessed: Vec::new(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl QuestionsStore for crate::redis::Redis {
+    async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError> {
+        let keys: Vec<_> = qids.iter().map(crate::redis::question_key).collect();
+        Ok(Batch {
+            found: self
+                .items(&keys)
+                .await?
+                .into_iter()
+                .flatten()
+                .map(|mut q| {
+                    q.retain(|k, _| ATTRIBUTES.contains(&k.as_str()));
+                    q
+                })
+                .collect(),
+            unprocessed: Vec::new(),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl QuestionsStore for crate::sqlite::Sqlite {
+    async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError> {
+        let found = self.run(|con| {
+            qids.iter()
+                .map(|qid| crate::sqlite::get(con, crate::sqlite::Table::Questions, qid))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        Ok(Batch {
+            found: found
+                .into_iter()
+                .flatten()
+                .map(|mut q| {
+                    q.retain(|k, _| ATTRIBUTES.contains(&k.as_str()));
+                    q
+                })
+                .collect(),
+            unprocessed: Vec::new(),
+        })
+    }
+}
+
+/// Fetches one batch of questions, re-requesting the ones the store didn't get around to.
+///
+/// DynamoDB only leaves keys unprocessed when it's short on capacity, so it's given a while (the
+/// same backoff as throttled requests get) before each re-request, and only so many of them.
+async fn questions_batch<S>(store: &S, mut todo: Vec<Ulid>) -> Result<Vec<Item>, StoreError>
+where
+    S: QuestionsStore + ?Sized,
+{
+    let mut found = Vec::with_capacity(todo.len());
+    for attempt in 0..MAX_RETRIES {
+        if todo.is_empty() {
+            break;
+        }
+        if attempt != 0 {
+            let delay = super::retry::backoff(attempt - 1);
+            debug!(
+                attempt,
+                ?delay,
+                left = todo.len(),
+                "re-requesting unprocessed questions"
+            );
+            tokio::time::sleep(delay).await;
+        }
+        let r = store.questions(&todo).await?;
+        found.extend(r.found);
+        todo = r.unprocessed;
+    }
+    if !todo.is_empty() {
+        warn!(left = todo.len(), "giving up on unprocessed questions");
+    }
+    Ok(found)
+}
+
+/// Fetches any number of questions from `store`, keyed by their id.
+async fn by_id<S>(store: &S, qids: &[Ulid]) -> Result<HashMap<String, Item>, StoreError>
+where
+    S: QuestionsStore + ?Sized,
+{
+    let batches = batches(qids);
+    let results =
+        futures_util::future::join_all(batches.into_iter().map(|b| questions_batch(store, b)))
+            .await;
+
+    let mut found = HashMap::with_capacity(qids.len());
+    for r in results {
+        for q in r? {
+            if let Some(qid) = q.get("id").and_then(|v| v.as_s().ok()) {
+                found.insert(qid.clone(), q);
+            }
+        }
+    }
+    Ok(found)
+}
+
+impl dyn Store {
+    /// Fetches any number of questions, keyed by their id.
+    ///
+    /// The batches are requested all at once, so this takes about as long as the slowest one.
+    /// Questions that don't exist (or that DynamoDB repeatedly fails to return) are left out.
+    pub(super) async fn questions_by_id(
         &self,
         qids: &[Ulid],
-    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                let keys = qids
-                    .iter()
-                    .map(|qid| {
-                        HashMap::from_iter([(
-                            String::from("id"),
-                            AttributeValue::S(qid.to_string()),
-                        )])
-                    })
-                    .collect();
-                dynamo
-                    .batch_get_item()
-                    .request_items(
-                        "questions",
-                        KeysAndAttributes::builder()
-                            .set_keys(Some(keys))
-                            .projection_expression("id,#text,#when,who")
-                            .expression_attribute_names("#text", "text")
-                            .expression_attribute_names("#when", "when")
-                            .build(),
-                    )
-                    .send()
-                    .await
+    ) -> Result<HashMap<String, Item>, StoreError> {
+        by_id(self, qids).await
+    }
+}
+
+/// Splits `qids` into as few BatchGetItem requests as will fit them.
+fn batches(qids: &[Ulid]) -> Vec<Vec<Ulid>> {
+    let mut qids = qids.to_vec();
+    // dynamodb rejects batches with duplicate keys
+    qids.sort_unstable();
+    qids.dedup();
+    qids.chunks(BATCH_SIZE).map(<[Ulid]>::to_vec).collect()
+}
+
+/// Turns a stored question into what we send to clients, keyed by its id.
+fn serialize_question(q: &Item) -> Option<(String, Value)> {
+    let qid = q
+        .get("id")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|v| ulid::Ulid::from_string(v).ok());
+    let text = q.get("text").and_then(|v| v.as_s().ok());
+    let who = q.get("who").and_then(|v| v.as_s().ok());
+    let when = q
+        .get("when")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    match (qid, text, when) {
+        (Some(qid), Some(text), Some(when)) => {
+            let mut v = serde_json::json!({
+                "text": text,
+                "when": when,
+            });
+            if let Some(who) = who {
+                v["who"] = who.clone().into();
             }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local { questions, .. } = &mut *local;
-
-                let unprocessed: Vec<_> = qids
-                    .iter()
-                    .filter(|qid| !questions.contains_key(qid))
-                    .map(|qid| {
-                        HashMap::from_iter([(
-                            String::from("id"),
-                            AttributeValue::S(qid.to_string()),
-                        )])
-                    })
-                    .collect();
-                let unprocessed = if unprocessed.is_empty() {
-                    None
-                } else {
-                    Some(HashMap::from_iter([(
-                        String::from("questions"),
-                        KeysAndAttributes::builder()
-                            .set_keys(Some(unprocessed))
-                            .projection_expression("text,when,who")
-                            .build(),
-                    )]))
-                };
-
-                Ok(BatchGetItemOutput::builder()
-                    .set_unprocessed_keys(unprocessed)
-                    .set_responses(Some(HashMap::from_iter([(
-                        String::from("questions"),
-                        qids.iter()
-                            .filter_map(|qid| {
-                                Some(
-                                    questions
-                                        .get(qid)?
-                                        .iter()
-                                        .filter(|&(k, _)| {
-                                            matches!(*k, "id" | "text" | "when" | "who")
-                                        })
-                                        .map(|(k, v)| (k.to_string(), v.clone()))
-                                        .collect(),
-                                )
-                            })
-                            .collect(),
-                    )])))
-                    .build())
+            if let Some(answer) = q.get("answer").and_then(|v| v.as_s().ok()) {
+                v["answer"] = answer.clone().into();
             }
+            Some((qid.to_string(), v))
+        }
+        _ => {
+            error!(?q, "bad data types for id/text/when");
+            None
         }
     }
 }
 
+/// Gives the texts (and authors, and answers) of the comma-separated `qids`.
+///
+/// The response is dated to when the most recently changed of the questions last changed.
 pub(super) async fn questions(
     Path(qids): Path<String>,
     State(dynamo): State<Backend>,
 ) -> (
-    AppendHeaders<[(HeaderName, &'static str); 1]>,
-    Result<Json<Value>, StatusCode>,
+    (
+        AppendHeaders<[(HeaderName, &'static str); 1]>,
+        Option<LastModified>,
+    ),
+    Result<Json<Value>, ApiError>,
 ) {
     let qids: Vec<_> = match qids.split(',').map(Ulid::from_string).collect() {
         Ok(v) => v,
@@ -116,120 +284,122 @@ pub(super) async fn questions(
             warn!(%qids, error = %e, "got invalid uuid set");
             return (
                 // a bad request will never become good
-                AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
-                Err(http::StatusCode::BAD_REQUEST),
+                (
+                    AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                    None,
+                ),
+                Err(ApiError::BadRequest),
             );
         }
     };
     match dynamo.questions(&qids).await {
         Ok(v) => {
-            if v.responses().map_or(true, |r| r.is_empty()) {
+            if v.found.is_empty() {
                 warn!(?qids, "no valid qids");
                 return (
                     // it should be unlikely that someone fetches a question that hasn't been asked
                     // it's _possible_ that it happens and _then_ a question is assigned that uuid,
                     // but it too seems rare.
-                    AppendHeaders([(header::CACHE_CONTROL, "max-age=600")]),
-                    Err(http::StatusCode::NOT_FOUND),
+                    (
+                        AppendHeaders([(header::CACHE_CONTROL, "max-age=600")]),
+                        None,
+                    ),
+                    Err(ApiError::QuestionNotFound),
                 );
             }
-            let r = v.responses().unwrap();
-            let t = if let Some(t) = r.get("questions") {
-                t
-            } else {
-                error!(?qids, ?v, "got non-empty non-questions response");
-                return (
-                    AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
-                    Err(http::StatusCode::INTERNAL_SERVER_ERROR),
-                );
-            };
-
+            let t = &v.found;
             let r = t
                 .iter()
-                .map(|q| {
-                    let qid = q
-                        .get("id")
-                        .and_then(|v| v.as_s().ok())
-                        .and_then(|v| ulid::Ulid::from_string(v).ok());
-                    let text = q.get("text").and_then(|v| v.as_s().ok());
-                    let who = q.get("who").and_then(|v| v.as_s().ok());
-                    let when = q
-                        .get("when")
-                        .and_then(|v| v.as_n().ok())
-                        .and_then(|v| v.parse::<usize>().ok());
-                    match (qid, text, when) {
-                        (Some(qid), Some(text), Some(when)) => {
-                            let mut v = serde_json::json!({
-                                "text": text,
-                                "when": when,
-                            });
-                            if let Some(who) = who {
-                                v["who"] = who.clone().into();
-                            }
-                            Ok((qid.to_string(), v))
-                        }
-                        _ => {
-                            error!(?qids, ?q, "bad data types for id/text/when");
-                            Err(StatusCode::INTERNAL_SERVER_ERROR)
-                        }
-                    }
-                })
+                .map(|q| serialize_question(q).ok_or(ApiError::Internal))
                 .collect::<Result<_, _>>()
                 .map(Json);
             if r.is_ok() {
+                let modified = t.iter().filter_map(crate::lastmodified::of).max();
                 (
-                    AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                    (
+                        AppendHeaders([(header::CACHE_CONTROL, "max-age=864001")]),
+                        modified.map(LastModified),
+                    ),
                     r,
                 )
             } else {
-                (AppendHeaders([(header::CACHE_CONTROL, "no-cache")]), r)
+                (
+                    (AppendHeaders([(header::CACHE_CONTROL, "no-cache")]), None),
+                    r,
+                )
             }
         }
         Err(e) => {
             error!(?qids, error = %e, "dynamodb question request failed");
             (
-                AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
-                Err(http::StatusCode::INTERNAL_SERVER_ERROR),
+                (AppendHeaders([(header::CACHE_CONTROL, "no-cache")]), None),
+                Err(ApiError::Internal),
             )
         }
     }
 }
 
+/// Fetches many questions at once, for clients that would otherwise need very long URLs.
+///
+/// Unlike [`questions`], questions that don't exist are just left out of the response.
+pub(super) async fn questions_post(
+    State(dynamo): State<Backend>,
+    Json(qids): Json<Vec<Ulid>>,
+) -> Result<Json<Value>, ApiError> {
+    match dynamo.questions_by_id(&qids).await {
+        Ok(qs) => Ok(Json(
+            qs.values()
+                .filter_map(serialize_question)
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+        )),
+        Err(e) => {
+            error!(n = qids.len(), error = %e, "dynamodb batch question request failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::Attr;
+    use http::StatusCode;
 
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
         let _secret = e["secret"].as_str().unwrap();
         let q1 = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello world".into(),
                 asker: None,
+                tags: Vec::new(),
             }),
         )
         .await
         .unwrap();
         let qid1 = q1["id"].as_str().unwrap();
         let q2 = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello moon".into(),
                 asker: Some("person".into()),
+                tags: Vec::new(),
             }),
         )
         .await
         .unwrap();
         let qid2 = q2["id"].as_str().unwrap();
 
-        let qids = super::questions(Path(format!("{qid1},{qid2}")), State(backend.clone()))
-            .await
-            .1
-            .unwrap();
+        let ((_, modified), qids) =
+            super::questions(Path(format!("{qid1},{qid2}")), State(backend.clone())).await;
+        let qids = qids.unwrap();
 
         let qids = qids.as_object().unwrap();
         let q1 = &qids[qid1];
@@ -240,18 +410,131 @@ mod tests {
         assert_eq!(q2["text"], "hello moon");
         assert_eq!(q2["who"], "person");
         assert!(q2["when"].is_u64());
+        // dated by the newer of the two
+        let newest = q1["when"].as_u64().max(q2["when"].as_u64()).unwrap();
+        assert_eq!(modified, Some(LastModified(newest)));
+
+        // the batch version gives the same, and just ignores unknown (and repeated) qids
+        let unknown = Ulid::new();
+        let batch = super::questions_post(
+            State(backend.clone()),
+            Json(vec![
+                qid1.parse().unwrap(),
+                unknown,
+                qid2.parse().unwrap(),
+                qid1.parse().unwrap(),
+            ]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(batch.0, serde_json::Value::Object(qids.clone()));
+        let batch = super::questions_post(State(backend.clone()), Json(vec![unknown]))
+            .await
+            .unwrap();
+        assert_eq!(batch.0, serde_json::json!({}));
+
+        // but asking for just questions that don't exist is a 404
+        let (_, missing) =
+            super::questions(Path(unknown.to_string()), State(backend.clone())).await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
 
         backend.delete(&eid).await;
     }
 
+    #[test]
+    fn batching() {
+        let qids: Vec<_> = (0..250).map(|_| Ulid::new()).collect();
+        let b = batches(&qids);
+        assert_eq!(b.len(), 3);
+        assert_eq!(b.iter().map(Vec::len).sum::<usize>(), 250);
+        assert!(b.iter().all(|b| b.len() <= BATCH_SIZE));
+
+        // duplicates don't take up room
+        let twice: Vec<_> = qids[..100].iter().chain(&qids[..100]).copied().collect();
+        assert_eq!(batches(&twice).len(), 1);
+        assert!(batches(&[]).is_empty());
+    }
+
+    /// A store that holds some questions back, and counts how often it's asked.
+    #[derive(Default)]
+    struct Flaky {
+        requests: std::sync::atomic::AtomicUsize,
+        /// Left unprocessed the first time they're asked for.
+        once: Mutex<std::collections::HashSet<Ulid>>,
+        /// Left unprocessed every time.
+        never: std::collections::HashSet<Ulid>,
+    }
+
+    #[async_trait]
+    impl QuestionsStore for Flaky {
+        async fn questions(&self, qids: &[Ulid]) -> Result<Batch, StoreError> {
+            self.requests
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let mut once = self.once.lock().unwrap();
+            let (held, given): (Vec<Ulid>, Vec<Ulid>) = qids
+                .iter()
+                .partition(|qid| once.remove(qid) || self.never.contains(qid));
+            let key =
+                |qid: &Ulid| HashMap::from_iter([(String::from("id"), Attr::S(qid.to_string()))]);
+            Ok(Batch {
+                found: given.iter().map(key).collect(),
+                unprocessed: held,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trips() {
+        let mut qids: Vec<_> = (0..250).map(|_| Ulid::new()).collect();
+        qids.sort_unstable();
+        let requests = |store: &Flaky| store.requests.load(std::sync::atomic::Ordering::SeqCst);
+
+        // one request per batch when everything comes back straight away
+        let store = Flaky::default();
+        assert_eq!(by_id(&store, &qids).await.unwrap().len(), 250);
+        assert_eq!(requests(&store), qids.len().div_ceil(BATCH_SIZE));
+
+        // and one more for a batch that comes back partially
+        let store = Flaky {
+            once: Mutex::new(qids[..5].iter().copied().collect()),
+            ..Default::default()
+        };
+        assert_eq!(by_id(&store, &qids).await.unwrap().len(), 250);
+        assert_eq!(requests(&store), qids.len().div_ceil(BATCH_SIZE) + 1);
+
+        // but questions that never come back are only asked for so many times
+        let store = Flaky {
+            never: qids[..5].iter().copied().collect(),
+            ..Default::default()
+        };
+        assert_eq!(by_id(&store, &qids).await.unwrap().len(), 245);
+        assert_eq!(
+            requests(&store),
+            qids.len().div_ceil(BATCH_SIZE) + MAX_RETRIES as usize - 1
+        );
+    }
+
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
     }
 }
@@ -0,0 +1,382 @@
+use axum::{
+    extract::ConnectInfo,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::{header, HeaderMap, HeaderValue, Request, StatusCode};
+use lambda_http::request::RequestContext;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tower::Layer;
+use tower_service::Service;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 60;
+
+/// Once we track this many clients, we forget about the ones that have been idle for long enough
+/// that their bucket is full again.
+const PRUNE_AT: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// A token bucket per client IP.
+///
+/// Every client can make up to a minute's worth of requests in a burst, after which it gets one
+/// more request every `60 / per_minute` seconds.
+#[derive(Debug)]
+struct Limiter {
+    per_minute: u32,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl Limiter {
+    fn capacity(&self) -> f64 {
+        f64::from(self.per_minute)
+    }
+
+    fn refill(&self, b: &mut Bucket, now: Instant) {
+        let rate = self.capacity() / 60.0;
+        let elapsed = now.saturating_duration_since(b.last).as_secs_f64();
+        b.tokens = (b.tokens + elapsed * rate).min(self.capacity());
+        b.last = now;
+    }
+
+    /// Takes a token from `ip`'s bucket, or says how long until there'll be one.
+    fn check(&mut self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.buckets.len() >= PRUNE_AT {
+            let capacity = self.capacity();
+            let buckets = std::mem::take(&mut self.buckets);
+            self.buckets = buckets
+                .into_iter()
+                .filter_map(|(ip, mut b)| {
+                    self.refill(&mut b, now);
+                    (b.tokens < capacity).then_some((ip, b))
+                })
+                .collect();
+        }
+
+        let mut b = self.buckets.get(&ip).copied().unwrap_or(Bucket {
+            tokens: self.capacity(),
+            last: now,
+        });
+        self.refill(&mut b, now);
+        let r = if b.tokens >= 1.0 {
+            b.tokens -= 1.0;
+            Ok(())
+        } else {
+            let rate = self.capacity() / 60.0;
+            Err(Duration::from_secs_f64((1.0 - b.tokens) / rate))
+        };
+        self.buckets.insert(ip, b);
+        r
+    }
+}
+
+const DEFAULT_PROXY_HOPS: usize = 1;
+
+/// Returns how many proxies in front of API Gateway add the address they got a request from to
+/// `X-Forwarded-For`, as configured through `TRUSTED_PROXY_HOPS`.
+///
+/// That's one by default, for CloudFront. `TRUSTED_PROXY_HOPS=0` is for when clients talk to API
+/// Gateway directly. Panics if `TRUSTED_PROXY_HOPS` is set but isn't a number.
+pub(super) fn proxy_hops() -> usize {
+    static HOPS: OnceLock<usize> = OnceLock::new();
+    *HOPS.get_or_init(|| match std::env::var("TRUSTED_PROXY_HOPS") {
+        Ok(n) => n
+            .parse()
+            .expect("TRUSTED_PROXY_HOPS must be a number of proxies"),
+        Err(_) => DEFAULT_PROXY_HOPS,
+    })
+}
+
+/// Returns the IP of the client as given by the first address in `X-Forwarded-For`, if any.
+///
+/// Only requests that have been through [`forwarded_for`] can be trusted to have the client's
+/// real address there.
+pub(super) fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+/// Returns the address API Gateway got the request from, which Lambda passes along.
+fn gateway_ip(extensions: &http::Extensions) -> Option<IpAddr> {
+    match extensions.get::<RequestContext>()? {
+        RequestContext::ApiGatewayV2(ctx) => ctx.http.source_ip.as_deref()?.parse().ok(),
+    }
+}
+
+/// Returns the IP of the client that sent `req`, as far as it can be trusted.
+///
+/// Clients can put whatever they like in `X-Forwarded-For`, so the only addresses we go by are the
+/// one API Gateway (or outside of Lambda, the server) got the request from, and the ones the
+/// `hops` proxies in front of it appended for where they got it from. Within those, the client is
+/// the one furthest out. A request that went around the proxies has fewer addresses than that, and
+/// then the furthest out we can trust is all there is.
+fn client_ip_with<B>(req: &Request<B>, hops: usize) -> Option<IpAddr> {
+    let peer = gateway_ip(req.extensions()).or_else(|| {
+        req.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ci| ci.0.ip())
+    })?;
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    let mut forwarded = forwarded.into_iter().rev().peekable();
+    // some gateways add the address they got the request from themselves.
+    if forwarded.peek() == Some(&Some(peer)) {
+        forwarded.next();
+    }
+    // counting from the peer, the last address was appended by the proxy closest to us, and so
+    // on. an address that doesn't parse can't be trusted to be the client, nor what's beyond it.
+    let client = forwarded.take(hops).map_while(|ip| ip).last();
+    Some(client.unwrap_or(peer))
+}
+
+/// Returns the IP of the client that sent `req`, going by [`proxy_hops`] proxies.
+pub(super) fn client_ip<B>(req: &Request<B>) -> Option<IpAddr> {
+    client_ip_with(req, proxy_hops())
+}
+
+/// Replaces `X-Forwarded-For` with just the [client's address](client_ip), so that the rate limits
+/// and de-duplicating votes by IP can't be dodged by making up addresses.
+pub(super) async fn forwarded_for<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    match client_ip(&req) {
+        Some(ip) => {
+            let ip = HeaderValue::from_str(&ip.to_string()).expect("ips are valid header values");
+            req.headers_mut().insert("x-forwarded-for", ip);
+        }
+        None => {
+            req.headers_mut().remove("x-forwarded-for");
+        }
+    }
+    next.run(req).await
+}
+
+/// Limits how often each client IP may make requests, as configured through
+/// `RATE_LIMIT_PER_MINUTE`.
+///
+/// All routes this layer is applied to (through the same layer or clones of it) share the limit.
+#[derive(Clone, Debug)]
+pub(super) struct RateLimitLayer {
+    limiter: Option<Arc<Mutex<Limiter>>>,
+}
+
+impl RateLimitLayer {
+    /// Panics if `RATE_LIMIT_PER_MINUTE` is set but isn't a whole number.
+    ///
+    /// Setting `RATE_LIMIT_PER_MINUTE=0` turns rate limiting off.
+    pub(super) fn from_env() -> Self {
+        let per_minute = match std::env::var("RATE_LIMIT_PER_MINUTE") {
+            Ok(n) => n
+                .parse()
+                .expect("RATE_LIMIT_PER_MINUTE must be a whole number of requests"),
+            Err(_) => DEFAULT_REQUESTS_PER_MINUTE,
+        };
+        Self::new(per_minute)
+    }
+
+    pub(super) fn new(per_minute: u32) -> Self {
+        Self {
+            limiter: (per_minute != 0).then(|| {
+                Arc::new(Mutex::new(Limiter {
+                    per_minute,
+                    buckets: HashMap::new(),
+                }))
+            }),
+        }
+    }
+}
+
+impl RateLimitLayer {
+    /// Counts a request from `ip` that doesn't go through the layer, like a vote over a
+    /// websocket, against the same limit. Says how long until the client may try again if it's
+    /// over the limit.
+    pub(super) fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        match &self.limiter {
+            Some(limiter) => limiter.lock().unwrap().check(ip, Instant::now()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct RateLimit<S> {
+    inner: S,
+    limiter: Option<Arc<Mutex<Limiter>>>,
+}
+
+impl<S, B> Service<Request<B>> for RateLimit<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let (Some(limiter), Some(ip)) = (&self.limiter, client_ip(&req)) {
+            let r = limiter.lock().unwrap().check(ip, Instant::now());
+            if let Err(wait) = r {
+                warn!(%ip, path = %req.uri().path(), "rate limiting client");
+                // round up, since waiting for less than we said wouldn't be enough.
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                let res = (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, HeaderValue::from(secs))],
+                    Json(crate::error::ApiError::RateLimited.body()),
+                )
+                    .into_response();
+                return Box::pin(async move { Ok(res) });
+            }
+        }
+
+        Box::pin(self.inner.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn bucket() {
+        let mut l = Limiter {
+            per_minute: 2,
+            buckets: HashMap::new(),
+        };
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let t = Instant::now();
+
+        assert!(l.check(ip, t).is_ok());
+        assert!(l.check(ip, t).is_ok());
+        let wait = l.check(ip, t).unwrap_err();
+        assert_eq!(wait.as_secs(), 30);
+        // other clients aren't affected
+        assert!(l.check(other, t).is_ok());
+        // and the bucket refills over time
+        assert!(l.check(ip, t + Duration::from_secs(15)).is_err());
+        assert!(l.check(ip, t + Duration::from_secs(30)).is_ok());
+        assert!(l.check(ip, t + Duration::from_secs(30)).is_err());
+    }
+
+    fn from(peer: &str, forwarded: Option<&str>) -> Request<axum::body::Body> {
+        let mut req = Request::post("/");
+        if let Some(forwarded) = forwarded {
+            req = req.header("x-forwarded-for", forwarded);
+        }
+        let mut req = req.body(axum::body::Body::empty()).unwrap();
+        let peer = SocketAddr::new(peer.parse().unwrap(), 443);
+        req.extensions_mut().insert(ConnectInfo(peer));
+        req
+    }
+
+    #[test]
+    fn trusted() {
+        let ip = |peer, forwarded, hops| client_ip_with(&from(peer, forwarded), hops).unwrap();
+        let addr = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        // without proxies, it's whoever we got the request from
+        assert_eq!(ip("10.0.0.1", None, 0), addr("10.0.0.1"));
+        assert_eq!(ip("10.0.0.1", Some("1.2.3.4"), 0), addr("10.0.0.1"));
+        // a proxy says who it got the request from, but what's beyond that is made up
+        assert_eq!(ip("10.0.0.1", Some("1.2.3.4, 5.6.7.8"), 1), addr("5.6.7.8"));
+        assert_eq!(
+            ip("10.0.0.1", Some("1.2.3.4, 5.6.7.8, 10.0.0.1"), 1),
+            addr("5.6.7.8")
+        );
+        assert_eq!(
+            ip("10.0.0.1", Some("1.2.3.4, nonsense"), 1),
+            addr("10.0.0.1")
+        );
+        // requests that went around the proxy are taken to be from where they came from
+        assert_eq!(ip("10.0.0.1", None, 1), addr("10.0.0.1"));
+
+        // behind API Gateway, it's where the gateway got the request from
+        let mut req = from("10.0.0.1", Some("1.2.3.4"));
+        let mut ctx =
+            lambda_http::aws_lambda_events::apigw::ApiGatewayV2httpRequestContext::default();
+        ctx.http.source_ip = Some("5.6.7.8".into());
+        req.extensions_mut()
+            .insert(RequestContext::ApiGatewayV2(ctx));
+        assert_eq!(client_ip_with(&req, 0), Some(addr("5.6.7.8")));
+        assert_eq!(client_ip_with(&req, 1), Some(addr("1.2.3.4")));
+    }
+
+    #[tokio::test]
+    async fn layer() {
+        let app = Router::new()
+            .route("/", post(|| async {}).layer(RateLimitLayer::new(1)))
+            .layer(axum::middleware::from_fn(forwarded_for));
+        let send = |peer: &'static str, forwarded: &'static str| {
+            app.clone().oneshot(from(peer, Some(forwarded)))
+        };
+
+        assert_eq!(
+            send("10.0.0.1", "1.1.1.1").await.unwrap().status(),
+            StatusCode::OK
+        );
+        // making up where the request came from doesn't get a client a fresh bucket
+        let res = send("10.0.0.1", "2.2.2.2, 1.1.1.1").await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+        assert_eq!(
+            send("10.0.0.2", "2.2.2.2, 1.1.1.1").await.unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send("10.0.0.2", "2.2.2.2, 3.3.3.3").await.unwrap().status(),
+            StatusCode::OK
+        );
+
+        // 0 means no limit
+        let app = Router::new().route("/", post(|| async {}).layer(RateLimitLayer::new(0)));
+        for _ in 0..3 {
+            let res = app.clone().oneshot(from("10.0.0.1", None)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use super::Local;
+use std::{
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime},
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+/// The most events reaped while holding the lock once, so that a pile of expired events doesn't
+/// hold up requests. The rest are left for the next round.
+const MAX_PER_ROUND: usize = 100;
+
+/// Returns how often the local backend looks for expired events, as configured through
+/// `REAP_INTERVAL_SECS`.
+///
+/// `None` means it never does, which is what `REAP_INTERVAL_SECS=0` asks for; expired events are
+/// then only removed when they're next looked up. Panics if `REAP_INTERVAL_SECS` is set but isn't
+/// a number.
+pub(super) fn interval() -> Option<Duration> {
+    static INTERVAL: OnceLock<Option<Duration>> = OnceLock::new();
+    *INTERVAL.get_or_init(|| {
+        let secs = match std::env::var("REAP_INTERVAL_SECS") {
+            Ok(n) => n
+                .parse()
+                .expect("REAP_INTERVAL_SECS must be a number of seconds"),
+            Err(_) => DEFAULT_INTERVAL_SECS,
+        };
+        (secs != 0).then(|| Duration::from_secs(secs))
+    })
+}
+
+impl Local {
+    /// Removes up to `max` events that have expired, along with their questions and votes.
+    ///
+    /// Returns how many events were removed.
+    fn reap_expired(&mut self, max: usize) -> usize {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expired: Vec<Ulid> = self
+            .events
+            .iter()
+            .filter(|(_, e)| {
+                e.get("expire")
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .is_some_and(|at| at <= now)
+            })
+            .map(|(eid, _)| *eid)
+            .take(max)
+            .collect();
+        for eid in &expired {
+            self.remove_event(eid);
+        }
+        expired.len()
+    }
+}
+
+/// Removes expired events from the local backend's state `local` every [`interval`], for as long
+/// as the server runs.
+///
+/// DynamoDB has TTLs to do this, so it needs no reaping.
+pub(super) fn reap_periodically(local: Arc<Mutex<Local>>) {
+    let Some(every) = interval() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        // a slow round shouldn't be followed by a burst of catch-up rounds.
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let n = super::lock(&local).reap_expired(MAX_PER_ROUND);
+            if n != 0 {
+                info!(n, "reaped expired events");
+            } else {
+                trace!("no expired events to reap");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Attr;
+
+    #[tokio::test]
+    async fn reap() {
+        let (backend, local) = crate::local_state();
+        let mut eids = Vec::new();
+        for _ in 0..3 {
+            let e = crate::new::new(axum::extract::State(backend.clone()), String::new())
+                .await
+                .unwrap();
+            eids.push(Ulid::from_string(e["id"].as_str().unwrap()).unwrap());
+        }
+        let mut local = crate::lock(&local);
+        // as though the first two had been around for a while
+        for eid in &eids[..2] {
+            local
+                .events
+                .get_mut(eid)
+                .unwrap()
+                .insert("expire", Attr::N(0.to_string()));
+        }
+
+        assert_eq!(local.reap_expired(1), 1);
+        assert_eq!(local.reap_expired(MAX_PER_ROUND), 1);
+        assert_eq!(local.reap_expired(MAX_PER_ROUND), 0);
+        assert!(!local.events.contains_key(&eids[0]));
+        assert!(!local.questions_by_eid.contains_key(&eids[1]));
+        assert!(local.events.contains_key(&eids[2]));
+    }
+}
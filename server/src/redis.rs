@@ -0,0 +1,387 @@
+use crate::store::{self, Attr, Item, Kind, StoreError};
+use redis::aio::MultiplexedConnection;
+use redis::{FromRedisValue, Pipeline};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How many times a transaction starts over because what it read changed before it could write,
+/// before it gives up.
+///
+/// Every vote on a question is a transaction on it, so popular questions see a lot of these.
+const MAX_CONFLICTS: u32 = 20;
+
+/// Returns the Redis server to keep events and questions in, as configured through `REDIS_URL`.
+///
+/// Panics if `REDIS_URL` isn't set, since there's no sensible server to default to.
+pub(super) fn url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        std::env::var("REDIS_URL").expect("BACKEND=redis needs a REDIS_URL like redis://127.0.0.1/")
+    })
+}
+
+/// Events and questions kept in Redis.
+///
+/// Each event is a hash at `event:<eid>`, and each question a hash at `question:<qid>`, with one
+/// field per attribute. The questions of an event are also kept in a sorted set at
+/// `questions:<eid>`, scored by votes, so that the top questions can be listed without looking at
+/// all the others. Keys expire along with the items they're for, like DynamoDB's TTLs do.
+pub(super) struct Redis {
+    client: redis::Client,
+    /// Shared by everything that doesn't need a connection to itself.
+    con: MultiplexedConnection,
+    /// Connections for transactions, which each need one to themselves since `WATCH` watches on
+    /// behalf of the whole connection.
+    idle: Mutex<Vec<MultiplexedConnection>>,
+}
+
+impl std::fmt::Debug for Redis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Redis").finish_non_exhaustive()
+    }
+}
+
+impl From<redis::RedisError> for StoreError {
+    fn from(e: redis::RedisError) -> Self {
+        Self::Other(Box::new(e))
+    }
+}
+
+pub(super) fn event_key(eid: &Ulid) -> String {
+    format!("event:{eid}")
+}
+
+pub(super) fn question_key(qid: &Ulid) -> String {
+    format!("question:{qid}")
+}
+
+/// The sorted set of the questions of `eid`, scored by votes.
+pub(super) fn questions_key(eid: &Ulid) -> String {
+    format!("questions:{eid}")
+}
+
+/// The hash of how each voter voted on `qid`.
+pub(super) fn voters_key(qid: &Ulid) -> String {
+    format!("voters:{qid}")
+}
+
+/// The hash of how many up-votes each voter has used of the budget of `eid`.
+pub(super) fn spent_key(eid: &Ulid) -> String {
+    format!("spent:{eid}")
+}
+
+/// The list of when votes in `eid` came in, as `<when>:<delta>`.
+pub(super) fn vote_log_key(eid: &Ulid) -> String {
+    format!("vote_log:{eid}")
+}
+
+pub(super) fn idempotency_key(eid: &Ulid, key: &str) -> String {
+    format!("idempotency:{eid}:{key}")
+}
+
+pub(super) fn code_key(code: &str) -> String {
+    format!("code:{code}")
+}
+
+/// Gives `v` as it's kept in a hash field, where it holds an attribute of the given `kind`.
+fn encode(kind: Kind, v: &Attr) -> Result<String, StoreError> {
+    Ok(match (kind, v) {
+        (Kind::S, Attr::S(s)) | (Kind::N, Attr::N(s)) => s.clone(),
+        (Kind::Bool, Attr::Bool(b)) => b.to_string(),
+        (Kind::Ss, Attr::Ss(ss)) => serde_json::to_string(ss).expect("strings serialize"),
+        (Kind::M, Attr::M(m)) => serde_json::to_string(m).expect("attributes serialize"),
+        _ => return Err(StoreError::Other(format!("{v:?} isn't a {kind:?}").into())),
+    })
+}
+
+/// Turns the value of a hash field back into the attribute of the given `kind` it holds.
+fn decode(kind: Kind, v: String) -> Result<Attr, StoreError> {
+    let malformed = |e: serde_json::Error| StoreError::Other(Box::new(e));
+    Ok(match kind {
+        Kind::S => Attr::S(v),
+        Kind::N => Attr::N(v),
+        Kind::Bool => Attr::Bool(v == "true"),
+        Kind::Ss => Attr::Ss(serde_json::from_str(&v).map_err(malformed)?),
+        Kind::M => Attr::M(serde_json::from_str(&v).map_err(malformed)?),
+    })
+}
+
+/// Turns the fields of a hash holding one of the given `attributes` back into an item.
+///
+/// Hashes that aren't there come back from Redis as empty, and so give `None`.
+fn decode_item(
+    attributes: &[(&str, Kind)],
+    fields: HashMap<String, String>,
+) -> Result<Option<Item>, StoreError> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    fields
+        .into_iter()
+        .map(|(k, v)| {
+            let v = decode(store::kind_of(attributes, &k)?, v)?;
+            Ok((k, v))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// The attributes kept in the hash at `key`.
+fn attributes_of(key: &str) -> &'static [(&'static str, Kind)] {
+    if key.starts_with("event:") {
+        store::EVENT_ATTRIBUTES
+    } else {
+        store::QUESTION_ATTRIBUTES
+    }
+}
+
+/// Adds writing the `attrs` of the item at `key` to `pipe`, leaving any other fields be.
+///
+/// If the item has an `expire` time, the key is set to expire then too.
+pub(super) fn put<'a, K>(
+    pipe: &mut Pipeline,
+    key: &str,
+    attrs: impl IntoIterator<Item = (&'a K, &'a Attr)>,
+) -> Result<(), StoreError>
+where
+    K: AsRef<str> + ?Sized + 'a,
+{
+    let attributes = attributes_of(key);
+    let mut fields = Vec::new();
+    let mut expire = None;
+    for (k, v) in attrs {
+        let k = k.as_ref();
+        let v = encode(store::kind_of(attributes, k)?, v)?;
+        if k == "expire" {
+            expire = Some(v.clone());
+        }
+        fields.push((k.to_string(), v));
+    }
+    if fields.is_empty() {
+        return Ok(());
+    }
+    pipe.cmd("HSET").arg(key).arg(&fields).ignore();
+    if let Some(expire) = expire {
+        pipe.cmd("EXPIREAT").arg(key).arg(expire).ignore();
+    }
+    Ok(())
+}
+
+/// Adds writing whatever changed between `before` and `after` at `key` to `pipe`.
+pub(super) fn put_changes(
+    pipe: &mut Pipeline,
+    key: &str,
+    before: &Item,
+    after: &Item,
+) -> Result<(), StoreError> {
+    put(
+        pipe,
+        key,
+        after.iter().filter(|&(k, v)| before.get(k) != Some(v)),
+    )?;
+    let gone: Vec<_> = before.keys().filter(|k| !after.contains_key(*k)).collect();
+    if !gone.is_empty() {
+        pipe.hdel(key, gone).ignore();
+    }
+    if key.starts_with("question:") && before.get("votes") != after.get("votes") {
+        index(pipe, after);
+    }
+    Ok(())
+}
+
+/// Adds (re)placing the question `q` in the sorted set of its event's questions to `pipe`.
+pub(super) fn index(pipe: &mut Pipeline, q: &Item) {
+    let id = q.get("id").and_then(|v| v.as_s().ok());
+    let eid = q.get("eid").and_then(|v| v.as_s().ok());
+    let votes = q
+        .get("votes")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if let (Some(id), Some(eid), Some(votes)) = (id, eid, votes) {
+        pipe.zadd(format!("questions:{eid}"), id, votes).ignore();
+    }
+}
+
+/// Adds writing the newly asked (or imported) question `q` to `pipe`, listed among the other
+/// questions of its event.
+///
+/// The list of an event's questions is kept for as long as the question most recently added to it.
+pub(super) fn add_question(pipe: &mut Pipeline, q: &Item) -> Result<(), StoreError> {
+    let (Some(Attr::S(id)), Some(Attr::S(eid))) = (q.get("id"), q.get("eid")) else {
+        return Err(StoreError::Other("question without id or eid".into()));
+    };
+    put(pipe, &format!("question:{id}"), q)?;
+    index(pipe, q);
+    if let Some(Attr::N(expire)) = q.get("expire") {
+        pipe.cmd("EXPIREAT")
+            .arg(format!("questions:{eid}"))
+            .arg(expire)
+            .ignore();
+    }
+    Ok(())
+}
+
+/// Adds having `key` expire as long from now as a newly asked question would to `pipe`, for
+/// bookkeeping (like who voted how) that needn't outlive the questions it's about.
+pub(super) fn keep(pipe: &mut Pipeline, key: &str) {
+    let secs = crate::ask::QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60;
+    pipe.expire(key, secs as i64).ignore();
+}
+
+impl Redis {
+    /// Connects to the Redis server at `url`.
+    pub(super) async fn connect(url: &str) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url)?;
+        let con = client.get_multiplexed_async_connection().await?;
+        Ok(Self {
+            client,
+            con,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Runs a single command.
+    pub(super) async fn query<T: FromRedisValue>(&self, cmd: &redis::Cmd) -> Result<T, StoreError> {
+        Ok(cmd.query_async(&mut self.con.clone()).await?)
+    }
+
+    /// Runs all the commands in `pipe` in one go.
+    pub(super) async fn run<T: FromRedisValue>(&self, pipe: &Pipeline) -> Result<T, StoreError> {
+        Ok(pipe.query_async(&mut self.con.clone()).await?)
+    }
+
+    /// Fetches the item at `key`, if there is one.
+    pub(super) async fn item(&self, key: &str) -> Result<Option<Item>, StoreError> {
+        let fields = self.query(redis::cmd("HGETALL").arg(key)).await?;
+        decode_item(attributes_of(key), fields)
+    }
+
+    /// Fetches the items at `keys` in one go, with `None` for the ones that aren't there.
+    pub(super) async fn items(&self, keys: &[String]) -> Result<Vec<Option<Item>>, StoreError> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.hgetall(key);
+        }
+        let all: Vec<HashMap<String, String>> = self.run(&pipe).await?;
+        keys.iter()
+            .zip(all)
+            .map(|(key, fields)| decode_item(attributes_of(key), fields))
+            .collect()
+    }
+
+    /// Lists the questions of `eid`, most-voted first.
+    pub(super) async fn qids_of(&self, eid: &Ulid) -> Result<Vec<Ulid>, StoreError> {
+        let qids: Vec<String> = self
+            .query(
+                redis::cmd("ZREVRANGE")
+                    .arg(questions_key(eid))
+                    .arg(0)
+                    .arg(-1),
+            )
+            .await?;
+        Ok(qids.iter().filter_map(|qid| qid.parse().ok()).collect())
+    }
+
+    /// Fetches the questions of `eid`, most-voted first.
+    pub(super) async fn questions_of(&self, eid: &Ulid) -> Result<Vec<Item>, StoreError> {
+        let keys: Vec<_> = self.qids_of(eid).await?.iter().map(question_key).collect();
+        // questions that expired (or were deleted) since we listed them are just left out.
+        Ok(self.items(&keys).await?.into_iter().flatten().collect())
+    }
+
+    /// Has `f` decide what to write given the items at `keys`, and writes it only if none of them
+    /// changed since they were read, starting over if any did.
+    ///
+    /// `f` adds its writes to the pipeline it's given, which runs as a transaction. Whatever it
+    /// returns is returned once that's gone through, unless it fails, in which case nothing is
+    /// written.
+    pub(super) async fn transact<T, F>(&self, keys: &[String], mut f: F) -> Result<T, StoreError>
+    where
+        F: FnMut(Vec<Option<Item>>, &mut Pipeline) -> Result<T, StoreError>,
+    {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .pop();
+        let mut con = match idle {
+            Some(con) => con,
+            None => self.client.get_multiplexed_async_connection().await?,
+        };
+        for attempt in 0..MAX_CONFLICTS {
+            redis::cmd("WATCH")
+                .arg(keys)
+                .query_async::<()>(&mut con)
+                .await?;
+            let mut read = redis::pipe();
+            for key in keys {
+                read.hgetall(key);
+            }
+            let fields: Vec<HashMap<String, String>> = read.query_async(&mut con).await?;
+            let items = keys
+                .iter()
+                .zip(fields)
+                .map(|(key, fields)| decode_item(attributes_of(key), fields))
+                .collect::<Result<_, _>>();
+            let mut write = redis::pipe();
+            write.atomic();
+            let out = match items.and_then(|items| f(items, &mut write)) {
+                Ok(out) => out,
+                Err(e) => {
+                    redis::cmd("UNWATCH").query_async::<()>(&mut con).await?;
+                    self.idle
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(con);
+                    return Err(e);
+                }
+            };
+            // a transaction whose watched keys changed gives nil rather than its results.
+            let done: Option<redis::Value> = write.query_async(&mut con).await?;
+            if done.is_some() {
+                self.idle
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(con);
+                return Ok(out);
+            }
+            trace!(?keys, "transaction conflicted, starting over");
+            // whoever got in first is likely to be at it again, so give them a moment.
+            tokio::time::sleep(crate::retry::backoff(attempt)).await;
+        }
+        self.idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(con);
+        Err(StoreError::Other(
+            format!("gave up on transaction after {MAX_CONFLICTS} conflicts").into(),
+        ))
+    }
+
+    /// Has `f` change the item at `key`, and writes back whatever it changed.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if there's no such item. Otherwise gives the
+    /// item as it is afterwards, along with whatever `f` returned.
+    pub(super) async fn modify<T, F>(&self, key: &str, mut f: F) -> Result<(Item, T), StoreError>
+    where
+        F: FnMut(&mut Item) -> Result<T, StoreError>,
+    {
+        self.transact(&[key.to_string()], |mut items, pipe| {
+            let Some(before) = items.pop().flatten() else {
+                return Err(StoreError::ConditionFailed);
+            };
+            let mut after = before.clone();
+            let out = f(&mut after)?;
+            put_changes(pipe, key, &before, &after)?;
+            Ok((after, out))
+        })
+        .await
+    }
+}
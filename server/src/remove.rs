@@ -0,0 +1,241 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::extract::{Path, State};
+use http::StatusCode;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How a storage engine deletes single questions.
+#[async_trait]
+pub(super) trait RemoveStore {
+    /// Permanently deletes the question `qid`, provided it belongs to the event `eid`.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if the question does not exist in that event.
+    async fn remove(&self, eid: &Ulid, qid: &Ulid) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl RemoveStore for aws_sdk_dynamodb::Client {
+    async fn remove(&self, eid: &Ulid, qid: &Ulid) -> Result<(), StoreError> {
+        // NOTE: the question also disappears from the event's list since the `top` index
+        // is maintained by dynamodb.
+        self.delete_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .condition_expression("eid = :eid")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RemoveStore for Mutex<Local> {
+    async fn remove(&self, eid: &Ulid, qid: &Ulid) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            client_votes,
+            ..
+        } = &mut *local;
+
+        let qs = questions_by_eid.get_mut(eid);
+        let Some(i) = qs.as_ref().and_then(|qs| qs.iter().position(|q| q == qid)) else {
+            return Err(StoreError::ConditionFailed);
+        };
+        qs.expect("found qid in event's question list").remove(i);
+        questions.remove(qid);
+        client_votes.retain(|(q, _), _| q != qid);
+        local.publish(eid, "remove", serde_json::json!({ "qid": qid.to_string() }));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl RemoveStore for crate::redis::Redis {
+    async fn remove(&self, eid: &Ulid, qid: &Ulid) -> Result<(), StoreError> {
+        use crate::redis::{question_key, questions_key, voters_key};
+
+        self.transact(&[question_key(qid)], |mut items, pipe| {
+            if !items
+                .pop()
+                .flatten()
+                .is_some_and(|q| crate::store::belongs_to(&q, eid))
+            {
+                return Err(StoreError::ConditionFailed);
+            }
+            pipe.del(question_key(qid))
+                .del(voters_key(qid))
+                .zrem(questions_key(eid), qid.to_string())
+                .ignore();
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl RemoveStore for crate::sqlite::Sqlite {
+    async fn remove(&self, eid: &Ulid, qid: &Ulid) -> Result<(), StoreError> {
+        self.run(|con| {
+            let removed = con.execute(
+                "DELETE FROM questions WHERE id = ?1 AND eid = ?2",
+                [qid.to_string(), eid.to_string()],
+            )?;
+            if removed == 0 {
+                return Err(StoreError::ConditionFailed);
+            }
+            con.execute("DELETE FROM voters WHERE qid = ?1", [qid.to_string()])?;
+            Ok(())
+        })
+    }
+}
+
+pub(super) async fn remove(
+    Path((eid, secret, qid)): Path<(Ulid, String, Ulid)>,
+    State(dynamo): State<Backend>,
+) -> Result<StatusCode, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    match dynamo.remove(&eid, &qid).await {
+        Ok(_) => {
+            debug!(%eid, %qid, "deleted question");
+            if let Err(e) = dynamo.bury(&eid, &[qid]).await {
+                // the question is gone either way, but clients syncing the list won't hear of it.
+                error!(%eid, %qid, error = %e, "dynamodb request to record deleted question failed");
+            }
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%eid, %qid, "attempted to delete question that isn't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to delete question failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, Json};
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        // a question can't be deleted through another event
+        let e2 = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid2 = Ulid::from_string(e2["id"].as_str().unwrap()).unwrap();
+        let secret2 = e2["secret"].as_str().unwrap();
+        assert_eq!(
+            super::remove(
+                Path((eid2, secret2.to_string(), qid)),
+                State(backend.clone())
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        backend.delete(&eid2).await;
+
+        // nor without the right secret
+        assert_eq!(
+            super::remove(
+                Path((eid, "wrong".to_string(), qid)),
+                State(backend.clone())
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            super::remove(Path((eid, secret.to_string(), qid)), State(backend.clone()))
+                .await
+                .unwrap(),
+            StatusCode::NO_CONTENT
+        );
+
+        // the question is gone everywhere
+        let qs = crate::list::list_all(
+            Path((eid, secret.to_string())),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(qs.as_array().unwrap().len(), 0);
+        assert_eq!(
+            crate::questions::questions(Path(qid.to_string()), State(backend.clone()))
+                .await
+                .1
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        // and deleting it again gives a 404
+        assert_eq!(
+            super::remove(Path((eid, secret.to_string(), qid)), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,375 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::list::ListStore;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    error::TransactWriteItemsErrorKind,
+    model::{AttributeValue, TransactWriteItem, Update},
+    types::SdkError,
+};
+use axum::extract::{Path, State};
+use axum::response::Json;
+use http::StatusCode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The most questions that can be put in order at once, since that has to fit in a transaction.
+pub(super) const MAX_REORDER: usize = 100;
+
+/// Gives the stored question `qid` its place in the running order `qids`, or takes it out of the
+/// order if it isn't in `qids`.
+fn place<K>(q: &mut HashMap<K, Attr>, qid: &Ulid, qids: &[Ulid])
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    let order = qids
+        .iter()
+        .position(|q| q == qid)
+        .map(|i| Attr::N(i.to_string()));
+    let before = match order {
+        Some(ref order) => q.insert("order".into(), order.clone()),
+        None => q.remove("order"),
+    };
+    if before != order {
+        crate::sync::touch(q);
+    }
+}
+
+/// How a storage engine keeps the host's own order of questions.
+#[async_trait]
+pub(super) trait ReorderStore {
+    /// Puts the questions `qids` of `eid` in that running order, and takes all other questions in
+    /// the event out of it.
+    ///
+    /// Either all of the questions get their place, or none do. Returns `false` if any of the
+    /// questions are not in `eid`.
+    async fn reorder(&self, eid: &Ulid, qids: &[Ulid]) -> Result<bool, StoreError>;
+}
+
+#[async_trait]
+impl ReorderStore for aws_sdk_dynamodb::Client {
+    async fn reorder(&self, eid: &Ulid, qids: &[Ulid]) -> Result<bool, StoreError> {
+        let eid_v = AttributeValue::S(eid.to_string());
+        if !qids.is_empty() {
+            let writes = qids
+                .iter()
+                .enumerate()
+                .map(|(i, qid)| {
+                    TransactWriteItem::builder()
+                        .update(
+                            Update::builder()
+                                .table_name("questions")
+                                .key("id", AttributeValue::S(qid.to_string()))
+                                .update_expression("SET #order = :order, updated_at = :updated")
+                                .condition_expression("eid = :eid")
+                                .expression_attribute_names("#order", "order")
+                                .expression_attribute_values(":eid", eid_v.clone())
+                                .expression_attribute_values(":updated", crate::sync::now())
+                                .expression_attribute_values(
+                                    ":order",
+                                    AttributeValue::N(i.to_string()),
+                                )
+                                .build(),
+                        )
+                        .build()
+                })
+                .collect();
+            match self
+                .transact_write_items()
+                .set_transact_items(Some(writes))
+                .send()
+                .await
+            {
+                Ok(_) => {}
+                Err(SdkError::ServiceError { ref err, .. })
+                    if matches!(
+                        &err.kind,
+                        TransactWriteItemsErrorKind::TransactionCanceledException(e)
+                            if e.cancellation_reasons().unwrap_or_default().iter().any(
+                                |r| r.code() == Some("ConditionalCheckFailed")
+                            )
+                    ) =>
+                {
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // the rest lose their place, which doesn't need to be all-or-nothing: a question
+        // that's left with an old place just sorts a little oddly until the next reorder.
+        let mut start = None;
+        loop {
+            let r = self
+                .list(eid, true, &Default::default(), None, start)
+                .await?;
+            let removals = r
+                .items
+                .iter()
+                .filter(|q| q.contains_key("order"))
+                .filter_map(|q| q.get("id"))
+                .filter(|qid| {
+                    qid.as_s()
+                        .ok()
+                        .and_then(|qid| Ulid::from_string(qid).ok())
+                        .is_some_and(|qid| !qids.contains(&qid))
+                })
+                .map(|qid| {
+                    self.update_item()
+                        .table_name("questions")
+                        .key("id", qid.clone().into())
+                        .update_expression("REMOVE #order SET updated_at = :updated")
+                        .expression_attribute_names("#order", "order")
+                        .expression_attribute_values(":updated", crate::sync::now())
+                        .send()
+                });
+            for r in futures_util::future::join_all(removals).await {
+                r?;
+            }
+            start = r.next;
+            if start.is_none() {
+                break;
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl ReorderStore for Mutex<Local> {
+    async fn reorder(&self, eid: &Ulid, qids: &[Ulid]) -> Result<bool, StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let Some(qs) = questions_by_eid.get(eid) else {
+            return Ok(false);
+        };
+        if !qids.iter().all(|qid| qs.contains(qid)) {
+            return Ok(false);
+        }
+        for qid in qs {
+            place(
+                questions.get_mut(qid).expect("listed questions exist"),
+                qid,
+                qids,
+            );
+        }
+
+        local.publish(
+            eid,
+            "reorder",
+            serde_json::json!({
+                "order": qids.iter().map(Ulid::to_string).collect::<Vec<_>>(),
+            }),
+        );
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ReorderStore for crate::redis::Redis {
+    async fn reorder(&self, eid: &Ulid, qids: &[Ulid]) -> Result<bool, StoreError> {
+        let qs = self.qids_of(eid).await?;
+        if !qids.iter().all(|qid| qs.contains(qid)) {
+            return Ok(false);
+        }
+        let keys: Vec<_> = qs.iter().map(crate::redis::question_key).collect();
+        self.transact(&keys, |items, pipe| {
+            for ((qid, key), before) in qs.iter().zip(&keys).zip(items) {
+                let Some(before) = before else {
+                    // deleted since we listed it, which only matters if it was to get a place.
+                    if qids.contains(qid) {
+                        return Ok(false);
+                    }
+                    continue;
+                };
+                let mut after = before.clone();
+                place(&mut after, qid, qids);
+                crate::redis::put_changes(pipe, key, &before, &after)?;
+            }
+            Ok(true)
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ReorderStore for crate::sqlite::Sqlite {
+    async fn reorder(&self, eid: &Ulid, qids: &[Ulid]) -> Result<bool, StoreError> {
+        self.run(|con| {
+            let qs: Vec<(Ulid, _)> = crate::sqlite::questions(con, eid)?
+                .into_iter()
+                .filter_map(|q| {
+                    let qid = q.get("id")?.as_s().ok()?.parse().ok()?;
+                    Some((qid, q))
+                })
+                .collect();
+            if !qids.iter().all(|qid| qs.iter().any(|(id, _)| id == qid)) {
+                return Ok(false);
+            }
+            for (qid, before) in &qs {
+                let mut after = before.clone();
+                place(&mut after, qid, qids);
+                crate::sqlite::update(con, crate::sqlite::Table::Questions, qid, before, &after)?;
+            }
+            Ok(true)
+        })
+    }
+}
+
+/// Sets the host's running order of questions, which `?sort=order` lists them in.
+///
+/// Questions that aren't named go after the ordered ones, by votes.
+pub(super) async fn reorder(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Json(qids): Json<Vec<Ulid>>,
+) -> Result<StatusCode, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    if qids.len() > MAX_REORDER {
+        warn!(%eid, n = qids.len(), "rejecting overly large reorder");
+        return Err(ApiError::BadRequest);
+    }
+    let mut unique = qids.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() != qids.len() {
+        warn!(%eid, "got reorder that names a question twice");
+        return Err(ApiError::BadRequest);
+    }
+
+    match dynamo.reorder(&eid, &qids).await {
+        Ok(true) => {
+            debug!(%eid, n = qids.len(), "reordered questions");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => {
+            warn!(%eid, "attempted to reorder questions that aren't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to reorder questions failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let mut qids = Vec::new();
+        for _ in 0..4 {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        // so the unordered questions have a vote order to fall back to
+        let _ = crate::vote::vote(
+            Path((qids[1], crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let reorder = |qids: Vec<Ulid>| {
+            super::reorder(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Json(qids),
+            )
+        };
+        let listed = || async {
+            let params = serde_json::from_value(serde_json::json!({ "sort": "order" })).unwrap();
+            let qs = crate::list::list(Path(eid), State(backend.clone()), Query(params))
+                .await
+                .1
+                .unwrap()
+                .0;
+            qs.as_array()
+                .unwrap()
+                .iter()
+                .map(|q| q["qid"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // questions from elsewhere can't be put in order
+        assert_eq!(
+            reorder(vec![qids[0], Ulid::new()]).await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            reorder(vec![qids[0], qids[0]]).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(
+            reorder(vec![qids[3], qids[0]]).await.unwrap(),
+            StatusCode::NO_CONTENT
+        );
+        let order = listed().await;
+        assert_eq!(
+            order[..3],
+            [qids[3], qids[0], qids[1]].map(|q| q.to_string())
+        );
+
+        // a new order replaces the old one entirely
+        reorder(vec![qids[2]]).await.unwrap();
+        let order = listed().await;
+        assert_eq!(order[..2], [qids[2], qids[1]].map(|q| q.to_string()));
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,295 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, ReturnValue};
+use axum::extract::{Path, State};
+use http::StatusCode;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_REPORTS_TO_HIDE: u64 = 5;
+
+/// How many reports it takes for a question to be hidden automatically, as configured through
+/// `REPORTS_TO_HIDE`. `0` means questions are never hidden because of reports.
+///
+/// Panics if `REPORTS_TO_HIDE` is set but isn't a whole number.
+pub(super) fn reports_to_hide() -> u64 {
+    static N: OnceLock<u64> = OnceLock::new();
+    *N.get_or_init(|| match std::env::var("REPORTS_TO_HIDE") {
+        Ok(n) => n
+            .parse()
+            .expect("REPORTS_TO_HIDE must be a whole number of reports"),
+        Err(_) => DEFAULT_REPORTS_TO_HIDE,
+    })
+}
+
+/// Gives the number of times the stored question `q` has been reported.
+pub(super) fn reports_of<K>(q: &std::collections::HashMap<K, Attr>) -> u64
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    q.get("reports")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Adds one report to the stored question `q`, and hides it if that takes it to `threshold`
+/// reports.
+///
+/// Returns true if the report is what hid the question.
+fn add_report<K>(q: &mut std::collections::HashMap<K, Attr>, threshold: u64) -> bool
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    let reports = reports_of(q) + 1;
+    q.insert("reports".into(), Attr::N(reports.to_string()));
+    crate::sync::touch(q);
+    let hide = threshold != 0 && reports == threshold && q.get("hidden") != Some(&Attr::Bool(true));
+    if hide {
+        q.insert("hidden".into(), Attr::Bool(true));
+    }
+    hide
+}
+
+/// How a storage engine counts guests' reports of questions.
+#[async_trait]
+pub(super) trait ReportStore {
+    /// Adds one report to `qid`, and hides it if that takes it to `threshold` reports.
+    ///
+    /// Only reaching the threshold hides the question, so a host that unhides it again doesn't
+    /// have it disappear on the very next report.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if the question does not exist. Otherwise returns
+    /// the question's new attributes.
+    async fn report(&self, qid: &Ulid, threshold: u64) -> Result<Item, StoreError>;
+}
+
+#[async_trait]
+impl ReportStore for aws_sdk_dynamodb::Client {
+    async fn report(&self, qid: &Ulid, threshold: u64) -> Result<Item, StoreError> {
+        let upd = || {
+            self.update_item()
+                .table_name("questions")
+                .key("id", AttributeValue::S(qid.to_string()))
+                .expression_attribute_values(":updated", crate::sync::now())
+                .return_values(ReturnValue::AllNew)
+        };
+        let r = upd()
+            .update_expression("SET updated_at = :updated ADD reports :one")
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+            .send()
+            .await?;
+        let r = store::from_dynamo(r.attributes.unwrap_or_default());
+        let reports = reports_of(&r);
+        let hidden = r.get("hidden") == Some(&Attr::Bool(true));
+        if threshold == 0 || reports != threshold || hidden {
+            return Ok(r);
+        }
+
+        // dynamodb can't compare against the count it's in the middle of updating, so
+        // hiding takes a second write.
+        match upd()
+            .update_expression("SET hidden = :true, updated_at = :updated")
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .send()
+            .await
+        {
+            Ok(h) => Ok(store::from_dynamo(h.attributes.unwrap_or_default())),
+            Err(e) => match StoreError::from(e) {
+                // deleted in the meantime, which is about as good as hidden
+                StoreError::ConditionFailed => Ok(r),
+                e => Err(e),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl ReportStore for Mutex<Local> {
+    async fn report(&self, qid: &Ulid, threshold: u64) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local { questions, .. } = &mut *local;
+
+        let Some(q) = questions.get_mut(qid) else {
+            return Err(StoreError::ConditionFailed);
+        };
+        let hide = add_report(q, threshold);
+
+        let ret = store::from_local(q);
+        if hide {
+            let update = serde_json::json!({ "qid": qid.to_string(), "hidden": true });
+            let eid = crate::stream::eid_of(q);
+            local.publish(&eid, "toggle", update);
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ReportStore for crate::redis::Redis {
+    async fn report(&self, qid: &Ulid, threshold: u64) -> Result<Item, StoreError> {
+        let (q, _) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                Ok(add_report(q, threshold))
+            })
+            .await?;
+        Ok(q)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ReportStore for crate::sqlite::Sqlite {
+    async fn report(&self, qid: &Ulid, threshold: u64) -> Result<Item, StoreError> {
+        let (q, _) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            Ok(add_report(q, threshold))
+        })?;
+        Ok(q)
+    }
+}
+
+/// Flags a question as abusive.
+///
+/// Questions that get reported often enough are hidden until the host looks at them.
+pub(super) async fn report(
+    Path(qid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+) -> Result<StatusCode, ApiError> {
+    match dynamo.report(&qid, reports_to_hide()).await {
+        Ok(v) => {
+            let reports = reports_of(&v);
+            debug!(%qid, reports, "reported question");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%qid, "attempted to report non-existing question");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%qid, error = %e, "dynamodb request to report question failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, Json};
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let host = || {
+            crate::list::list_all(
+                Path((eid, secret.to_string())),
+                State(backend.clone()),
+                Query(Default::default()),
+            )
+        };
+
+        let threshold = reports_to_hide();
+        for _ in 1..threshold {
+            let r = super::report(Path(qid), State(backend.clone()))
+                .await
+                .unwrap();
+            assert_eq!(r, StatusCode::NO_CONTENT);
+        }
+        let qs = host().await.1.unwrap().0;
+        assert_eq!(qs[0]["reports"], threshold - 1);
+        assert_eq!(qs[0]["hidden"], false);
+        // guests don't get to see who's been reported
+        let qs = crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap()
+            .0;
+        assert!(qs[0].get("reports").is_none());
+
+        // one more report, and the question is hidden
+        super::report(Path(qid), State(backend.clone()))
+            .await
+            .unwrap();
+        let qs = host().await.1.unwrap().0;
+        assert_eq!(qs[0]["reports"], threshold);
+        assert_eq!(qs[0]["hidden"], true);
+
+        // which the host can undo if the reports were unfounded
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret.to_string(),
+                qid,
+                crate::toggle::Property::Hidden,
+            )),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("off"),
+        )
+        .await
+        .unwrap();
+        super::report(Path(qid), State(backend.clone()))
+            .await
+            .unwrap();
+        let qs = host().await.1.unwrap().0;
+        assert_eq!(qs[0]["reports"], threshold + 1);
+        assert_eq!(qs[0]["hidden"], false);
+
+        assert_eq!(
+            super::report(Path(Ulid::new()), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,156 @@
+use aws_sdk_dynamodb::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use rand::Rng;
+use std::{future::Future, sync::OnceLock, time::Duration};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// How long to wait (at most) before the first retry. Each retry after that waits up to twice as
+/// long as the one before it.
+const BASE_DELAY: Duration = Duration::from_millis(25);
+
+/// The longest we'll wait before any one retry.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Error codes DynamoDB uses to say that it's shedding load rather than that the request is bad.
+const TRANSIENT_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "InternalServerError",
+    "ServiceUnavailable",
+];
+
+/// Returns how many times a throttled request is retried, as configured through
+/// `DYNAMODB_MAX_RETRIES`.
+///
+/// Panics if `DYNAMODB_MAX_RETRIES` is set but isn't a number.
+pub(super) fn max_retries() -> u32 {
+    static N: OnceLock<u32> = OnceLock::new();
+    *N.get_or_init(|| match std::env::var("DYNAMODB_MAX_RETRIES") {
+        Ok(n) => n
+            .parse()
+            .expect("DYNAMODB_MAX_RETRIES must be a number of retries"),
+        Err(_) => DEFAULT_MAX_RETRIES,
+    })
+}
+
+/// Whether `e` is the kind of failure that may well go away if we just try again.
+fn is_transient<E: ProvideErrorKind>(e: &SdkError<E>) -> bool {
+    match e {
+        SdkError::TimeoutError(_) => true,
+        SdkError::DispatchFailure(e) => e.is_io() || e.is_timeout(),
+        SdkError::ServiceError { err, .. } => {
+            err.code().is_some_and(|c| TRANSIENT_CODES.contains(&c))
+        }
+        SdkError::ConstructionFailure(_) | SdkError::ResponseError { .. } => false,
+    }
+}
+
+/// How long to wait before the `attempt`th retry, with full jitter so that requests that were
+/// throttled together don't all come back at once.
+pub(super) fn backoff(attempt: u32) -> Duration {
+    let cap = BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY);
+    cap.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+/// Sends the request that `send` makes, and sends it again (after a while) if DynamoDB says it's
+/// overloaded.
+///
+/// This comes on top of the retries the SDK does on its own, for the requests where a `500` hurts
+/// the most. Anything that isn't throttling or a transient failure is returned straight away.
+pub(super) async fn retry<T, E, F, Fut>(mut send: F) -> Result<T, SdkError<E>>
+where
+    E: ProvideErrorKind,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, SdkError<E>>>,
+{
+    let mut attempt = 0;
+    loop {
+        match send().await {
+            Err(e) if attempt < max_retries() && is_transient(&e) => {
+                let delay = backoff(attempt);
+                attempt += 1;
+                debug!(attempt, ?delay, "retrying throttled dynamodb request");
+                tokio::time::sleep(delay).await;
+            }
+            r => return r,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::error::{
+        GetItemError, GetItemErrorKind, ProvisionedThroughputExceededException,
+    };
+
+    fn throttled() -> SdkError<GetItemError> {
+        crate::mint_service_error(GetItemError::new(
+            GetItemErrorKind::ProvisionedThroughputExceededException(
+                ProvisionedThroughputExceededException::builder().build(),
+            ),
+            aws_smithy_types::Error::builder()
+                .code("ProvisionedThroughputExceededException")
+                .build(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn throttling() {
+        // goes through once the throttling passes
+        let mut tries = 0;
+        let r = retry(|| {
+            tries += 1;
+            let r = if tries < 3 {
+                Err(throttled())
+            } else {
+                Ok(tries)
+            };
+            async move { r }
+        })
+        .await;
+        assert_eq!(r.unwrap(), 3);
+
+        // but not forever
+        let mut tries = 0;
+        let r: Result<(), _> = retry(|| {
+            tries += 1;
+            async { Err(throttled()) }
+        })
+        .await;
+        assert!(r.is_err());
+        assert_eq!(tries, max_retries() + 1);
+    }
+
+    #[tokio::test]
+    async fn permanent() {
+        let mut tries = 0;
+        let r: Result<(), _> = retry(|| {
+            tries += 1;
+            async {
+                Err(crate::mint_service_error(GetItemError::generic(
+                    aws_smithy_types::Error::builder()
+                        .code("ValidationException")
+                        .build(),
+                )))
+            }
+        })
+        .await;
+        assert!(r.is_err());
+        assert_eq!(tries, 1);
+    }
+
+    #[test]
+    fn delays() {
+        for attempt in 0..40 {
+            assert!(backoff(attempt) <= MAX_DELAY);
+        }
+    }
+}
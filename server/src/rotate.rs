@@ -0,0 +1,168 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How a storage engine replaces an event's secret.
+#[async_trait]
+pub(super) trait RotateStore {
+    /// Replaces the stored secret of `eid` with `secret_hash`.
+    async fn rotate(&self, eid: &Ulid, secret_hash: String) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl RotateStore for aws_sdk_dynamodb::Client {
+    async fn rotate(&self, eid: &Ulid, secret_hash: String) -> Result<(), StoreError> {
+        let r = self
+            .update_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .update_expression("SET secret = :secret")
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":secret", AttributeValue::S(secret_hash))
+            .send()
+            .await;
+        // so that the old secret stops working here right away, not once it's forgotten.
+        super::secretcache::invalidate(eid);
+        r?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RotateStore for Mutex<Local> {
+    async fn rotate(&self, eid: &Ulid, secret_hash: String) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local { events, .. } = &mut *local;
+
+        events
+            .get_mut(eid)
+            .expect("rotating secret of non-existing event")
+            .insert("secret", Attr::S(secret_hash));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl RotateStore for crate::redis::Redis {
+    async fn rotate(&self, eid: &Ulid, secret_hash: String) -> Result<(), StoreError> {
+        self.modify(&crate::redis::event_key(eid), |e| {
+            e.insert("secret".into(), Attr::S(secret_hash.clone()));
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl RotateStore for crate::sqlite::Sqlite {
+    async fn rotate(&self, eid: &Ulid, secret_hash: String) -> Result<(), StoreError> {
+        self.modify(crate::sqlite::Table::Events, eid, |e| {
+            e.insert("secret".into(), Attr::S(secret_hash));
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+/// Gives the event a new secret, for when the old one has ended up in the wrong hands.
+///
+/// The old secret stops working right away, though other instances may take it for up to
+/// `SECRET_CACHE_TTL_SECS` (see [`crate::secretcache`]).
+pub(super) async fn rotate(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let secret = crate::new::generate_secret();
+    let hash = super::hash_secret(&secret);
+    // tokens issued for the old secret stop working along with it.
+    let token = crate::hosttoken::issue(&eid, &hash);
+    match dynamo.rotate(&eid, hash).await {
+        Ok(_) => {
+            info!(%eid, "rotated event secret");
+            let mut body = serde_json::json!({ "secret": secret });
+            if let Some(token) = token {
+                body["token"] = token.into();
+            }
+            Ok(Json(body))
+        }
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request to rotate event secret failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let old = e["secret"].as_str().unwrap();
+
+        let r = super::rotate(Path((eid, old.to_string())), State(backend.clone()))
+            .await
+            .unwrap();
+        let new = r["secret"].as_str().unwrap();
+        assert_ne!(new, old);
+
+        crate::check_secret(&backend, &eid, new).await.unwrap();
+        assert_eq!(
+            crate::check_secret(&backend, &eid, old).await.unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        // so the old secret can't be used to take the event back either
+        assert_eq!(
+            super::rotate(Path((eid, old.to_string())), State(backend.clone()))
+                .await
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+
+/// Whether `c`, right after a `<`, means the `<` starts an HTML tag (or comment, or doctype).
+fn starts_tag(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')
+}
+
+/// Strips HTML tags and control characters from text that guests submitted.
+///
+/// Clients shouldn't be rendering question text as HTML in the first place, but this way markup
+/// can't sneak through one that does, and it doesn't show up as line noise in one that doesn't.
+/// Angle brackets that can't start a tag (`a < b`, `<3`) are left alone, as are newlines.
+pub(super) fn strip(text: &str) -> Cow<'_, str> {
+    let clean = |c: char| !c.is_control() || c == '\n';
+    let has_tag = text
+        .char_indices()
+        .any(|(i, c)| c == '<' && text[i + 1..].chars().next().is_some_and(starts_tag));
+    if !has_tag && text.chars().all(clean) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '<' && chars.peek().copied().is_some_and(starts_tag) {
+            // everything up to the end of the tag goes. a tag that's never closed takes the rest
+            // of the text with it, since that's how a browser would read it too.
+            for c in chars.by_ref() {
+                if c == '>' {
+                    break;
+                }
+            }
+        } else if c == '\t' {
+            out.push(' ');
+        } else if clean(c) {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        assert_eq!(
+            strip("<script>alert('hi')</script> what's up?"),
+            "alert('hi') what's up?"
+        );
+        assert_eq!(strip("is <b>this</b> bold?"), "is this bold?");
+        assert_eq!(
+            strip("<img src=x onerror=alert(1)>hello world"),
+            "hello world"
+        );
+        assert_eq!(strip("hello <!-- hidden --> world"), "hello  world");
+        assert_eq!(strip("hello <script"), "hello ");
+    }
+
+    #[test]
+    fn untouched() {
+        for text in [
+            "is 3 < 5 > 2?",
+            "we <3 this talk",
+            "what about a<-b & c -> d?",
+            "how's the 🦀 doing? ¿qué tal?",
+            "two\nlines",
+        ] {
+            assert!(
+                matches!(strip(text), Cow::Borrowed(t) if t == text),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn control() {
+        assert_eq!(strip("hello\u{0}\u{7} world\r\n"), "hello world\n");
+        assert_eq!(strip("hello\tworld"), "hello world");
+    }
+}
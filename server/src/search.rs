@@ -0,0 +1,264 @@
+use super::Backend;
+use crate::error::ApiError;
+use axum::extract::{Path, Query, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::collections::HashSet;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_RESULTS: usize = 10;
+const MAX_RESULTS: usize = 50;
+
+/// How alike a question has to be to the query to be worth showing at all.
+const MIN_SCORE: f64 = 0.2;
+
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct Params {
+    q: String,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Lowercases `s` and reduces it to words separated by single spaces, so that punctuation and
+/// spacing don't get in the way of a match.
+fn normalize(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The three-character windows of the (normalized) `s`, padded so that short words have some too.
+fn trigrams(s: &str) -> HashSet<[char; 3]> {
+    let padded: Vec<char> = format!("  {s} ").chars().collect();
+    padded.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// Scores how well `text` matches the (normalized) `query`, from 0 (not at all) to 1.
+///
+/// This is the share of the query's trigrams that also appear in the text, so typos only cost a
+/// little, and a long question isn't penalized for saying more than the query does. Texts that
+/// contain the query outright always score 1.
+fn score(query: &str, query_trigrams: &HashSet<[char; 3]>, text: &str) -> f64 {
+    let text = normalize(text);
+    if text.contains(query) {
+        return 1.0;
+    }
+    let text = trigrams(&text);
+    let shared = query_trigrams.intersection(&text).count();
+    shared as f64 / query_trigrams.len() as f64
+}
+
+/// Finds the event's questions that look like the query, best match first, so that guests can
+/// check whether their question has been asked already.
+///
+/// Hidden questions are never searched. There's no search index, so this reads every question of
+/// the event (and its text) each time; in DynamoDB that costs about as much as a guest loading
+/// the full list of questions and all their texts.
+pub(super) async fn search(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+    Query(params): Query<Params>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let query = normalize(&params.q);
+    if query.is_empty() {
+        warn!(%eid, q = params.q, "got empty search");
+        return Err(ApiError::BadRequest);
+    }
+    // so that old events get a 404, same as their question list.
+    dynamo.get_secret(&eid).await?;
+
+    let mut qids = Vec::new();
+    let mut start = None;
+    loop {
+        let r = match dynamo
+            .list(&eid, false, &Default::default(), None, start)
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                error!(%eid, error = %e, "dynamodb request to list questions to search failed");
+                return Err(ApiError::Internal);
+            }
+        };
+        qids.extend(
+            r.items
+                .iter()
+                .filter_map(|q| q.get("id")?.as_s().ok()?.parse::<Ulid>().ok()),
+        );
+        start = r.next;
+        if start.is_none() {
+            break;
+        }
+    }
+    let texts = match dynamo.questions_by_id(&qids).await {
+        Ok(texts) => texts,
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for question texts to search failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    let query_trigrams = trigrams(&query);
+    let mut found: Vec<_> = texts
+        .iter()
+        .filter_map(|(qid, q)| {
+            let text = q.get("text")?.as_s().ok()?;
+            let score = score(&query, &query_trigrams, text);
+            (score >= MIN_SCORE).then_some((qid, text, score))
+        })
+        .collect();
+    // ties go to the question asked first, which is the one the others would be duplicates of.
+    found.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+    found.truncate(
+        params
+            .limit
+            .unwrap_or(DEFAULT_RESULTS)
+            .clamp(1, MAX_RESULTS),
+    );
+
+    debug!(%eid, n = found.len(), of = qids.len(), "searched questions");
+    Ok(Json(
+        found
+            .into_iter()
+            .map(|(qid, text, score)| {
+                // two decimals is plenty to rank by, and keeps the numbers readable.
+                let score = (score * 100.0).round() / 100.0;
+                serde_json::json!({ "qid": qid, "text": text, "score": score })
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    #[test]
+    fn scoring() {
+        let s = |query: &str, text: &str| {
+            let query = normalize(query);
+            score(&query, &trigrams(&query), text)
+        };
+        assert_eq!(normalize("  What's   the PLAN?"), "what s the plan");
+        assert_eq!(s("the plan", "What's the plan?"), 1.0);
+        // typos only cost a little
+        assert!(s("roadmap for nxet year", "What's the roadmap for next year?") > 0.6);
+        assert!(s("pizza", "What's the roadmap for next year?") < MIN_SCORE);
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let mut qids = Vec::new();
+        for body in [
+            "What's the roadmap for next year?",
+            "Will there be a roadmap?",
+            "Where's the pizza?",
+            "What is the road map for next year?",
+        ] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(q["id"].as_str().unwrap().to_string());
+        }
+        let search = |q: &str, limit| {
+            super::search(
+                Path(eid),
+                State(backend.clone()),
+                Query(Params { q: q.into(), limit }),
+            )
+        };
+
+        let found = search("roadmap for next year", None).await.unwrap();
+        let found = found.as_array().unwrap();
+        let found: Vec<_> = found.iter().map(|q| q["qid"].as_str().unwrap()).collect();
+        assert_eq!(found[..2], [&*qids[0], &*qids[3]]);
+        assert!(!found.contains(&&*qids[2]));
+
+        let found = search("pizza", Some(1)).await.unwrap();
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["qid"], qids[2]);
+        assert_eq!(found[0]["text"], "Where's the pizza?");
+        assert_eq!(found[0]["score"], 1.0);
+
+        // hidden questions can't be found
+        let _ = crate::toggle::toggle(
+            Path((
+                eid,
+                secret,
+                qids[2].parse().unwrap(),
+                crate::toggle::Property::Hidden,
+            )),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            search("pizza", None).await.unwrap().0,
+            serde_json::json!([])
+        );
+
+        assert_eq!(
+            search(" ?! ", None).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            super::search(
+                Path(Ulid::new()),
+                State(backend.clone()),
+                Query(Params {
+                    q: "pizza".into(),
+                    limit: None,
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_SIZE: usize = 1024;
+const DEFAULT_TTL_SECS: u64 = 30;
+
+/// Returns how many events' secrets are remembered at once, as configured through
+/// `SECRET_CACHE_SIZE`.
+///
+/// `SECRET_CACHE_SIZE=0` turns the cache off. Panics if `SECRET_CACHE_SIZE` is set but isn't a
+/// number.
+pub(super) fn size() -> usize {
+    static SIZE: OnceLock<usize> = OnceLock::new();
+    *SIZE.get_or_init(|| match std::env::var("SECRET_CACHE_SIZE") {
+        Ok(n) => n
+            .parse()
+            .expect("SECRET_CACHE_SIZE must be a number of events"),
+        Err(_) => DEFAULT_SIZE,
+    })
+}
+
+/// Returns for how long a remembered secret is trusted, as configured through
+/// `SECRET_CACHE_TTL_SECS`.
+///
+/// Every instance has a cache of its own, so this is also how long a rotated secret may keep
+/// working through other instances. Panics if `SECRET_CACHE_TTL_SECS` is set but isn't a number.
+pub(super) fn ttl() -> Duration {
+    static TTL: OnceLock<Duration> = OnceLock::new();
+    *TTL.get_or_init(|| match std::env::var("SECRET_CACHE_TTL_SECS") {
+        Ok(n) => Duration::from_secs(
+            n.parse()
+                .expect("SECRET_CACHE_TTL_SECS must be a number of seconds"),
+        ),
+        Err(_) => Duration::from_secs(DEFAULT_TTL_SECS),
+    })
+}
+
+struct Entry {
+    /// The secret as stored, so hashed for all but old events.
+    secret: String,
+    /// When the event expires, if it does.
+    expire: Option<u64>,
+    fetched: Instant,
+    /// When the entry was last used, in lookups since the cache was made.
+    used: u64,
+}
+
+/// The stored secrets of recently used events, so that a host clicking through their questions
+/// doesn't cost a read of the event for every click.
+struct Cache {
+    entries: HashMap<Ulid, Entry>,
+    lookups: u64,
+    size: usize,
+    ttl: Duration,
+}
+
+impl Cache {
+    fn new(size: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            lookups: 0,
+            size,
+            ttl,
+        }
+    }
+
+    fn get(&mut self, eid: &Ulid, now: Instant) -> Option<(String, Option<u64>)> {
+        self.lookups += 1;
+        let entry = self.entries.get_mut(eid)?;
+        if now.duration_since(entry.fetched) >= self.ttl {
+            self.entries.remove(eid);
+            return None;
+        }
+        entry.used = self.lookups;
+        Some((entry.secret.clone(), entry.expire))
+    }
+
+    fn insert(&mut self, eid: Ulid, secret: String, expire: Option<u64>, now: Instant) {
+        if self.size == 0 {
+            return;
+        }
+        if self.entries.len() >= self.size && !self.entries.contains_key(&eid) {
+            // the cache is small enough that finding the least recently used entry is cheap.
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.used)
+                .map(|(eid, _)| *eid)
+                .expect("cache is full, so not empty");
+            self.entries.remove(&lru);
+        }
+        self.entries.insert(
+            eid,
+            Entry {
+                secret,
+                expire,
+                fetched: now,
+                used: self.lookups,
+            },
+        );
+    }
+}
+
+fn cache() -> std::sync::MutexGuard<'static, Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| Mutex::new(Cache::new(size(), ttl())))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Gives the stored secret of `eid` and when the event expires, if they're remembered.
+pub(super) fn get(eid: &Ulid) -> Option<(String, Option<u64>)> {
+    cache().get(eid, Instant::now())
+}
+
+/// Remembers the stored secret of `eid`, and when the event expires.
+pub(super) fn insert(eid: Ulid, secret: String, expire: Option<u64>) {
+    cache().insert(eid, secret, expire, Instant::now());
+}
+
+/// Forgets the secret of `eid`, for when it's changed or the event is gone.
+pub(super) fn invalidate(eid: &Ulid) {
+    cache().entries.remove(eid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru() {
+        let now = Instant::now();
+        let (a, b, c) = (Ulid::new(), Ulid::new(), Ulid::new());
+        let mut cache = Cache::new(2, Duration::from_secs(10));
+        cache.insert(a, "a".into(), None, now);
+        cache.insert(b, "b".into(), Some(1), now);
+        assert_eq!(cache.get(&a, now), Some(("a".into(), None)));
+        // b was used longest ago, so it makes room
+        cache.insert(c, "c".into(), None, now);
+        assert_eq!(cache.get(&b, now), None);
+        assert_eq!(cache.get(&a, now).unwrap().0, "a");
+        assert_eq!(cache.get(&c, now).unwrap().0, "c");
+
+        // entries are only trusted for so long
+        assert_eq!(cache.get(&a, now + Duration::from_secs(10)), None);
+        assert_eq!(cache.entries.len(), 1);
+
+        let mut off = Cache::new(0, Duration::from_secs(10));
+        off.insert(a, "a".into(), None, now);
+        assert_eq!(off.get(&a, now), None);
+    }
+}
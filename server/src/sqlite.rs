@@ -0,0 +1,505 @@
+use crate::store::{self, Attr, Item, Kind, StoreError};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::SystemTime;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Returns the database file to keep events and questions in, as configured through `SQLITE_PATH`.
+///
+/// Panics if `SQLITE_PATH` isn't set, since a database that silently ends up somewhere unexpected
+/// is no better than none.
+pub(super) fn path() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        std::env::var("SQLITE_PATH")
+            .expect("BACKEND=sqlite needs a SQLITE_PATH like ./wewerewondering.db")
+    })
+}
+
+/// The schema, one migration at a time.
+///
+/// Each one runs once, in order, and the database's `user_version` records how many have, so
+/// changes to the schema go in a new migration at the end rather than into the ones already here.
+/// The columns of `events` and `questions` are the attributes in [`store::EVENT_ATTRIBUTES`] and
+/// [`store::QUESTION_ATTRIBUTES`], with `NULL` for attributes an item doesn't have.
+const MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE events (
+    id TEXT PRIMARY KEY,
+    secret TEXT,
+    "when" INTEGER,
+    expire INTEGER,
+    vote_salt TEXT,
+    code TEXT,
+    title TEXT,
+    description TEXT,
+    moderated INTEGER,
+    max_questions INTEGER,
+    vote_budget INTEGER,
+    webhook_url TEXT,
+    downvotes_disabled INTEGER,
+    hide_counts INTEGER,
+    archived INTEGER,
+    questions_locked INTEGER,
+    removed TEXT
+);
+CREATE TABLE questions (
+    id TEXT PRIMARY KEY,
+    eid TEXT NOT NULL,
+    votes INTEGER NOT NULL,
+    version INTEGER,
+    text TEXT,
+    "when" INTEGER,
+    expire INTEGER,
+    hidden INTEGER,
+    approved INTEGER,
+    vote_budget INTEGER,
+    downvotes_disabled INTEGER,
+    hide_counts INTEGER,
+    tags TEXT,
+    who TEXT,
+    answer TEXT,
+    answered INTEGER,
+    modified INTEGER,
+    updated_at INTEGER,
+    pinned INTEGER,
+    note TEXT,
+    reports INTEGER,
+    reactions TEXT,
+    "order" INTEGER,
+    archived INTEGER
+);
+CREATE INDEX questions_by_votes ON questions (eid, votes DESC);
+CREATE TABLE voters (
+    qid TEXT NOT NULL,
+    voter TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    PRIMARY KEY (qid, voter)
+);
+CREATE TABLE spent (
+    eid TEXT NOT NULL,
+    voter TEXT NOT NULL,
+    used INTEGER NOT NULL,
+    PRIMARY KEY (eid, voter)
+);
+CREATE TABLE vote_log (
+    eid TEXT NOT NULL,
+    "when" INTEGER NOT NULL,
+    delta INTEGER NOT NULL
+);
+CREATE INDEX vote_log_by_event ON vote_log (eid);
+CREATE TABLE idempotency (
+    eid TEXT NOT NULL,
+    key TEXT NOT NULL,
+    qid TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    expire INTEGER NOT NULL,
+    PRIMARY KEY (eid, key)
+);
+CREATE TABLE codes (
+    code TEXT PRIMARY KEY,
+    eid TEXT NOT NULL,
+    expire INTEGER NOT NULL
+);
+"#];
+
+/// Events and questions kept in a SQLite database.
+///
+/// Events and questions each have a table with a column per attribute. Everything else (who voted
+/// how, vote budgets, the vote log, idempotency keys, and short codes) has a table of its own.
+/// Expired rows are swept out whenever the database is opened, and otherwise ignored where it
+/// matters, much like DynamoDB's TTLs.
+///
+/// The connection is shared behind a lock, and every operation runs as a transaction while holding
+/// it, so operations never see each other half-done.
+pub(super) struct Sqlite {
+    con: Mutex<Connection>,
+}
+
+impl std::fmt::Debug for Sqlite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sqlite").finish_non_exhaustive()
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Other(Box::new(e))
+    }
+}
+
+/// The tables that hold items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Table {
+    Events,
+    Questions,
+}
+
+impl Table {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Events => "events",
+            Self::Questions => "questions",
+        }
+    }
+
+    fn attributes(self) -> &'static [(&'static str, Kind)] {
+        match self {
+            Self::Events => store::EVENT_ATTRIBUTES,
+            Self::Questions => store::QUESTION_ATTRIBUTES,
+        }
+    }
+
+    /// All the columns of the table, quoted, since some of them (like `when`) are keywords.
+    fn columns(self) -> String {
+        self.attributes()
+            .iter()
+            .map(|(k, _)| format!("\"{k}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Gives the current time in seconds since the epoch, which is what `expire` columns hold.
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Gives `v` as it's kept in a column holding an attribute of the given `kind`.
+fn encode(kind: Kind, v: &Attr) -> Result<Value, StoreError> {
+    Ok(match (kind, v) {
+        (Kind::S, Attr::S(s)) => Value::Text(s.clone()),
+        (Kind::N, Attr::N(n)) => match n.parse::<i64>() {
+            Ok(n) => Value::Integer(n),
+            Err(_) => Value::Real(
+                n.parse()
+                    .map_err(|_| StoreError::Other(format!("{n:?} isn't a number").into()))?,
+            ),
+        },
+        (Kind::Bool, Attr::Bool(b)) => Value::Integer(i64::from(*b)),
+        (Kind::Ss, Attr::Ss(ss)) => {
+            Value::Text(serde_json::to_string(ss).expect("strings serialize"))
+        }
+        (Kind::M, Attr::M(m)) => {
+            Value::Text(serde_json::to_string(m).expect("attributes serialize"))
+        }
+        _ => return Err(StoreError::Other(format!("{v:?} isn't a {kind:?}").into())),
+    })
+}
+
+/// Turns what's in a column holding an attribute of the given `kind` back into that attribute.
+///
+/// `NULL` means the item doesn't have the attribute, and so gives `None`.
+fn decode(kind: Kind, v: Value) -> Result<Option<Attr>, StoreError> {
+    let malformed = |e: serde_json::Error| StoreError::Other(Box::new(e));
+    Ok(Some(match (kind, v) {
+        (_, Value::Null) => return Ok(None),
+        (Kind::S, Value::Text(s)) => Attr::S(s),
+        (Kind::N, Value::Integer(n)) => Attr::N(n.to_string()),
+        (Kind::N, Value::Real(n)) => Attr::N(n.to_string()),
+        (Kind::Bool, Value::Integer(b)) => Attr::Bool(b != 0),
+        (Kind::Ss, Value::Text(ss)) => Attr::Ss(serde_json::from_str(&ss).map_err(malformed)?),
+        (Kind::M, Value::Text(m)) => Attr::M(serde_json::from_str(&m).map_err(malformed)?),
+        (kind, v) => {
+            return Err(StoreError::Other(
+                format!("stored {v:?} isn't a {kind:?}").into(),
+            ))
+        }
+    }))
+}
+
+/// Turns a row with all the [columns](Table::columns) of `table` back into an item.
+fn decode_row(table: Table, row: &rusqlite::Row<'_>) -> Result<Item, StoreError> {
+    let mut item = Item::new();
+    for (i, &(k, kind)) in table.attributes().iter().enumerate() {
+        if let Some(v) = decode(kind, row.get(i)?)? {
+            item.insert(k.to_string(), v);
+        }
+    }
+    Ok(item)
+}
+
+/// Fetches the item `id` from `table`, if there is one.
+pub(super) fn get(con: &Connection, table: Table, id: &Ulid) -> Result<Option<Item>, StoreError> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE id = ?1",
+        table.columns(),
+        table.name()
+    );
+    let mut stmt = con.prepare_cached(&sql)?;
+    let mut rows = stmt.query([id.to_string()])?;
+    match rows.next()? {
+        Some(row) => decode_row(table, row).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Fetches the questions of `eid`, most-voted first.
+pub(super) fn questions(con: &Connection, eid: &Ulid) -> Result<Vec<Item>, StoreError> {
+    let sql = format!(
+        "SELECT {} FROM questions WHERE eid = ?1 ORDER BY votes DESC, id DESC",
+        Table::Questions.columns()
+    );
+    let mut stmt = con.prepare_cached(&sql)?;
+    let mut rows = stmt.query([eid.to_string()])?;
+    let mut qs = Vec::new();
+    while let Some(row) = rows.next()? {
+        qs.push(decode_row(Table::Questions, row)?);
+    }
+    Ok(qs)
+}
+
+/// Returns true if there's an event `eid`.
+pub(super) fn has_event(con: &Connection, eid: &Ulid) -> Result<bool, StoreError> {
+    Ok(con
+        .prepare_cached("SELECT 1 FROM events WHERE id = ?1")?
+        .exists([eid.to_string()])?)
+}
+
+/// Writes the new `item` to `table`.
+///
+/// Fails if there's already an item with its id.
+pub(super) fn insert<'a, K>(
+    con: &Connection,
+    table: Table,
+    item: impl IntoIterator<Item = (&'a K, &'a Attr)>,
+) -> Result<(), StoreError>
+where
+    K: AsRef<str> + ?Sized + 'a,
+{
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for (k, v) in item {
+        let k = k.as_ref();
+        values.push(encode(store::kind_of(table.attributes(), k)?, v)?);
+        columns.push(format!("\"{k}\""));
+    }
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table.name(),
+        columns.join(", "),
+        vec!["?"; values.len()].join(", ")
+    );
+    con.execute(&sql, params_from_iter(values))?;
+    Ok(())
+}
+
+/// Writes whatever changed between `before` and `after` in the item `id` of `table`.
+pub(super) fn update(
+    con: &Connection,
+    table: Table,
+    id: &Ulid,
+    before: &Item,
+    after: &Item,
+) -> Result<(), StoreError> {
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for &(k, kind) in table.attributes() {
+        match (before.get(k), after.get(k)) {
+            (before, Some(v)) if before != Some(v) => values.push(encode(kind, v)?),
+            (Some(_), None) => values.push(Value::Null),
+            _ => continue,
+        }
+        columns.push(format!("\"{k}\" = ?"));
+    }
+    if let Some(k) = after.keys().find(|k| !before.contains_key(*k)) {
+        // makes sure there's no attribute the table has no column for.
+        store::kind_of(table.attributes(), k)?;
+    }
+    if columns.is_empty() {
+        return Ok(());
+    }
+    values.push(Value::Text(id.to_string()));
+    let sql = format!(
+        "UPDATE {} SET {} WHERE id = ?",
+        table.name(),
+        columns.join(", ")
+    );
+    con.execute(&sql, params_from_iter(values))?;
+    Ok(())
+}
+
+/// Has `f` change the item `id` of `table`, and writes back whatever it changed.
+///
+/// Fails with [`StoreError::ConditionFailed`] if there's no such item. Otherwise gives the item as
+/// it is afterwards, along with whatever `f` returned.
+pub(super) fn modify<T, F>(
+    con: &Connection,
+    table: Table,
+    id: &Ulid,
+    f: F,
+) -> Result<(Item, T), StoreError>
+where
+    F: FnOnce(&mut Item) -> Result<T, StoreError>,
+{
+    let Some(before) = get(con, table, id)? else {
+        return Err(StoreError::ConditionFailed);
+    };
+    let mut after = before.clone();
+    let out = f(&mut after)?;
+    update(con, table, id, &before, &after)?;
+    Ok((after, out))
+}
+
+impl Sqlite {
+    /// Opens (or creates) the database at `path`, bringing its schema up to date.
+    pub(super) fn open(path: &str) -> Result<Self, StoreError> {
+        let mut con = Connection::open(path)?;
+        // lets readers in other processes (like the sqlite3 shell) look while we write.
+        con.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        migrate(&mut con)?;
+        sweep(&con)?;
+        Ok(Self {
+            con: Mutex::new(con),
+        })
+    }
+
+    /// Locks the connection.
+    ///
+    /// Like for the local backend, a request that panicked while holding it shouldn't take every
+    /// later request down with it, and since it panicked mid-transaction, none of it was written.
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.con.lock().unwrap_or_else(|e| {
+            warn!("recovering sqlite connection from a panicked request");
+            e.into_inner()
+        })
+    }
+
+    /// Runs `f` as a transaction, which is only committed if `f` succeeds.
+    pub(super) fn run<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        F: FnOnce(&Connection) -> Result<T, StoreError>,
+    {
+        let mut con = self.lock();
+        let tx = con.transaction()?;
+        let out = f(&tx)?;
+        tx.commit()?;
+        Ok(out)
+    }
+
+    /// Fetches the item `id` from `table`, if there is one.
+    pub(super) fn item(&self, table: Table, id: &Ulid) -> Result<Option<Item>, StoreError> {
+        self.run(|con| get(con, table, id))
+    }
+
+    /// Fetches the questions of `eid`, most-voted first.
+    pub(super) fn questions_of(&self, eid: &Ulid) -> Result<Vec<Item>, StoreError> {
+        self.run(|con| questions(con, eid))
+    }
+
+    /// Has `f` change the item `id` of `table`, and writes back whatever it changed.
+    ///
+    /// See [`modify`].
+    pub(super) fn modify<T, F>(
+        &self,
+        table: Table,
+        id: &Ulid,
+        f: F,
+    ) -> Result<(Item, T), StoreError>
+    where
+        F: FnOnce(&mut Item) -> Result<T, StoreError>,
+    {
+        self.run(|con| modify(con, table, id, f))
+    }
+}
+
+/// Runs the [migrations](MIGRATIONS) the database at `con` hasn't had yet.
+fn migrate(con: &mut Connection) -> Result<(), StoreError> {
+    let done: usize = con.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if done > MIGRATIONS.len() {
+        return Err(StoreError::Other(
+            format!(
+                "database has had {done} migrations, but only {} are known",
+                MIGRATIONS.len()
+            )
+            .into(),
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(done) {
+        info!(migration = i + 1, "migrating sqlite database");
+        let tx = con.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Deletes whatever expired, along with what was only kept for its sake.
+fn sweep(con: &Connection) -> Result<(), StoreError> {
+    let now = now();
+    con.execute_batch(&format!(
+        "BEGIN;
+         DELETE FROM events WHERE expire <= {now};
+         DELETE FROM questions WHERE expire <= {now};
+         DELETE FROM idempotency WHERE expire <= {now};
+         DELETE FROM codes WHERE expire <= {now};
+         DELETE FROM voters WHERE qid NOT IN (SELECT id FROM questions);
+         DELETE FROM spent WHERE eid NOT IN (SELECT id FROM events);
+         DELETE FROM vote_log WHERE eid NOT IN (SELECT id FROM events);
+         COMMIT;"
+    ))?;
+    Ok(())
+}
+
+/// Gives the first column of the row `sql` (run with `params`) gives, if it gives one.
+pub(super) fn first<T, P>(con: &Connection, sql: &str, params: P) -> Result<Option<T>, StoreError>
+where
+    T: rusqlite::types::FromSql,
+    P: rusqlite::Params,
+{
+    Ok(con
+        .prepare_cached(sql)?
+        .query_row(params, |row| row.get(0))
+        .optional()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_has_every_attribute() {
+        let db = Sqlite::open(":memory:").unwrap();
+        let con = db.lock();
+        for table in [Table::Events, Table::Questions] {
+            let mut stmt = con
+                .prepare(&format!(
+                    "SELECT name FROM pragma_table_info('{}')",
+                    table.name()
+                ))
+                .unwrap();
+            let columns: Vec<String> = stmt
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let attributes: Vec<_> = table.attributes().iter().map(|(k, _)| *k).collect();
+            assert_eq!(columns, attributes, "{table:?}");
+        }
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let dir = std::env::temp_dir().join(format!("wewerewondering-{}.db", Ulid::new()));
+        let path = dir.to_str().unwrap();
+        drop(Sqlite::open(path).unwrap());
+        // a second open finds the schema already there, rather than failing to create it again.
+        let db = Sqlite::open(path).unwrap();
+        let done: usize = db
+            .lock()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(done, MIGRATIONS.len());
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::list::ListStore;
+use crate::store::{Attr, StoreError};
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    response::AppendHeaders,
+    Json,
+};
+use http::header::{self, HeaderName};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+#[derive(Debug, Default)]
+pub(super) struct Stats {
+    questions: usize,
+    answered: usize,
+    hidden: usize,
+    votes: u64,
+    /// The most-voted question and its vote count. Ties go to the lowest qid, so it's stable.
+    top: Option<(String, u64)>,
+}
+
+impl Stats {
+    fn add<K>(&mut self, q: &HashMap<K, Attr>)
+    where
+        K: std::borrow::Borrow<str> + std::hash::Hash + Eq + std::fmt::Debug,
+    {
+        let Some(qid) = q.get("id").and_then(|v| v.as_s().ok()) else {
+            error!(?q, "found question without an id");
+            return;
+        };
+        let votes = q
+            .get("votes")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+
+        self.questions += 1;
+        self.answered += usize::from(q.get("answered").is_some());
+        self.hidden += usize::from(q.get("hidden") == Some(&Attr::Bool(true)));
+        self.votes += votes;
+        let better = match &self.top {
+            None => true,
+            Some((top, top_votes)) => {
+                (votes, std::cmp::Reverse(qid)) > (*top_votes, std::cmp::Reverse(top))
+            }
+        };
+        if better {
+            self.top = Some((qid.clone(), votes));
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "questions": self.questions,
+            "answered": self.answered,
+            "hidden": self.hidden,
+            "votes": self.votes,
+            "top": self.top.as_ref().map(|(qid, _)| qid),
+        })
+    }
+}
+
+/// How a storage engine sums up an event's questions.
+#[async_trait]
+pub(super) trait StatsStore {
+    /// Summarizes the questions of `eid`.
+    async fn stats(&self, eid: &Ulid) -> Result<Stats, StoreError>;
+}
+
+#[async_trait]
+impl StatsStore for aws_sdk_dynamodb::Client {
+    async fn stats(&self, eid: &Ulid) -> Result<Stats, StoreError> {
+        let mut stats = Stats::default();
+        // there's no server-side aggregation in dynamodb, so this is one pass over the index,
+        // which has everything we need anyway.
+        let mut start = None;
+        loop {
+            let r = self
+                .list(eid, true, &Default::default(), None, start)
+                .await?;
+            for q in &r.items {
+                stats.add(q);
+            }
+            start = r.next;
+            if start.is_none() {
+                break;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[async_trait]
+impl StatsStore for Mutex<Local> {
+    async fn stats(&self, eid: &Ulid) -> Result<Stats, StoreError> {
+        let mut stats = Stats::default();
+        let local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &*local;
+
+        for qid in questions_by_eid.get(eid).into_iter().flatten() {
+            stats.add(&questions[qid]);
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl StatsStore for crate::redis::Redis {
+    async fn stats(&self, eid: &Ulid) -> Result<Stats, StoreError> {
+        let mut stats = Stats::default();
+        for q in self.questions_of(eid).await? {
+            stats.add(&q);
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl StatsStore for crate::sqlite::Sqlite {
+    async fn stats(&self, eid: &Ulid) -> Result<Stats, StoreError> {
+        let mut stats = Stats::default();
+        for q in self.questions_of(eid)? {
+            stats.add(&q);
+        }
+        Ok(stats)
+    }
+}
+
+pub(super) async fn stats(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+) -> (
+    AppendHeaders<[(HeaderName, &'static str); 1]>,
+    Result<Json<serde_json::Value>, ApiError>,
+) {
+    if let Err(e) = super::check_secret(&dynamo, &eid, &secret).await {
+        return (
+            AppendHeaders([(header::CACHE_CONTROL, "max-age=86400")]),
+            Err(e),
+        );
+    }
+
+    match dynamo.stats(&eid).await {
+        Ok(stats) => (
+            // as fresh as the host's question list
+            AppendHeaders([(header::CACHE_CONTROL, "max-age=3")]),
+            Ok(Json(stats.to_json())),
+        ),
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for event stats failed");
+            (
+                AppendHeaders([(header::CACHE_CONTROL, "no-cache")]),
+                Err(ApiError::Internal),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let stats = || async {
+            super::stats(Path((eid, secret.to_string())), State(backend.clone()))
+                .await
+                .1
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(
+            stats().await,
+            serde_json::json!({
+                "questions": 0,
+                "answered": 0,
+                "hidden": 0,
+                "votes": 0,
+                "top": null,
+            })
+        );
+
+        let mut qids = Vec::new();
+        for _ in 0..3 {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        // all tied, so any of them will do
+        let top = Ulid::from_string(stats().await["top"].as_str().unwrap()).unwrap();
+        assert!(qids.contains(&top));
+
+        let _ = crate::vote::vote(
+            Path((qids[1], crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        for (qid, property) in [
+            (qids[0], crate::toggle::Property::Answered),
+            (qids[2], crate::toggle::Property::Hidden),
+        ] {
+            let _ = crate::toggle::toggle(
+                Path((eid, secret.to_string(), qid, property)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                String::from("on"),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            stats().await,
+            serde_json::json!({
+                "questions": 3,
+                "answered": 1,
+                "hidden": 1,
+                "votes": 4,
+                "top": qids[1].to_string(),
+            })
+        );
+
+        assert_eq!(
+            super::stats(Path((eid, "wrong".into())), State(backend.clone()))
+                .await
+                .1
+                .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
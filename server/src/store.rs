@@ -0,0 +1,295 @@
+use aws_sdk_dynamodb::{model::AttributeValue, types::SdkError};
+use aws_smithy_types::retry::ProvideErrorKind;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+/// A stored value, whichever storage engine it's stored in.
+///
+/// These are the same kinds of values DynamoDB has (less the binary ones, which we never store),
+/// and serialize to the same JSON shape DynamoDB itself uses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Attr {
+    S(String),
+    N(String),
+    #[serde(rename = "BOOL")]
+    Bool(bool),
+    #[serde(rename = "SS")]
+    Ss(Vec<String>),
+    #[serde(rename = "NS")]
+    Ns(Vec<String>),
+    #[serde(rename = "NULL")]
+    Null(bool),
+    L(Vec<Attr>),
+    M(HashMap<String, Attr>),
+}
+
+impl Attr {
+    pub(super) fn as_s(&self) -> Result<&String, &Self> {
+        match self {
+            Self::S(s) => Ok(s),
+            _ => Err(self),
+        }
+    }
+
+    pub(super) fn as_n(&self) -> Result<&String, &Self> {
+        match self {
+            Self::N(n) => Ok(n),
+            _ => Err(self),
+        }
+    }
+
+    pub(super) fn as_bool(&self) -> Result<&bool, &Self> {
+        match self {
+            Self::Bool(b) => Ok(b),
+            _ => Err(self),
+        }
+    }
+
+    pub(super) fn as_ss(&self) -> Result<&Vec<String>, &Self> {
+        match self {
+            Self::Ss(ss) => Ok(ss),
+            _ => Err(self),
+        }
+    }
+
+    pub(super) fn as_m(&self) -> Result<&HashMap<String, Attr>, &Self> {
+        match self {
+            Self::M(m) => Ok(m),
+            _ => Err(self),
+        }
+    }
+}
+
+/// A stored event or question, by attribute name.
+pub(super) type Item = HashMap<String, Attr>;
+
+/// The kinds of [`Attr`] that events and questions are stored with.
+///
+/// DynamoDB and the local backend keep the kind with each value, but storage engines that keep
+/// plain numbers and strings go by what kind each attribute is meant to be.
+#[cfg(any(feature = "redis", feature = "sqlite"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Kind {
+    S,
+    N,
+    Bool,
+    Ss,
+    M,
+}
+
+/// Every attribute an event can be stored with, and what kind of value it holds.
+#[cfg(any(feature = "redis", feature = "sqlite"))]
+pub(super) const EVENT_ATTRIBUTES: &[(&str, Kind)] = &[
+    ("id", Kind::S),
+    ("secret", Kind::S),
+    ("when", Kind::N),
+    ("expire", Kind::N),
+    ("vote_salt", Kind::S),
+    ("code", Kind::S),
+    ("title", Kind::S),
+    ("description", Kind::S),
+    ("moderated", Kind::Bool),
+    ("max_questions", Kind::N),
+    ("vote_budget", Kind::N),
+    ("webhook_url", Kind::S),
+    ("downvotes_disabled", Kind::Bool),
+    ("hide_counts", Kind::Bool),
+    ("archived", Kind::Bool),
+    ("questions_locked", Kind::Bool),
+    ("removed", Kind::Ss),
+];
+
+/// Every attribute a question can be stored with, and what kind of value it holds.
+#[cfg(any(feature = "redis", feature = "sqlite"))]
+pub(super) const QUESTION_ATTRIBUTES: &[(&str, Kind)] = &[
+    ("id", Kind::S),
+    ("eid", Kind::S),
+    ("votes", Kind::N),
+    ("version", Kind::N),
+    ("text", Kind::S),
+    ("when", Kind::N),
+    ("expire", Kind::N),
+    ("hidden", Kind::Bool),
+    ("approved", Kind::Bool),
+    ("vote_budget", Kind::N),
+    ("downvotes_disabled", Kind::Bool),
+    ("hide_counts", Kind::Bool),
+    ("tags", Kind::Ss),
+    ("who", Kind::S),
+    ("answer", Kind::S),
+    ("answered", Kind::N),
+    ("modified", Kind::N),
+    ("updated_at", Kind::N),
+    ("pinned", Kind::Bool),
+    ("note", Kind::S),
+    ("reports", Kind::N),
+    ("reactions", Kind::M),
+    ("order", Kind::N),
+    ("archived", Kind::Bool),
+];
+
+/// Gives what kind of value the attribute `name` holds, going by `attributes`.
+///
+/// Fails for attributes that aren't in the list, since there'd be no telling how to store them.
+#[cfg(any(feature = "redis", feature = "sqlite"))]
+pub(super) fn kind_of(attributes: &[(&str, Kind)], name: &str) -> Result<Kind, StoreError> {
+    attributes
+        .iter()
+        .find(|&&(k, _)| k == name)
+        .map(|&(_, kind)| kind)
+        .ok_or_else(|| StoreError::Other(format!("unknown attribute {name}").into()))
+}
+
+/// Returns true if the stored question `q` is a question of `eid`.
+#[cfg(any(feature = "redis", feature = "sqlite"))]
+pub(super) fn belongs_to(q: &Item, eid: &ulid::Ulid) -> bool {
+    q.get("eid") == Some(&Attr::S(eid.to_string()))
+}
+
+impl From<Attr> for AttributeValue {
+    fn from(v: Attr) -> Self {
+        match v {
+            Attr::S(s) => Self::S(s),
+            Attr::N(n) => Self::N(n),
+            Attr::Bool(b) => Self::Bool(b),
+            Attr::Ss(ss) => Self::Ss(ss),
+            Attr::Ns(ns) => Self::Ns(ns),
+            Attr::Null(n) => Self::Null(n),
+            Attr::L(l) => Self::L(l.into_iter().map(Self::from).collect()),
+            Attr::M(m) => Self::M(m.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+impl From<AttributeValue> for Attr {
+    fn from(v: AttributeValue) -> Self {
+        match v {
+            AttributeValue::S(s) => Self::S(s),
+            AttributeValue::N(n) => Self::N(n),
+            AttributeValue::Bool(b) => Self::Bool(b),
+            AttributeValue::Ss(ss) => Self::Ss(ss),
+            AttributeValue::Ns(ns) => Self::Ns(ns),
+            AttributeValue::L(l) => Self::L(l.into_iter().map(Self::from).collect()),
+            AttributeValue::M(m) => Self::M(from_dynamo(m)),
+            // nothing we write is binary (or of a type newer than the sdk), so this never happens.
+            _ => Self::Null(true),
+        }
+    }
+}
+
+/// Turns an item as DynamoDB returns it into an [`Item`].
+pub(super) fn from_dynamo(item: HashMap<String, AttributeValue>) -> Item {
+    item.into_iter().map(|(k, v)| (k, v.into())).collect()
+}
+
+/// Turns an [`Item`] into one DynamoDB can take.
+pub(super) fn to_dynamo(item: Item) -> HashMap<String, AttributeValue> {
+    item.into_iter().map(|(k, v)| (k, v.into())).collect()
+}
+
+/// Gives the attributes of an item as it's stored in the local backend.
+pub(super) fn from_local(item: &HashMap<&'static str, Attr>) -> Item {
+    item.iter()
+        .map(|(&k, v)| (k.to_string(), v.clone()))
+        .collect()
+}
+
+/// Why a storage operation didn't go through.
+#[derive(Debug)]
+pub(super) enum StoreError {
+    /// The condition the write was made on didn't hold, so nothing was written.
+    ConditionFailed,
+    /// Part of a transaction didn't go through, so none of it did.
+    TransactionCanceled,
+    /// What the operation needs isn't there, like a table that was never made.
+    NotFound,
+    /// Anything else, which there's nothing to do about but to give up.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConditionFailed => f.write_str("the condition on the write didn't hold"),
+            Self::TransactionCanceled => f.write_str("the transaction was canceled"),
+            Self::NotFound => f.write_str("the requested resource doesn't exist"),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Other(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<SdkError<E>> for StoreError
+where
+    E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+{
+    fn from(e: SdkError<E>) -> Self {
+        if let SdkError::ServiceError { ref err, .. } = e {
+            match err.code() {
+                Some("ConditionalCheckFailedException") => return Self::ConditionFailed,
+                Some("TransactionCanceledException") => return Self::TransactionCanceled,
+                Some("ResourceNotFoundException") => return Self::NotFound,
+                _ => {}
+            }
+        }
+        Self::Other(Box::new(e))
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for StoreError {
+    fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        Self::Other(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::error::{
+        ConditionalCheckFailedException, UpdateItemError, UpdateItemErrorKind,
+    };
+
+    #[test]
+    fn round_trip() {
+        let v = Attr::M(HashMap::from([
+            ("s".to_string(), Attr::S("hello".into())),
+            (
+                "l".to_string(),
+                Attr::L(vec![Attr::N("1".into()), Attr::Bool(true)]),
+            ),
+            ("ss".to_string(), Attr::Ss(vec!["a".into()])),
+        ]));
+        assert_eq!(Attr::from(AttributeValue::from(v.clone())), v);
+        assert_eq!(
+            serde_json::to_value(Attr::Bool(true)).unwrap(),
+            serde_json::json!({ "BOOL": true })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let ccf = crate::mint_service_error(UpdateItemError::new(
+            UpdateItemErrorKind::ConditionalCheckFailedException(
+                ConditionalCheckFailedException::builder().build(),
+            ),
+            aws_smithy_types::Error::builder()
+                .code("ConditionalCheckFailedException")
+                .build(),
+        ));
+        assert!(matches!(StoreError::from(ccf), StoreError::ConditionFailed));
+        let other = crate::mint_service_error(UpdateItemError::generic(
+            aws_smithy_types::Error::builder()
+                .code("ValidationException")
+                .build(),
+        ));
+        assert!(matches!(StoreError::from(other), StoreError::Other(_)));
+    }
+}
@@ -0,0 +1,327 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::Attr;
+use axum::{
+    extract::{Path, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use http::HeaderMap;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, OnceLock},
+};
+use tokio::sync::{broadcast, watch};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How many past updates are kept around for clients that reconnect with `Last-Event-ID`.
+const BACKLOG: usize = 128;
+
+/// Signals open streams that the server is shutting down.
+fn shutting_down() -> &'static watch::Sender<bool> {
+    static SHUTDOWN: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SHUTDOWN.get_or_init(|| watch::channel(false).0)
+}
+
+/// Completes once [`shutdown`] has been called.
+pub(super) fn until_shutdown() -> impl std::future::Future<Output = ()> + Send + 'static {
+    let mut shutdown = shutting_down().subscribe();
+    async move {
+        while !*shutdown.borrow_and_update() {
+            if shutdown.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Ends all open event streams.
+///
+/// Streams never complete on their own, so without this a graceful shutdown would wait forever.
+/// Clients will reconnect (to wherever the server comes back up) and catch up from there.
+pub(super) fn shutdown() {
+    shutting_down().send_replace(true);
+}
+
+/// An update to an event's questions, as sent to subscribed clients.
+#[derive(Clone, Debug)]
+pub(super) struct Update {
+    pub(super) id: u64,
+    pub(super) kind: &'static str,
+    pub(super) data: Arc<Value>,
+}
+
+impl Update {
+    fn to_event(&self) -> Event {
+        Event::default()
+            .id(self.id.to_string())
+            .event(self.kind)
+            .data(self.data.to_string())
+    }
+}
+
+/// The live updates for a single event.
+#[derive(Clone, Debug)]
+pub(super) struct Feed {
+    next_id: u64,
+    backlog: VecDeque<Update>,
+    tx: broadcast::Sender<Update>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self {
+            next_id: 1,
+            backlog: VecDeque::with_capacity(BACKLOG),
+            tx: broadcast::channel(BACKLOG).0,
+        }
+    }
+}
+
+/// Returns the event that a question stored in the `Local` backend belongs to.
+pub(super) fn eid_of(q: &HashMap<&'static str, Attr>) -> Ulid {
+    q["eid"]
+        .as_s()
+        .ok()
+        .and_then(|eid| Ulid::from_string(eid).ok())
+        .expect("questions always belong to an event")
+}
+
+impl Local {
+    /// Notifies everyone subscribed to `eid` that something happened to one of its questions.
+    pub(super) fn publish(&mut self, eid: &Ulid, kind: &'static str, data: Value) {
+        let feed = self.feeds.entry(*eid).or_default();
+        let update = Update {
+            id: feed.next_id,
+            kind,
+            data: Arc::new(data),
+        };
+        feed.next_id += 1;
+        if feed.backlog.len() == BACKLOG {
+            feed.backlog.pop_front();
+        }
+        feed.backlog.push_back(update.clone());
+        // it's fine for there to be no subscribers
+        let _ = feed.tx.send(update);
+    }
+}
+
+/// How a storage engine hands out live updates to events.
+pub(super) trait StreamStore {
+    /// Subscribes to all updates to `eid` from here on out, along with the ones since
+    /// `last_seen` that are still in the backlog.
+    ///
+    /// Returns `None` if the storage engine has no live updates to give.
+    fn subscribe(
+        &self,
+        eid: &Ulid,
+        last_seen: Option<u64>,
+    ) -> Option<(Vec<Update>, broadcast::Receiver<Update>)>;
+}
+
+impl StreamStore for aws_sdk_dynamodb::Client {
+    fn subscribe(
+        &self,
+        _eid: &Ulid,
+        _last_seen: Option<u64>,
+    ) -> Option<(Vec<Update>, broadcast::Receiver<Update>)> {
+        // lambdas can't keep connections open, so there's nowhere for updates to come from.
+        None
+    }
+}
+
+impl StreamStore for Mutex<Local> {
+    fn subscribe(
+        &self,
+        eid: &Ulid,
+        last_seen: Option<u64>,
+    ) -> Option<(Vec<Update>, broadcast::Receiver<Update>)> {
+        let mut local = super::lock(self);
+        let feed = local.feeds.entry(*eid).or_default();
+        let missed: Vec<_> = if let Some(last_seen) = last_seen {
+            feed.backlog
+                .iter()
+                .filter(|u| u.id > last_seen)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Some((missed, feed.tx.subscribe()))
+    }
+}
+
+#[cfg(feature = "redis")]
+impl StreamStore for crate::redis::Redis {
+    fn subscribe(
+        &self,
+        _eid: &Ulid,
+        _last_seen: Option<u64>,
+    ) -> Option<(Vec<Update>, broadcast::Receiver<Update>)> {
+        // changes aren't published through redis, so there's nothing to stream.
+        None
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl StreamStore for crate::sqlite::Sqlite {
+    fn subscribe(
+        &self,
+        _eid: &Ulid,
+        _last_seen: Option<u64>,
+    ) -> Option<(Vec<Update>, broadcast::Receiver<Update>)> {
+        // changes aren't published through sqlite, so there's nothing to stream.
+        None
+    }
+}
+
+pub(super) async fn stream(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // make sure the event exists so that clients of old events stop trying
+    dynamo.get_secret(&eid).await?;
+
+    let last_seen = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let Some((missed, rx)) = dynamo.subscribe(&eid, last_seen) else {
+        warn!(%eid, "event stream requested from backend without live updates");
+        return Err(ApiError::NotImplemented);
+    };
+    debug!(%eid, ?last_seen, missed = missed.len(), "client subscribed to event");
+    // counted for as long as the stream is open.
+    let viewer = crate::presence::Viewer::join(&dynamo, eid);
+
+    // if a client falls too far behind we end the stream, and it'll then reconnect with the
+    // last id it saw to catch up from the backlog.
+    let live = BroadcastStream::new(rx)
+        .take_while(|u| u.is_ok())
+        .filter_map(|u| u.ok());
+    let stream = tokio_stream::iter(missed).chain(live).map(move |u| {
+        let _ = &viewer;
+        Ok(u.to_event())
+    });
+    let stream = futures_util::StreamExt::take_until(stream, until_shutdown());
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::{IntoResponse, Json};
+    use hyper::body::HttpBody;
+
+    async fn next(body: &mut axum::body::BoxBody) -> String {
+        let chunk = body.data().await.unwrap().unwrap();
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let ask = |body: &'static str| {
+            crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+
+        let q1 = ask("hello world").await.unwrap();
+        let qid1 = q1["id"].as_str().unwrap();
+
+        let sse = super::stream(Path(eid), State(backend.clone()), HeaderMap::new()).await;
+        let Some(_) = backend.subscribe(&eid, None) else {
+            assert_eq!(sse.err(), Some(ApiError::NotImplemented));
+            backend.delete(&eid).await;
+            return;
+        };
+        let mut body = sse.unwrap().into_response().into_body();
+
+        // subscribing makes one more viewer
+        let update = next(&mut body).await;
+        assert!(update.contains("id:2\n"), "{update}");
+        assert!(update.contains("event:presence\n"), "{update}");
+        assert!(update.contains(r#"{"viewers":1}"#), "{update}");
+
+        // only updates after subscribing are sent
+        let q2 = ask("hello moon").await.unwrap();
+        let qid2 = q2["id"].as_str().unwrap();
+        let update = next(&mut body).await;
+        assert!(update.contains("id:3\n"), "{update}");
+        assert!(update.contains("event:ask\n"), "{update}");
+        assert!(update.contains(qid2), "{update}");
+
+        let _ = crate::vote::vote(
+            Path((Ulid::from_string(qid1).unwrap(), crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let update = next(&mut body).await;
+        assert!(update.contains("event:vote\n"), "{update}");
+        assert!(update.contains(qid1), "{update}");
+
+        // reconnecting replays everything that was missed
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", "2".parse().unwrap());
+        let mut body = super::stream(Path(eid), State(backend.clone()), headers)
+            .await
+            .unwrap()
+            .into_response()
+            .into_body();
+        assert!(next(&mut body).await.contains("id:3\n"));
+        assert!(next(&mut body).await.contains("id:4\n"));
+
+        // non-existing events give 404
+        assert_eq!(
+            super::stream(Path(Ulid::new()), State(backend.clone()), HeaderMap::new())
+                .await
+                .err(),
+            Some(ApiError::EventNotFound)
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
@@ -0,0 +1,235 @@
+use super::Local;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use std::sync::Mutex;
+use std::{collections::HashMap, time::SystemTime};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Gives the time to store as a question's `updated_at` when it changes now.
+pub(super) fn now<A: From<Attr>>() -> A {
+    crate::to_dynamo_timestamp(SystemTime::now())
+}
+
+/// Marks the stored question `q` as changed just now.
+pub(super) fn touch<K>(q: &mut HashMap<K, Attr>)
+where
+    K: std::hash::Hash + Eq + From<&'static str>,
+{
+    q.insert("updated_at".into(), now());
+}
+
+/// Gives when the stored question `q` last changed, in seconds since the epoch.
+///
+/// That's when it was asked, or if later, when it was last voted on, toggled, edited, or
+/// otherwise changed. Edits and answers keep their time as `modified` rather than `updated_at`.
+pub(super) fn updated<K>(q: &HashMap<K, Attr>) -> u64
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    ["when", "modified", "updated_at"]
+        .into_iter()
+        .filter_map(|k| q.get(k)?.as_n().ok()?.parse().ok())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Encodes the deletion of `qid` at `when` as an entry of the event's `removed` set.
+fn tombstone(qid: &Ulid, when: u64) -> String {
+    format!("{when}:{qid}")
+}
+
+/// Gives when the last of the questions the stored event `e` says were deleted went, in seconds
+/// since the epoch.
+pub(super) fn last_removed(e: &Item) -> Option<u64> {
+    e.get("removed")?
+        .as_ss()
+        .ok()?
+        .iter()
+        .filter_map(|t| t.split_once(':')?.0.parse().ok())
+        .max()
+}
+
+/// Gives the questions the stored event `e` says were deleted no earlier than `since`.
+pub(super) fn removed_since(e: &Item, since: u64) -> Vec<String> {
+    let Some(removed) = e.get("removed").and_then(|v| v.as_ss().ok()) else {
+        return Vec::new();
+    };
+    let mut qids: Vec<_> = removed
+        .iter()
+        .filter_map(|t| {
+            let (when, qid) = t.split_once(':')?;
+            (when.parse::<u64>().ok()? >= since).then(|| qid.to_string())
+        })
+        .collect();
+    qids.sort_unstable();
+    qids
+}
+
+/// Gives the `removed` attribute to add to an event when `qids` are deleted now.
+pub(super) fn tombstones<A: From<Attr>>(qids: &[Ulid]) -> A {
+    let when = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    Attr::Ss(qids.iter().map(|qid| tombstone(qid, when)).collect()).into()
+}
+
+/// Records in the stored event `e` that `qids` have been deleted just now.
+pub(super) fn add_tombstones<K>(e: &mut HashMap<K, Attr>, qids: &[Ulid])
+where
+    K: std::hash::Hash + Eq + From<&'static str>,
+{
+    let Attr::Ss(new) = tombstones(qids) else {
+        unreachable!("tombstones are a string set");
+    };
+    match e
+        .entry("removed".into())
+        .or_insert_with(|| Attr::Ss(Vec::new()))
+    {
+        Attr::Ss(removed) => removed.extend(new),
+        _ => unreachable!("removed is always a string set"),
+    }
+}
+
+impl Local {
+    /// Records in `eid` that `qids` have been deleted, so clients syncing the list learn of it.
+    pub(super) fn bury(&mut self, eid: &Ulid, qids: &[Ulid]) {
+        if let Some(e) = self.events.get_mut(eid) {
+            add_tombstones(e, qids);
+        }
+    }
+}
+
+/// How a storage engine remembers which questions were deleted, for clients that sync.
+#[async_trait]
+pub(super) trait SyncStore {
+    /// Records in `eid` that `qids` have been deleted, so clients syncing the list learn of it.
+    async fn bury(&self, eid: &Ulid, qids: &[Ulid]) -> Result<(), StoreError>;
+
+    /// Fetches which questions of `eid` have been deleted, and when, as the event's `removed`
+    /// attribute. Gives `None` if there's no such event.
+    async fn removed(&self, eid: &Ulid) -> Result<Option<Item>, StoreError>;
+}
+
+#[async_trait]
+impl SyncStore for aws_sdk_dynamodb::Client {
+    async fn bury(&self, eid: &Ulid, qids: &[Ulid]) -> Result<(), StoreError> {
+        self.update_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .update_expression("ADD removed :gone")
+            .condition_expression("attribute_exists(id)")
+            .expression_attribute_values(":gone", tombstones(qids))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn removed(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let e = self
+            .get_item()
+            .table_name("events")
+            .key("id", AttributeValue::S(eid.to_string()))
+            .projection_expression("removed")
+            .send()
+            .await?;
+        Ok(e.item.map(store::from_dynamo))
+    }
+}
+
+#[async_trait]
+impl SyncStore for Mutex<Local> {
+    async fn bury(&self, eid: &Ulid, qids: &[Ulid]) -> Result<(), StoreError> {
+        super::lock(self).bury(eid, qids);
+        Ok(())
+    }
+
+    async fn removed(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let local = super::lock(self);
+        Ok(local.events.get(eid).map(|e| {
+            e.iter()
+                .filter(|&(k, _)| *k == "removed")
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect()
+        }))
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl SyncStore for crate::redis::Redis {
+    async fn bury(&self, eid: &Ulid, qids: &[Ulid]) -> Result<(), StoreError> {
+        let buried = self
+            .modify(&crate::redis::event_key(eid), |e| {
+                add_tombstones(e, qids);
+                Ok(())
+            })
+            .await;
+        match buried {
+            // like for the local backend, there's nothing to record deletions in any more.
+            Ok(_) | Err(StoreError::ConditionFailed) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn removed(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let mut e = self.item(&crate::redis::event_key(eid)).await?;
+        if let Some(e) = &mut e {
+            e.retain(|k, _| k == "removed");
+        }
+        Ok(e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl SyncStore for crate::sqlite::Sqlite {
+    async fn bury(&self, eid: &Ulid, qids: &[Ulid]) -> Result<(), StoreError> {
+        let buried = self.modify(crate::sqlite::Table::Events, eid, |e| {
+            add_tombstones(e, qids);
+            Ok(())
+        });
+        match buried {
+            // like for the local backend, there's nothing to record deletions in any more.
+            Ok(_) | Err(StoreError::ConditionFailed) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn removed(&self, eid: &Ulid) -> Result<Option<Item>, StoreError> {
+        let mut e = self.item(crate::sqlite::Table::Events, eid)?;
+        if let Some(e) = &mut e {
+            e.retain(|k, _| k == "removed");
+        }
+        Ok(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed() {
+        let (a, b) = (Ulid::new(), Ulid::new());
+        let e = HashMap::from_iter([(
+            String::from("removed"),
+            Attr::Ss(vec![tombstone(&a, 100), tombstone(&b, 200)]),
+        )]);
+        assert_eq!(removed_since(&e, 0).len(), 2);
+        assert_eq!(removed_since(&e, 200), vec![b.to_string()]);
+        assert!(removed_since(&e, 201).is_empty());
+        assert!(removed_since(&HashMap::new(), 0).is_empty());
+
+        let n = |v: u64| Attr::N(v.to_string());
+        let mut q = HashMap::from_iter([("when", n(100))]);
+        assert_eq!(updated(&q), 100);
+        q.insert("updated_at", n(300));
+        q.insert("modified", n(200));
+        assert_eq!(updated(&q), 300);
+    }
+}
@@ -0,0 +1,316 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::extract::{Path, State};
+use axum::response::Json;
+use std::sync::Mutex;
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// The most tags a single question can have.
+pub(super) const MAX_TAGS: usize = 5;
+
+/// The longest a single tag can be, in characters.
+pub(super) const MAX_TAG_LEN: usize = 32;
+
+/// Normalizes a single tag so that `Rust` and ` rust ` end up the same.
+pub(super) fn normalize(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalizes the tags given for a question in `eid`, dropping empty and duplicate ones.
+///
+/// Fails if there are too many tags, or any of them is too long.
+pub(super) fn clean(eid: &Ulid, tags: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut clean: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize(&tag);
+        if tag.is_empty() || clean.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LEN {
+            warn!(%eid, tag, "rejecting overly long tag");
+            return Err(ApiError::BadRequest);
+        }
+        clean.push(tag);
+    }
+    if clean.len() > MAX_TAGS {
+        warn!(%eid, n = clean.len(), "rejecting question with too many tags");
+        return Err(ApiError::BadRequest);
+    }
+    Ok(clean)
+}
+
+/// Reads the tags of a stored question, in a stable order.
+pub(super) fn of(q: &Item) -> Vec<String> {
+    let mut tags = q
+        .get("tags")
+        .and_then(|v| v.as_ss().ok())
+        .cloned()
+        .unwrap_or_default();
+    // string sets don't have an order, so make one up.
+    tags.sort_unstable();
+    tags
+}
+
+/// How a storage engine changes the tags of a question.
+#[async_trait]
+pub(super) trait TagsStore {
+    /// Replaces the tags of `qid`, provided it belongs to the event `eid`.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if the question does not exist in that event.
+    async fn set_tags(&self, eid: &Ulid, qid: &Ulid, tags: Vec<String>) -> Result<(), StoreError>;
+}
+
+#[async_trait]
+impl TagsStore for aws_sdk_dynamodb::Client {
+    async fn set_tags(&self, eid: &Ulid, qid: &Ulid, tags: Vec<String>) -> Result<(), StoreError> {
+        let q = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .condition_expression("eid = :eid")
+            .expression_attribute_names("#tags", "tags")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .expression_attribute_values(":updated", crate::sync::now());
+        // dynamodb doesn't allow empty sets, so no tags means no attribute.
+        let q = if tags.is_empty() {
+            q.update_expression("REMOVE #tags SET updated_at = :updated")
+        } else {
+            q.update_expression("SET #tags = :tags, updated_at = :updated")
+                .expression_attribute_values(":tags", AttributeValue::Ss(tags))
+        };
+        super::retry::retry(|| q.clone().send()).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TagsStore for Mutex<Local> {
+    async fn set_tags(&self, eid: &Ulid, qid: &Ulid, tags: Vec<String>) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *local;
+
+        let q = questions_by_eid
+            .get(eid)
+            .filter(|qs| qs.contains(qid))
+            .and_then(|_| questions.get_mut(qid));
+        let Some(q) = q else {
+            return Err(StoreError::ConditionFailed);
+        };
+        if tags.is_empty() {
+            q.remove("tags");
+        } else {
+            q.insert("tags", Attr::Ss(tags));
+        }
+        crate::sync::touch(q);
+        local.publish(eid, "tags", serde_json::json!({ "qid": qid.to_string() }));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl TagsStore for crate::redis::Redis {
+    async fn set_tags(&self, eid: &Ulid, qid: &Ulid, tags: Vec<String>) -> Result<(), StoreError> {
+        self.modify(&crate::redis::question_key(qid), |q| {
+            if !crate::store::belongs_to(q, eid) {
+                return Err(StoreError::ConditionFailed);
+            }
+            if tags.is_empty() {
+                q.remove("tags");
+            } else {
+                q.insert("tags".into(), Attr::Ss(tags.clone()));
+            }
+            crate::sync::touch(q);
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl TagsStore for crate::sqlite::Sqlite {
+    async fn set_tags(&self, eid: &Ulid, qid: &Ulid, tags: Vec<String>) -> Result<(), StoreError> {
+        self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if !crate::store::belongs_to(q, eid) {
+                return Err(StoreError::ConditionFailed);
+            }
+            if tags.is_empty() {
+                q.remove("tags");
+            } else {
+                q.insert("tags".into(), Attr::Ss(tags));
+            }
+            crate::sync::touch(q);
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+pub(super) async fn tags(
+    Path((eid, secret, qid)): Path<(Ulid, String, Ulid)>,
+    State(dynamo): State<Backend>,
+    Json(tags): Json<Vec<String>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+    let mut tags = clean(&eid, tags)?;
+
+    match dynamo.set_tags(&eid, &qid, tags.clone()).await {
+        Ok(_) => {
+            debug!(%eid, %qid, ?tags, "set question tags");
+            tags.sort_unstable();
+            Ok(Json(serde_json::json!({ "tags": tags })))
+        }
+        Err(StoreError::ConditionFailed) => {
+            warn!(%eid, %qid, "attempted to tag question that isn't in event");
+            Err(ApiError::QuestionNotFound)
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to set question tags failed");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let ask = |tags: &[&str]| {
+            crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: tags.iter().map(|t| t.to_string()).collect(),
+                }),
+            )
+        };
+        let q1 = ask(&["Rust", " rust", "async"]).await.unwrap();
+        let qid1 = q1["id"].as_str().unwrap();
+        let q2 = ask(&[]).await.unwrap();
+        let qid2 = Ulid::from_string(q2["id"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            ask(&["a", "b", "c", "d", "e", "f"]).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let list = |tag: Option<&str>| {
+            let params = serde_json::from_value(serde_json::json!({ "tag": tag })).unwrap();
+            crate::list::list(Path(eid), State(backend.clone()), Query(params))
+        };
+        let qs = list(None).await.1.unwrap().0;
+        assert_eq!(qs.as_array().unwrap().len(), 2);
+        let q = qs.as_array().unwrap().iter().find(|q| q["qid"] == qid1);
+        assert_eq!(q.unwrap()["tags"], serde_json::json!(["async", "rust"]));
+
+        let qs = list(Some("RUST")).await.1.unwrap().0;
+        let qs = qs.as_array().unwrap();
+        assert_eq!(qs.len(), 1);
+        assert_eq!(qs[0]["qid"], qid1);
+        assert_eq!(list(Some("go")).await.1.unwrap().0, serde_json::json!([]));
+
+        // hosts can change their mind
+        let r = super::tags(
+            Path((eid, secret.to_string(), qid2)),
+            State(backend.clone()),
+            Json(vec!["Go".into(), "rust".into()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(r["tags"], serde_json::json!(["go", "rust"]));
+        assert_eq!(
+            list(Some("rust"))
+                .await
+                .1
+                .unwrap()
+                .0
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        let _ = super::tags(
+            Path((eid, secret.to_string(), qid2)),
+            State(backend.clone()),
+            Json(Vec::new()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(list(Some("go")).await.1.unwrap().0, serde_json::json!([]));
+
+        // but only for questions in their own event
+        assert_eq!(
+            super::tags(
+                Path((eid, secret.to_string(), Ulid::new())),
+                State(backend.clone()),
+                Json(Vec::new()),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[test]
+    fn cleaning() {
+        let eid = Ulid::new();
+        let tags = |tags: &[&str]| clean(&eid, tags.iter().map(|t| t.to_string()).collect());
+        assert_eq!(tags(&[" Rust ", "RUST", "", "go"]).unwrap(), ["rust", "go"]);
+        assert!(tags(&["a"; 10]).is_ok());
+        assert_eq!(
+            tags(&["a", "b", "c", "d", "e", "f"]).unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            tags(&[&"x".repeat(MAX_TAG_LEN + 1)]).unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
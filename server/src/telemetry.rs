@@ -0,0 +1,145 @@
+use aws_smithy_client::{erase::DynConnector, http_connector::HttpConnector};
+use aws_smithy_http::body::SdkBody;
+use opentelemetry::{
+    sdk::{trace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use std::{
+    sync::OnceLock,
+    task::{Context, Poll},
+};
+use tracing::{instrument::Instrumented, Instrument};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// What traces are reported as coming from, unless `OTEL_SERVICE_NAME` says otherwise.
+const SERVICE_NAME: &str = "wewerewondering-api";
+
+/// Returns the OTLP endpoint to export traces to, as configured through
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`.
+///
+/// Traces aren't exported at all (nor any spans made just for them) unless this is set.
+pub(super) fn endpoint() -> Option<&'static str> {
+    static ENDPOINT: OnceLock<Option<String>> = OnceLock::new();
+    ENDPOINT
+        .get_or_init(|| {
+            std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|e| !e.trim().is_empty())
+        })
+        .as_deref()
+}
+
+/// Builds the layer that hands spans to OpenTelemetry for export over OTLP (gRPC), if there's an
+/// endpoint to export them to.
+///
+/// Spans are exported in batches in the background, so requests don't wait on the collector.
+/// Panics if the exporter can't be set up.
+pub(super) fn layer<S>() -> Option<OpenTelemetryLayer<S, trace::Tracer>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let endpoint = endpoint()?;
+    let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| SERVICE_NAME.to_string());
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new([KeyValue::new("service.name", service)])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .unwrap_or_else(|e| panic!("could not set up trace export to {endpoint}: {e}"));
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Sends off whatever spans haven't been exported yet.
+pub(super) fn shutdown() {
+    if endpoint().is_some() {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+/// Loads the AWS configuration from the environment, with every request to AWS given its own span
+/// if traces are exported.
+// debug builds only talk to DynamoDB in tests.
+#[allow(dead_code)]
+pub(super) async fn aws_config() -> aws_config::SdkConfig {
+    let loader = aws_config::from_env();
+    if endpoint().is_none() {
+        return loader.load().await;
+    }
+    let https =
+        aws_smithy_client::hyper_ext::Adapter::builder().build(aws_smithy_client::conns::https());
+    loader
+        .http_connector(HttpConnector::Prebuilt(Some(DynConnector::new(Traced(
+            https,
+        )))))
+        .load()
+        .await
+}
+
+/// Wraps each request to AWS in a span, so that traces show how long every DynamoDB call (and
+/// every retry of one) took.
+#[derive(Clone, Debug)]
+struct Traced<S>(S);
+
+impl<S> tower::Service<http::Request<SdkBody>> for Traced<S>
+where
+    S: tower::Service<http::Request<SdkBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<SdkBody>) -> Self::Future {
+        let span = tracing::info_span!(
+            "dynamodb",
+            operation = operation(&req),
+            otel.name = format!("DynamoDB.{}", operation(&req)),
+            otel.kind = "client",
+        );
+        self.0.call(req).instrument(span)
+    }
+}
+
+/// Gives the name of the DynamoDB operation `req` is for (like `GetItem`).
+fn operation<B>(req: &http::Request<B>) -> &str {
+    req.headers()
+        .get("x-amz-target")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('.'))
+        .map_or("unknown", |(_, op)| op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations() {
+        let req = |target: Option<&str>| {
+            let mut req = http::Request::post("/");
+            if let Some(target) = target {
+                req = req.header("x-amz-target", target);
+            }
+            req.body(()).unwrap()
+        };
+        assert_eq!(
+            operation(&req(Some("DynamoDB_20120810.UpdateItem"))),
+            "UpdateItem"
+        );
+        assert_eq!(operation(&req(None)), "unknown");
+    }
+}
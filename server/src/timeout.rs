@@ -0,0 +1,108 @@
+use crate::error::ApiError;
+use axum::{
+    extract::State,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::Request;
+use std::{sync::OnceLock, time::Duration};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 10_000;
+
+/// Returns how long a request gets before we give up on it, as configured through
+/// `REQUEST_TIMEOUT_MS`.
+///
+/// Panics if `REQUEST_TIMEOUT_MS` is set but isn't a positive number of milliseconds.
+pub(super) fn request_timeout() -> Duration {
+    static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+    *TIMEOUT.get_or_init(|| match std::env::var("REQUEST_TIMEOUT_MS") {
+        Ok(ms) => match ms.parse() {
+            Ok(0) | Err(_) => {
+                panic!("REQUEST_TIMEOUT_MS must be a positive number of milliseconds")
+            }
+            Ok(ms) => Duration::from_millis(ms),
+        },
+        Err(_) => Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS),
+    })
+}
+
+/// Answers with a `504` if the handler hasn't come up with a response within `timeout`, so that a
+/// hung call to DynamoDB doesn't hang the request along with it.
+///
+/// Only getting to the response is timed, not sending its body. That's what keeps event streams
+/// and websockets (whose handlers respond right away and then keep going) out of it.
+pub(super) async fn timeout<B>(
+    State(timeout): State<Duration>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = req.uri().path().to_string();
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(res) => res,
+        Err(_) => {
+            error!(path, ?timeout, "request timed out");
+            ApiError::Timeout.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::StreamBody, routing::get, Router};
+    use futures_util::StreamExt;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn app() -> Router {
+        Router::new()
+            .route("/fast", get(|| async { "hello" }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(TIMEOUT * 10).await;
+                    "hello"
+                }),
+            )
+            // like an event stream, which responds straight away but keeps sending for longer
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks = futures_util::stream::iter(["hello", " ", "world"]).then(
+                        |chunk| async move {
+                            tokio::time::sleep(TIMEOUT).await;
+                            Ok::<_, std::convert::Infallible>(chunk)
+                        },
+                    );
+                    StreamBody::new(chunks)
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(TIMEOUT, timeout))
+    }
+
+    #[tokio::test]
+    async fn timeouts() {
+        let get = |path: &'static str| {
+            app().oneshot(Request::get(path).body(axum::body::Body::empty()).unwrap())
+        };
+
+        let res = get("/fast").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = get("/slow").await.unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "timeout");
+
+        let res = get("/stream").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "hello world");
+    }
+}
@@ -0,0 +1,345 @@
+use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::StoreError;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use axum::extract::{Path, Query, State};
+use axum::response::Json;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::{
+    collections::BTreeMap,
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+const DEFAULT_BUCKET_SECS: u64 = 60;
+
+/// Returns true if every vote should also be logged with when it came in, as configured through
+/// `VOTE_TIMESERIES`.
+///
+/// This is off by default, since it's one more write for every vote. Panics if `VOTE_TIMESERIES`
+/// is set to something other than `on` or `off`.
+pub(super) fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| match std::env::var("VOTE_TIMESERIES").as_deref() {
+        Ok("on") => true,
+        Ok("off") | Err(_) => false,
+        Ok(v) => panic!("VOTE_TIMESERIES must be on or off, not {v}"),
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// How a storage engine logs when votes came in.
+#[async_trait]
+pub(super) trait TimeseriesStore {
+    /// Notes that the votes of `qid` in `eid` just moved by `delta`.
+    async fn log_vote(&self, eid: &Ulid, qid: &Ulid, delta: isize) -> Result<(), StoreError>;
+
+    /// Gives when each logged vote in `eid` came in, and by how much it moved the votes.
+    async fn vote_log(&self, eid: &Ulid) -> Result<Vec<(u64, isize)>, StoreError>;
+}
+
+#[async_trait]
+impl TimeseriesStore for aws_sdk_dynamodb::Client {
+    async fn log_vote(&self, eid: &Ulid, qid: &Ulid, delta: isize) -> Result<(), StoreError> {
+        // the sort key is a ulid so that entries come back in the order they were made,
+        // and so that two votes in the same millisecond don't overwrite each other.
+        let put = self
+            .put_item()
+            .table_name("vote_log")
+            .item("eid", AttributeValue::S(eid.to_string()))
+            .item("id", AttributeValue::S(Ulid::new().to_string()))
+            .item("qid", AttributeValue::S(qid.to_string()))
+            .item("delta", AttributeValue::N(delta.to_string()))
+            .item("when", AttributeValue::N(now().to_string()))
+            .item(
+                "expire",
+                crate::to_dynamo_timestamp(
+                    SystemTime::now()
+                        + Duration::from_secs(
+                            crate::ask::QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60,
+                        ),
+                ),
+            );
+        super::retry::retry(|| put.clone().send()).await?;
+        Ok(())
+    }
+
+    async fn vote_log(&self, eid: &Ulid) -> Result<Vec<(u64, isize)>, StoreError> {
+        let mut logged = Vec::new();
+        let mut start = None;
+        loop {
+            let query = self
+                .query()
+                .table_name("vote_log")
+                .key_condition_expression("eid = :eid")
+                .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+                .projection_expression("#when, delta")
+                .expression_attribute_names("#when", "when")
+                .set_exclusive_start_key(start);
+            let r = super::retry::retry(|| query.clone().send()).await?;
+            logged.extend(r.items().into_iter().flatten().filter_map(|v| {
+                let when = v.get("when")?.as_n().ok()?.parse().ok()?;
+                let delta = v.get("delta")?.as_n().ok()?.parse().ok()?;
+                Some((when, delta))
+            }));
+            start = r.last_evaluated_key().cloned();
+            if start.is_none() {
+                break;
+            }
+        }
+        Ok(logged)
+    }
+}
+
+#[async_trait]
+impl TimeseriesStore for Mutex<Local> {
+    async fn log_vote(&self, _eid: &Ulid, qid: &Ulid, delta: isize) -> Result<(), StoreError> {
+        let mut local = super::lock(self);
+        let Local { vote_log, .. } = &mut *local;
+        vote_log.push((*qid, now(), delta));
+        Ok(())
+    }
+
+    async fn vote_log(&self, eid: &Ulid) -> Result<Vec<(u64, isize)>, StoreError> {
+        let local = super::lock(self);
+        let Some(qids) = local.questions_by_eid.get(eid) else {
+            return Ok(Vec::new());
+        };
+        Ok(local
+            .vote_log
+            .iter()
+            .filter(|(qid, _, _)| qids.contains(qid))
+            .map(|&(_, when, delta)| (when, delta))
+            .collect())
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl TimeseriesStore for crate::redis::Redis {
+    async fn log_vote(&self, eid: &Ulid, _qid: &Ulid, delta: isize) -> Result<(), StoreError> {
+        let key = crate::redis::vote_log_key(eid);
+        let mut pipe = redis::pipe();
+        pipe.rpush(&key, format!("{}:{delta}", now())).ignore();
+        crate::redis::keep(&mut pipe, &key);
+        self.run::<()>(&pipe).await
+    }
+
+    async fn vote_log(&self, eid: &Ulid) -> Result<Vec<(u64, isize)>, StoreError> {
+        let logged: Vec<String> = self
+            .query(
+                redis::cmd("LRANGE")
+                    .arg(crate::redis::vote_log_key(eid))
+                    .arg(0)
+                    .arg(-1),
+            )
+            .await?;
+        Ok(logged
+            .iter()
+            .filter_map(|v| {
+                let (when, delta) = v.split_once(':')?;
+                Some((when.parse().ok()?, delta.parse().ok()?))
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl TimeseriesStore for crate::sqlite::Sqlite {
+    async fn log_vote(&self, eid: &Ulid, _qid: &Ulid, delta: isize) -> Result<(), StoreError> {
+        self.run(|con| {
+            con.execute(
+                "INSERT INTO vote_log (eid, \"when\", delta) VALUES (?1, ?2, ?3)",
+                rusqlite::params![eid.to_string(), now(), delta],
+            )?;
+            Ok(())
+        })
+    }
+
+    async fn vote_log(&self, eid: &Ulid) -> Result<Vec<(u64, isize)>, StoreError> {
+        self.run(|con| {
+            let mut stmt = con.prepare_cached(
+                "SELECT \"when\", delta FROM vote_log WHERE eid = ?1 ORDER BY rowid",
+            )?;
+            let logged = stmt
+                .query_map([eid.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            Ok(logged)
+        })
+    }
+}
+
+/// Adds up `logged` votes into buckets of `bucket` seconds, keyed by when each bucket starts.
+///
+/// Each bucket has how far the votes in it moved counts up and down, so taking back an up-vote
+/// shows up as a down-vote.
+fn buckets(logged: &[(u64, isize)], bucket: u64) -> BTreeMap<u64, (u64, u64)> {
+    let mut out: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+    for &(when, delta) in logged {
+        let (up, down) = out.entry(when - when % bucket).or_default();
+        if delta > 0 {
+            *up += delta.unsigned_abs() as u64;
+        } else {
+            *down += delta.unsigned_abs() as u64;
+        }
+    }
+    out
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub(super) struct Params {
+    /// How many seconds each bucket covers.
+    #[serde(default)]
+    bucket: Option<u64>,
+}
+
+/// Tells the host how many votes came in when, in buckets of `?bucket=` seconds (a minute by
+/// default).
+///
+/// Only the buckets that got votes are included. Answers `501` unless `VOTE_TIMESERIES` is on.
+pub(super) async fn timeseries(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Query(params): Query<Params>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+    if !enabled() {
+        return Err(ApiError::NotImplemented);
+    }
+    let bucket = params.bucket.unwrap_or(DEFAULT_BUCKET_SECS);
+    if bucket == 0 {
+        warn!(%eid, "got vote timeseries request with empty buckets");
+        return Err(ApiError::BadRequest);
+    }
+
+    let logged = match dynamo.vote_log(&eid).await {
+        Ok(logged) => logged,
+        Err(e) => {
+            error!(%eid, error = %e, "dynamodb request for vote log failed");
+            return Err(ApiError::Internal);
+        }
+    };
+    let series: Vec<_> = buckets(&logged, bucket)
+        .into_iter()
+        .map(|(start, (up, down))| serde_json::json!({ "start": start, "up": up, "down": down }))
+        .collect();
+    debug!(%eid, bucket, n = series.len(), "summarized vote timeseries");
+    Ok(Json(
+        serde_json::json!({ "bucket": bucket, "series": series }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+
+    #[test]
+    fn bucketing() {
+        let logged = [(100, 1), (119, 1), (120, -1), (150, 1), (301, -2)];
+        assert_eq!(
+            buckets(&logged, 60).into_iter().collect::<Vec<_>>(),
+            [(60, (2, 0)), (120, (1, 1)), (300, (0, 2))]
+        );
+        assert_eq!(
+            buckets(&logged, 1000).into_iter().collect::<Vec<_>>(),
+            [(0, (3, 3))]
+        );
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+
+        // the vote handler only logs with VOTE_TIMESERIES on, so log directly.
+        for delta in [1, 1, -1] {
+            backend.log_vote(&eid, &qid, delta).await.unwrap();
+        }
+        // votes in other events don't count
+        backend
+            .log_vote(&Ulid::new(), &Ulid::new(), 1)
+            .await
+            .unwrap();
+
+        let logged = backend.vote_log(&eid).await.unwrap();
+        assert_eq!(logged.len(), 3);
+        let series = buckets(&logged, u64::MAX);
+        assert_eq!(series.into_values().collect::<Vec<_>>(), [(2, 1)]);
+
+        assert_eq!(
+            timeseries(
+                Path((eid, "wrong".into())),
+                State(backend.clone()),
+                Query(Default::default())
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+        // and tests run without VOTE_TIMESERIES
+        assert_eq!(
+            timeseries(
+                Path((eid, secret)),
+                State(backend.clone()),
+                Query(Default::default())
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_IMPLEMENTED
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}
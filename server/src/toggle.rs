@@ -1,158 +1,584 @@
 use crate::to_dynamo_timestamp;
 
 use super::{Backend, Local};
-use aws_sdk_dynamodb::{
-    error::UpdateItemError, model::AttributeValue, output::UpdateItemOutput, types::SdkError,
-};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::{AttributeValue, ReturnValue};
 use axum::{
     extract::{Path, State},
     Json,
 };
-use http::StatusCode;
+use http::HeaderMap;
 use serde::Deserialize;
+use std::sync::Mutex;
 use std::{collections::HashMap, time::SystemTime};
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
+/// The properties of a question hosts can toggle.
+///
+/// These are the only attributes a toggle ever writes, and since the property comes from the path,
+/// anything else is turned away with a `400` before the handler (or storage) sees it.
 #[derive(Deserialize, Debug, Copy, Clone)]
 #[serde(rename_all = "lowercase")]
 pub(super) enum Property {
     Hidden,
     Answered,
+    Pinned,
+    Approved,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub(super) enum ToggleRequest {
     Hidden(bool),
     Answered(Option<SystemTime>),
+    Pinned(bool),
+    /// Approving a question in a moderated event also makes it visible to guests.
+    Approved(bool),
 }
 
-impl Backend {
-    pub(super) async fn toggle(
+impl ToggleRequest {
+    fn new(property: Property, on: bool) -> Self {
+        match property {
+            Property::Hidden => Self::Hidden(on),
+            Property::Answered => Self::Answered(on.then(SystemTime::now)),
+            Property::Pinned => Self::Pinned(on),
+            Property::Approved => Self::Approved(on),
+        }
+    }
+
+    /// The attribute that holds the property being toggled.
+    fn attribute(&self) -> &'static str {
+        match self {
+            Self::Hidden(_) => "hidden",
+            Self::Answered(_) => "answered",
+            Self::Pinned(_) => "pinned",
+            Self::Approved(_) => "approved",
+        }
+    }
+
+    /// What we tell the host the question now looks like, given what the toggle returned.
+    fn response(&self, out: &Item) -> serde_json::Value {
+        let mut v = match *self {
+            Self::Hidden(set) => serde_json::json!({ "hidden": set }),
+            Self::Answered(Some(time)) => {
+                let time = time
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                serde_json::json!({ "answered": time })
+            }
+            Self::Answered(None) => serde_json::json!({}),
+            Self::Pinned(set) => serde_json::json!({ "pinned": set }),
+            Self::Approved(set) => serde_json::json!({ "approved": set, "hidden": !set }),
+        };
+        v["version"] = crate::version::of(out).into();
+        v
+    }
+}
+
+/// Whether the property that `req` toggles is currently set on `q`.
+fn is_set<K>(q: &HashMap<K, Attr>, req: ToggleRequest) -> bool
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    match (req, q.get(req.attribute())) {
+        (ToggleRequest::Answered(_), answered) => answered.is_some(),
+        (_, Some(v)) => v.as_bool().is_ok_and(|set| *set),
+        (_, None) => false,
+    }
+}
+
+/// Makes the change `req` asks for to the stored question `q`.
+fn flip<K>(q: &mut HashMap<K, Attr>, req: ToggleRequest)
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    match req {
+        ToggleRequest::Hidden(set) => q.insert("hidden".into(), Attr::Bool(set)),
+        ToggleRequest::Answered(time) => {
+            if let Some(time) = time {
+                q.insert("answered".into(), to_dynamo_timestamp(time))
+            } else {
+                q.remove("answered")
+            }
+        }
+        ToggleRequest::Pinned(set) => q.insert("pinned".into(), Attr::Bool(set)),
+        ToggleRequest::Approved(set) => {
+            q.insert("hidden".into(), Attr::Bool(!set));
+            q.insert("approved".into(), Attr::Bool(set))
+        }
+    };
+}
+
+impl Local {
+    // mirrors the dynamodb implementation of `ToggleStore::toggle`, errors and all.
+    fn toggle(
+        &mut self,
+        eid: &Ulid,
+        qid: &Ulid,
+        req: ToggleRequest,
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let Local {
+            questions,
+            questions_by_eid,
+            ..
+        } = &mut *self;
+
+        let q = match questions.get_mut(qid) {
+            Some(q)
+                if questions_by_eid.get(eid).is_some_and(|qs| qs.contains(qid))
+                    && expected.is_none_or(|expected| is_set(q, req) == expected)
+                    && version.is_none_or(|version| crate::version::of(q) == version) =>
+            {
+                q
+            }
+            _ => {
+                return Err(StoreError::ConditionFailed);
+            }
+        };
+        flip(q, req);
+        crate::version::bump(q);
+        crate::sync::touch(q);
+        let ret = store::from_local(q);
+
+        let mut update = match req {
+            ToggleRequest::Hidden(set) => {
+                serde_json::json!({ "qid": qid.to_string(), "hidden": set })
+            }
+            ToggleRequest::Answered(_) => {
+                let answered = q.get("answered").and_then(|v| v.as_n().ok());
+                serde_json::json!({ "qid": qid.to_string(), "answered": answered })
+            }
+            ToggleRequest::Pinned(set) => {
+                serde_json::json!({ "qid": qid.to_string(), "pinned": set })
+            }
+            ToggleRequest::Approved(set) => {
+                serde_json::json!({ "qid": qid.to_string(), "approved": set, "hidden": !set })
+            }
+        };
+        update["version"] = crate::version::of(q).into();
+        self.publish(eid, "toggle", update);
+
+        Ok(ret)
+    }
+}
+
+/// How a storage engine flips the properties of questions.
+#[async_trait]
+pub(super) trait ToggleStore {
+    /// Fails with [`StoreError::ConditionFailed`] if the question does not exist in the event `eid`,
+    /// if the property isn't currently set the way the caller `expected`, or if the question is no
+    /// longer at `version`.
+    ///
+    /// Returns the attributes the toggle changed, which include the question's new version.
+    async fn toggle(
         &self,
+        eid: &Ulid,
         qid: &Ulid,
         req: ToggleRequest,
-    ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                let q = dynamo
-                    .update_item()
-                    .table_name("questions")
-                    .key("id", AttributeValue::S(qid.to_string()));
-
-                let q = match req {
-                    ToggleRequest::Hidden(set) => q
-                        .update_expression("SET #field = :set")
-                        .expression_attribute_names("#field", "hidden")
-                        .expression_attribute_values(":set", AttributeValue::Bool(set)),
-                    ToggleRequest::Answered(time) => {
-                        if let Some(time) = time {
-                            q.update_expression("SET #field = :set")
-                                .expression_attribute_names("#field", "answered")
-                                .expression_attribute_values(":set", to_dynamo_timestamp(time))
-                        } else {
-                            q.update_expression("REMOVE #field")
-                                .expression_attribute_names("#field", "answered")
-                        }
-                    }
-                };
-                q.send().await
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError>;
+
+    /// Applies the same toggle to each of `qids`, returning the outcome for each in order.
+    async fn toggle_many(
+        &self,
+        eid: &Ulid,
+        qids: &[Ulid],
+        req: ToggleRequest,
+    ) -> Vec<Result<Item, StoreError>>;
+
+    /// Returns true if `qid` is a question of `eid`.
+    async fn in_event(&self, eid: &Ulid, qid: &Ulid) -> Result<bool, StoreError>;
+}
+
+#[async_trait]
+impl ToggleStore for aws_sdk_dynamodb::Client {
+    async fn toggle(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        req: ToggleRequest,
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let q = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .expression_attribute_names("#version", "version")
+            .expression_attribute_values(":eid", AttributeValue::S(eid.to_string()))
+            .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+            .expression_attribute_values(":updated", crate::sync::now())
+            .return_values(ReturnValue::UpdatedNew);
+
+        // #field is named below, since that's where the update needs it anyway.
+        let mut conditions = vec!["eid = :eid"];
+        let q = match (req, expected) {
+            (_, None) => q,
+            (ToggleRequest::Answered(_), Some(true)) => {
+                conditions.push("attribute_exists(#field)");
+                q
             }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local { questions, .. } = &mut *local;
-
-                fn invert(q: &mut HashMap<&'static str, AttributeValue>, key: &'static str) {
-                    if let AttributeValue::Bool(b) = q[key] {
-                        q.insert(key, AttributeValue::Bool(!b));
-                    } else {
-                        unreachable!("all properties are bools");
-                    }
+            (ToggleRequest::Answered(_), Some(false)) => {
+                conditions.push("attribute_not_exists(#field)");
+                q
+            }
+            (_, Some(true)) => {
+                conditions.push("#field = :expected");
+                q.expression_attribute_values(":expected", AttributeValue::Bool(true))
+            }
+            (_, Some(false)) => {
+                conditions.push("(attribute_not_exists(#field) OR #field = :expected)");
+                q.expression_attribute_values(":expected", AttributeValue::Bool(false))
+            }
+        };
+        let q = if let Some(version) = version {
+            conditions.push(crate::version::condition(version));
+            q.expression_attribute_values(":version", AttributeValue::N(version.to_string()))
+        } else {
+            q
+        };
+        let q = q.condition_expression(conditions.join(" AND "));
+
+        let q = match req {
+            ToggleRequest::Hidden(set) => q
+                .update_expression("SET #field = :set, updated_at = :updated ADD #version :one")
+                .expression_attribute_names("#field", "hidden")
+                .expression_attribute_values(":set", AttributeValue::Bool(set)),
+            ToggleRequest::Answered(time) => {
+                if let Some(time) = time {
+                    q.update_expression(
+                        "SET #field = :set, updated_at = :updated ADD #version :one",
+                    )
+                    .expression_attribute_names("#field", "answered")
+                    .expression_attribute_values(":set", to_dynamo_timestamp(time))
+                } else {
+                    q.update_expression("REMOVE #field SET updated_at = :updated ADD #version :one")
+                        .expression_attribute_names("#field", "answered")
                 }
+            }
+            ToggleRequest::Pinned(set) => q
+                .update_expression("SET #field = :set, updated_at = :updated ADD #version :one")
+                .expression_attribute_names("#field", "pinned")
+                .expression_attribute_values(":set", AttributeValue::Bool(set)),
+            ToggleRequest::Approved(set) => q
+                .update_expression(
+                    "SET #field = :set, #hidden = :hidden, updated_at = :updated ADD #version :one",
+                )
+                .expression_attribute_names("#field", "approved")
+                .expression_attribute_names("#hidden", "hidden")
+                .expression_attribute_values(":set", AttributeValue::Bool(set))
+                .expression_attribute_values(":hidden", AttributeValue::Bool(!set)),
+        };
+        let q = super::retry::retry(|| q.clone().send()).await?;
+        Ok(store::from_dynamo(q.attributes.unwrap_or_default()))
+    }
 
-                let q = questions
-                    .get_mut(qid)
-                    .expect("toggle property on unknown question ");
-                match req {
-                    ToggleRequest::Hidden(set) => q.insert("hidden", AttributeValue::Bool(set)),
-                    ToggleRequest::Answered(time) => {
-                        if let Some(time) = time {
-                            q.insert("answered", to_dynamo_timestamp(time))
-                        } else {
-                            q.remove("answered")
-                        }
-                    }
-                };
+    async fn toggle_many(
+        &self,
+        eid: &Ulid,
+        qids: &[Ulid],
+        req: ToggleRequest,
+    ) -> Vec<Result<Item, StoreError>> {
+        // a transaction would fail all of them if one doesn't belong to the event, and
+        // the host would rather have the rest go through.
+        futures_util::future::join_all(
+            qids.iter()
+                .map(|qid| self.toggle(eid, qid, req, None, None)),
+        )
+        .await
+    }
+
+    async fn in_event(&self, eid: &Ulid, qid: &Ulid) -> Result<bool, StoreError> {
+        let eid = eid.to_string();
+        let get = self
+            .get_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .projection_expression("eid");
+        let r = super::retry::retry(|| get.clone().send()).await?;
+        Ok(r.item().and_then(|q| q.get("eid")) == Some(&AttributeValue::S(eid)))
+    }
+}
+
+#[async_trait]
+impl ToggleStore for Mutex<Local> {
+    async fn toggle(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        req: ToggleRequest,
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        super::lock(self).toggle(eid, qid, req, expected, version)
+    }
+
+    async fn toggle_many(
+        &self,
+        eid: &Ulid,
+        qids: &[Ulid],
+        req: ToggleRequest,
+    ) -> Vec<Result<Item, StoreError>> {
+        let mut local = super::lock(self);
+        qids.iter()
+            .map(|qid| local.toggle(eid, qid, req, None, None))
+            .collect()
+    }
+
+    async fn in_event(&self, eid: &Ulid, qid: &Ulid) -> Result<bool, StoreError> {
+        let eid = eid.to_string();
+        Ok(super::lock(self)
+            .questions
+            .get(qid)
+            .and_then(|q| q.get("eid"))
+            == Some(&Attr::S(eid)))
+    }
+}
 
-                Ok(UpdateItemOutput::builder().build())
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ToggleStore for crate::redis::Redis {
+    async fn toggle(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        req: ToggleRequest,
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if !store::belongs_to(q, eid)
+                    || expected.is_some_and(|expected| is_set(q, req) != expected)
+                    || version.is_some_and(|version| crate::version::of(q) != version)
+                {
+                    return Err(StoreError::ConditionFailed);
+                }
+                flip(q, req);
+                crate::version::bump(q);
+                crate::sync::touch(q);
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+
+    async fn toggle_many(
+        &self,
+        eid: &Ulid,
+        qids: &[Ulid],
+        req: ToggleRequest,
+    ) -> Vec<Result<Item, StoreError>> {
+        futures_util::future::join_all(
+            qids.iter()
+                .map(|qid| self.toggle(eid, qid, req, None, None)),
+        )
+        .await
+    }
+
+    async fn in_event(&self, eid: &Ulid, qid: &Ulid) -> Result<bool, StoreError> {
+        let in_event: Option<String> = self
+            .query(
+                redis::cmd("HGET")
+                    .arg(crate::redis::question_key(qid))
+                    .arg("eid"),
+            )
+            .await?;
+        Ok(in_event == Some(eid.to_string()))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ToggleStore for crate::sqlite::Sqlite {
+    async fn toggle(
+        &self,
+        eid: &Ulid,
+        qid: &Ulid,
+        req: ToggleRequest,
+        expected: Option<bool>,
+        version: Option<u64>,
+    ) -> Result<Item, StoreError> {
+        let (q, ()) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if !store::belongs_to(q, eid)
+                || expected.is_some_and(|expected| is_set(q, req) != expected)
+                || version.is_some_and(|version| crate::version::of(q) != version)
+            {
+                return Err(StoreError::ConditionFailed);
             }
+            flip(q, req);
+            crate::version::bump(q);
+            crate::sync::touch(q);
+            Ok(())
+        })?;
+        Ok(q)
+    }
+
+    async fn toggle_many(
+        &self,
+        eid: &Ulid,
+        qids: &[Ulid],
+        req: ToggleRequest,
+    ) -> Vec<Result<Item, StoreError>> {
+        let mut toggled = Vec::with_capacity(qids.len());
+        for qid in qids {
+            toggled.push(self.toggle(eid, qid, req, None, None).await);
         }
+        toggled
+    }
+
+    async fn in_event(&self, eid: &Ulid, qid: &Ulid) -> Result<bool, StoreError> {
+        let in_event: Option<String> = self.run(|con| {
+            crate::sqlite::first(
+                con,
+                "SELECT eid FROM questions WHERE id = ?1",
+                [qid.to_string()],
+            )
+        })?;
+        Ok(in_event == Some(eid.to_string()))
     }
 }
 
+/// A toggle that only goes through if the property is currently set the way the client last saw
+/// it, so that two hosts toggling at once can't undo each other without noticing.
+#[derive(Deserialize, Debug)]
+struct Conditional {
+    value: bool,
+    #[serde(default)]
+    expected: Option<bool>,
+}
+
+/// Toggles a property of a question.
+///
+/// The body is either `on` or `off`, or `{ "value": <bool>, "expected": <bool> }` to only toggle
+/// if the property is currently `expected`, and get a `409` otherwise. Likewise, the toggle only
+/// goes through if the question is still at the version given in `If-Match`.
 pub(super) async fn toggle(
     Path((eid, secret, qid, property)): Path<(Ulid, String, Ulid, Property)>,
     State(dynamo): State<Backend>,
+    headers: HeaderMap,
     body: String,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     super::check_secret(&dynamo, &eid, &secret).await?;
+    let version = crate::version::if_match(&headers)?;
 
-    let req = match (&*body, property) {
-        ("on", Property::Hidden) => ToggleRequest::Hidden(true),
-        ("off", Property::Hidden) => ToggleRequest::Hidden(false),
-        ("on", Property::Answered) => ToggleRequest::Answered(Some(SystemTime::now())),
-        ("off", Property::Answered) => ToggleRequest::Answered(None),
-        _ => {
-            error!(%qid, body, "invalid toggle value");
-            return Err(http::StatusCode::BAD_REQUEST);
-        }
+    let (req, expected) = match &*body {
+        "on" => (ToggleRequest::new(property, true), None),
+        "off" => (ToggleRequest::new(property, false), None),
+        _ => match serde_json::from_str::<Conditional>(&body) {
+            Ok(c) => (ToggleRequest::new(property, c.value), c.expected),
+            Err(_) => {
+                error!(%qid, body, "invalid toggle value");
+                return Err(ApiError::BadRequest);
+            }
+        },
     };
 
-    match dynamo.toggle(&qid, req).await {
-        Ok(_) => {
+    match dynamo.toggle(&eid, &qid, req, expected, version).await {
+        Ok(out) => {
             debug!(%eid, %qid, p = ?property, "toggled question property");
-            match req {
-                ToggleRequest::Hidden(set) => Ok(Json(serde_json::json!({ "hidden": set }))),
-                ToggleRequest::Answered(time) => {
-                    if let Some(time) = time {
-                        let time = time
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs();
-                        Ok(Json(serde_json::json!({ "answered": time })))
-                    } else {
-                        Ok(Json(serde_json::json!({})))
+            crate::listcache::invalidate(&eid);
+            Ok(Json(req.response(&out)))
+        }
+        Err(StoreError::ConditionFailed) => {
+            // the condition doesn't say which part of it failed, so go look.
+            let in_event = match (expected, version) {
+                (None, None) => false,
+                _ => match dynamo.in_event(&eid, &qid).await {
+                    Ok(in_event) => in_event,
+                    Err(e) => {
+                        error!(%qid, error = %e, "dynamodb request for toggled question failed");
+                        return Err(ApiError::Internal);
                     }
-                }
+                },
+            };
+            if in_event {
+                warn!(%eid, %qid, p = ?property, ?expected, ?version, "conditional toggle lost a race");
+                Err(ApiError::Conflict)
+            } else {
+                warn!(%eid, %qid, "attempted to toggle question that isn't in event");
+                Err(ApiError::QuestionNotFound)
             }
         }
         Err(e) => {
             error!(%qid, error = %e, "dynamodb request to toggle question property failed");
-            Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal)
         }
     }
 }
 
+#[derive(Deserialize, Debug)]
+pub(super) struct BulkToggle {
+    property: Property,
+    qids: Vec<Ulid>,
+    value: bool,
+}
+
+/// Toggles the same property on many questions at once.
+///
+/// Each question is toggled on its own, so some may fail while others go through. The response
+/// maps each qid to either what the single-question toggle would have returned, or an error.
+pub(super) async fn toggle_bulk(
+    Path((eid, secret)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    Json(bulk): Json<BulkToggle>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    super::check_secret(&dynamo, &eid, &secret).await?;
+
+    let req = ToggleRequest::new(bulk.property, bulk.value);
+    let results = dynamo.toggle_many(&eid, &bulk.qids, req).await;
+    let mut out = serde_json::Map::with_capacity(results.len());
+    for (qid, r) in bulk.qids.iter().zip(results) {
+        let v = match r {
+            Ok(out) => req.response(&out),
+            Err(StoreError::ConditionFailed) => {
+                warn!(%eid, %qid, "attempted to toggle question that isn't in event");
+                ApiError::QuestionNotFound.body()
+            }
+            Err(e) => {
+                error!(%qid, error = %e, "dynamodb request to toggle question property failed");
+                ApiError::Internal.body()
+            }
+        };
+        out.insert(qid.to_string(), v);
+    }
+    debug!(%eid, p = ?bulk.property, n = out.len(), "bulk toggled question property");
+    crate::listcache::invalidate(&eid);
+    Ok(Json(serde_json::Value::Object(out)))
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::UNIX_EPOCH;
 
     use super::*;
-    use axum::Json;
+    use axum::{extract::Query, Json};
+    use http::StatusCode;
     use serde_json::Value;
 
+    type Expect = Option<(bool, Box<dyn Fn(&Value)>, u64)>;
+
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
         let secret = e["secret"].as_str().unwrap();
         let q = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello world".into(),
                 asker: None,
+                tags: Vec::new(),
             }),
         )
         .await
@@ -160,7 +586,7 @@ mod tests {
         let qid = q["id"].as_str().unwrap();
         let qid_u = Ulid::from_string(qid).unwrap();
 
-        let check = |qids: Value, expect: Option<(bool, Box<dyn Fn(&Value) -> ()>, u64)>| {
+        let check = |qids: Value, expect: Expect| {
             let qids = qids.as_array().unwrap();
             let q = qids.iter().find(|q| dbg!(&q["qid"]) == dbg!(qid));
             if let Some((hidden, check_answered, votes)) = expect {
@@ -170,7 +596,7 @@ mod tests {
                 );
                 let q = q.unwrap();
                 assert_eq!(q["votes"].as_u64().unwrap(), votes);
-                check_answered(&q);
+                check_answered(q);
                 assert_eq!(q["hidden"].as_bool().unwrap(), hidden);
                 assert_eq!(qids.len(), 1, "extra questions in response: {qids:?}");
             } else {
@@ -185,9 +611,18 @@ mod tests {
             let answered = &json["answered"];
             assert!(answered.is_u64(), "answered is not a u64: {answered}");
             let answered = answered.as_u64().unwrap();
-            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-            assert!(now >= answered, "answered is time travelling: [now: {now} | answered: {answered}]");
-            assert!(now - answered <= 30, "answered not within the last 30 sec: [now: {now} | answered: {answered}]")
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            assert!(
+                now >= answered,
+                "answered is time travelling: [now: {now} | answered: {answered}]"
+            );
+            assert!(
+                now - answered <= 30,
+                "answered not within the last 30 sec: [now: {now} | answered: {answered}]"
+            )
         };
 
         let check_answered_unset = |json: &Value| {
@@ -195,26 +630,24 @@ mod tests {
             assert!(answered.is_null(), "answered should be null: {answered}");
         };
 
-
         // only admin should see hidden
         let toggle_res = super::toggle(
-            Path((
-                eid.clone(),
-                secret.to_string(),
-                qid_u.clone(),
-                Property::Hidden,
-            )),
+            Path((eid, secret.to_string(), qid_u, Property::Hidden)),
             State(backend.clone()),
+            http::HeaderMap::new(),
             String::from("on"),
         )
         .await
         .unwrap();
-        assert_eq!(toggle_res["hidden"].as_bool().expect("hidden should be a bool"), true);
+        assert!(toggle_res["hidden"]
+            .as_bool()
+            .expect("hidden should be a bool"));
 
         check(
             crate::list::list_all(
-                Path((eid.clone(), secret.to_string())),
+                Path((eid, secret.to_string())),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -223,7 +656,7 @@ mod tests {
             Some((true, Box::new(check_answered_unset), 1)),
         );
         check(
-            crate::list::list(Path(eid.clone()), State(backend.clone()))
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
                 .await
                 .1
                 .unwrap()
@@ -233,28 +666,22 @@ mod tests {
 
         // should toggle back
         let toggle_res = super::toggle(
-            Path((
-                eid.clone(),
-                secret.to_string(),
-                qid_u.clone(),
-                Property::Hidden,
-            )),
+            Path((eid, secret.to_string(), qid_u, Property::Hidden)),
             State(backend.clone()),
+            http::HeaderMap::new(),
             String::from("off"),
         )
         .await
         .unwrap();
-        assert_eq!(toggle_res["hidden"].as_bool().expect("hidden should be a bool"), false);
+        assert!(!toggle_res["hidden"]
+            .as_bool()
+            .expect("hidden should be a bool"));
 
         // and should now show up as answered
         let toggle_res = super::toggle(
-            Path((
-                eid.clone(),
-                secret.to_string(),
-                qid_u.clone(),
-                Property::Answered,
-            )),
+            Path((eid, secret.to_string(), qid_u, Property::Answered)),
             State(backend.clone()),
+            http::HeaderMap::new(),
             String::from("on"),
         )
         .await
@@ -263,8 +690,9 @@ mod tests {
 
         check(
             crate::list::list_all(
-                Path((eid.clone(), secret.to_string())),
+                Path((eid, secret.to_string())),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -273,7 +701,7 @@ mod tests {
             Some((false, Box::new(check_answered_set), 1)),
         );
         check(
-            crate::list::list(Path(eid.clone()), State(backend.clone()))
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
                 .await
                 .1
                 .unwrap()
@@ -283,13 +711,9 @@ mod tests {
 
         // answered should toggle back
         let toggle_res = super::toggle(
-            Path((
-                eid.clone(),
-                secret.to_string(),
-                qid_u.clone(),
-                Property::Answered,
-            )),
+            Path((eid, secret.to_string(), qid_u, Property::Answered)),
             State(backend.clone()),
+            http::HeaderMap::new(),
             String::from("off"),
         )
         .await
@@ -298,8 +722,9 @@ mod tests {
 
         check(
             crate::list::list_all(
-                Path((eid.clone(), secret.to_string())),
+                Path((eid, secret.to_string())),
                 State(backend.clone()),
+                Query(Default::default()),
             )
             .await
             .1
@@ -308,7 +733,7 @@ mod tests {
             Some((false, Box::new(check_answered_unset), 1)),
         );
         check(
-            crate::list::list(Path(eid.clone()), State(backend.clone()))
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
                 .await
                 .1
                 .unwrap()
@@ -316,18 +741,453 @@ mod tests {
             Some((false, Box::new(check_answered_unset), 1)),
         );
 
+        // pinned questions go first regardless of votes
+        let q2 = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello moon".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid2 = q2["id"].as_str().unwrap();
+        let _ = crate::vote::vote(
+            Path((Ulid::from_string(qid2).unwrap(), crate::vote::UpDown::Up)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let order = || async {
+            let qs =
+                crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                    .await
+                    .1
+                    .unwrap()
+                    .0;
+            qs.as_array()
+                .unwrap()
+                .iter()
+                .map(|q| {
+                    (
+                        q["qid"].as_str().unwrap().to_string(),
+                        q["pinned"].as_bool().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            order().await,
+            [(qid2.to_string(), false), (qid.to_string(), false)]
+        );
+
+        let toggle_res = super::toggle(
+            Path((eid, secret.to_string(), qid_u, Property::Pinned)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(toggle_res["pinned"], true);
+        assert_eq!(
+            order().await,
+            [(qid.to_string(), true), (qid2.to_string(), false)]
+        );
+
+        let toggle_res = super::toggle(
+            Path((eid, secret.to_string(), qid_u, Property::Pinned)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("off"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(toggle_res["pinned"], false);
+        assert_eq!(
+            order().await,
+            [(qid2.to_string(), false), (qid.to_string(), false)]
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    async fn moderated(backend: Backend) {
+        let backend = &backend;
+        let ask = |eid| {
+            crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+        };
+        let guest_view = |eid| async move {
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                .await
+                .1
+                .unwrap()
+                .0
+        };
+        let host_view = |eid, secret: String| async move {
+            crate::list::list_all(
+                Path((eid, secret)),
+                State(backend.clone()),
+                Query(Default::default()),
+            )
+            .await
+            .1
+            .unwrap()
+            .0
+        };
+
+        // in unmoderated events, questions show up right away
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let _ = ask(eid).await.unwrap();
+        assert_eq!(guest_view(eid).await.as_array().unwrap().len(), 1);
+        let qs = host_view(eid, secret).await;
+        assert_eq!(qs[0]["hidden"], false);
+        assert_eq!(qs[0].get("approved"), None);
+        backend.delete(&eid).await;
+
+        // in moderated events, they wait for the host
+        let e = crate::new::new(
+            State(backend.clone()),
+            String::from(r#"{"moderated": true}"#),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = ask(eid).await.unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        assert_eq!(guest_view(eid).await, serde_json::json!([]));
+        let qs = host_view(eid, secret.clone()).await;
+        assert_eq!(qs[0]["hidden"], true);
+        assert_eq!(qs[0]["approved"], false);
+
+        let toggle_res = super::toggle(
+            Path((eid, secret.clone(), qid, Property::Approved)),
+            State(backend.clone()),
+            http::HeaderMap::new(),
+            String::from("on"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(toggle_res["approved"], true);
+        assert_eq!(toggle_res["hidden"], false);
+        let qs = guest_view(eid).await;
+        assert_eq!(qs.as_array().unwrap().len(), 1);
+        assert_eq!(qs[0]["qid"], qid.to_string());
+        assert_eq!(host_view(eid, secret).await[0]["approved"], true);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn bulk(backend: Backend) {
+        let backend = &backend;
+        let new = || async {
+            let e = crate::new::new(State(backend.clone()), String::new())
+                .await
+                .unwrap();
+            let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+            (eid, e["secret"].as_str().unwrap().to_string())
+        };
+        let ask = |eid| async move {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            Ulid::from_string(q["id"].as_str().unwrap()).unwrap()
+        };
+
+        let (eid, secret) = new().await;
+        let (eid2, secret2) = new().await;
+        let qid1 = ask(eid).await;
+        let qid2 = ask(eid).await;
+        let other = ask(eid2).await;
+
+        let res = super::toggle_bulk(
+            Path((eid, secret.clone())),
+            State(backend.clone()),
+            Json(BulkToggle {
+                property: Property::Hidden,
+                qids: vec![qid1, other, qid2],
+                value: true,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            res[qid1.to_string()],
+            serde_json::json!({ "hidden": true, "version": 2 })
+        );
+        assert_eq!(
+            res[qid2.to_string()],
+            serde_json::json!({ "hidden": true, "version": 2 })
+        );
+        // questions from other events are left alone
+        assert_eq!(res[other.to_string()], ApiError::QuestionNotFound.body());
+        assert_eq!(
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                .await
+                .1
+                .unwrap()
+                .0,
+            serde_json::json!([])
+        );
+        let qs = crate::list::list(
+            Path(eid2),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .1
+        .unwrap()
+        .0;
+        assert_eq!(qs.as_array().unwrap().len(), 1);
+
+        // and the same goes for single toggles
+        assert_eq!(
+            super::toggle(
+                Path((eid, secret.clone(), other, Property::Hidden)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                String::from("on"),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        // only the host gets to toggle
+        assert_eq!(
+            super::toggle_bulk(
+                Path((eid, secret2.clone())),
+                State(backend.clone()),
+                Json(BulkToggle {
+                    property: Property::Hidden,
+                    qids: vec![qid1],
+                    value: false,
+                }),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        backend.delete(&eid).await;
+        backend.delete(&eid2).await;
+    }
+
+    async fn conditional(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap().to_string();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let toggle = |qid, property, body: Value| {
+            super::toggle(
+                Path((eid, secret.clone(), qid, property)),
+                State(backend.clone()),
+                http::HeaderMap::new(),
+                body.to_string(),
+            )
+        };
+
+        for property in [Property::Answered, Property::Hidden, Property::Pinned] {
+            // two hosts both see the question unset and set it, and only the first one gets to
+            let r = toggle(
+                qid,
+                property,
+                serde_json::json!({ "value": true, "expected": false }),
+            )
+            .await
+            .unwrap();
+            assert_ne!(r.0, serde_json::json!({}));
+            assert_eq!(
+                toggle(
+                    qid,
+                    property,
+                    serde_json::json!({ "value": true, "expected": false })
+                )
+                .await
+                .unwrap_err(),
+                StatusCode::CONFLICT
+            );
+            // but one who saw it set can unset it
+            let _ = toggle(
+                qid,
+                property,
+                serde_json::json!({ "value": false, "expected": true }),
+            )
+            .await
+            .unwrap();
+            // and without an expected value, the toggle always goes through
+            let _ = toggle(qid, property, serde_json::json!({ "value": false }))
+                .await
+                .unwrap();
+        }
+
+        let qs = crate::list::list_all(
+            Path((eid, secret.clone())),
+            State(backend.clone()),
+            Query(Default::default()),
+        )
+        .await
+        .1
+        .unwrap();
+        assert_eq!(qs[0].get("answered"), None);
+        assert_eq!(qs[0]["hidden"], false);
+        assert_eq!(qs[0]["pinned"], false);
+
+        // questions that aren't there are still just not found
+        assert_eq!(
+            toggle(
+                Ulid::new(),
+                Property::Hidden,
+                serde_json::json!({ "value": true, "expected": false })
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            toggle(
+                qid,
+                Property::Hidden,
+                serde_json::json!({ "expected": false })
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
 
         backend.delete(&eid).await;
     }
 
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_moderated() {
+        moderated(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_moderated() {
+        moderated(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_moderated() {
+        moderated(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_moderated() {
+        moderated(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_bulk() {
+        bulk(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_bulk() {
+        bulk(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_bulk() {
+        bulk(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_bulk() {
+        bulk(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_conditional() {
+        conditional(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_conditional() {
+        conditional(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_conditional() {
+        conditional(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_conditional() {
+        conditional(crate::sqlite_backend().await).await;
     }
 }
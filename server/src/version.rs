@@ -0,0 +1,87 @@
+use crate::error::ApiError;
+use crate::store::Attr;
+use http::{header, HeaderMap};
+use std::collections::HashMap;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Gives the version of the stored question `q`, which goes up by one every time it changes.
+///
+/// Questions asked before there were versions count as version 0.
+pub(super) fn of<K>(q: &HashMap<K, Attr>) -> u64
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    q.get("version")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Moves the stored question `q` on to its next version.
+pub(super) fn bump<K>(q: &mut HashMap<K, Attr>)
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    let next = of(q) + 1;
+    q.insert("version".into(), Attr::N(next.to_string()));
+}
+
+/// The DynamoDB condition that a question is still at version `:version`, where `#version` names
+/// the version attribute.
+pub(super) fn condition(expected: u64) -> &'static str {
+    if expected == 0 {
+        "(attribute_not_exists(#version) OR #version = :version)"
+    } else {
+        "#version = :version"
+    }
+}
+
+/// Gives the version the client's `If-Match` header says the question must still be at for the
+/// change to go through, if it gave one.
+///
+/// Versions may be sent bare (`3`) or like an entity tag (`"3"`), and `*` matches any version.
+pub(super) fn if_match(headers: &HeaderMap) -> Result<Option<u64>, ApiError> {
+    let Some(v) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let v = v.to_str().map(str::trim).unwrap_or_default();
+    if v == "*" {
+        return Ok(None);
+    }
+    match v.trim_start_matches("W/").trim_matches('"').parse() {
+        Ok(version) => Ok(Some(version)),
+        Err(_) => {
+            warn!(?v, "got invalid If-Match version");
+            Err(ApiError::BadRequest)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn parse() {
+        let if_match = |v: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_MATCH, HeaderValue::from_static(v));
+            if_match(&headers)
+        };
+        assert_eq!(super::if_match(&HeaderMap::new()), Ok(None));
+        assert_eq!(if_match("*"), Ok(None));
+        assert_eq!(if_match("3"), Ok(Some(3)));
+        assert_eq!(if_match("\"3\""), Ok(Some(3)));
+        assert_eq!(if_match("W/\"0\""), Ok(Some(0)));
+        assert_eq!(if_match("three"), Err(ApiError::BadRequest));
+
+        let mut q: HashMap<&str, _> = HashMap::new();
+        assert_eq!(of(&q), 0);
+        bump(&mut q);
+        bump(&mut q);
+        assert_eq!(of(&q), 2);
+    }
+}
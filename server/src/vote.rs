@@ -1,91 +1,1188 @@
 use super::{Backend, Local};
+use crate::error::ApiError;
+use crate::store::{self, Attr, Item, StoreError};
+use crate::{dedup::DedupStore, timeseries::TimeseriesStore};
+use async_trait::async_trait;
 use aws_sdk_dynamodb::{
-    error::UpdateItemError,
     model::{AttributeValue, ReturnValue},
     output::UpdateItemOutput,
-    types::SdkError,
 };
 use axum::extract::{Path, State};
 use axum::response::Json;
-use http::StatusCode;
-use serde::Deserialize;
+use futures_util::FutureExt;
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
 use ulid::Ulid;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
-#[derive(Deserialize, Debug, Copy, Clone)]
+/// Header through which clients identify themselves so that their votes can be de-duplicated.
+const CLIENT_ID_HEADER: &str = "x-client-id";
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub(super) enum UpDown {
     Up,
     Down,
+    /// Takes back the client's vote, if it has one.
+    None,
+}
+
+impl UpDown {
+    pub(super) fn delta(self) -> isize {
+        match self {
+            UpDown::Up => 1,
+            UpDown::Down => -1,
+            UpDown::None => 0,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            UpDown::Up => "up",
+            UpDown::Down => "down",
+            UpDown::None => "none",
+        }
+    }
+
+    fn from_attr(v: &Attr) -> Option<Self> {
+        match v.as_s().ok()?.as_str() {
+            "up" => Some(UpDown::Up),
+            "down" => Some(UpDown::Down),
+            _ => None,
+        }
+    }
+}
+
+/// The reactions a question can get on top of its votes.
+///
+/// Unlike votes, reactions only ever go up, and aren't de-duplicated per client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Reaction {
+    Laugh,
+    Heart,
+    Clap,
+}
+
+impl Reaction {
+    pub(super) const ALL: [Reaction; 3] = [Reaction::Laugh, Reaction::Heart, Reaction::Clap];
+
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            Reaction::Laugh => "laugh",
+            Reaction::Heart => "heart",
+            Reaction::Clap => "clap",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+}
+
+/// Returns true if guests shouldn't see how many votes the stored question `q` has.
+pub(super) fn hides_counts<K>(q: &HashMap<K, Attr>) -> bool
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    q.get("hide_counts") == Some(&Attr::Bool(true))
+}
+
+/// Gives the reaction counts of the stored question `q`, with every reaction present.
+pub(super) fn reactions_of<K>(q: &HashMap<K, Attr>) -> serde_json::Value
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    let reactions = q.get("reactions").and_then(|v| v.as_m().ok());
+    Reaction::ALL
+        .into_iter()
+        .map(|r| {
+            let n = reactions
+                .and_then(|m| m.get(r.as_str()))
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0);
+            (r.as_str().to_string(), serde_json::Value::from(n))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Adds `delta` to the votes of the stored question `q`, though never below zero, and moves it on
+/// to its next version if that changed them.
+fn add_votes<K>(q: &mut HashMap<K, Attr>, delta: isize)
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    let changed = if let Some(Attr::N(n)) = q.get_mut("votes") {
+        let real_n = n.parse::<isize>().expect("votes values are numbers");
+        // never let the count go below zero
+        *n = (real_n + delta).max(0).to_string();
+        *n != real_n.to_string()
+    } else {
+        unreachable!("no votes for question");
+    };
+    if changed {
+        crate::version::bump(q);
+        crate::sync::touch(q);
+    }
 }
 
-impl Backend {
-    pub(super) async fn vote(
+/// Adds one `reaction` to the stored question `q`.
+fn add_reaction<K>(q: &mut HashMap<K, Attr>, reaction: Reaction)
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq + From<&'static str>,
+{
+    let reactions = q
+        .entry("reactions".into())
+        .or_insert_with(|| Attr::M(HashMap::new()));
+    let Attr::M(reactions) = reactions else {
+        unreachable!("reactions are always a map");
+    };
+    let n = reactions
+        .entry(reaction.as_str().to_string())
+        .or_insert_with(|| Attr::N(0.to_string()));
+    let Attr::N(n) = n else {
+        unreachable!("reaction counts are always numbers");
+    };
+    *n = (n.parse::<u64>().expect("reaction counts are numbers") + 1).to_string();
+    crate::sync::touch(q);
+}
+
+/// How a storage engine counts votes and reactions.
+#[async_trait]
+pub(super) trait VoteStore {
+    /// Adds `delta` to the votes of `qid`, though never below zero.
+    ///
+    /// The question moves on to its next version if its votes change.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if there's no such question, or its event has
+    /// been archived. Otherwise returns the question's new attributes.
+    async fn vote(&self, qid: &Ulid, delta: isize) -> Result<Item, StoreError>;
+
+    /// Records that `voter` has voted `direction` on `qid`.
+    ///
+    /// Fails with [`StoreError::ConditionFailed`] if the voter has already voted in that
+    /// direction, and otherwise returns the voter's previous vote (if any).
+    async fn cast(
         &self,
         qid: &Ulid,
+        voter: &str,
         direction: UpDown,
-    ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>> {
-        match self {
-            Self::Dynamo(dynamo) => {
-                let upd = dynamo
-                    .update_item()
-                    .table_name("questions")
-                    .key("id", AttributeValue::S(qid.to_string()));
-
-                let upd = match direction {
-                    UpDown::Up => upd.update_expression("SET votes = votes + :one"),
-                    UpDown::Down => upd.update_expression("SET votes = votes - :one"),
-                };
-                let upd = upd.expression_attribute_values(":one", AttributeValue::N(1.to_string()));
-
-                upd.return_values(ReturnValue::AllNew).send().await
-            }
-            Self::Local(local) => {
-                let mut local = local.lock().unwrap();
-                let Local { questions, .. } = &mut *local;
-
-                let ret = UpdateItemOutput::builder();
-                let q = questions
-                    .get_mut(qid)
-                    .expect("voting for non-existing question");
-                if let Some(AttributeValue::N(n)) = q.get_mut("votes") {
-                    let real_n = n.parse::<usize>().expect("votes values are numbers");
-                    let new_n = match direction {
-                        UpDown::Up => real_n + 1,
-                        UpDown::Down => real_n - 1,
-                    };
-                    *n = new_n.to_string();
-                } else {
-                    unreachable!("no votes for question");
+    ) -> Result<Option<UpDown>, StoreError>;
+
+    /// Forgets about `voter`'s vote on `qid`.
+    ///
+    /// Returns the voter's previous vote (if any).
+    async fn retract(&self, qid: &Ulid, voter: &str) -> Result<Option<UpDown>, StoreError>;
+
+    /// Looks up the voting rules of the event of `qid`.
+    ///
+    /// Returns `None` if there's no such question.
+    async fn vote_rules(&self, qid: &Ulid) -> Result<Option<Rules>, StoreError>;
+
+    /// Adds `delta` to the number of up-votes `voter` has used in `eid`, unless that would take
+    /// it past `budget` (or below zero).
+    ///
+    /// Returns the number of used up-votes afterwards, or `None` if the budget doesn't allow it.
+    async fn spend(
+        &self,
+        eid: &Ulid,
+        voter: &str,
+        delta: i64,
+        budget: u64,
+    ) -> Result<Option<u64>, StoreError>;
+
+    /// Adds one `reaction` to `qid`.
+    ///
+    /// Returns the question's new attributes. Fails with [`StoreError::ConditionFailed`] if
+    /// there's no such question, or its event has been archived.
+    async fn react(&self, qid: &Ulid, reaction: Reaction) -> Result<Item, StoreError>;
+
+    /// Applies each of `queued` in turn for `voter` (or whoever votes from `ip`), just like a
+    /// single vote would be.
+    async fn vote_batch(
+        &self,
+        queued: &[Queued],
+        voter: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Vec<Result<serde_json::Value, ApiError>>;
+}
+
+#[async_trait]
+impl VoteStore for aws_sdk_dynamodb::Client {
+    async fn vote(&self, qid: &Ulid, delta: isize) -> Result<Item, StoreError> {
+        let upd = |delta: isize| {
+            let upd = self
+                .update_item()
+                .table_name("questions")
+                .key("id", AttributeValue::S(qid.to_string()))
+                .condition_expression("attribute_exists(id) AND attribute_not_exists(archived)")
+                .expression_attribute_values(":delta", AttributeValue::N(delta.to_string()))
+                .return_values(ReturnValue::AllNew);
+            // ADD is applied by dynamodb itself, so concurrent votes can't overwrite
+            // each other.
+            if delta == 0 {
+                upd.update_expression("ADD votes :delta")
+            } else {
+                upd.update_expression("SET updated_at = :updated ADD votes :delta, #version :one")
+                    .expression_attribute_names("#version", "version")
+                    .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+                    .expression_attribute_values(":updated", crate::sync::now())
+            }
+        };
+
+        if delta >= 0 {
+            let r = super::retry::retry(|| upd(delta).send()).await?;
+            return Ok(attributes(r));
+        }
+
+        let floored = upd(delta)
+            .condition_expression(
+                "attribute_exists(id) AND votes >= :floor AND attribute_not_exists(archived)",
+            )
+            .expression_attribute_values(":floor", AttributeValue::N((-delta).to_string()));
+        match super::retry::retry(|| floored.clone().send()).await {
+            Ok(r) => return Ok(attributes(r)),
+            Err(e) => match StoreError::from(e) {
+                StoreError::ConditionFailed => {}
+                e => return Err(e),
+            },
+        }
+        // the vote would take the count below zero (like a flipped vote at a count of
+        // one), so stop at zero instead.
+        let clamped = self
+            .update_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .update_expression("SET votes = :zero, updated_at = :updated ADD #version :one")
+            .condition_expression(
+                "votes < :floor AND votes > :zero AND attribute_not_exists(archived)",
+            )
+            .expression_attribute_names("#version", "version")
+            .expression_attribute_values(":zero", AttributeValue::N(0.to_string()))
+            .expression_attribute_values(":floor", AttributeValue::N((-delta).to_string()))
+            .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+            .expression_attribute_values(":updated", crate::sync::now())
+            .return_values(ReturnValue::AllNew);
+        match super::retry::retry(|| clamped.clone().send()).await {
+            Ok(r) => Ok(attributes(r)),
+            Err(e) => match StoreError::from(e) {
+                StoreError::ConditionFailed => {
+                    // the count is at zero already, so leave it as-is. if it failed because
+                    // of archiving instead, this fails the same way.
+                    let r = super::retry::retry(|| upd(0).send()).await?;
+                    Ok(attributes(r))
+                }
+                e => Err(e),
+            },
+        }
+    }
+
+    async fn cast(
+        &self,
+        qid: &Ulid,
+        voter: &str,
+        direction: UpDown,
+    ) -> Result<Option<UpDown>, StoreError> {
+        let put = self
+            .put_item()
+            .table_name("votes")
+            .item("qid", AttributeValue::S(qid.to_string()))
+            .item("voter", AttributeValue::S(voter.to_string()))
+            .item("dir", AttributeValue::S(direction.as_str().to_string()))
+            .item(
+                "expire",
+                crate::to_dynamo_timestamp(
+                    SystemTime::now()
+                        + Duration::from_secs(
+                            crate::ask::QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60,
+                        ),
+                ),
+            )
+            .condition_expression("attribute_not_exists(#dir) OR #dir <> :dir")
+            .expression_attribute_names("#dir", "dir")
+            .expression_attribute_values(":dir", AttributeValue::S(direction.as_str().to_string()))
+            .return_values(ReturnValue::AllOld);
+        let r = super::retry::retry(|| put.clone().send()).await?;
+        Ok(r.attributes()
+            .and_then(|a| a.get("dir"))
+            .and_then(|v| UpDown::from_attr(&v.clone().into())))
+    }
+
+    async fn retract(&self, qid: &Ulid, voter: &str) -> Result<Option<UpDown>, StoreError> {
+        let delete = self
+            .delete_item()
+            .table_name("votes")
+            .key("qid", AttributeValue::S(qid.to_string()))
+            .key("voter", AttributeValue::S(voter.to_string()))
+            .return_values(ReturnValue::AllOld);
+        let r = super::retry::retry(|| delete.clone().send()).await?;
+        Ok(r.attributes()
+            .and_then(|a| a.get("dir"))
+            .and_then(|v| UpDown::from_attr(&v.clone().into())))
+    }
+
+    async fn vote_rules(&self, qid: &Ulid) -> Result<Option<Rules>, StoreError> {
+        let get = self
+            .get_item()
+            .table_name("questions")
+            .key("id", AttributeValue::S(qid.to_string()))
+            .projection_expression("eid,vote_budget,downvotes_disabled");
+        let r = super::retry::retry(|| get.clone().send()).await?;
+        Ok(r.item.map(store::from_dynamo).as_ref().map(rules_of))
+    }
+
+    async fn spend(
+        &self,
+        eid: &Ulid,
+        voter: &str,
+        delta: i64,
+        budget: u64,
+    ) -> Result<Option<u64>, StoreError> {
+        // the budgets share the votes table, with the event in place of the question.
+        let upd = self
+            .update_item()
+            .table_name("votes")
+            .key("qid", AttributeValue::S(eid.to_string()))
+            .key("voter", AttributeValue::S(voter.to_string()))
+            .update_expression("ADD used :delta SET expire = :expire")
+            .expression_attribute_values(":delta", AttributeValue::N(delta.to_string()))
+            .expression_attribute_values(
+                ":expire",
+                crate::to_dynamo_timestamp(
+                    SystemTime::now()
+                        + Duration::from_secs(
+                            crate::ask::QUESTIONS_EXPIRE_AFTER_DAYS * 24 * 60 * 60,
+                        ),
+                ),
+            )
+            .return_values(ReturnValue::UpdatedNew);
+        let upd = if delta > 0 {
+            upd.condition_expression("attribute_not_exists(used) OR used <= :max")
+                .expression_attribute_values(
+                    ":max",
+                    AttributeValue::N((budget as i64 - delta).to_string()),
+                )
+        } else {
+            upd.condition_expression("used >= :min")
+                .expression_attribute_values(":min", AttributeValue::N((-delta).to_string()))
+        };
+        match super::retry::retry(|| upd.clone().send()).await {
+            Ok(r) => Ok(Some(
+                r.attributes()
+                    .and_then(|a| a.get("used"))
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            )),
+            Err(e) => match StoreError::from(e) {
+                StoreError::ConditionFailed => Ok(None),
+                e => Err(e),
+            },
+        }
+    }
+
+    async fn react(&self, qid: &Ulid, reaction: Reaction) -> Result<Item, StoreError> {
+        let upd = || {
+            self.update_item()
+                .table_name("questions")
+                .key("id", AttributeValue::S(qid.to_string()))
+                .expression_attribute_names("#reactions", "reactions")
+                .expression_attribute_values(":updated", crate::sync::now())
+                .return_values(ReturnValue::AllNew)
+        };
+        // a nested counter can only be set once the map it's in exists, and questions
+        // asked before there were reactions don't have one.
+        let bump = || {
+            upd()
+                .update_expression(
+                    "SET #reactions.#r = if_not_exists(#reactions.#r, :zero) + :one, \
+                     updated_at = :updated",
+                )
+                .expression_attribute_names("#r", reaction.as_str())
+                .expression_attribute_values(":zero", AttributeValue::N(0.to_string()))
+                .expression_attribute_values(":one", AttributeValue::N(1.to_string()))
+        };
+        // without the existence check, reacting to an unknown question would create a
+        // stray item for it that never expires.
+        let exists = "attribute_exists(id) AND attribute_not_exists(archived)";
+        let bump =
+            || bump().condition_expression(format!("attribute_exists(#reactions) AND {exists}"));
+        match super::retry::retry(|| bump().send()).await {
+            Ok(r) => return Ok(attributes(r)),
+            Err(e) => match StoreError::from(e) {
+                StoreError::ConditionFailed => {}
+                e => return Err(e),
+            },
+        }
+        let init = upd()
+            .update_expression("SET #reactions = :init, updated_at = :updated")
+            .condition_expression(format!("attribute_not_exists(#reactions) AND {exists}"))
+            .expression_attribute_values(
+                ":init",
+                AttributeValue::M(HashMap::from_iter([(
+                    reaction.as_str().to_string(),
+                    AttributeValue::N(1.to_string()),
+                )])),
+            );
+        match super::retry::retry(|| init.clone().send()).await {
+            Ok(r) => Ok(attributes(r)),
+            Err(e) => match StoreError::from(e) {
+                StoreError::ConditionFailed => {
+                    // someone else's reaction created the map in the meantime. if it failed
+                    // because of archiving instead, this fails the same way.
+                    let r = super::retry::retry(|| bump().send()).await?;
+                    Ok(attributes(r))
+                }
+                e => Err(e),
+            },
+        }
+    }
+
+    async fn vote_batch(
+        &self,
+        queued: &[Queued],
+        voter: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Vec<Result<serde_json::Value, ApiError>> {
+        let mut results = Vec::with_capacity(queued.len());
+        for &Queued { qid, updown } in queued {
+            results.push(apply(self, &qid, updown, voter, ip).await);
+        }
+        results
+    }
+}
+
+#[async_trait]
+impl VoteStore for Mutex<Local> {
+    async fn vote(&self, qid: &Ulid, delta: isize) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local { questions, .. } = &mut *local;
+
+        let q = match questions.get_mut(qid) {
+            Some(q) if !q.contains_key("archived") => q,
+            _ => {
+                return Err(StoreError::ConditionFailed);
+            }
+        };
+        add_votes(q, delta);
+        let ret = store::from_local(q);
+        let mut update = serde_json::json!({
+            "qid": qid.to_string(),
+            "version": crate::version::of(q),
+        });
+        // anyone can follow the stream, so it only says that the votes changed.
+        if !hides_counts(q) {
+            update["votes"] = serde_json::json!(q["votes"].as_n().ok());
+        }
+        let eid = crate::stream::eid_of(q);
+        local.publish(&eid, "vote", update);
+        Ok(ret)
+    }
+
+    async fn cast(
+        &self,
+        qid: &Ulid,
+        voter: &str,
+        direction: UpDown,
+    ) -> Result<Option<UpDown>, StoreError> {
+        let mut local = super::lock(self);
+        let Local { client_votes, .. } = &mut *local;
+
+        let previous = client_votes.insert((*qid, voter.to_string()), direction);
+        if previous == Some(direction) {
+            return Err(StoreError::ConditionFailed);
+        }
+        Ok(previous)
+    }
+
+    async fn retract(&self, qid: &Ulid, voter: &str) -> Result<Option<UpDown>, StoreError> {
+        let mut local = super::lock(self);
+        let Local { client_votes, .. } = &mut *local;
+
+        Ok(client_votes.remove(&(*qid, voter.to_string())))
+    }
+
+    async fn vote_rules(&self, qid: &Ulid) -> Result<Option<Rules>, StoreError> {
+        Ok(super::lock(self).questions.get(qid).map(rules_of))
+    }
+
+    async fn spend(
+        &self,
+        eid: &Ulid,
+        voter: &str,
+        delta: i64,
+        budget: u64,
+    ) -> Result<Option<u64>, StoreError> {
+        let mut local = super::lock(self);
+        let used = local
+            .spent_votes
+            .entry((*eid, voter.to_string()))
+            .or_default();
+        match used.checked_add_signed(delta) {
+            Some(n) if n <= budget => {
+                *used = n;
+                Ok(Some(n))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn react(&self, qid: &Ulid, reaction: Reaction) -> Result<Item, StoreError> {
+        let mut local = super::lock(self);
+        let Local { questions, .. } = &mut *local;
+
+        let q = match questions.get_mut(qid) {
+            Some(q) if !q.contains_key("archived") => q,
+            _ => {
+                return Err(StoreError::ConditionFailed);
+            }
+        };
+        add_reaction(q, reaction);
+
+        let ret = store::from_local(q);
+        let update = serde_json::json!({
+            "qid": qid.to_string(),
+            "reactions": reactions_of(q),
+        });
+        let eid = crate::stream::eid_of(q);
+        local.publish(&eid, "react", update);
+        Ok(ret)
+    }
+
+    async fn vote_batch(
+        &self,
+        queued: &[Queued],
+        voter: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Vec<Result<serde_json::Value, ApiError>> {
+        let mut local = super::lock(self);
+        // the votes go through the same steps as single votes, but against state that only this
+        // batch can get at while it holds the lock. it goes back once they've all been applied.
+        let batch = Mutex::new(std::mem::take(&mut *local));
+        let results = queued
+            .iter()
+            .map(|&Queued { qid, updown }| {
+                apply(&batch, &qid, updown, voter, ip)
+                    .now_or_never()
+                    .expect("the local backend never waits")
+            })
+            .collect();
+        *local = batch.into_inner().unwrap_or_else(|e| e.into_inner());
+        results
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl VoteStore for crate::redis::Redis {
+    async fn vote(&self, qid: &Ulid, delta: isize) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if q.contains_key("archived") {
+                    return Err(StoreError::ConditionFailed);
+                }
+                add_votes(q, delta);
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+
+    async fn cast(
+        &self,
+        qid: &Ulid,
+        voter: &str,
+        direction: UpDown,
+    ) -> Result<Option<UpDown>, StoreError> {
+        let key = crate::redis::voters_key(qid);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hget(&key, voter)
+            .hset(&key, voter, direction.as_str())
+            .ignore();
+        crate::redis::keep(&mut pipe, &key);
+        let (previous,): (Option<String>,) = self.run(&pipe).await?;
+        let previous = previous.and_then(|dir| UpDown::from_attr(&Attr::S(dir)));
+        // the vote is already recorded then, so writing it again changed nothing.
+        if previous == Some(direction) {
+            return Err(StoreError::ConditionFailed);
+        }
+        Ok(previous)
+    }
+
+    async fn retract(&self, qid: &Ulid, voter: &str) -> Result<Option<UpDown>, StoreError> {
+        let key = crate::redis::voters_key(qid);
+        let mut pipe = redis::pipe();
+        pipe.atomic().hget(&key, voter).hdel(&key, voter).ignore();
+        let (previous,): (Option<String>,) = self.run(&pipe).await?;
+        Ok(previous.and_then(|dir| UpDown::from_attr(&Attr::S(dir))))
+    }
+
+    async fn vote_rules(&self, qid: &Ulid) -> Result<Option<Rules>, StoreError> {
+        let q = self.item(&crate::redis::question_key(qid)).await?;
+        Ok(q.as_ref().map(rules_of))
+    }
+
+    async fn spend(
+        &self,
+        eid: &Ulid,
+        voter: &str,
+        delta: i64,
+        budget: u64,
+    ) -> Result<Option<u64>, StoreError> {
+        let key = crate::redis::spent_key(eid);
+        let mut pipe = redis::pipe();
+        pipe.hincr(&key, voter, delta);
+        crate::redis::keep(&mut pipe, &key);
+        let (used,): (i64,) = self.run(&pipe).await?;
+        match u64::try_from(used) {
+            Ok(n) if n <= budget => Ok(Some(n)),
+            _ => {
+                // over budget (or below zero), so take it back again.
+                self.query::<()>(redis::cmd("HINCRBY").arg(&key).arg(voter).arg(-delta))
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn react(&self, qid: &Ulid, reaction: Reaction) -> Result<Item, StoreError> {
+        let (q, ()) = self
+            .modify(&crate::redis::question_key(qid), |q| {
+                if q.contains_key("archived") {
+                    return Err(StoreError::ConditionFailed);
                 }
-                let ret = ret.set_attributes(Some(
-                    q.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
-                ));
-                Ok(ret.build())
+                add_reaction(q, reaction);
+                Ok(())
+            })
+            .await?;
+        Ok(q)
+    }
+
+    async fn vote_batch(
+        &self,
+        queued: &[Queued],
+        voter: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Vec<Result<serde_json::Value, ApiError>> {
+        let mut results = Vec::with_capacity(queued.len());
+        for &Queued { qid, updown } in queued {
+            results.push(apply(self, &qid, updown, voter, ip).await);
+        }
+        results
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl VoteStore for crate::sqlite::Sqlite {
+    async fn vote(&self, qid: &Ulid, delta: isize) -> Result<Item, StoreError> {
+        self.run(|con| {
+            // counted in sql rather than through `modify`, so that votes add up even if something
+            // else has the database open too. like add_votes, the count never goes below zero,
+            // and only a vote that moved it makes for a new version.
+            con.execute(
+                "UPDATE questions
+                 SET votes = MAX(votes + ?2, 0), version = COALESCE(version, 0) + 1, updated_at = ?3
+                 WHERE id = ?1 AND archived IS NULL AND MAX(votes + ?2, 0) != votes",
+                rusqlite::params![qid.to_string(), delta, crate::sqlite::now()],
+            )?;
+            match crate::sqlite::get(con, crate::sqlite::Table::Questions, qid)? {
+                Some(q) if !q.contains_key("archived") => Ok(q),
+                _ => Err(StoreError::ConditionFailed),
+            }
+        })
+    }
+
+    async fn cast(
+        &self,
+        qid: &Ulid,
+        voter: &str,
+        direction: UpDown,
+    ) -> Result<Option<UpDown>, StoreError> {
+        self.run(|con| {
+            let previous: Option<String> = crate::sqlite::first(
+                con,
+                "SELECT direction FROM voters WHERE qid = ?1 AND voter = ?2",
+                rusqlite::params![qid.to_string(), voter],
+            )?;
+            let previous = previous.and_then(|dir| UpDown::from_attr(&Attr::S(dir)));
+            // the vote is already recorded then, so writing it again changed nothing.
+            if previous == Some(direction) {
+                return Err(StoreError::ConditionFailed);
+            }
+            con.execute(
+                "INSERT INTO voters (qid, voter, direction) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (qid, voter) DO UPDATE SET direction = excluded.direction",
+                rusqlite::params![qid.to_string(), voter, direction.as_str()],
+            )?;
+            Ok(previous)
+        })
+    }
+
+    async fn retract(&self, qid: &Ulid, voter: &str) -> Result<Option<UpDown>, StoreError> {
+        self.run(|con| {
+            let previous: Option<String> = crate::sqlite::first(
+                con,
+                "DELETE FROM voters WHERE qid = ?1 AND voter = ?2 RETURNING direction",
+                rusqlite::params![qid.to_string(), voter],
+            )?;
+            Ok(previous.and_then(|dir| UpDown::from_attr(&Attr::S(dir))))
+        })
+    }
+
+    async fn vote_rules(&self, qid: &Ulid) -> Result<Option<Rules>, StoreError> {
+        let q = self.item(crate::sqlite::Table::Questions, qid)?;
+        Ok(q.as_ref().map(rules_of))
+    }
+
+    async fn spend(
+        &self,
+        eid: &Ulid,
+        voter: &str,
+        delta: i64,
+        budget: u64,
+    ) -> Result<Option<u64>, StoreError> {
+        self.run(|con| {
+            con.execute(
+                "INSERT INTO spent (eid, voter, used) VALUES (?1, ?2, 0) ON CONFLICT DO NOTHING",
+                rusqlite::params![eid.to_string(), voter],
+            )?;
+            // over budget (or below zero), nothing is updated, and so nothing returned.
+            crate::sqlite::first(
+                con,
+                "UPDATE spent SET used = used + ?3
+                 WHERE eid = ?1 AND voter = ?2 AND used + ?3 BETWEEN 0 AND ?4
+                 RETURNING used",
+                rusqlite::params![eid.to_string(), voter, delta, budget],
+            )
+        })
+    }
+
+    async fn react(&self, qid: &Ulid, reaction: Reaction) -> Result<Item, StoreError> {
+        let (q, ()) = self.modify(crate::sqlite::Table::Questions, qid, |q| {
+            if q.contains_key("archived") {
+                return Err(StoreError::ConditionFailed);
             }
+            add_reaction(q, reaction);
+            Ok(())
+        })?;
+        Ok(q)
+    }
+
+    async fn vote_batch(
+        &self,
+        queued: &[Queued],
+        voter: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Vec<Result<serde_json::Value, ApiError>> {
+        let mut results = Vec::with_capacity(queued.len());
+        for &Queued { qid, updown } in queued {
+            results.push(apply(self, &qid, updown, voter, ip).await);
+        }
+        results
+    }
+}
+
+/// Gives the attributes a vote or reaction left the question with.
+fn attributes(r: UpdateItemOutput) -> Item {
+    store::from_dynamo(r.attributes.unwrap_or_default())
+}
+
+/// Gives the event of the stored question `q` and its vote budget, if it has one.
+fn budget_of<K>(q: &HashMap<K, Attr>) -> Option<(Ulid, u64)>
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    let eid = q.get("eid")?.as_s().ok()?.parse().ok()?;
+    let budget = q.get("vote_budget")?.as_n().ok()?.parse().ok()?;
+    Some((eid, budget))
+}
+
+/// What the event of a question lets its guests do when they vote.
+pub(super) struct Rules {
+    /// The question's event.
+    eid: Option<Ulid>,
+    /// The event and its vote budget, if it has one.
+    budget: Option<(Ulid, u64)>,
+    /// Whether guests can down-vote.
+    downvotes: bool,
+}
+
+/// Gives the voting rules kept with the stored question `q`.
+fn rules_of<K>(q: &HashMap<K, Attr>) -> Rules
+where
+    K: std::borrow::Borrow<str> + std::hash::Hash + Eq,
+{
+    Rules {
+        eid: q
+            .get("eid")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|v| v.parse().ok()),
+        budget: budget_of(q),
+        downvotes: q.get("downvotes_disabled") != Some(&Attr::Bool(true)),
+    }
+}
+
+/// Extracts the voter identity supplied by the client, if any.
+fn voter(headers: &HeaderMap) -> Result<Option<&str>, ApiError> {
+    if crate::dedup::mode() == crate::dedup::Dedup::Off {
+        return Ok(None);
+    }
+    let Some(voter) = headers.get(CLIENT_ID_HEADER) else {
+        return Ok(None);
+    };
+    match voter.to_str() {
+        Ok(voter) if !voter.is_empty() && voter.len() <= 128 => Ok(Some(voter)),
+        _ => {
+            warn!(?voter, "got invalid client id");
+            Err(ApiError::BadRequest)
+        }
+    }
+}
+
+/// Moves `voter`'s use of the vote budget of `eid` by `delta`, which should not be positive.
+///
+/// Returns how many up-votes the voter has used after that. Failures are only logged, since
+/// they'd at worst leave a voter with one vote less than they should have.
+async fn settle<S>(
+    dynamo: &S,
+    qid: &Ulid,
+    (eid, budget): (Ulid, u64),
+    voter: &str,
+    delta: i64,
+) -> Option<u64>
+where
+    S: VoteStore + ?Sized,
+{
+    match dynamo.spend(&eid, voter, delta, budget).await {
+        // NOTE: a zero delta only fails if the voter hasn't used any votes yet
+        Ok(used) => Some(used.unwrap_or(0)),
+        Err(e) => {
+            error!(%qid, %eid, error = %e, "dynamodb request to update vote budget failed");
+            None
         }
     }
 }
 
+/// Votes on a question, and gives its new count so that clients don't have to fetch it again.
+///
+/// The response also says how much this vote moved the count (`0` for a repeated vote), and what
+/// the voter's vote is now, so that clients can show the right button as pressed.
 pub(super) async fn vote(
     Path((qid, direction)): Path<(Ulid, UpDown)>,
     State(dynamo): State<Backend>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    match dynamo.vote(&qid, direction).await {
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let voter = voter(&headers)?;
+    let ip = crate::dedup::ip(&headers);
+    apply(&*dynamo, &qid, direction, voter, ip).await.map(Json)
+}
+
+/// Votes `direction` on `qid` for `voter` (if known), and gives what the vote endpoint responds
+/// with.
+///
+/// Votes without a voter are told apart by `ip` instead, if given.
+async fn apply<S>(
+    dynamo: &S,
+    qid: &Ulid,
+    direction: UpDown,
+    voter: Option<&str>,
+    ip: Option<IpAddr>,
+) -> Result<serde_json::Value, ApiError>
+where
+    S: VoteStore + DedupStore + TimeseriesStore + Sync + ?Sized,
+{
+    let qid = *qid;
+    let Rules {
+        eid,
+        budget,
+        downvotes,
+    } = match dynamo.vote_rules(&qid).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            warn!(%qid, "vote on non-existing question");
+            return Err(ApiError::QuestionNotFound);
+        }
+        Err(e) => {
+            error!(%qid, error = %e, "dynamodb request for voting rules failed");
+            return Err(ApiError::Internal);
+        }
+    };
+    if direction == UpDown::Down && !downvotes {
+        warn!(%qid, "rejecting down-vote in event without them");
+        return Err(ApiError::DownvotesDisabled);
+    }
+    let hashed = match (voter, ip, eid) {
+        (None, Some(ip), Some(eid)) => crate::dedup::voter(dynamo, &eid, ip).await,
+        _ => None,
+    };
+    let voter = voter.or(hashed.as_deref());
+    if budget.is_some() && voter.is_none() {
+        // there's no telling whose budget the vote would come out of.
+        warn!(%qid, "got vote without client id in event with a vote budget");
+        return Err(ApiError::BadRequest);
+    }
+    // how many up-votes the voter has used of the budget, once we know.
+    let mut used = None;
+
+    let delta = if direction == UpDown::None {
+        let Some(voter) = voter else {
+            // without knowing who's asking, there's no telling which vote to take back.
+            warn!(%qid, "got vote retraction without client id");
+            return Err(ApiError::BadRequest);
+        };
+        match dynamo.retract(&qid, voter).await {
+            Ok(previous) => {
+                if let (Some(UpDown::Up), Some(budget)) = (previous, budget) {
+                    used = settle(dynamo, &qid, budget, voter, -1).await;
+                }
+                -previous.map_or(0, UpDown::delta)
+            }
+            Err(e) => {
+                error!(%qid, error = %e, "dynamodb request to retract vote failed");
+                return Err(ApiError::Internal);
+            }
+        }
+    } else if let Some(voter) = voter {
+        // up-votes have to fit in the budget before they're recorded.
+        let spent = match budget {
+            Some((eid, budget)) if direction == UpDown::Up => {
+                match dynamo.spend(&eid, voter, 1, budget).await {
+                    Ok(Some(n)) => {
+                        used = Some(n);
+                        true
+                    }
+                    Ok(None) => {
+                        debug!(%qid, voter, "rejecting vote beyond budget");
+                        return Err(ApiError::VoteBudgetExhausted);
+                    }
+                    Err(e) => {
+                        error!(%qid, error = %e, "dynamodb request to spend vote failed");
+                        return Err(ApiError::Internal);
+                    }
+                }
+            }
+            _ => false,
+        };
+        let refund = spent.then_some(budget).flatten();
+        match dynamo.cast(&qid, voter, direction).await {
+            Ok(previous) => {
+                if let (Some(UpDown::Up), Some(budget)) = (previous, budget) {
+                    // an up-vote turned into a down-vote
+                    used = settle(dynamo, &qid, budget, voter, -1).await;
+                }
+                direction.delta() - previous.map_or(0, UpDown::delta)
+            }
+            Err(StoreError::ConditionFailed) => {
+                debug!(%qid, voter, "ignoring repeated vote");
+                if let Some(budget) = refund {
+                    used = settle(dynamo, &qid, budget, voter, -1).await;
+                }
+                0
+            }
+            Err(e) => {
+                error!(%qid, error = %e, "dynamodb request to record vote failed");
+                if let Some(budget) = refund {
+                    settle(dynamo, &qid, budget, voter, -1).await;
+                }
+                return Err(ApiError::Internal);
+            }
+        }
+    } else {
+        direction.delta()
+    };
+    if let (None, Some(budget), Some(voter)) = (used, budget, voter) {
+        used = settle(dynamo, &qid, budget, voter, 0).await;
+    }
+
+    // NOTE: a repeated vote still goes through with a delta of 0 so that we get the current count
+    // back through the same code path.
+    match dynamo.vote(&qid, delta).await {
         Ok(v) => {
-            debug!(%qid, "voted for question");
+            debug!(%qid, delta, "voted for question");
+            if let (true, Some(eid)) = (delta != 0, eid) {
+                crate::listcache::invalidate(&eid);
+            }
+            if delta != 0 && crate::timeseries::enabled() {
+                let eid = v
+                    .get("eid")
+                    .and_then(|v| v.as_s().ok())
+                    .and_then(|eid| eid.parse::<Ulid>().ok());
+                if let Some(eid) = eid {
+                    // like the vote budget, losing an entry here isn't worth failing the vote over.
+                    if let Err(e) = dynamo.log_vote(&eid, &qid, delta).await {
+                        error!(%qid, %eid, error = %e, "dynamodb request to log vote failed");
+                    }
+                }
+            }
             let new_count = v
-                .attributes()
-                .and_then(|a| a.get("votes"))
+                .get("votes")
                 .and_then(|v| v.as_n().ok())
                 .and_then(|v| v.parse::<isize>().ok());
-            Ok(Json(serde_json::json!({ "votes": new_count })))
+            let hidden = hides_counts(&v);
+            let version = crate::version::of(&v);
+            // without a client id, there's nothing to go on but the vote itself.
+            let mut v = serde_json::json!({
+                "qid": qid.to_string(),
+                "delta": delta,
+                "your_vote": direction.as_str(),
+                "version": version,
+            });
+            if !hidden {
+                v["votes"] = new_count.into();
+            }
+            // `null` for events where votes aren't limited.
+            v["remaining_votes"] = budget
+                .map(|(_, budget)| budget.saturating_sub(used.unwrap_or(0)))
+                .into();
+            Ok(v)
+        }
+        Err(StoreError::ConditionFailed) => {
+            // the question was there when we looked up its rules, so it was either frozen or
+            // deleted since. the client's own vote may have been recorded already, but either
+            // way that no longer matters.
+            if let Ok(None) = dynamo.vote_rules(&qid).await {
+                warn!(%qid, "vote on question deleted while voting");
+                return Err(ApiError::QuestionNotFound);
+            }
+            warn!(%qid, "vote on question in archived event");
+            Err(ApiError::EventArchived)
         }
         Err(e) => {
             error!(%qid, error = %e, "dynamodb request to vote for question failed");
-            Err(http::StatusCode::INTERNAL_SERVER_ERROR)
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+/// The most votes one batch may have.
+const MAX_BATCH: usize = 100;
+
+/// One of the votes in a batch.
+#[derive(Deserialize, Debug)]
+pub(super) struct Queued {
+    qid: Ulid,
+    updown: UpDown,
+}
+
+/// Applies several votes at once, for clients that queued them up while they were offline.
+///
+/// The votes are applied in order, each one just like [`vote`] would, so the same client's
+/// repeated votes are still ignored. Batches need a client id (or an IP to go by, with
+/// `VOTE_DEDUP=ip`), since otherwise one request could move a question by as many votes as fit in
+/// the batch. Each vote succeeds or fails on its own: the response has one
+/// entry per vote, in the same order, with either what [`vote`] would have responded with or the
+/// error it would have failed with.
+///
+/// This isn't done in a single DynamoDB transaction. A vote is a conditional write of the
+/// client's vote followed by an update of the count that depends on how the first write went,
+/// which a transaction can't express, and one failed vote would take the whole batch down with it.
+/// The local backend does apply the whole batch under one lock, though.
+pub(super) async fn votes(
+    State(dynamo): State<Backend>,
+    headers: HeaderMap,
+    Json(queued): Json<Vec<Queued>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let voter = voter(&headers)?;
+    let ip = crate::dedup::ip(&headers);
+    if voter.is_none() && ip.is_none() {
+        warn!("got vote batch without client id");
+        return Err(ApiError::BadRequest);
+    }
+    if queued.is_empty() || queued.len() > MAX_BATCH {
+        warn!(n = queued.len(), "got vote batch of unreasonable size");
+        return Err(ApiError::BadRequest);
+    }
+
+    let results: Vec<_> = dynamo
+        .vote_batch(&queued, voter, ip)
+        .await
+        .into_iter()
+        .zip(&queued)
+        .map(|(r, q)| match r {
+            Ok(v) => v,
+            Err(e) => {
+                let mut body = e.body();
+                body["qid"] = q.qid.to_string().into();
+                body
+            }
+        })
+        .collect();
+    debug!(n = results.len(), "applied vote batch");
+    Ok(Json(serde_json::Value::Array(results)))
+}
+
+/// Votes on or reacts to a question, depending on what `reaction` is.
+///
+/// `up`, `down`, and `none` are votes, and work just like [`vote`]. Anything else has to be one of
+/// the [`Reaction`]s.
+pub(super) async fn react(
+    Path((qid, reaction)): Path<(Ulid, String)>,
+    State(dynamo): State<Backend>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let direction = match reaction.as_str() {
+        "up" => Some(UpDown::Up),
+        "down" => Some(UpDown::Down),
+        "none" => Some(UpDown::None),
+        _ => None,
+    };
+    if let Some(direction) = direction {
+        return vote(Path((qid, direction)), State(dynamo), headers).await;
+    }
+    let Some(reaction) = Reaction::parse(&reaction) else {
+        warn!(%qid, reaction, "got unknown reaction");
+        return Err(ApiError::BadRequest);
+    };
+
+    let eid = match dynamo.vote_rules(&qid).await {
+        Ok(Some(rules)) => rules.eid,
+        Ok(None) => {
+            warn!(%qid, "reaction to non-existing question");
+            return Err(ApiError::QuestionNotFound);
+        }
+        Err(e) => {
+            error!(%qid, error = %e, "dynamodb request for question failed");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    match dynamo.react(&qid, reaction).await {
+        Ok(q) => {
+            debug!(%qid, reaction = reaction.as_str(), "reacted to question");
+            // lists carry the reactions too.
+            if let Some(eid) = eid {
+                crate::listcache::invalidate(&eid);
+            }
+            let votes = q
+                .get("votes")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|v| v.parse::<isize>().ok());
+            let mut v = serde_json::json!({ "reactions": reactions_of(&q) });
+            if !hides_counts(&q) {
+                v["votes"] = votes.into();
+            }
+            Ok(Json(v))
+        }
+        Err(StoreError::ConditionFailed) => {
+            // the question was there a moment ago, so it's far more likely to have been frozen
+            // than deleted since.
+            warn!(%qid, "reaction to question in archived event");
+            Err(ApiError::EventArchived)
+        }
+        Err(e) => {
+            error!(%qid, error = %e, "dynamodb request to react to question failed");
+            Err(ApiError::Internal)
         }
     }
 }
@@ -93,28 +1190,34 @@ pub(super) async fn vote(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::extract::Query;
+    use http::StatusCode;
 
     async fn inner(backend: Backend) {
-        let e = crate::new::new(State(backend.clone())).await.unwrap();
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
         let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
         let _secret = e["secret"].as_str().unwrap();
         let q1 = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello world".into(),
                 asker: None,
+                tags: Vec::new(),
             }),
         )
         .await
         .unwrap();
         let qid1 = Ulid::from_string(q1["id"].as_str().unwrap()).unwrap();
         let q2 = crate::ask::ask(
-            Path(eid.clone()),
+            Path(eid),
             State(backend.clone()),
             Json(crate::ask::Question {
                 body: "hello moon".into(),
                 asker: Some("person".into()),
+                tags: Vec::new(),
             }),
         )
         .await
@@ -129,11 +1232,15 @@ mod tests {
             }
         };
 
-        super::vote(Path((qid2.clone(), UpDown::Up)), State(backend.clone()))
-            .await
-            .unwrap();
+        let _ = super::vote(
+            Path((qid2, UpDown::Up)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
         check(
-            crate::list::list(Path(eid.clone()), State(backend.clone()))
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
                 .await
                 .1
                 .unwrap()
@@ -141,32 +1248,842 @@ mod tests {
             &[(&qid2, 2), (&qid1, 1)],
         );
 
-        super::vote(Path((qid1.clone(), UpDown::Up)), State(backend.clone()))
-            .await
-            .unwrap();
-        super::vote(Path((qid2.clone(), UpDown::Down)), State(backend.clone()))
-            .await
-            .unwrap();
-        check(
-            crate::list::list(Path(eid.clone()), State(backend.clone()))
-                .await
-                .1
-                .unwrap()
-                .0,
-            &[(&qid1, 2), (&qid2, 1)],
-        );
+        let _ = super::vote(
+            Path((qid1, UpDown::Up)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let _ = super::vote(
+            Path((qid2, UpDown::Down)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        check(
+            crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                .await
+                .1
+                .unwrap()
+                .0,
+            &[(&qid1, 2), (&qid2, 1)],
+        );
+
+        // down votes never take a question below zero votes
+        let q3 = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello sun".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid3 = Ulid::from_string(q3["id"].as_str().unwrap()).unwrap();
+        for expect in [0, 0] {
+            let v = super::vote(
+                Path((qid3, UpDown::Down)),
+                State(backend.clone()),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(v["votes"], expect);
+        }
+        let v = super::vote(
+            Path((qid3, UpDown::Up)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["votes"], 1);
+        // flipping an up-vote at a count of one stops at zero too
+        let mut flipper = HeaderMap::new();
+        flipper.insert(CLIENT_ID_HEADER, "flipper".parse().unwrap());
+        let flip = |direction| {
+            super::vote(
+                Path((qid3, direction)),
+                State(backend.clone()),
+                flipper.clone(),
+            )
+        };
+        assert_eq!(flip(UpDown::Up).await.unwrap()["votes"], 2);
+        let v = super::vote(
+            Path((qid3, UpDown::Down)),
+            State(backend.clone()),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["votes"], 1);
+        let v = flip(UpDown::Down).await.unwrap();
+        assert_eq!(v["votes"], 0);
+        assert_eq!(v["delta"], -2);
+        assert_eq!(flip(UpDown::Up).await.unwrap()["votes"], 2);
+
+        // votes from an identified client are only counted once per direction
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "client".parse().unwrap());
+        let vote_as = |direction| {
+            super::vote(
+                Path((qid2, direction)),
+                State(backend.clone()),
+                headers.clone(),
+            )
+        };
+        let v = vote_as(UpDown::Up).await.unwrap();
+        assert_eq!(v["votes"], 2);
+        assert_eq!(v["your_vote"], "up");
+        let v = vote_as(UpDown::Up).await.unwrap();
+        assert_eq!(v["votes"], 2);
+        assert_eq!(v["your_vote"], "up");
+        // flipping direction undoes the previous vote too
+        let v = vote_as(UpDown::Down).await.unwrap();
+        assert_eq!(v["votes"], 0);
+        assert_eq!(v["your_vote"], "down");
+        let v = vote_as(UpDown::Down).await.unwrap();
+        assert_eq!(v["votes"], 0);
+        let v = vote_as(UpDown::Up).await.unwrap();
+        assert_eq!(v["votes"], 2);
+
+        // votes can be taken back, but only once
+        let v = vote_as(UpDown::None).await.unwrap();
+        assert_eq!(v["votes"], 1);
+        assert_eq!(v["your_vote"], "none");
+        let v = vote_as(UpDown::None).await.unwrap();
+        assert_eq!(v["votes"], 1);
+        let v = vote_as(UpDown::Up).await.unwrap();
+        assert_eq!(v["votes"], 2);
+        let v = vote_as(UpDown::None).await.unwrap();
+        assert_eq!(v["votes"], 1);
+
+        // retracting requires knowing who the client is
+        assert_eq!(
+            super::vote(
+                Path((qid2, UpDown::None)),
+                State(backend.clone()),
+                HeaderMap::new()
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // an empty client id is rejected
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "".parse().unwrap());
+        assert_eq!(
+            super::vote(Path((qid2, UpDown::Up)), State(backend.clone()), headers)
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        // the count of a question that isn't there can't be moved either
+        let r = backend.vote(&Ulid::new(), 1).await;
+        assert!(
+            matches!(r, Err(StoreError::ConditionFailed)),
+            "vote on non-existing question: {r:?}"
+        );
+        assert_eq!(
+            super::vote(
+                Path((Ulid::new(), UpDown::Up)),
+                State(backend.clone()),
+                HeaderMap::new()
+            )
+            .await
+            .unwrap_err(),
+            ApiError::QuestionNotFound
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    async fn reactions(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let react = |reaction: &str| {
+            super::react(
+                Path((qid, reaction.to_string())),
+                State(backend.clone()),
+                HeaderMap::new(),
+            )
+        };
+
+        let v = react("heart").await.unwrap();
+        assert_eq!(
+            v.0,
+            serde_json::json!({
+                "votes": 1,
+                "reactions": { "laugh": 0, "heart": 1, "clap": 0 },
+            })
+        );
+        let _ = react("heart").await.unwrap();
+        let _ = react("clap").await.unwrap();
+        // votes still go through the same route
+        let v = react("up").await.unwrap();
+        assert_eq!(v["votes"], 2);
+        assert_eq!(react("shrug").await.unwrap_err(), StatusCode::BAD_REQUEST);
+
+        let qs = crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+            .await
+            .1
+            .unwrap()
+            .0;
+        assert_eq!(qs[0]["votes"], 2);
+        assert_eq!(
+            qs[0]["reactions"],
+            serde_json::json!({ "laugh": 0, "heart": 2, "clap": 1 })
+        );
+
+        // reacting doesn't make up questions, nor change archived ones
+        assert_eq!(
+            super::react(
+                Path((Ulid::new(), "heart".to_string())),
+                State(backend.clone()),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap_err(),
+            ApiError::QuestionNotFound
+        );
+        let secret = e["secret"].as_str().unwrap().to_string();
+        crate::archive::archive(Path((eid, secret)), State(backend.clone()))
+            .await
+            .unwrap();
+        assert_eq!(react("heart").await.unwrap_err(), ApiError::EventArchived);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn budget(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            serde_json::json!({ "vote_budget": 2 }).to_string(),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let mut qids = Vec::new();
+        for _ in 0..3 {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: "hello world".into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "client".parse().unwrap());
+        let vote = |qid, direction, headers: HeaderMap| {
+            super::vote(Path((qid, direction)), State(backend.clone()), headers)
+        };
+
+        // the budget can't be dodged by staying anonymous
+        assert_eq!(
+            vote(qids[0], UpDown::Up, HeaderMap::new())
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let v = vote(qids[0], UpDown::Up, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 1);
+        // repeated votes don't cost anything
+        let v = vote(qids[0], UpDown::Up, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 1);
+        let v = vote(qids[1], UpDown::Up, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 0);
+        assert_eq!(
+            vote(qids[2], UpDown::Up, headers.clone())
+                .await
+                .unwrap_err(),
+            StatusCode::FORBIDDEN
+        );
+        // down-votes don't need any budget
+        let v = vote(qids[2], UpDown::Down, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 0);
+
+        // taking a vote back (or flipping it) refunds it
+        let v = vote(qids[0], UpDown::None, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 1);
+        let v = vote(qids[1], UpDown::Down, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 2);
+        let v = vote(qids[2], UpDown::Up, headers.clone()).await.unwrap();
+        assert_eq!(v["remaining_votes"], 1);
+        assert_eq!(v["votes"], 2);
+
+        // other clients have their own budget
+        let mut other = HeaderMap::new();
+        other.insert(CLIENT_ID_HEADER, "other".parse().unwrap());
+        let v = vote(qids[0], UpDown::Up, other).await.unwrap();
+        assert_eq!(v["remaining_votes"], 1);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn downvotes(backend: Backend) {
+        let e = crate::new::new(
+            State(backend.clone()),
+            serde_json::json!({ "downvotes_enabled": false }).to_string(),
+        )
+        .await
+        .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "client".parse().unwrap());
+        let vote = |direction| {
+            super::vote(
+                Path((qid, direction)),
+                State(backend.clone()),
+                headers.clone(),
+            )
+        };
+
+        assert_eq!(
+            vote(UpDown::Down).await.unwrap_err(),
+            ApiError::DownvotesDisabled
+        );
+        // but up-votes (and taking them back) still work
+        assert_eq!(vote(UpDown::Up).await.unwrap()["votes"], 2);
+        assert_eq!(vote(UpDown::None).await.unwrap()["votes"], 1);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn concurrent(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let votes = |direction, n| {
+            futures_util::future::join_all((0..n).map(|_| {
+                super::vote(
+                    Path((qid, direction)),
+                    State(backend.clone()),
+                    HeaderMap::new(),
+                )
+            }))
+        };
+        let count = || async {
+            let qs =
+                crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                    .await
+                    .1
+                    .unwrap();
+            qs[0]["votes"].clone()
+        };
+
+        // none of the votes get lost, on top of the asker's own
+        for v in votes(UpDown::Up, 50).await {
+            let _ = v.unwrap();
+        }
+        assert_eq!(count().await, 51);
+
+        // and the count still doesn't go below zero
+        for v in votes(UpDown::Down, 60).await {
+            let _ = v.unwrap();
+        }
+        assert_eq!(count().await, 0);
+
+        backend.delete(&eid).await;
+    }
+
+    async fn batch(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let mut qids = Vec::new();
+        for body in ["hello world", "hello moon"] {
+            let q = crate::ask::ask(
+                Path(eid),
+                State(backend.clone()),
+                Json(crate::ask::Question {
+                    body: body.into(),
+                    asker: None,
+                    tags: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+            qids.push(Ulid::from_string(q["id"].as_str().unwrap()).unwrap());
+        }
+        let gone = Ulid::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "batcher".parse().unwrap());
+        let batch = |queued: Vec<(Ulid, UpDown)>, headers: HeaderMap| {
+            super::votes(
+                State(backend.clone()),
+                headers,
+                Json(
+                    queued
+                        .into_iter()
+                        .map(|(qid, updown)| Queued { qid, updown })
+                        .collect(),
+                ),
+            )
+        };
+
+        let r = batch(
+            vec![
+                (qids[0], UpDown::Up),
+                (qids[1], UpDown::Up),
+                (gone, UpDown::Up),
+                // repeated votes are still only counted once
+                (qids[0], UpDown::Up),
+                (qids[1], UpDown::None),
+            ],
+            headers.clone(),
+        )
+        .await
+        .unwrap();
+        let r = r.as_array().unwrap();
+        assert_eq!(r.len(), 5);
+        assert_eq!(r[0]["qid"], qids[0].to_string());
+        assert_eq!(r[0]["votes"], 2);
+        assert_eq!(r[1]["votes"], 2);
+        assert_eq!(r[2]["qid"], gone.to_string());
+        assert_eq!(r[2]["error"], "question_not_found");
+        assert_eq!(r[3]["votes"], 2);
+        assert_eq!(r[4]["votes"], 1);
+        assert_eq!(r[4]["your_vote"], "none");
+
+        // the same client's votes are de-duplicated across the single and batch endpoints
+        let v = super::vote(
+            Path((qids[0], UpDown::Up)),
+            State(backend.clone()),
+            headers.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["votes"], 2);
+
+        assert_eq!(
+            batch(vec![(qids[0], UpDown::Up)], HeaderMap::new())
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            batch(Vec::new(), headers.clone()).await.unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            batch(vec![(qids[0], UpDown::Up); MAX_BATCH + 1], headers)
+                .await
+                .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        backend.delete(&eid).await;
+    }
+
+    async fn totals(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_ID_HEADER, "voter".parse().unwrap());
+
+        for (direction, delta, total) in [
+            (UpDown::Up, 1, 2),
+            (UpDown::Down, -2, 0),
+            // repeated votes don't count, but still say where things stand
+            (UpDown::Down, 0, 0),
+            (UpDown::None, 1, 1),
+        ] {
+            let v = super::vote(
+                Path((qid, direction)),
+                State(backend.clone()),
+                headers.clone(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(v["qid"], qid.to_string());
+            assert_eq!(v["delta"], delta);
+            assert_eq!(v["votes"], total);
+            assert_eq!(v["your_vote"], direction.as_str());
+
+            // and the total is what everyone else sees next
+            let qs =
+                crate::list::list(Path(eid), State(backend.clone()), Query(Default::default()))
+                    .await
+                    .1
+                    .unwrap()
+                    .0;
+            assert_eq!(qs[0]["votes"], v["votes"]);
+        }
+
+        backend.delete(&eid).await;
+    }
+
+    async fn ips(backend: Backend, local: Option<&Mutex<Local>>) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = Ulid::from_string(q["id"].as_str().unwrap()).unwrap();
+        let (home, office): (IpAddr, IpAddr) =
+            ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+
+        let v = apply(&*backend, &qid, UpDown::Up, None, Some(home))
+            .await
+            .unwrap();
+        assert_eq!(v["votes"], 2);
+        // the same IP only gets the one vote
+        let v = apply(&*backend, &qid, UpDown::Up, None, Some(home))
+            .await
+            .unwrap();
+        assert_eq!(v["delta"], 0);
+        // but others do
+        let v = apply(&*backend, &qid, UpDown::Up, None, Some(office))
+            .await
+            .unwrap();
+        assert_eq!(v["votes"], 3);
+        // and can take theirs back
+        let v = apply(&*backend, &qid, UpDown::None, None, Some(office))
+            .await
+            .unwrap();
+        assert_eq!(v["votes"], 2);
+        // nor does making up where the vote came from count as another IP, as long as the proxy
+        // in front (which appended 10.0.0.9) says it came from the same one.
+        let seen = |forwarded: &'static str| {
+            let app = axum::Router::new()
+                .route(
+                    "/",
+                    axum::routing::post(|headers: HeaderMap| async move {
+                        crate::ratelimit::forwarded_ip(&headers)
+                            .unwrap()
+                            .to_string()
+                    }),
+                )
+                .layer(axum::middleware::from_fn(crate::ratelimit::forwarded_for));
+            let mut req = http::Request::post("/")
+                .header("x-forwarded-for", forwarded)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let peer = std::net::SocketAddr::new("10.0.0.3".parse().unwrap(), 443);
+            req.extensions_mut()
+                .insert(axum::extract::ConnectInfo(peer));
+            async move {
+                use tower::ServiceExt;
+                let res = app.oneshot(req).await.unwrap();
+                let ip = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                std::str::from_utf8(&ip).unwrap().parse::<IpAddr>().unwrap()
+            }
+        };
+        let v = apply(
+            &*backend,
+            &qid,
+            UpDown::Up,
+            None,
+            Some(seen("1.1.1.1, 10.0.0.9").await),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["delta"], 1);
+        let v = apply(
+            &*backend,
+            &qid,
+            UpDown::Up,
+            None,
+            Some(seen("2.2.2.2, 10.0.0.9").await),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["delta"], 0);
+        let v = apply(
+            &*backend,
+            &qid,
+            UpDown::None,
+            None,
+            Some(seen("3.3.3.3, 10.0.0.9").await),
+        )
+        .await
+        .unwrap();
+        assert_eq!(v["votes"], 2);
+        // while a client id still wins out over the IP
+        let v = apply(&*backend, &qid, UpDown::Up, Some("phone"), Some(home))
+            .await
+            .unwrap();
+        assert_eq!(v["votes"], 3);
+
+        if let Some(local) = local {
+            // only hashes are stored
+            let local = crate::lock(local);
+            assert!(local
+                .client_votes
+                .keys()
+                .all(|(_, voter)| !voter.contains("10.0.0")));
+        }
 
         backend.delete(&eid).await;
     }
 
+    #[tokio::test]
+    async fn local_ips() {
+        let (backend, local) = crate::local_state();
+        ips(backend, Some(&local)).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_ips() {
+        ips(crate::dynamo_backend().await, None).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_ips() {
+        ips(crate::redis_backend().await, None).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_ips() {
+        ips(crate::sqlite_backend().await, None).await;
+    }
+
+    #[tokio::test]
+    async fn local_totals() {
+        totals(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_totals() {
+        totals(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_totals() {
+        totals(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_totals() {
+        totals(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_batch() {
+        batch(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_batch() {
+        batch(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_batch() {
+        batch(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_batch() {
+        batch(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_concurrent() {
+        concurrent(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_concurrent() {
+        concurrent(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_concurrent() {
+        concurrent(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_concurrent() {
+        concurrent(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_budget() {
+        budget(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_budget() {
+        budget(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_budget() {
+        budget(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_budget() {
+        budget(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_downvotes() {
+        downvotes(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_downvotes() {
+        downvotes(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_downvotes() {
+        downvotes(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_downvotes() {
+        downvotes(crate::sqlite_backend().await).await;
+    }
+
+    #[tokio::test]
+    async fn local_reactions() {
+        reactions(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb_reactions() {
+        reactions(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis_reactions() {
+        reactions(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_reactions() {
+        reactions(crate::sqlite_backend().await).await;
+    }
+
     #[tokio::test]
     async fn local() {
-        inner(Backend::local().await).await;
+        inner(crate::local_backend().await).await;
     }
 
     #[tokio::test]
     #[ignore]
     async fn dynamodb() {
-        inner(Backend::dynamo().await).await;
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
     }
 }
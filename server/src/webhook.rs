@@ -0,0 +1,130 @@
+use crate::error::ApiError;
+use http::{header, Request, Uri};
+use hyper::{client::HttpConnector, Body, Client};
+use hyper_rustls::HttpsConnector;
+use std::{sync::OnceLock, time::Duration};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Header that tells the receiver of a webhook which event it's about.
+const EVENT_ID_HEADER: &str = "x-event-id";
+
+/// How long a webhook gets to respond before we give up on it.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest webhook URL we accept.
+const MAX_URL_LEN: usize = 2048;
+
+fn client() -> &'static Client<HttpsConnector<HttpConnector>> {
+    static CLIENT: OnceLock<Client<HttpsConnector<HttpConnector>>> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Client::builder().build(https)
+    })
+}
+
+/// Checks that `url` is something we can POST to.
+pub(super) fn check(url: &str) -> Result<(), ApiError> {
+    let ok = url.len() <= MAX_URL_LEN
+        && url.parse::<Uri>().is_ok_and(|uri| {
+            matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
+        });
+    if ok {
+        Ok(())
+    } else {
+        warn!(url, "rejecting invalid webhook url");
+        Err(ApiError::BadRequest)
+    }
+}
+
+/// Tells the webhook at `url` about something that happened in `eid`, in the background.
+///
+/// The webhook is the integrator's problem, so failures are logged but otherwise ignored. On
+/// Lambda, a delivery that's still running when the response goes out may only finish on the
+/// instance's next request, which is why the timeout is short.
+pub(super) fn notify(eid: Ulid, url: String, body: serde_json::Value) {
+    tokio::spawn(async move {
+        let req = Request::post(&url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(EVENT_ID_HEADER, eid.to_string())
+            .body(Body::from(body.to_string()));
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                warn!(%eid, url, error = %e, "could not build webhook request");
+                return;
+            }
+        };
+        match tokio::time::timeout(TIMEOUT, client().request(req)).await {
+            Ok(Ok(res)) if res.status().is_success() => {
+                debug!(%eid, url, "delivered webhook");
+            }
+            Ok(Ok(res)) => {
+                warn!(%eid, url, status = %res.status(), "webhook rejected delivery");
+            }
+            Ok(Err(e)) => {
+                warn!(%eid, url, error = %e, "webhook delivery failed");
+            }
+            Err(_) => {
+                warn!(%eid, url, "webhook timed out");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use http::StatusCode;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn urls() {
+        assert!(check("https://example.com/hook").is_ok());
+        assert!(check("http://localhost:8080").is_ok());
+        assert!(check("ftp://example.com").is_err());
+        assert!(check("example.com/hook").is_err());
+        assert!(check(&format!("https://example.com/{}", "a".repeat(MAX_URL_LEN))).is_err());
+    }
+
+    #[tokio::test]
+    async fn delivery() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: http::HeaderMap, body: String| {
+                let tx = tx.clone();
+                async move {
+                    let eid = headers[EVENT_ID_HEADER].to_str().unwrap().to_string();
+                    tx.send((eid, body)).unwrap();
+                    StatusCode::NO_CONTENT
+                }
+            }),
+        );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let eid = Ulid::new();
+        notify(
+            eid,
+            format!("http://{addr}/hook"),
+            serde_json::json!({ "text": "hello world" }),
+        );
+        let (got, body) = tokio::time::timeout(TIMEOUT, rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(got, eid.to_string());
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["text"], "hello world");
+    }
+}
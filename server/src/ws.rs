@@ -0,0 +1,446 @@
+use super::Backend;
+use crate::error::ApiError;
+use crate::ratelimit::RateLimitLayer;
+use crate::stream::Update;
+use crate::{toggle::Property, vote::UpDown};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    response::Response,
+    Extension,
+};
+use http::{HeaderMap, HeaderValue};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use ulid::Ulid;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn};
+
+/// How long clients have to send their handshake after connecting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first message a client sends.
+///
+/// Clients that include the event's secret get to do what hosts can do over the socket. Since
+/// browsers can't set headers on websocket requests, this is also where the client id goes.
+#[derive(Deserialize, Debug, Default)]
+struct Handshake {
+    secret: Option<String>,
+    client_id: Option<String>,
+}
+
+/// Everything a client can ask for after the handshake.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Request {
+    Vote {
+        qid: Ulid,
+        direction: UpDown,
+    },
+    /// Only for hosts.
+    Toggle {
+        qid: Ulid,
+        property: Property,
+        value: bool,
+    },
+}
+
+/// Serves live updates for an event over a websocket, and takes votes (and host toggles) the
+/// other way.
+///
+/// Each request gets a `reply` message with the same body the corresponding HTTP endpoint would
+/// have returned, or the status and error code it would have failed with. Votes only go to
+/// questions of the socket's event, and count against `limit` just like votes over HTTP do.
+pub(super) async fn ws(
+    Path(eid): Path<Ulid>,
+    State(dynamo): State<Backend>,
+    Extension(limit): Extension<RateLimitLayer>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // make sure the event exists so that clients of old events stop trying
+    dynamo.get_secret(&eid).await?;
+
+    let Some((_, rx)) = dynamo.subscribe(&eid, None) else {
+        warn!(%eid, "event websocket requested from backend without live updates");
+        return Err(ApiError::NotImplemented);
+    };
+
+    Ok(upgrade.on_upgrade(move |socket| async move {
+        serve(socket, eid, dynamo, rx, limit, headers).await;
+        debug!(%eid, "websocket client went away");
+    }))
+}
+
+fn reply(r: Result<axum::Json<Value>, ApiError>) -> Message {
+    let v = match r {
+        Ok(axum::Json(data)) => serde_json::json!({ "type": "reply", "ok": true, "data": data }),
+        Err(e) => serde_json::json!({
+            "type": "reply",
+            "ok": false,
+            "status": e.status().as_u16(),
+            "error": e.code(),
+        }),
+    };
+    Message::Text(v.to_string())
+}
+
+async fn serve(
+    mut socket: WebSocket,
+    eid: Ulid,
+    dynamo: Backend,
+    mut rx: broadcast::Receiver<Update>,
+    limit: RateLimitLayer,
+    mut headers: HeaderMap,
+) {
+    // the address doesn't change for as long as the socket is open.
+    let ip = crate::ratelimit::forwarded_ip(&headers);
+
+    let handshake = match tokio::time::timeout(HANDSHAKE_TIMEOUT, socket.recv()).await {
+        Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<Handshake>(&text),
+        Ok(Some(Ok(_))) => Ok(Handshake::default()),
+        Ok(_) => return,
+        Err(_) => {
+            debug!(%eid, "websocket client never sent handshake");
+            return;
+        }
+    };
+    let Ok(handshake) = handshake else {
+        warn!(%eid, "got invalid websocket handshake");
+        let _ = socket.send(reply(Err(ApiError::BadRequest))).await;
+        return;
+    };
+    let secret = match handshake.secret {
+        Some(secret) => match super::check_secret(&dynamo, &eid, &secret).await {
+            Ok(()) => Some(secret),
+            Err(status) => {
+                let _ = socket.send(reply(Err(status))).await;
+                return;
+            }
+        },
+        None => None,
+    };
+    if let Some(client_id) = handshake.client_id {
+        match HeaderValue::from_str(&client_id) {
+            Ok(v) => {
+                headers.insert("x-client-id", v);
+            }
+            Err(_) => {
+                warn!(%eid, "got invalid client id in websocket handshake");
+                let _ = socket.send(reply(Err(ApiError::BadRequest))).await;
+                return;
+            }
+        }
+    }
+
+    let ready = serde_json::json!({ "type": "ready", "host": secret.is_some() });
+    if socket.send(Message::Text(ready.to_string())).await.is_err() {
+        return;
+    }
+    debug!(%eid, host = secret.is_some(), "websocket client subscribed to event");
+    let _viewer = crate::presence::Viewer::join(&dynamo, eid);
+
+    let shutdown = crate::stream::until_shutdown();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            update = rx.recv() => {
+                let update = match update {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(_)) => {
+                        // same as for the event stream: the client reconnects and re-fetches.
+                        debug!(%eid, "websocket client fell behind");
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let msg = serde_json::json!({
+                    "type": "update",
+                    "id": update.id,
+                    "kind": update.kind,
+                    "data": &*update.data,
+                });
+                if socket.send(Message::Text(msg.to_string())).await.is_err() {
+                    return;
+                }
+            }
+            msg = socket.recv() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    // pings are answered for us
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    Some(Ok(Message::Binary(_))) => {
+                        if socket.send(reply(Err(ApiError::BadRequest))).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                };
+                let r = match serde_json::from_str::<Request>(&text) {
+                    Ok(Request::Vote { qid, direction }) => {
+                        vote(&dynamo, &limit, ip, &eid, qid, direction, &headers).await
+                    }
+                    Ok(Request::Toggle { qid, property, value }) => match &secret {
+                        // the toggle checks the secret again, so rotating it cuts off hosts
+                        // that are still connected with the old one.
+                        Some(secret) => crate::toggle::toggle(
+                            Path((eid, secret.clone(), qid, property)),
+                            State(dynamo.clone()),
+                            HeaderMap::new(),
+                            String::from(if value { "on" } else { "off" }),
+                        )
+                        .await,
+                        None => {
+                            warn!(%eid, %qid, "guest attempted toggle over websocket");
+                            Err(ApiError::ForbiddenSecret)
+                        }
+                    },
+                    Err(e) => {
+                        warn!(%eid, error = %e, "got invalid websocket request");
+                        Err(ApiError::BadRequest)
+                    }
+                };
+                if socket.send(reply(r)).await.is_err() {
+                    return;
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Votes `direction` on `qid` for a client of the socket of `eid`.
+async fn vote(
+    dynamo: &Backend,
+    limit: &RateLimitLayer,
+    ip: Option<std::net::IpAddr>,
+    eid: &Ulid,
+    qid: Ulid,
+    direction: UpDown,
+    headers: &HeaderMap,
+) -> Result<axum::Json<Value>, ApiError> {
+    if let Some(ip) = ip {
+        if limit.check(ip).is_err() {
+            warn!(%eid, %ip, "rate limiting websocket client");
+            return Err(ApiError::RateLimited);
+        }
+    }
+    // otherwise one socket could vote on every event's questions.
+    match dynamo.in_event(eid, &qid).await {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!(%eid, %qid, "websocket vote on question of another event");
+            return Err(ApiError::QuestionNotFound);
+        }
+        Err(e) => {
+            error!(%eid, %qid, error = %e, "dynamodb request to check question's event failed");
+            return Err(ApiError::Internal);
+        }
+    }
+    crate::vote::vote(
+        Path((qid, direction)),
+        State(dynamo.clone()),
+        headers.clone(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use futures_util::{SinkExt, StreamExt};
+    use http::StatusCode;
+    use std::net::SocketAddr;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    async fn next<S>(ws: &mut tokio_tungstenite::WebSocketStream<S>) -> Value
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        loop {
+            let msg = ws.next().await.unwrap().unwrap();
+            let msg: Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            // these come whenever someone connects, which is beside the point here.
+            if msg["kind"] != "presence" {
+                return msg;
+            }
+        }
+    }
+
+    async fn inner(backend: Backend) {
+        let e = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let eid = Ulid::from_string(e["id"].as_str().unwrap()).unwrap();
+        let secret = e["secret"].as_str().unwrap();
+        let q = crate::ask::ask(
+            Path(eid),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello world".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let qid = q["id"].as_str().unwrap();
+
+        let app = Router::new()
+            .route(
+                "/api/event/:eid/ws",
+                get(super::ws).layer(axum::Extension(RateLimitLayer::new(2))),
+            )
+            .layer(axum::middleware::from_fn(crate::ratelimit::forwarded_for))
+            .with_state(backend.clone());
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        if backend.subscribe(&eid, None).is_none() {
+            // the dynamodb backend has no updates to give, so it refuses the upgrade.
+            let e = tokio_tungstenite::connect_async(format!("ws://{addr}/api/event/{eid}/ws"))
+                .await
+                .unwrap_err();
+            let tokio_tungstenite::tungstenite::Error::Http(res) = e else {
+                panic!("websocket on dynamodb backend failed without a response: {e}");
+            };
+            assert_eq!(res.status(), StatusCode::NOT_IMPLEMENTED);
+            backend.delete(&eid).await;
+            return;
+        }
+
+        let connect = |handshake: Value| async move {
+            let (mut ws, _) =
+                tokio_tungstenite::connect_async(format!("ws://{addr}/api/event/{eid}/ws"))
+                    .await
+                    .unwrap();
+            ws.send(WsMessage::Text(handshake.to_string()))
+                .await
+                .unwrap();
+            ws
+        };
+
+        // guests can vote, and see the result as an update
+        let mut guest = connect(serde_json::json!({ "client_id": "guest" })).await;
+        assert_eq!(
+            next(&mut guest).await,
+            serde_json::json!({ "type": "ready", "host": false })
+        );
+        let vote = serde_json::json!({ "type": "vote", "qid": qid, "direction": "up" });
+        guest.send(WsMessage::Text(vote.to_string())).await.unwrap();
+        let mut got = [next(&mut guest).await, next(&mut guest).await];
+        got.sort_by_key(|m| m["type"].as_str().unwrap().to_string());
+        assert_eq!(got[0]["type"], "reply");
+        assert_eq!(got[0]["ok"], true);
+        assert_eq!(got[0]["data"]["votes"], 2);
+        assert_eq!(got[1]["type"], "update");
+        assert_eq!(got[1]["kind"], "vote");
+        assert_eq!(got[1]["data"]["qid"], qid);
+
+        // only on questions of this event, though
+        let other = crate::new::new(State(backend.clone()), String::new())
+            .await
+            .unwrap();
+        let other = Ulid::from_string(other["id"].as_str().unwrap()).unwrap();
+        let elsewhere = crate::ask::ask(
+            Path(other),
+            State(backend.clone()),
+            Json(crate::ask::Question {
+                body: "hello elsewhere".into(),
+                asker: None,
+                tags: Vec::new(),
+            }),
+        )
+        .await
+        .unwrap();
+        let vote = serde_json::json!({ "type": "vote", "qid": elsewhere["id"], "direction": "up" });
+        guest.send(WsMessage::Text(vote.to_string())).await.unwrap();
+        assert_eq!(next(&mut guest).await["error"], "question_not_found");
+        // and no faster than over http
+        let vote = serde_json::json!({ "type": "vote", "qid": qid, "direction": "down" });
+        guest.send(WsMessage::Text(vote.to_string())).await.unwrap();
+        assert_eq!(next(&mut guest).await["error"], "rate_limited");
+        backend.delete(&other).await;
+
+        // but can't toggle
+        let toggle = serde_json::json!({
+            "type": "toggle",
+            "qid": qid,
+            "property": "hidden",
+            "value": true,
+        });
+        guest
+            .send(WsMessage::Text(toggle.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            next(&mut guest).await,
+            serde_json::json!({ "type": "reply", "ok": false, "status": 401, "error": "forbidden_secret" })
+        );
+
+        // hosts can
+        let mut host = connect(serde_json::json!({ "secret": secret })).await;
+        assert_eq!(next(&mut host).await["host"], true);
+        host.send(WsMessage::Text(toggle.to_string()))
+            .await
+            .unwrap();
+        let mut got = [next(&mut host).await, next(&mut host).await];
+        got.sort_by_key(|m| m["type"].as_str().unwrap().to_string());
+        assert_eq!(
+            got[0]["data"],
+            serde_json::json!({ "hidden": true, "version": 3 })
+        );
+        assert_eq!(got[1]["kind"], "toggle");
+        // and everyone else hears about it
+        let update = next(&mut guest).await;
+        assert_eq!(update["kind"], "toggle");
+
+        // a wrong secret is turned away
+        let mut bad = connect(serde_json::json!({ "secret": "wrong" })).await;
+        assert_eq!(
+            next(&mut bad).await,
+            serde_json::json!({ "type": "reply", "ok": false, "status": 401, "error": "forbidden_secret" })
+        );
+
+        // hanging up doesn't upset anyone
+        drop(guest);
+        host.close(None).await.unwrap();
+
+        backend.delete(&eid).await;
+    }
+
+    #[tokio::test]
+    async fn local() {
+        inner(crate::local_backend().await).await;
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn dynamodb() {
+        inner(crate::dynamo_backend().await).await;
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore]
+    async fn redis() {
+        inner(crate::redis_backend().await).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite() {
+        inner(crate::sqlite_backend().await).await;
+    }
+}